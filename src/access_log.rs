@@ -0,0 +1,480 @@
+use crate::handlers::framing::{response_body_length, BodyLength};
+use std::cell::RefCell;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufWriter, ReadBuf};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{error, warn};
+
+/// 写入任务处理不过来时最多排队的日志行数，超过后丢弃新行而不是阻塞转发
+const QUEUE_CAPACITY: usize = 4096;
+
+/// 访问日志（NCSA通用日志格式）
+///
+/// 日志行经有界通道交给后台任务缓冲写入，记录日志不会阻塞连接处理；
+/// 通道已满时丢弃该行并记录告警
+#[derive(Debug, Clone)]
+pub struct AccessLog {
+    sender: mpsc::Sender<Message>,
+}
+
+#[derive(Debug)]
+enum Message {
+    Line(String),
+    Flush(oneshot::Sender<()>),
+}
+
+impl AccessLog {
+    /// 以追加方式打开日志文件并启动写入任务
+    pub async fn open(path: &Path) -> io::Result<Self> {
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        let (sender, mut receiver) = mpsc::channel(QUEUE_CAPACITY);
+
+        tokio::spawn(async move {
+            let mut writer = BufWriter::new(file);
+            while let Some(message) = receiver.recv().await {
+                // 通道中积压的行一并写入后再刷新，减少系统调用
+                let mut next = Some(message);
+                let mut acks = Vec::new();
+                while let Some(message) = next {
+                    match message {
+                        Message::Line(line) => {
+                            if let Err(e) = writer.write_all(line.as_bytes()).await {
+                                error!("写入访问日志失败: {}", e);
+                            }
+                        }
+                        Message::Flush(ack) => acks.push(ack),
+                    }
+                    next = receiver.try_recv().ok();
+                }
+                if let Err(e) = writer.flush().await {
+                    error!("刷新访问日志失败: {}", e);
+                }
+                for ack in acks {
+                    let _ = ack.send(());
+                }
+            }
+        });
+
+        Ok(AccessLog { sender })
+    }
+
+    /// 追加一条访问记录
+    pub fn log(&self, entry: &AccessEntry) {
+        if self
+            .sender
+            .try_send(Message::Line(format!("{}\n", entry)))
+            .is_err()
+        {
+            warn!("访问日志队列已满，丢弃记录: {}", entry);
+        }
+    }
+
+    /// 等待此前提交的记录全部写入文件
+    pub async fn flush(&self) {
+        let (ack, done) = oneshot::channel();
+        if self.sender.send(Message::Flush(ack)).await.is_ok() {
+            let _ = done.await;
+        }
+    }
+}
+
+/// 单个请求的访问记录
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessEntry {
+    pub client_ip: IpAddr,
+    pub user: Option<String>,
+    pub time: SystemTime,
+    /// 请求方法，CONNECT隧道为 `CONNECT`，SOCKS为 `SOCKS4`/`SOCKS5`
+    pub method: String,
+    /// 目标 `host:port`
+    pub target: Option<String>,
+    /// 返回给客户端的状态码，未知时为 `None`
+    pub status: Option<u16>,
+    /// 返回给客户端的字节数
+    pub bytes: u64,
+    /// 转发的请求体字节数，隧道等无法区分消息体时为 `None`
+    pub req_bytes: Option<u64>,
+    /// 返回的响应体字节数，隧道等无法区分消息体时为 `None`
+    pub resp_bytes: Option<u64>,
+}
+
+impl fmt::Display for AccessEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} - {} [{}] \"{} {}\" ",
+            self.client_ip,
+            self.user.as_deref().unwrap_or("-"),
+            clf_time(self.time),
+            self.method,
+            self.target.as_deref().unwrap_or("-"),
+        )?;
+        match self.status {
+            Some(status) => write!(f, "{} ", status)?,
+            None => write!(f, "- ")?,
+        }
+        match self.bytes {
+            0 => write!(f, "-")?,
+            bytes => write!(f, "{}", bytes)?,
+        }
+        write!(
+            f,
+            " req_bytes={} resp_bytes={}",
+            optional(self.req_bytes),
+            optional(self.resp_bytes)
+        )
+    }
+}
+
+/// 未知的数值记为 `-`
+fn optional(value: Option<u64>) -> String {
+    value.map_or_else(|| "-".to_string(), |value| value.to_string())
+}
+
+/// 按通用日志格式格式化UTC时间，如 `10/Oct/2000:13:55:36 +0000`
+fn clf_time(time: SystemTime) -> String {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+
+    // 由自1970-01-01起的天数推算公历日期
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
+
+/// 连接处理过程中逐步收集的访问记录字段
+///
+/// 保持的连接上每个请求各有一条记录，见 [`next_request`]
+#[derive(Debug, Default)]
+pub struct RequestRecord {
+    pub user: Option<String>,
+    pub method: Option<String>,
+    pub target: Option<String>,
+    pub status: Option<u16>,
+    pub bytes: u64,
+    pub req_bytes: Option<u64>,
+    pub resp_bytes: Option<u64>,
+    /// 请求开始的时间，连接上的第一个请求为 `None`，取连接建立的时间
+    pub time: Option<SystemTime>,
+    /// 同一连接上此前已结束的请求，按先后排列
+    pub finished: Vec<RequestRecord>,
+}
+
+tokio::task_local! {
+    /// 当前连接的访问记录
+    static RECORD: RefCell<RequestRecord>;
+}
+
+/// 在访问记录作用域内运行连接任务，返回任务结果与收集到的访问记录
+pub async fn request_scope<F: Future>(future: F) -> (F::Output, RequestRecord) {
+    RECORD
+        .scope(RefCell::new(RequestRecord::default()), async {
+            let output = future.await;
+            let record = RECORD.with(|record| record.take());
+            (output, record)
+        })
+        .await
+}
+
+fn update(update: impl FnOnce(&mut RequestRecord)) {
+    let _ = RECORD.try_with(|record| update(&mut record.borrow_mut()));
+}
+
+/// 结束当前请求的记录，开始同一连接上下一个请求的记录
+///
+/// 已收集的字段移入 [`RequestRecord::finished`]，请求级字段清空，认证用户沿用到下一个请求；
+/// `same_target` 表示下一个请求复用当前的源站连接，目标一并沿用
+pub fn next_request(same_target: bool) {
+    update(|record| {
+        let target = if same_target {
+            record.target.clone()
+        } else {
+            record.target.take()
+        };
+        let finished = RequestRecord {
+            user: record.user.clone(),
+            method: record.method.take(),
+            target,
+            status: record.status.take(),
+            bytes: std::mem::take(&mut record.bytes),
+            req_bytes: record.req_bytes.take(),
+            resp_bytes: record.resp_bytes.take(),
+            time: record.time.replace(SystemTime::now()),
+            finished: Vec::new(),
+        };
+        record.finished.push(finished);
+    });
+}
+
+/// 记录通过认证的用户名
+pub fn record_user(user: &str) {
+    update(|record| record.user = Some(user.to_string()));
+}
+
+/// 记录请求方法
+pub fn record_method(method: &str) {
+    update(|record| record.method = Some(method.to_string()));
+}
+
+/// 记录目标地址，只保留首次记录的目标（备用目标不覆盖原始目标）
+pub fn record_target(host: &str, port: u16) {
+    update(|record| {
+        if record.target.is_none() {
+            record.target = Some(if host.contains(':') {
+                format!("[{}]:{}", host, port)
+            } else {
+                format!("{}:{}", host, port)
+            });
+        }
+    });
+}
+
+/// 记录代理返回给客户端的状态码
+pub fn record_status(status: u16) {
+    update(|record| record.status = Some(status));
+}
+
+/// 记录 `200 OK` 形式的状态文本中的状态码
+pub fn record_status_text(status: &str) {
+    if let Some(code) = status
+        .split_whitespace()
+        .next()
+        .and_then(|c| c.parse().ok())
+    {
+        record_status(code);
+    }
+}
+
+/// 记录返回给客户端的字节数
+pub fn record_bytes(bytes: u64) {
+    update(|record| record.bytes += bytes);
+}
+
+/// 记录一次HTTP转发的请求体与响应体字节数，同一请求多次记录时累加
+pub fn record_body_bytes(request: u64, response: u64) {
+    update(|record| {
+        record.req_bytes = Some(record.req_bytes.unwrap_or(0) + request);
+        record.resp_bytes = Some(record.resp_bytes.unwrap_or(0) + response);
+    });
+}
+
+/// 从响应头（如 `HTTP/1.1 200 OK`）中解析状态码
+pub fn parse_status(response: &[u8]) -> Option<u16> {
+    let line_end = response
+        .windows(2)
+        .position(|w| w == b"\r\n")
+        .unwrap_or(response.len());
+    let line = std::str::from_utf8(&response[..line_end]).ok()?;
+    let mut parts = line.split_whitespace();
+    if !parts.next()?.starts_with("HTTP/") {
+        return None;
+    }
+    parts.next()?.parse().ok()
+}
+
+/// 响应头部的最大长度，超过后不再划分响应
+const MAX_RESPONSE_HEAD: usize = 64 * 1024;
+
+/// 透传读写并观察经过的响应的流包装
+///
+/// 从首个响应中解析状态码，并按 `Content-Length` 依次划分响应以统计响应体字节数；
+/// 遇到分块编码或到连接关闭为止的响应后，之后的数据都计为响应体。
+/// 只观察经过的数据，不额外读取，因此不会改变转发的时序
+pub struct StatusSniffer<S> {
+    inner: S,
+    status_recorded: bool,
+    state: ResponseState,
+    body_bytes: u64,
+}
+
+/// 观察中的响应所处的位置
+enum ResponseState {
+    /// 正在读取响应头部
+    Head(Vec<u8>),
+    /// 响应体剩余的字节数
+    Body(u64),
+    /// 无法继续划分，之后的数据都属于当前响应体
+    Untracked,
+}
+
+impl<S> StatusSniffer<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            status_recorded: false,
+            state: ResponseState::Head(Vec::new()),
+            body_bytes: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// 已读到的响应体字节数（不含响应头部）
+    pub fn body_bytes(&self) -> u64 {
+        self.body_bytes
+    }
+
+    fn observe(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            match &mut self.state {
+                ResponseState::Untracked => {
+                    self.body_bytes += data.len() as u64;
+                    return;
+                }
+                ResponseState::Body(remaining) => {
+                    let n = (*remaining).min(data.len() as u64);
+                    self.body_bytes += n;
+                    *remaining -= n;
+                    if *remaining == 0 {
+                        self.state = ResponseState::Head(Vec::new());
+                    }
+                    data = &data[n as usize..];
+                }
+                ResponseState::Head(head) => {
+                    let searched = head.len().saturating_sub(3);
+                    let previous = head.len();
+                    head.extend_from_slice(data);
+                    let end = match head[searched..].windows(4).position(|w| w == b"\r\n\r\n") {
+                        Some(pos) => searched + pos + 4,
+                        None => {
+                            if head.len() > MAX_RESPONSE_HEAD {
+                                self.state = ResponseState::Untracked;
+                            }
+                            return;
+                        }
+                    };
+                    head.truncate(end);
+                    data = &data[end - previous..];
+                    if !self.status_recorded {
+                        if let Some(status) = parse_status(head) {
+                            record_status(status);
+                        }
+                        self.status_recorded = true;
+                    }
+                    self.state = match response_body_length(head) {
+                        BodyLength::Empty => ResponseState::Head(Vec::new()),
+                        BodyLength::Fixed(length) => ResponseState::Body(length),
+                        BodyLength::Chunked | BodyLength::UntilClose => ResponseState::Untracked,
+                    };
+                }
+            }
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for StatusSniffer<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if matches!(result, Poll::Ready(Ok(()))) && buf.filled().len() > filled {
+            let data = buf.filled()[filled..].to_vec();
+            self.observe(&data);
+        }
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for StatusSniffer<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_common_log_format() {
+        let entry = AccessEntry {
+            client_ip: "192.168.1.100".parse().unwrap(),
+            user: Some("alice".to_string()),
+            time: UNIX_EPOCH + Duration::from_secs(971_186_136),
+            method: "GET".to_string(),
+            target: Some("example.com:80".to_string()),
+            status: Some(200),
+            bytes: 2326,
+            req_bytes: Some(0),
+            resp_bytes: Some(2100),
+        };
+        assert_eq!(
+            entry.to_string(),
+            "192.168.1.100 - alice [10/Oct/2000:13:55:36 +0000] \"GET example.com:80\" 200 2326 req_bytes=0 resp_bytes=2100"
+        );
+
+        let tunnel = AccessEntry {
+            method: "CONNECT".to_string(),
+            target: Some("example.com:443".to_string()),
+            req_bytes: None,
+            resp_bytes: None,
+            ..entry
+        };
+        assert!(tunnel
+            .to_string()
+            .ends_with(" 200 2326 req_bytes=- resp_bytes=-"));
+    }
+
+    #[tokio::test]
+    async fn test_sniffer_counts_response_bodies() {
+        use tokio::io::AsyncReadExt;
+
+        let responses: &[u8] = b"HTTP/1.1 100 Continue\r\n\r\n\
+            HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello\
+            HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\n";
+        let mut sniffer = StatusSniffer::new(responses);
+        let mut buffer = [0u8; 7];
+        while sniffer.read(&mut buffer).await.unwrap() > 0 {}
+        // 分块编码的帧格式计入响应体
+        assert_eq!(sniffer.body_bytes(), 5 + 13);
+    }
+}
@@ -0,0 +1,193 @@
+use crate::cidr::IpCidr;
+use std::error::Error;
+use std::fmt;
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+
+/// 目标主机匹配模式
+///
+/// 支持精确域名（`example.com`）、通配子域名（`*.example.com`，不含 `example.com` 本身）
+/// 以及IP地址或网段（`10.0.0.0/8`）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostPattern {
+    Exact(String),
+    /// 保存去掉 `*` 后的后缀，如 `.example.com`
+    Wildcard(String),
+    Network(IpCidr),
+}
+
+impl HostPattern {
+    /// 判断目标主机是否匹配
+    ///
+    /// IP网段只匹配字面IP形式的目标，不做DNS解析
+    pub fn matches(&self, host: &str) -> bool {
+        let host = normalize_host(host);
+        match self {
+            HostPattern::Exact(name) => host == *name,
+            HostPattern::Wildcard(suffix) => host.len() > suffix.len() && host.ends_with(suffix),
+            HostPattern::Network(cidr) => host.parse::<IpAddr>().is_ok_and(|ip| cidr.contains(ip)),
+        }
+    }
+}
+
+impl FromStr for HostPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err("空的匹配模式".to_string());
+        }
+        if let Some(suffix) = s.strip_prefix("*.") {
+            if suffix.is_empty() || suffix.contains('*') {
+                return Err(format!("无效的通配模式: {}", s));
+            }
+            return Ok(HostPattern::Wildcard(format!(
+                ".{}",
+                normalize_host(suffix)
+            )));
+        }
+        if s.contains('*') {
+            return Err(format!("通配符只能出现在开头: {}", s));
+        }
+        if let Ok(cidr) = s.trim_start_matches('[').trim_end_matches(']').parse() {
+            return Ok(HostPattern::Network(cidr));
+        }
+        Ok(HostPattern::Exact(normalize_host(s)))
+    }
+}
+
+impl fmt::Display for HostPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HostPattern::Exact(name) => write!(f, "{}", name),
+            HostPattern::Wildcard(suffix) => write!(f, "*{}", suffix),
+            HostPattern::Network(cidr) => write!(f, "{}", cidr),
+        }
+    }
+}
+
+/// 统一主机名格式：小写、去掉末尾的点和IPv6字面量的方括号
+fn normalize_host(host: &str) -> String {
+    host.trim()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .trim_end_matches('.')
+        .to_ascii_lowercase()
+}
+
+/// 出站目标访问规则
+///
+/// 命中黑名单的目标一律拒绝；白名单非空时只放行命中白名单的目标
+#[derive(Debug, Clone, Default)]
+pub struct AccessRules {
+    allow: Vec<HostPattern>,
+    block: Vec<HostPattern>,
+}
+
+impl AccessRules {
+    pub fn new(allow: Vec<HostPattern>, block: Vec<HostPattern>) -> Self {
+        Self { allow, block }
+    }
+
+    /// 从白名单和黑名单文件加载规则
+    ///
+    /// 文件每行一个模式，忽略空行和以 `#` 开头的注释行
+    pub fn from_files(
+        allow_file: Option<&Path>,
+        block_file: Option<&Path>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let load = |path: Option<&Path>| match path {
+            Some(path) => load_patterns(path),
+            None => Ok(Vec::new()),
+        };
+        Ok(Self::new(load(allow_file)?, load(block_file)?))
+    }
+
+    /// 白名单模式数量
+    pub fn allow_count(&self) -> usize {
+        self.allow.len()
+    }
+
+    /// 黑名单模式数量
+    pub fn block_count(&self) -> usize {
+        self.block.len()
+    }
+
+    /// 判断是否允许连接目标主机
+    pub fn is_allowed(&self, host: &str) -> bool {
+        if self.block.iter().any(|pattern| pattern.matches(host)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|pattern| pattern.matches(host))
+    }
+}
+
+/// 读取模式文件
+fn load_patterns(path: &Path) -> Result<Vec<HostPattern>, Box<dyn Error + Send + Sync>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("读取规则文件 {} 失败: {}", path.display(), e))?;
+
+    let mut patterns = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let pattern = line
+            .parse()
+            .map_err(|e| format!("{} 第 {} 行: {}", path.display(), index + 1, e))?;
+        patterns.push(pattern);
+    }
+    Ok(patterns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(values: &[&str]) -> Vec<HostPattern> {
+        values.iter().map(|value| value.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_wildcard_matching() {
+        let pattern: HostPattern = "*.Example.com".parse().unwrap();
+        assert!(pattern.matches("api.example.com"));
+        assert!(pattern.matches("a.b.EXAMPLE.com."));
+        assert!(!pattern.matches("example.com"));
+        assert!(!pattern.matches("badexample.com"));
+
+        let exact: HostPattern = "example.com".parse().unwrap();
+        assert!(exact.matches("EXAMPLE.com"));
+        assert!(!exact.matches("api.example.com"));
+
+        assert!("api.*.com".parse::<HostPattern>().is_err());
+    }
+
+    #[test]
+    fn test_cidr_matching() {
+        let rules = AccessRules::new(
+            Vec::new(),
+            patterns(&["10.0.0.0/8", "::1", "169.254.169.254"]),
+        );
+        assert!(!rules.is_allowed("10.1.2.3"));
+        assert!(!rules.is_allowed("[::1]"));
+        assert!(!rules.is_allowed("169.254.169.254"));
+        assert!(rules.is_allowed("11.0.0.1"));
+        assert!(rules.is_allowed("example.com"));
+    }
+
+    #[test]
+    fn test_block_overrides_allow() {
+        let rules = AccessRules::new(
+            patterns(&["*.example.com", "192.168.0.0/16"]),
+            patterns(&["admin.example.com"]),
+        );
+        assert!(rules.is_allowed("www.example.com"));
+        assert!(rules.is_allowed("192.168.1.1"));
+        assert!(!rules.is_allowed("admin.example.com"));
+        assert!(!rules.is_allowed("example.org"));
+    }
+}
@@ -0,0 +1,59 @@
+use crate::metrics::metrics;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{AcquireError, OwnedSemaphorePermit, Semaphore, TryAcquireError};
+
+/// 获取连接许可并测量排队时间
+///
+/// 从接受连接到获得许可之间的等待计入全局指标，
+/// 便于区分瓶颈在并发上限还是后端响应。许可已耗尽时先调用
+/// `on_saturated`，再排队等待许可释放
+///
+/// # 返回
+/// 返回许可以及等待的时长
+pub async fn acquire_permit(
+    semaphore: Arc<Semaphore>,
+    on_saturated: impl FnOnce(),
+) -> Result<(OwnedSemaphorePermit, Duration), AcquireError> {
+    let start = Instant::now();
+    let permit = match semaphore.clone().try_acquire_owned() {
+        Ok(permit) => permit,
+        Err(error) => {
+            if error == TryAcquireError::NoPermits {
+                on_saturated();
+            }
+            semaphore.acquire_owned().await?
+        }
+    };
+    let waited = start.elapsed();
+    metrics().record_permit_wait(waited);
+    Ok((permit, waited))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[tokio::test]
+    async fn test_queued_connection_records_wait() {
+        let semaphore = Arc::new(Semaphore::new(1));
+        let (held, waited) = acquire_permit(semaphore.clone(), || panic!("许可未耗尽"))
+            .await
+            .unwrap();
+        assert!(waited < Duration::from_millis(50));
+
+        // 许可耗尽后排队的连接需等待许可释放
+        let saturated = Arc::new(AtomicBool::new(false));
+        let flag = saturated.clone();
+        let queued = tokio::spawn(acquire_permit(semaphore.clone(), move || {
+            flag.store(true, Ordering::SeqCst)
+        }));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(saturated.load(Ordering::SeqCst));
+        drop(held);
+
+        let (_permit, waited) = queued.await.unwrap().unwrap();
+        assert!(waited >= Duration::from_millis(150), "等待 {:?}", waited);
+    }
+}
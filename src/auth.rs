@@ -1,20 +1,455 @@
 use base64::Engine;
+use md5::Md5;
+use ring::hmac;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use subtle::{Choice, ConstantTimeEq};
 use tracing::{debug, warn};
 
+/// 认证质询中的realm
+pub const REALM: &str = "RustProxy";
+
+/// Digest nonce的默认有效期
+const DEFAULT_NONCE_TTL: Duration = Duration::from_secs(300);
+
+/// 同时跟踪请求计数的已使用nonce上限，超过后淘汰最早使用的nonce
+const MAX_NONCES: usize = 10_000;
+
+/// nonce中签名部分的字节数（截断的HMAC-SHA256）
+const NONCE_MAC_LEN: usize = 16;
+
 #[derive(Debug, Clone)]
 pub struct AuthConfig {
-    pub username: String,
-    pub password: String,
+    users: HashMap<String, String>,
+    digest: Option<DigestConfig>,
+}
+
+/// HTTP Digest认证（RFC 7616，qop=auth，MD5或SHA-256）
+///
+/// nonce由签发时间、随机数和代理密钥的HMAC签名组成，签发时不保存任何状态，
+/// 未认证的客户端反复索取质询也不会占用内存或挤掉其他客户端的nonce；
+/// 只有认证通过的nonce才记录最近接受的请求计数 `nc`。
+/// 签名无效、过期或计数未递增的nonce一律拒绝，防止响应被重放
+#[derive(Debug, Clone)]
+pub struct DigestConfig {
+    realm: String,
+    nonce_ttl: Duration,
+    key: hmac::Key,
+    /// 签发时间的起点，nonce中的时间为相对该时刻的毫秒数
+    epoch: Instant,
+    used: Arc<Mutex<UsedNonces>>,
+}
+
+/// 已认证通过的nonce及其最近接受的请求计数
+#[derive(Debug, Default)]
+struct UsedNonces {
+    last_nc: HashMap<String, u32>,
+    /// 按首次使用顺序排列的nonce及其签发时间，用于过期清理和容量淘汰
+    order: VecDeque<(u64, String)>,
+    /// 因容量淘汰而丢失计数的nonce中最晚的签发时间，不晚于该时间且未被跟踪的nonce不再接受
+    evicted_until: Option<u64>,
+}
+
+/// Digest算法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DigestAlgorithm {
+    Md5,
+    Sha256,
+}
+
+impl DigestAlgorithm {
+    fn parse(name: &str) -> Option<Self> {
+        if name.eq_ignore_ascii_case("MD5") {
+            Some(DigestAlgorithm::Md5)
+        } else if name.eq_ignore_ascii_case("SHA-256") {
+            Some(DigestAlgorithm::Sha256)
+        } else {
+            None
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            DigestAlgorithm::Md5 => "MD5",
+            DigestAlgorithm::Sha256 => "SHA-256",
+        }
+    }
+
+    /// 计算摘要的小写十六进制形式
+    fn hash(self, data: &str) -> String {
+        match self {
+            DigestAlgorithm::Md5 => format!("{:x}", Md5::digest(data.as_bytes())),
+            DigestAlgorithm::Sha256 => format!("{:x}", Sha256::digest(data.as_bytes())),
+        }
+    }
+}
+
+impl Default for DigestConfig {
+    fn default() -> Self {
+        Self::new(REALM.to_string())
+    }
+}
+
+impl DigestConfig {
+    pub fn new(realm: String) -> Self {
+        Self {
+            realm,
+            nonce_ttl: DEFAULT_NONCE_TTL,
+            key: hmac::Key::new(hmac::HMAC_SHA256, &rand::random::<[u8; 32]>()),
+            epoch: Instant::now(),
+            used: Arc::default(),
+        }
+    }
+
+    /// 设置nonce有效期
+    pub fn with_nonce_ttl(mut self, nonce_ttl: Duration) -> Self {
+        self.nonce_ttl = nonce_ttl;
+        self
+    }
+
+    /// 签发新的nonce：签发时间和随机数（各16位十六进制）之后接它们的签名
+    fn issue_nonce(&self) -> String {
+        let payload = format!("{:016x}{:016x}", self.now_millis(), rand::random::<u64>());
+        let tag = hmac::sign(&self.key, payload.as_bytes());
+        format!("{}{}", payload, to_hex(&tag.as_ref()[..NONCE_MAC_LEN]))
+    }
+
+    /// 校验nonce的签名和有效期，返回其签发时间
+    fn verify_nonce(&self, nonce: &str) -> Option<u64> {
+        if nonce.len() != 32 + NONCE_MAC_LEN * 2 || !nonce.is_ascii() {
+            return None;
+        }
+        let (payload, mac) = nonce.split_at(32);
+        let expected = hmac::sign(&self.key, payload.as_bytes());
+        let valid: bool = to_hex(&expected.as_ref()[..NONCE_MAC_LEN])
+            .as_bytes()
+            .ct_eq(mac.as_bytes())
+            .into();
+        if !valid {
+            return None;
+        }
+        let issued = u64::from_str_radix(&payload[..16], 16).ok()?;
+        let age = self.now_millis().checked_sub(issued)?;
+        (u128::from(age) < self.nonce_ttl.as_millis()).then_some(issued)
+    }
+
+    fn now_millis(&self) -> u64 {
+        self.epoch.elapsed().as_millis() as u64
+    }
+
+    /// 生成 `Proxy-Authenticate` 质询头，SHA-256与MD5各一条，共用同一个nonce
+    pub fn challenge(&self) -> String {
+        let nonce = self.issue_nonce();
+        [DigestAlgorithm::Sha256, DigestAlgorithm::Md5]
+            .iter()
+            .map(|algorithm| {
+                format!(
+                    "Proxy-Authenticate: Digest realm=\"{}\", nonce=\"{}\", qop=\"auth\", algorithm={}\r\n",
+                    self.realm,
+                    nonce,
+                    algorithm.name()
+                )
+            })
+            .collect()
+    }
+
+    /// 校验 `Digest` 之后的凭据参数，`password` 按用户名查找明文密码
+    ///
+    /// 凭据中的 `uri` 须与请求目标 `target` 一致（RFC 7616 第3.4.6节），
+    /// 防止截获的凭据被用于其他目标
+    fn validate<'a>(
+        &self,
+        credentials: &str,
+        method: &str,
+        target: &str,
+        password: impl Fn(&str) -> Option<&'a str>,
+    ) -> bool {
+        let params = parse_digest_params(credentials);
+        let param = |name: &str| params.get(name).map(String::as_str);
+
+        let (username, realm, nonce, uri, response, nc, cnonce) = match (
+            param("username"),
+            param("realm"),
+            param("nonce"),
+            param("uri"),
+            param("response"),
+            param("nc"),
+            param("cnonce"),
+        ) {
+            (Some(u), Some(r), Some(n), Some(uri), Some(resp), Some(nc), Some(c)) => {
+                (u, r, n, uri, resp, nc, c)
+            }
+            _ => {
+                warn!("Digest凭据缺少必需参数");
+                return false;
+            }
+        };
+        if realm != self.realm || param("qop") != Some("auth") {
+            warn!("Digest凭据的realm或qop不匹配: {}", username);
+            return false;
+        }
+        if !digest_uri_matches(uri, target) {
+            warn!(
+                "Digest凭据的uri与请求目标不一致: {} ({} != {})",
+                username, uri, target
+            );
+            return false;
+        }
+        let algorithm = match DigestAlgorithm::parse(param("algorithm").unwrap_or("MD5")) {
+            Some(algorithm) => algorithm,
+            None => {
+                warn!("不支持的Digest算法: {:?}", param("algorithm"));
+                return false;
+            }
+        };
+        let nc_value = match u32::from_str_radix(nc, 16) {
+            Ok(value) => value,
+            Err(_) => {
+                warn!("无效的Digest请求计数: {}", nc);
+                return false;
+            }
+        };
+
+        let issued = match self.verify_nonce(nonce) {
+            Some(issued) => issued,
+            None => {
+                warn!("Digest nonce无效或已过期: {}", username);
+                return false;
+            }
+        };
+
+        // 持锁完成计数检查和更新，同一计数的并发重放只有一个能通过
+        let mut used = self.used.lock().unwrap();
+        let last_nc = match used.last_nc.get(nonce) {
+            Some(&last_nc) => last_nc,
+            None if used.evicted_until.is_some_and(|until| issued <= until) => {
+                warn!("Digest nonce的请求计数已被淘汰: {}", username);
+                return false;
+            }
+            None => 0,
+        };
+        if nc_value <= last_nc {
+            warn!("Digest请求计数未递增，疑似重放: {}", username);
+            return false;
+        }
+
+        let password = match password(username) {
+            Some(password) => password,
+            None => {
+                warn!("认证失败: {}", username);
+                return false;
+            }
+        };
+        let ha1 = algorithm.hash(&format!("{}:{}:{}", username, realm, password));
+        let ha2 = algorithm.hash(&format!("{}:{}", method, uri));
+        let expected = algorithm.hash(&format!("{}:{}:{}:{}:auth:{}", ha1, nonce, nc, cnonce, ha2));
+
+        let is_valid: bool = expected
+            .as_bytes()
+            .ct_eq(response.to_ascii_lowercase().as_bytes())
+            .into();
+        if is_valid {
+            self.record_nc(&mut used, nonce, issued, nc_value);
+            debug!("Digest认证成功: {}", username);
+        } else {
+            warn!("认证失败: {}", username);
+        }
+        is_valid
+    }
+}
+
+impl DigestConfig {
+    /// 记录nonce最近接受的请求计数，顺带清理已过期的nonce
+    ///
+    /// 超过 [`MAX_NONCES`] 时淘汰最早使用的nonce，并拒绝此后再出现的同期nonce，
+    /// 以免丢失计数的nonce被重放
+    fn record_nc(&self, used: &mut UsedNonces, nonce: &str, issued: u64, nc: u32) {
+        if used.last_nc.insert(nonce.to_string(), nc).is_some() {
+            return;
+        }
+        used.order.push_back((issued, nonce.to_string()));
+
+        let ttl = self.nonce_ttl.as_millis() as u64;
+        let now = self.now_millis();
+        while let Some((issued, _)) = used.order.front() {
+            let expired = now.saturating_sub(*issued) >= ttl;
+            if !expired && used.order.len() <= MAX_NONCES {
+                break;
+            }
+            let (issued, nonce) = used.order.pop_front().unwrap();
+            used.last_nc.remove(&nonce);
+            if !expired {
+                used.evicted_until = used.evicted_until.max(Some(issued));
+            }
+        }
+    }
+}
+
+/// 小写十六进制形式
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Digest凭据的 `uri` 是否指向请求目标
+///
+/// 通常与请求行中的目标完全相同（CONNECT为 `host:port`）；
+/// 请求目标为绝对形式时，也接受只给出其路径部分的客户端
+fn digest_uri_matches(uri: &str, target: &str) -> bool {
+    if uri == target {
+        return true;
+    }
+    target
+        .split_once("://")
+        .map(|(_, rest)| rest.find('/').map_or("/", |start| &rest[start..]))
+        .is_some_and(|path| path == uri)
+}
+
+/// 解析 `key=value, key="quoted value"` 形式的Digest参数，参数名转为小写
+fn parse_digest_params(input: &str) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    let mut rest = input.trim();
+
+    while !rest.is_empty() {
+        let (key, after_key) = match rest.split_once('=') {
+            Some(split) => split,
+            None => break,
+        };
+        let after_key = after_key.trim_start();
+        let (value, remaining) = match after_key.strip_prefix('"') {
+            Some(quoted) => {
+                // 引号内可包含逗号，反斜杠转义下一个字符
+                let mut value = String::new();
+                let mut chars = quoted.char_indices();
+                let mut end = quoted.len();
+                while let Some((i, c)) = chars.next() {
+                    match c {
+                        '\\' => {
+                            if let Some((_, escaped)) = chars.next() {
+                                value.push(escaped);
+                            }
+                        }
+                        '"' => {
+                            end = i + 1;
+                            break;
+                        }
+                        c => value.push(c),
+                    }
+                }
+                (value, &quoted[end..])
+            }
+            None => {
+                let end = after_key.find(',').unwrap_or(after_key.len());
+                (after_key[..end].trim().to_string(), &after_key[end..])
+            }
+        };
+        params.insert(key.trim().to_ascii_lowercase(), value);
+        rest = remaining.trim_start().trim_start_matches(',').trim_start();
+    }
+    params
 }
 
 impl AuthConfig {
     pub fn new(username: String, password: String) -> Self {
-        Self { username, password }
+        Self {
+            users: HashMap::from([(username, password)]),
+            digest: None,
+        }
     }
 
-    pub fn validate_proxy_auth(&self, auth_header: Option<&str>) -> bool {
+    /// 同时接受Digest认证，Basic认证仍然可用
+    pub fn with_digest(mut self, digest: Option<DigestConfig>) -> Self {
+        self.digest = digest;
+        self
+    }
+
+    /// Digest认证配置，未启用时为 `None`
+    pub fn digest(&self) -> Option<&DigestConfig> {
+        self.digest.as_ref()
+    }
+
+    /// 407响应中的 `Proxy-Authenticate` 质询头，启用Digest时同时提供Digest质询
+    pub fn challenge(&self) -> String {
+        let mut challenge = format!("Proxy-Authenticate: Basic realm=\"{}\"\r\n", REALM);
+        if let Some(digest) = &self.digest {
+            challenge.push_str(&digest.challenge());
+        }
+        challenge
+    }
+
+    /// 从htpasswd风格的用户文件加载多个账号
+    ///
+    /// 每行一个 `user:password`，空行和以 `#` 开头的注释行会被忽略
+    pub fn from_users_file(path: &Path) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("读取用户文件 {} 失败: {}", path.display(), e))?;
+
+        let mut users = HashMap::new();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once(':') {
+                Some((username, password)) if !username.is_empty() => {
+                    users.insert(username.to_string(), password.to_string());
+                }
+                _ => {
+                    return Err(format!(
+                        "用户文件 {} 第 {} 行格式无效，应为 user:password",
+                        path.display(),
+                        index + 1
+                    )
+                    .into());
+                }
+            }
+        }
+
+        if users.is_empty() {
+            return Err(format!("用户文件 {} 中没有账号", path.display()).into());
+        }
+        Ok(Self {
+            users,
+            digest: None,
+        })
+    }
+
+    /// 添加或覆盖一个账号
+    pub fn add_user(&mut self, username: String, password: String) {
+        self.users.insert(username, password);
+    }
+
+    /// 已配置的账号数
+    pub fn user_count(&self) -> usize {
+        self.users.len()
+    }
+
+    /// 校验 `Proxy-Authorization` 头
+    ///
+    /// `method` 与 `target` 为请求行中的方法和请求目标，Digest认证用于计算摘要和校验 `uri`
+    pub fn validate_proxy_auth(
+        &self,
+        auth_header: Option<&str>,
+        method: &str,
+        target: &str,
+    ) -> bool {
         match auth_header {
             Some(header) => {
+                if let Some(credentials) = header.strip_prefix("Digest ") {
+                    return match &self.digest {
+                        Some(digest) => digest.validate(credentials, method, target, |username| {
+                            self.users.get(username).map(String::as_str)
+                        }),
+                        None => {
+                            warn!("未启用Digest认证");
+                            false
+                        }
+                    };
+                }
                 if !header.starts_with("Basic ") {
                     warn!("不支持的认证类型: {}", header);
                     return false;
@@ -25,14 +460,7 @@ impl AuthConfig {
                     Ok(decoded) => match String::from_utf8(decoded) {
                         Ok(credentials) => {
                             if let Some((username, password)) = credentials.split_once(':') {
-                                let is_valid =
-                                    username == self.username && password == self.password;
-                                if is_valid {
-                                    debug!("认证成功: {}", username);
-                                } else {
-                                    warn!("认证失败: {}", username);
-                                }
-                                is_valid
+                                self.validate_credentials(username, password)
                             } else {
                                 warn!("无效的认证凭据格式");
                                 false
@@ -53,16 +481,226 @@ impl AuthConfig {
         }
     }
 
-    pub fn generate_auth_header(&self) -> String {
-        let credentials = format!("{}:{}", self.username, self.password);
-        let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
-        format!("Basic {}", encoded)
+    /// 校验用户名和密码
+    ///
+    /// 以常量时间比较所有账号的用户名和密码，不会因提前不匹配而返回，
+    /// 避免通过响应时间逐字节猜测凭据
+    pub fn validate_credentials(&self, username: &str, password: &str) -> bool {
+        let mut matched = Choice::from(0);
+        for (expected_username, expected_password) in &self.users {
+            matched |= constant_time_eq(expected_username, username)
+                & constant_time_eq(expected_password, password);
+        }
+        let is_valid: bool = matched.into();
+        if is_valid {
+            debug!("认证成功: {}", username);
+        } else {
+            warn!("认证失败: {}", username);
+        }
+        is_valid
     }
 }
 
-pub fn check_authentication(auth_config: &Option<AuthConfig>, auth_header: Option<&str>) -> bool {
+/// 常量时间比较两个字符串
+///
+/// 先计算固定长度的摘要再比较，长度不同的输入也不会通过提前返回泄露长度
+fn constant_time_eq(expected: &str, actual: &str) -> Choice {
+    let expected = Sha1::digest(expected.as_bytes());
+    let actual = Sha1::digest(actual.as_bytes());
+    expected.as_slice().ct_eq(actual.as_slice())
+}
+
+/// 从 `Proxy-Authorization` 头（Basic或Digest）中取出用户名，仅用于日志，不做校验
+pub fn proxy_auth_username(auth_header: &str) -> Option<String> {
+    if let Some(credentials) = auth_header.strip_prefix("Digest ") {
+        return parse_digest_params(credentials).remove("username");
+    }
+    let encoded = auth_header.strip_prefix("Basic ")?;
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .ok()?;
+    let credentials = String::from_utf8(decoded).ok()?;
+    credentials
+        .split_once(':')
+        .map(|(username, _)| username.to_string())
+}
+
+pub fn check_authentication(
+    auth_config: &Option<AuthConfig>,
+    auth_header: Option<&str>,
+    method: &str,
+    target: &str,
+) -> bool {
     match auth_config {
-        Some(config) => config.validate_proxy_auth(auth_header),
+        Some(config) => config.validate_proxy_auth(auth_header, method, target),
         None => true, // 没有配置认证则允许所有请求
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_credentials() {
+        let mut auth = AuthConfig::new("alice".to_string(), "wonderland".to_string());
+        auth.add_user("bob".to_string(), "builder".to_string());
+
+        assert!(auth.validate_credentials("alice", "wonderland"));
+        assert!(auth.validate_credentials("bob", "builder"));
+
+        // 密码错误、前缀、长度不同、用户与密码错配
+        assert!(!auth.validate_credentials("alice", "wonder"));
+        assert!(!auth.validate_credentials("alice", "wonderlandx"));
+        assert!(!auth.validate_credentials("alice", ""));
+        assert!(!auth.validate_credentials("alic", "wonderland"));
+        assert!(!auth.validate_credentials("alice", "builder"));
+        assert!(!auth.validate_credentials("mallory", "wonderland"));
+
+        let header = format!(
+            "Basic {}",
+            base64::engine::general_purpose::STANDARD.encode("bob:builder")
+        );
+        assert!(auth.validate_proxy_auth(Some(&header), "GET", "http://example.com/"));
+        assert!(!auth.validate_proxy_auth(
+            Some("Basic bm9ib2R5Og=="),
+            "GET",
+            "http://example.com/"
+        ));
+        assert!(!auth.validate_proxy_auth(None, "GET", "http://example.com/"));
+    }
+
+    /// 按给定nonce和请求计数生成MD5 Digest凭据（CONNECT example.com:443）
+    fn md5_connect_header(nonce: &str, nc: &str) -> String {
+        let ha1 = md5_hex("alice:RustProxy:wonderland");
+        let ha2 = md5_hex("CONNECT:example.com:443");
+        let response = md5_hex(&format!("{}:{}:{}:0a4f113b:auth:{}", ha1, nonce, nc, ha2));
+        format!(
+            "Digest username=\"alice\", realm=\"RustProxy\", nonce=\"{}\", uri=\"example.com:443\", \
+             qop=auth, nc={}, cnonce=\"0a4f113b\", response=\"{}\", algorithm=MD5",
+            nonce, nc, response
+        )
+    }
+
+    #[test]
+    fn test_challenge_flood_keeps_nonce_in_use() {
+        let auth = AuthConfig::new("alice".to_string(), "wonderland".to_string())
+            .with_digest(Some(DigestConfig::default()));
+
+        let nonce = challenge_nonce(&auth.challenge(), "MD5");
+        let header = md5_connect_header(&nonce, "00000001");
+        assert!(auth.validate_proxy_auth(Some(&header), "CONNECT", "example.com:443"));
+
+        // 未认证的客户端反复索取质询，不会挤掉正在使用的nonce
+        for _ in 0..MAX_NONCES * 2 {
+            auth.challenge();
+        }
+        let header = md5_connect_header(&nonce, "00000002");
+        assert!(auth.validate_proxy_auth(Some(&header), "CONNECT", "example.com:443"));
+        assert!(!auth.validate_proxy_auth(Some(&header), "CONNECT", "example.com:443"));
+
+        // 签名被篡改或已过期的nonce被拒绝
+        let mut tampered = nonce.clone().into_bytes();
+        tampered[0] = if tampered[0] == b'0' { b'1' } else { b'0' };
+        let tampered = String::from_utf8(tampered).unwrap();
+        let header = md5_connect_header(&tampered, "00000001");
+        assert!(!auth.validate_proxy_auth(Some(&header), "CONNECT", "example.com:443"));
+
+        let expiring = AuthConfig::new("alice".to_string(), "wonderland".to_string()).with_digest(
+            Some(DigestConfig::default().with_nonce_ttl(Duration::from_millis(20))),
+        );
+        let nonce = challenge_nonce(&expiring.challenge(), "MD5");
+        std::thread::sleep(Duration::from_millis(40));
+        let header = md5_connect_header(&nonce, "00000001");
+        assert!(!expiring.validate_proxy_auth(Some(&header), "CONNECT", "example.com:443"));
+    }
+
+    #[test]
+    fn test_digest_uri_matches() {
+        assert!(digest_uri_matches("example.com:443", "example.com:443"));
+        assert!(!digest_uri_matches("example.com:443", "other.example:443"));
+        assert!(digest_uri_matches(
+            "http://example.com/a?b",
+            "http://example.com/a?b"
+        ));
+        assert!(digest_uri_matches("/a?b", "http://example.com/a?b"));
+        assert!(digest_uri_matches("/", "http://example.com"));
+        assert!(!digest_uri_matches("/other", "http://example.com/a"));
+        assert!(!digest_uri_matches("/a", "example.com:443"));
+    }
+
+    /// 从质询头中取出指定算法的nonce
+    fn challenge_nonce(challenge: &str, algorithm: &str) -> String {
+        let line = challenge
+            .lines()
+            .find(|line| line.ends_with(&format!("algorithm={}", algorithm)))
+            .unwrap();
+        let params = parse_digest_params(line.split_once("Digest ").unwrap().1);
+        params["nonce"].clone()
+    }
+
+    fn md5_hex(data: &str) -> String {
+        format!("{:x}", Md5::digest(data.as_bytes()))
+    }
+
+    fn sha256_hex(data: &str) -> String {
+        format!("{:x}", Sha256::digest(data.as_bytes()))
+    }
+
+    #[test]
+    fn test_digest_auth() {
+        let auth = AuthConfig::new("alice".to_string(), "wonderland".to_string())
+            .with_digest(Some(DigestConfig::default()));
+
+        let challenge = auth.challenge();
+        assert!(challenge.starts_with("Proxy-Authenticate: Basic realm=\"RustProxy\"\r\n"));
+        assert!(challenge.contains("Proxy-Authenticate: Digest realm=\"RustProxy\", nonce=\""));
+        assert!(challenge.contains("qop=\"auth\""));
+
+        // RFC 7616: response = H(H(user:realm:pass):nonce:nc:cnonce:qop:H(method:uri))
+        let nonce = challenge_nonce(&challenge, "MD5");
+        let ha1 = md5_hex("alice:RustProxy:wonderland");
+        let ha2 = md5_hex("CONNECT:example.com:443");
+        let response = md5_hex(&format!("{}:{}:00000001:0a4f113b:auth:{}", ha1, nonce, ha2));
+        let header = format!(
+            "Digest username=\"alice\", realm=\"RustProxy\", nonce=\"{}\", uri=\"example.com:443\", \
+             qop=auth, nc=00000001, cnonce=\"0a4f113b\", response=\"{}\", algorithm=MD5",
+            nonce, response
+        );
+        assert!(auth.validate_proxy_auth(Some(&header), "CONNECT", "example.com:443"));
+        assert_eq!(proxy_auth_username(&header).as_deref(), Some("alice"));
+
+        // 原样重放（请求计数未递增）被拒绝
+        assert!(!auth.validate_proxy_auth(Some(&header), "CONNECT", "example.com:443"));
+        // 方法不同则摘要不匹配
+        assert!(!auth.validate_proxy_auth(
+            Some(&header.replace("00000001", "00000002")),
+            "GET",
+            "example.com:443"
+        ));
+
+        // SHA-256
+        let nonce = challenge_nonce(&auth.challenge(), "SHA-256");
+        let ha1 = sha256_hex("alice:RustProxy:wonderland");
+        let ha2 = sha256_hex("GET:http://example.com/");
+        let response = sha256_hex(&format!("{}:{}:00000001:abcdef:auth:{}", ha1, nonce, ha2));
+        let header = format!(
+            "Digest username=\"alice\", realm=\"RustProxy\", nonce=\"{}\", uri=\"http://example.com/\", \
+             algorithm=SHA-256, qop=auth, nc=00000001, cnonce=\"abcdef\", response=\"{}\"",
+            nonce, response
+        );
+        assert!(auth.validate_proxy_auth(Some(&header), "GET", "http://example.com/"));
+
+        // 凭据的uri与请求目标不一致时拒绝，即使nonce尚未用过
+        let header = header.replace("00000001", "00000002");
+        assert!(!auth.validate_proxy_auth(Some(&header), "GET", "http://other.example/"));
+
+        // 未签发的nonce被拒绝
+        let forged = header.replace(&nonce, "00000000000000000000000000000000");
+        assert!(!auth.validate_proxy_auth(Some(&forged), "GET", "http://example.com/"));
+
+        // 未启用Digest时拒绝Digest凭据
+        let basic_only = AuthConfig::new("alice".to_string(), "wonderland".to_string());
+        assert!(!basic_only.validate_proxy_auth(Some(&header), "GET", "http://example.com/"));
+    }
+}
@@ -0,0 +1,158 @@
+use crate::config::Config;
+use crate::handlers::backend::parse_host_port;
+use crate::proxy::{bind_listener, is_dual_stack};
+use crate::reload::{load_access_rules, load_auth_config};
+use crate::tls;
+use std::error::Error;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::Path;
+
+/// 配置检查结果，每项为一行说明
+#[derive(Debug, Clone, Default)]
+pub struct CheckReport {
+    pub items: Vec<String>,
+}
+
+impl fmt::Display for CheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, item) in self.items.iter().enumerate() {
+            if index > 0 {
+                f.write_str("\n")?;
+            }
+            write!(f, "  - {}", item)?;
+        }
+        Ok(())
+    }
+}
+
+/// 检查配置而不提供服务
+///
+/// 加载用户文件、访问规则、TLS证书与密钥及其他引用的文件，解析各目标地址，
+/// 逐个绑定监听地址后立即释放。不接受任何连接，遇到第一个错误即返回
+pub async fn run(config: &Config) -> Result<CheckReport, Box<dyn Error + Send + Sync>> {
+    let mut report = CheckReport::default();
+
+    match load_auth_config(config)? {
+        Some(auth) => report
+            .items
+            .push(format!("认证账号: {} 个", auth.user_count())),
+        None => report.items.push("认证: 未启用".to_string()),
+    }
+    if let Some(rules) = load_access_rules(config)? {
+        report.items.push(format!(
+            "访问规则: 白名单 {} 条，黑名单 {} 条",
+            rules.allow_count(),
+            rules.block_count()
+        ));
+    }
+
+    match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => {
+            tls::server_config(cert, key, &config.tls_alpn, config.min_tls_version)?;
+            report
+                .items
+                .push(format!("监听TLS证书: {}", cert.display()));
+        }
+        (None, None) => {}
+        _ => return Err("--tls-cert 与 --tls-key 必须同时指定".into()),
+    }
+    if config.backend_tls {
+        tls::client_config(
+            config.backend_tls_ca.as_deref(),
+            config.backend_tls_insecure,
+        )?;
+        report.items.push("源站TLS: 已启用".to_string());
+    }
+
+    if let Some(upstream) = &config.upstream {
+        report.items.push(format!("上游代理: {}", upstream));
+    }
+    for (name, target) in [
+        ("备用目标", &config.fallback_dest),
+        ("就绪探测目标", &config.health_target),
+    ] {
+        if let Some(target) = target {
+            parse_host_port(target).map_err(|e| format!("{}: {}", name, e))?;
+            report.items.push(format!("{}: {}", name, target));
+        }
+    }
+    for (host, addr) in &config.routes {
+        parse_host_port(addr).map_err(|e| format!("路由 {}: {}", host, e))?;
+    }
+    if !config.routes.is_empty() {
+        report
+            .items
+            .push(format!("反向代理路由: {} 条", config.routes.len()));
+    }
+
+    for path in [&config.landing_page, &config.scanner_body]
+        .into_iter()
+        .flatten()
+    {
+        std::fs::read_to_string(path)
+            .map_err(|e| format!("读取 {} 失败: {}", path.display(), e))?;
+    }
+    if let Some(path) = &config.access_log {
+        check_writable(path)?;
+        report.items.push(format!("访问日志: {}", path.display()));
+    }
+
+    match &config.unix_socket {
+        Some(path) => {
+            // 不绑定，避免删除正在运行的实例的套接字文件
+            check_writable(path)?;
+            report.items.push(format!("监听: unix:{}", path.display()));
+        }
+        None => {
+            for addr in config.listen_addrs() {
+                report.items.push(format!("监听: {}", check_bind(addr)?));
+            }
+        }
+    }
+    for (name, port) in [
+        ("指标端点", config.metrics_port),
+        ("健康检查端点", config.health_port),
+    ] {
+        if let Some(port) = port {
+            let addr = check_bind(SocketAddr::new(config.ip, port))?;
+            report.items.push(format!("{}: {}", name, addr));
+        }
+    }
+
+    Ok(report)
+}
+
+/// 绑定后立即释放监听地址，返回用于摘要的地址说明
+fn check_bind(addr: SocketAddr) -> Result<String, String> {
+    let listener = bind_listener(addr).map_err(|e| format!("绑定 {} 失败: {}", addr, e))?;
+    drop(listener);
+    if is_dual_stack(addr) {
+        Ok(format!("{} (IPv4/IPv6双栈)", addr))
+    } else {
+        Ok(addr.to_string())
+    }
+}
+
+/// 检查文件所在目录存在，已存在的文件需可追加写入
+fn check_writable(path: &Path) -> Result<(), String> {
+    if path.exists() {
+        if path.is_dir() {
+            return Err(format!("{} 是目录", path.display()));
+        }
+        return std::fs::OpenOptions::new()
+            .append(true)
+            .open(path)
+            .map(drop)
+            .map_err(|e| format!("无法写入 {}: {}", path.display(), e));
+    }
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    if parent.is_dir() {
+        Ok(())
+    } else {
+        Err(format!("{} 所在目录不存在", path.display()))
+    }
+}
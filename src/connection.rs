@@ -1,9 +1,184 @@
+use crate::access_log;
+use crate::cidr::IpCidr;
+use crate::logging;
+use crate::parser::detector::parse_authority;
+use crate::parser::proxy_protocol::{parse_proxy_header, ProxyHeader};
+use crate::relay::{relay, RelayOptions};
+use crate::stream::ClientStream;
 use std::error::Error;
-use std::net::SocketAddr;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
 use tokio::net::TcpStream;
 use tracing::{debug, error, info};
 
+/// 请求头部的默认最大字节数
+pub const DEFAULT_MAX_HEADER_SIZE: usize = 64 * 1024;
+
+/// 读取请求头部时每次读取的默认字节数
+///
+/// 头部较长时分多次读取直到遇到结束符，不影响可接受的头部大小（由头部上限决定）；
+/// 较小的值减少每个连接的读取缓冲，较大的值让大头部用更少的系统调用读完
+pub const DEFAULT_INITIAL_READ_SIZE: usize = 4096;
+
+/// 读取HTTP请求头部的结果
+#[derive(Debug)]
+pub enum HeadRead {
+    /// 完整的头部（包含 `\r\n\r\n` 结束符）及其后已读取的多余字节
+    Complete { head: Vec<u8>, rest: Vec<u8> },
+    /// 头部超过上限仍未结束
+    TooLarge,
+    /// 头部结束前连接已关闭，包含已读取的数据
+    Closed(Vec<u8>),
+}
+
+/// 持续读取直到遇到HTTP头部结束符 `\r\n\r\n`
+///
+/// 每次最多读取 `read_size` 字节，头部可能跨越多次读取（大Cookie、长URL等），
+/// 超过 `max_header_size` 时返回 `TooLarge`
+pub async fn read_http_head<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    max_header_size: usize,
+    read_size: usize,
+) -> io::Result<HeadRead> {
+    let mut data = Vec::new();
+    let mut buffer = vec![0u8; read_size.max(1)];
+
+    loop {
+        let n = stream.read(&mut buffer).await?;
+        if n == 0 {
+            return Ok(HeadRead::Closed(data));
+        }
+
+        // 从上次扫描位置的前3个字节开始，避免遗漏跨读取边界的结束符
+        let scan_from = data.len().saturating_sub(3);
+        data.extend_from_slice(&buffer[..n]);
+
+        if let Some(pos) = find_head_end(&data[scan_from..]) {
+            let head_end = scan_from + pos;
+            if head_end > max_header_size {
+                return Ok(HeadRead::TooLarge);
+            }
+            let rest = data.split_off(head_end);
+            return Ok(HeadRead::Complete { head: data, rest });
+        }
+
+        if data.len() > max_header_size {
+            return Ok(HeadRead::TooLarge);
+        }
+    }
+}
+
+/// 读取连接开头的PROXY协议（v1或v2）头部，返回其中携带的原始客户端地址
+///
+/// 按解析结果每次只读取头部仍缺少的字节，头部之后的客户端数据留在连接中；
+/// 头部携带的地址不可用（LOCAL命令、`UNKNOWN`）时返回 `None`，头部无效时返回 `InvalidData`
+pub async fn read_proxy_header<S: AsyncRead + Unpin>(
+    stream: &mut S,
+) -> io::Result<Option<SocketAddr>> {
+    let mut data = Vec::new();
+    loop {
+        match parse_proxy_header(&data) {
+            ProxyHeader::Incomplete(more) => {
+                let start = data.len();
+                data.resize(start + more, 0);
+                stream.read_exact(&mut data[start..]).await?;
+            }
+            ProxyHeader::Complete(source) => return Ok(source),
+            ProxyHeader::Invalid => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "无效的PROXY协议头",
+                ))
+            }
+        }
+    }
+}
+
+/// 查找头部结束符，返回结束符之后的偏移
+fn find_head_end(data: &[u8]) -> Option<usize> {
+    data.windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map(|pos| pos + 4)
+}
+
+/// 先读出已预读的字节、再从底层流读取的流包装
+///
+/// 协议识别和头部读取会提前消费客户端数据，接手连接的处理器通过该包装
+/// 按原顺序读到全部数据，无需单独转发初始缓冲区；写入直接透传到底层流
+#[derive(Debug)]
+pub struct PrefetchedStream<S> {
+    inner: S,
+    prefetched: Vec<u8>,
+    pos: usize,
+}
+
+impl<S> PrefetchedStream<S> {
+    pub fn new(inner: S, prefetched: Vec<u8>) -> Self {
+        Self {
+            inner,
+            prefetched,
+            pos: 0,
+        }
+    }
+
+    /// 尚未读出的预读字节
+    pub fn prefetched(&self) -> &[u8] {
+        &self.prefetched[self.pos..]
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// 拆分为底层流和尚未读出的预读字节
+    pub fn into_parts(mut self) -> (S, Vec<u8>) {
+        let remaining = self.prefetched.split_off(self.pos);
+        (self.inner, remaining)
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for PrefetchedStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.pos < self.prefetched.len() {
+            let n = buf.remaining().min(self.prefetched.len() - self.pos);
+            let start = self.pos;
+            buf.put_slice(&self.prefetched[start..start + n]);
+            self.pos += n;
+            if self.pos == self.prefetched.len() {
+                self.prefetched = Vec::new();
+                self.pos = 0;
+            }
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for PrefetchedStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
 pub async fn handle_client(
     client_stream: TcpStream,
     client_addr: SocketAddr,
@@ -17,59 +192,15 @@ pub async fn handle_client(
                 client_addr, target_host, target_port
             );
 
-            let (mut client_reader, mut client_writer) = client_stream.into_split();
-            let (mut target_reader, mut target_writer) = target_stream.into_split();
-
-            let client_to_target = async {
-                let mut buffer = [0u8; 4096];
-                loop {
-                    match client_reader.read(&mut buffer).await {
-                        Ok(0) => {
-                            debug!("[{}] 客户端到目标服务器流结束", client_addr);
-                            break;
-                        }
-                        Ok(n) => {
-                            if let Err(e) = target_writer.write_all(&buffer[..n]).await {
-                                error!("[{}] 写入目标服务器失败: {}", client_addr, e);
-                                break;
-                            }
-                        }
-                        Err(e) => {
-                            error!("[{}] 读取客户端数据失败: {}", client_addr, e);
-                            break;
-                        }
-                    }
+            match relay(client_stream, target_stream, RelayOptions::default()).await {
+                Ok((sent, received)) => {
+                    debug!(
+                        "[{}] 连接结束，上行 {} 字节，下行 {} 字节",
+                        client_addr, sent, received
+                    );
                 }
-            };
-
-            let target_to_client = async {
-                let mut buffer = [0u8; 4096];
-                loop {
-                    match target_reader.read(&mut buffer).await {
-                        Ok(0) => {
-                            debug!("[{}] 目标服务器到客户端流结束", client_addr);
-                            break;
-                        }
-                        Ok(n) => {
-                            if let Err(e) = client_writer.write_all(&buffer[..n]).await {
-                                error!("[{}] 写入客户端失败: {}", client_addr, e);
-                                break;
-                            }
-                        }
-                        Err(e) => {
-                            error!("[{}] 读取目标服务器数据失败: {}", client_addr, e);
-                            break;
-                        }
-                    }
-                }
-            };
-
-            tokio::select! {
-                _ = client_to_target => {
-                    debug!("[{}] 客户端到目标服务器连接结束", client_addr);
-                }
-                _ = target_to_client => {
-                    debug!("[{}] 目标服务器到客户端连接结束", client_addr);
+                Err(e) => {
+                    error!("[{}] 转发数据失败: {}", client_addr, e);
                 }
             }
         }
@@ -103,34 +234,13 @@ pub async fn parse_connect_request(buffer: &[u8]) -> Option<(String, u16)> {
         return None;
     }
 
-    let host_port = parts[1];
-    let mut parts = host_port.split(':');
-    let host = parts.next()?.to_string();
-    let port = parts.next()?.parse::<u16>().ok()?;
-
-    Some((host, port))
+    parse_authority(parts[1], None)
 }
 
+/// 按 `Host` 头解析请求目标，头名不区分大小写且必须是完整的头部行
 pub async fn parse_http_request(buffer: &[u8]) -> Option<(String, u16)> {
-    let request = String::from_utf8_lossy(buffer);
-
-    if let Some(start) = request.find("Host: ") {
-        let host_start = start + 6;
-        if let Some(end) = request[host_start..].find('\r') {
-            let host_line = &request[host_start..host_start + end];
-            let mut parts = host_line.split(':');
-            let host = parts.next()?.to_string();
-            let port = if let Some(port_str) = parts.next() {
-                port_str.parse::<u16>().ok()
-            } else {
-                Some(80)
-            };
-
-            return port.map(|p| (host, p));
-        }
-    }
-
-    None
+    let host = extract_header(buffer, "Host")?;
+    parse_authority(&host, Some(80))
 }
 
 pub fn extract_proxy_auth(buffer: &[u8]) -> Option<String> {
@@ -146,23 +256,456 @@ pub fn extract_proxy_auth(buffer: &[u8]) -> Option<String> {
     None
 }
 
+/// 查找请求头部中HTTP字段语法不允许的字节，返回其位置
+///
+/// 允许可见字符、空格、制表符、`obs-text`（0x80-0xFF）以及作为行结束符的
+/// `\r\n`（兼容单独的 `\n`）；其余控制字符和单独的 `\r` 均视为非法。
+/// `head` 应只包含头部，请求体不做检查
+pub fn invalid_header_byte(head: &[u8]) -> Option<usize> {
+    head.iter().enumerate().position(|(i, &byte)| match byte {
+        b'\t' | b'\n' => false,
+        b'\r' => head.get(i + 1) != Some(&b'\n'),
+        0x00..=0x1f | 0x7f => true,
+        _ => false,
+    })
+}
+
+/// 请求行中的方法，首个词不全是大写字母时返回 `None`
+pub(crate) fn request_method(head: &[u8]) -> Option<&str> {
+    let end = head.iter().position(|&b| b == b' ')?;
+    let method = std::str::from_utf8(&head[..end]).ok()?;
+    if !method.is_empty() && method.bytes().all(|b| b.is_ascii_uppercase()) {
+        Some(method)
+    } else {
+        None
+    }
+}
+
+/// 请求行中的请求目标，CONNECT请求为 `host:port`
+pub(crate) fn request_target(head: &[u8]) -> Option<&str> {
+    let line_end = head
+        .windows(2)
+        .position(|w| w == b"\r\n")
+        .unwrap_or(head.len());
+    let line = std::str::from_utf8(&head[..line_end]).ok()?;
+    line.split_whitespace().nth(1)
+}
+
+/// 提取指定请求头的值（名称不区分大小写）
+pub fn extract_header(buffer: &[u8], name: &str) -> Option<String> {
+    let request = String::from_utf8_lossy(buffer);
+
+    request
+        .split("\r\n")
+        .skip(1)
+        .take_while(|line| !line.is_empty())
+        .find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            if key.trim().eq_ignore_ascii_case(name) {
+                Some(value.trim().to_string())
+            } else {
+                None
+            }
+        })
+}
+
+/// 推断客户端原始请求使用的协议
+///
+/// 仅当客户端地址属于受信任代理时才采用其 `X-Forwarded-Proto` 头，
+/// 否则一律视为 `http`
+pub fn infer_scheme(buffer: &[u8], client_ip: IpAddr, trusted_proxies: &[IpCidr]) -> &'static str {
+    if !trusted_proxies.iter().any(|cidr| cidr.contains(client_ip)) {
+        return "http";
+    }
+
+    match extract_header(buffer, "X-Forwarded-Proto") {
+        // 多级代理时取最左侧（最初）的协议
+        Some(proto)
+            if proto
+                .split(',')
+                .next()
+                .unwrap_or("")
+                .trim()
+                .eq_ignore_ascii_case("https") =>
+        {
+            "https"
+        }
+        _ => "http",
+    }
+}
+
+/// 租户标识的最大长度
+const MAX_TENANT_ID_LEN: usize = 64;
+
+/// 提取受信任客户端通过 `X-Tenant-Id` 指定的租户
+///
+/// 客户端地址不属于受信任代理、或租户标识为空、过长、包含字母数字和 `.-_`
+/// 以外的字符时返回 `None`
+pub fn extract_tenant(
+    buffer: &[u8],
+    client_ip: IpAddr,
+    trusted_proxies: &[IpCidr],
+) -> Option<String> {
+    if !trusted_proxies.iter().any(|cidr| cidr.contains(client_ip)) {
+        return None;
+    }
+
+    extract_header(buffer, "X-Tenant-Id").filter(|tenant| {
+        !tenant.is_empty()
+            && tenant.len() <= MAX_TENANT_ID_LEN
+            && tenant
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'-' | b'_'))
+    })
+}
+
+/// 按请求行的版本选择响应状态行使用的HTTP版本，无法识别时按HTTP/1.1处理
+pub fn response_version(head: &[u8]) -> &'static str {
+    let request = String::from_utf8_lossy(head);
+    let version = request
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(2));
+    match version {
+        Some("HTTP/1.0") => "HTTP/1.0",
+        _ => "HTTP/1.1",
+    }
+}
+
+/// 发送407响应，`challenge` 为完整的 `Proxy-Authenticate` 头（可包含多条质询）
+///
+/// 状态行使用请求的HTTP版本 `version`，并明确关闭连接
 pub async fn send_auth_required_response(
-    stream: &mut TcpStream,
+    stream: &mut ClientStream,
+    version: &str,
+    challenge: &str,
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
-    let response = "HTTP/1.0 407 Proxy Authentication Required\r\nProxy-Authenticate: Basic realm=\"RustProxy\"\r\n\r\n";
+    access_log::record_status(407);
+    let response = format!(
+        "{} 407 Proxy Authentication Required\r\n{}{}Connection: close\r\nContent-Length: 0\r\n\r\n",
+        version,
+        challenge,
+        logging::request_id_header()
+    );
     stream.write_all(response.as_bytes()).await?;
     Ok(())
 }
 
-pub async fn send_error_response(
-    stream: &mut TcpStream,
+/// 发送405响应，`allow` 为 `Allow` 头列出的允许方法
+///
+/// 状态行使用请求的HTTP版本 `version`，并明确关闭连接
+pub async fn send_method_not_allowed_response(
+    stream: &mut ClientStream,
+    version: &str,
+    allow: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    access_log::record_status(405);
+    let body = "不允许的请求方法\r\n";
+    let response = format!(
+        "{} 405 Method Not Allowed\r\nAllow: {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n{}",
+        version,
+        allow,
+        body.len(),
+        logging::request_id_header(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+/// 发送纯文本错误响应，状态行使用 `version`，响应后连接关闭
+pub async fn send_error_response<W: AsyncWrite + Unpin>(
+    stream: &mut W,
     status: &str,
     message: &str,
+    version: &str,
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
+    access_log::record_status_text(status);
+    let body = format!("{}\r\n", message);
     let response = format!(
-        "HTTP/1.0 {}\r\nContent-Type: text/plain\r\n\r\n{}\r\n",
-        status, message
+        "{} {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n{}",
+        version,
+        status,
+        body.len(),
+        logging::request_id_header(),
+        body
     );
     stream.write_all(response.as_bytes()).await?;
     Ok(())
 }
+
+/// 扫描器探测代理时常见的路径，以 `/` 结尾的项同时匹配其下的所有路径
+const SCANNER_PATHS: &[&str] = &[
+    "/robots.txt",
+    "/.env",
+    "/.git/",
+    "/.aws/",
+    "/wp-login.php",
+    "/wp-admin/",
+    "/xmlrpc.php",
+    "/phpmyadmin/",
+    "/server-status",
+    "/actuator/",
+    "/config.json",
+];
+
+/// 路径是否属于扫描器常见路径，忽略查询参数和大小写
+pub(crate) fn is_scanner_path(path: &str) -> bool {
+    let path = path
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    SCANNER_PATHS
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('/') {
+            Some(dir) => path == dir || path.starts_with(pattern),
+            None => path == *pattern,
+        })
+}
+
+/// 请求行为origin-form时返回请求路径
+fn origin_form_path(buffer: &[u8]) -> Option<String> {
+    let request = String::from_utf8_lossy(buffer);
+    let target = request.lines().next()?.split_whitespace().nth(1)?;
+    target.starts_with('/').then(|| target.to_string())
+}
+
+/// 判断请求是否直接访问代理自身
+///
+/// 请求行为origin-form且Host头（名称不区分大小写，其他头中的Host不算）指向代理监听地址时返回请求路径
+async fn self_request_path(stream: &ClientStream, buffer: &[u8]) -> Option<String> {
+    let target = origin_form_path(buffer)?;
+
+    let local_addr = stream.local_addr()?;
+    let (host, port) = parse_http_request(buffer).await?;
+    let host_matches = host == local_addr.ip().to_string()
+        || (local_addr.ip().is_loopback() && host.eq_ignore_ascii_case("localhost"));
+
+    if host_matches && port == local_addr.port() {
+        Some(target)
+    } else {
+        None
+    }
+}
+
+/// 不应转发的请求：直接访问代理自身，或启用 `deflect_scanners` 时的扫描器路径
+///
+/// 返回请求路径，由代理自身应答
+pub(crate) async fn deflected_path(
+    stream: &ClientStream,
+    buffer: &[u8],
+    deflect_scanners: bool,
+) -> Option<String> {
+    match self_request_path(stream, buffer).await {
+        Some(path) => Some(path),
+        None if deflect_scanners => origin_form_path(buffer).filter(|path| is_scanner_path(path)),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    async fn pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (connected, accepted) = tokio::join!(TcpStream::connect(addr), listener.accept());
+        (connected.unwrap(), accepted.unwrap().0)
+    }
+
+    #[tokio::test]
+    async fn test_prefetched_stream_replays_then_reads_live() {
+        let (mut client, server) = pair().await;
+        let mut stream = PrefetchedStream::new(server, b"GET / HTTP/1.1\r\n".to_vec());
+
+        client
+            .write_all(b"Host: example.com\r\n\r\n")
+            .await
+            .unwrap();
+        client.shutdown().await.unwrap();
+
+        // 小缓冲区分多次读取预读字节，确保跨读取不丢失也不重复
+        let mut received = Vec::new();
+        let mut chunk = [0u8; 5];
+        loop {
+            let n = stream.read(&mut chunk).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            received.extend_from_slice(&chunk[..n]);
+        }
+        assert_eq!(received, b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n");
+        assert!(stream.prefetched().is_empty());
+
+        stream.write_all(b"ok").await.unwrap();
+        let mut reply = [0u8; 2];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(&reply, b"ok");
+    }
+
+    #[tokio::test]
+    async fn test_read_http_head_across_reads() {
+        let (mut client, mut server) = pair().await;
+
+        let cookie = "a".repeat(10_000);
+        let request = format!(
+            "GET http://example.com/ HTTP/1.1\r\nHost: example.com\r\nCookie: {}\r\n\r\nbody",
+            cookie
+        );
+        let writer = tokio::spawn(async move {
+            for chunk in request.as_bytes().chunks(1000) {
+                client.write_all(chunk).await.unwrap();
+                tokio::task::yield_now().await;
+            }
+            client
+        });
+
+        match read_http_head(
+            &mut server,
+            DEFAULT_MAX_HEADER_SIZE,
+            DEFAULT_INITIAL_READ_SIZE,
+        )
+        .await
+        .unwrap()
+        {
+            HeadRead::Complete { head, rest } => {
+                assert!(head.ends_with(b"\r\n\r\n"));
+                assert!(String::from_utf8_lossy(&head).contains(&cookie));
+                assert!(b"body".starts_with(&rest));
+            }
+            other => panic!("Expected complete head, got {:?}", other),
+        }
+        writer.await.unwrap();
+    }
+
+    #[test]
+    fn test_infer_scheme_from_trusted_proxy() {
+        let buffer = b"GET / HTTP/1.1\r\nHost: example.com\r\nx-forwarded-proto: https\r\n\r\n";
+        let trusted: Vec<IpCidr> = vec!["10.0.0.0/8".parse().unwrap()];
+
+        assert_eq!(
+            infer_scheme(buffer, "10.1.2.3".parse().unwrap(), &trusted),
+            "https"
+        );
+        // 非受信任来源的头部被忽略
+        assert_eq!(
+            infer_scheme(buffer, "192.168.1.1".parse().unwrap(), &trusted),
+            "http"
+        );
+        assert_eq!(
+            infer_scheme(buffer, "10.1.2.3".parse().unwrap(), &[]),
+            "http"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parse_http_request_host_header() {
+        let target = parse_http_request(b"GET / HTTP/1.1\r\nhost: example.com:8080\r\n\r\n").await;
+        assert_eq!(target, Some(("example.com".to_string(), 8080)));
+
+        // 其他以Host结尾的头不能当作Host
+        let target = parse_http_request(
+            b"GET / HTTP/1.1\r\nX-Forwarded-Host: evil.com\r\nHost: example.com\r\n\r\n",
+        )
+        .await;
+        assert_eq!(target, Some(("example.com".to_string(), 80)));
+        assert!(
+            parse_http_request(b"GET / HTTP/1.1\r\nX-Forwarded-Host: evil.com\r\n\r\n")
+                .await
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_invalid_header_byte() {
+        assert_eq!(
+            invalid_header_byte(b"GET / HTTP/1.1\r\nHost: a\tb\xe4\r\n\r\n"),
+            None
+        );
+        assert_eq!(
+            invalid_header_byte(b"GET / HTTP/1.1\r\nX: a\x01\r\n\r\n"),
+            Some(20)
+        );
+        assert_eq!(
+            invalid_header_byte(b"GET / HTTP/1.1\r\nX: a\rb\r\n\r\n"),
+            Some(20)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_auth_required_matches_request_version() {
+        for (request, status_line) in [
+            (
+                &b"GET http://example.com/ HTTP/1.0\r\n\r\n"[..],
+                "HTTP/1.0 407 ",
+            ),
+            (b"GET http://example.com/ HTTP/1.1\r\n\r\n", "HTTP/1.1 407 "),
+            (b"CONNECT example.com:443 HTTP/1.0\r\n\r\n", "HTTP/1.0 407 "),
+        ] {
+            let (mut client, server) = pair().await;
+            let mut server = ClientStream::from(server);
+            let version = response_version(request);
+            send_auth_required_response(&mut server, version, "Proxy-Authenticate: Basic\r\n")
+                .await
+                .unwrap();
+            drop(server);
+
+            let mut response = String::new();
+            client.read_to_string(&mut response).await.unwrap();
+            assert!(response.starts_with(status_line), "响应: {}", response);
+            assert!(
+                response.contains("\r\nConnection: close\r\n"),
+                "{}",
+                response
+            );
+            assert!(
+                response.ends_with("\r\nContent-Length: 0\r\n\r\n"),
+                "{}",
+                response
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_error_response_content_length() {
+        let (mut client, server) = pair().await;
+        let mut server = ClientStream::from(server);
+        send_error_response(
+            &mut server,
+            "502 Bad Gateway",
+            "无法连接到 example.com:80",
+            "HTTP/1.1",
+        )
+        .await
+        .unwrap();
+        drop(server);
+
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).await.unwrap();
+        let response = String::from_utf8(response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 502 Bad Gateway\r\n"), "{}", head);
+        assert!(head.contains("\r\nConnection: close"), "{}", head);
+        let length = extract_header(response.as_bytes(), "Content-Length").unwrap();
+        assert_eq!(length.parse::<usize>().unwrap(), body.len());
+        assert_eq!(body, "无法连接到 example.com:80\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_read_http_head_too_large() {
+        let (mut client, mut server) = pair().await;
+
+        let request = format!("GET / HTTP/1.1\r\nCookie: {}\r\n\r\n", "a".repeat(2048));
+        client.write_all(request.as_bytes()).await.unwrap();
+
+        assert!(matches!(
+            read_http_head(&mut server, 1024, DEFAULT_INITIAL_READ_SIZE)
+                .await
+                .unwrap(),
+            HeadRead::TooLarge
+        ));
+    }
+}
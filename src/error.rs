@@ -0,0 +1,131 @@
+use std::error::Error;
+use std::fmt;
+use std::net::SocketAddr;
+
+/// 连接处理所处的阶段
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Phase {
+    /// 读取请求、识别协议
+    #[default]
+    Handshake,
+    /// 连接目标服务器
+    Connect,
+    /// 双向转发数据
+    Relay,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Phase::Handshake => write!(f, "握手"),
+            Phase::Connect => write!(f, "连接目标"),
+            Phase::Relay => write!(f, "转发"),
+        }
+    }
+}
+
+/// 单个连接的上下文快照
+///
+/// 随错误一起传递，使最终的日志行包含排查单个失败连接所需的全部信息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionContext {
+    pub client_addr: SocketAddr,
+    pub protocol: Option<String>,
+    pub user: Option<String>,
+    /// 受信任客户端通过 `X-Tenant-Id` 指定的租户
+    pub tenant: Option<String>,
+    pub destination: Option<String>,
+    pub phase: Phase,
+    /// 客户端→目标已转发的字节数
+    pub bytes_sent: u64,
+    /// 目标→客户端已转发的字节数
+    pub bytes_received: u64,
+}
+
+impl ConnectionContext {
+    pub fn new(client_addr: SocketAddr) -> Self {
+        Self {
+            client_addr,
+            protocol: None,
+            user: None,
+            tenant: None,
+            destination: None,
+            phase: Phase::default(),
+            bytes_sent: 0,
+            bytes_received: 0,
+        }
+    }
+}
+
+impl fmt::Display for ConnectionContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let or_dash = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+        write!(
+            f,
+            "客户端={} 协议={} 用户={} ",
+            self.client_addr,
+            or_dash(&self.protocol),
+            or_dash(&self.user)
+        )?;
+        if let Some(tenant) = &self.tenant {
+            write!(f, "租户={} ", tenant)?;
+        }
+        write!(
+            f,
+            "目标={} 阶段={} 上行={} 下行={}",
+            or_dash(&self.destination),
+            self.phase,
+            self.bytes_sent,
+            self.bytes_received
+        )
+    }
+}
+
+/// 携带连接上下文的代理错误
+#[derive(Debug)]
+pub struct ProxyError {
+    context: ConnectionContext,
+    source: Box<dyn Error + Send + Sync>,
+}
+
+impl ProxyError {
+    pub fn new(context: ConnectionContext, source: Box<dyn Error + Send + Sync>) -> Self {
+        Self { context, source }
+    }
+
+    /// 出错时的连接上下文
+    pub fn context(&self) -> &ConnectionContext {
+        &self.context
+    }
+}
+
+impl fmt::Display for ProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.source, self.context)
+    }
+}
+
+impl Error for ProxyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_includes_context() {
+        let mut context = ConnectionContext::new("127.0.0.1:5000".parse().unwrap());
+        context.protocol = Some("CONNECT".to_string());
+        context.destination = Some("example.com:443".to_string());
+        context.phase = Phase::Connect;
+
+        let error = ProxyError::new(context, "连接被拒绝".into());
+        assert_eq!(
+            error.to_string(),
+            "连接被拒绝 (客户端=127.0.0.1:5000 协议=CONNECT 用户=- 目标=example.com:443 阶段=连接目标 上行=0 下行=0)"
+        );
+    }
+}
@@ -1,28 +1,1198 @@
+use crate::access_rules::AccessRules;
+use crate::cidr::is_private;
+use crate::logging;
+use crate::metrics::metrics;
+use crate::relay::{RelayOptions, DEFAULT_BUFFER_SIZE, DEFAULT_TEARDOWN_GRACE};
+use crate::upstream::UpstreamProxy;
+use arc_swap::{ArcSwap, ArcSwapOption};
+use rustls::pki_types::ServerName;
+use rustls::ClientConfig;
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
-use tokio::net::TcpStream;
-use tracing::{debug, info};
+use std::fmt;
+use std::future::{poll_fn, Future};
+use std::io;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{lookup_host, TcpStream};
+use tokio::sync::Semaphore;
+use tokio::time::{timeout, timeout_at, Instant};
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+use tracing::{debug, info, warn};
+
+/// 连接失败是否由访问规则禁止导致
+pub fn is_access_denied(error: &(dyn Error + Send + Sync + 'static)) -> bool {
+    error
+        .downcast_ref::<io::Error>()
+        .is_some_and(|e| e.kind() == io::ErrorKind::PermissionDenied)
+}
+
+/// 连接失败是否因目标是代理自身的监听地址而被拒绝，见 [`BackendConnector::add_listen_addr`]
+///
+/// 这类错误同时满足 [`is_access_denied`]
+pub fn is_loop_detected(error: &(dyn Error + Send + Sync + 'static)) -> bool {
+    error
+        .downcast_ref::<io::Error>()
+        .and_then(|e| e.get_ref())
+        .is_some_and(|inner| inner.is::<LoopDetected>())
+}
+
+/// 目标被禁止时返回给客户端的说明，转发回环与其他禁止原因分开提示
+pub fn forbidden_message(
+    error: &(dyn Error + Send + Sync + 'static),
+    host: &str,
+    port: u16,
+) -> String {
+    if is_loop_detected(error) {
+        format!("检测到转发回环：{}:{} 是代理自身的监听地址", host, port)
+    } else {
+        format!("禁止访问 {}:{}", host, port)
+    }
+}
+
+/// 连接失败是否由超时导致（域名解析、TCP连接或TLS握手超时）
+pub fn is_timeout(error: &(dyn Error + Send + Sync + 'static)) -> bool {
+    error
+        .downcast_ref::<io::Error>()
+        .is_some_and(|e| e.kind() == io::ErrorKind::TimedOut)
+}
+
+/// 连接失败是否因上游代理拒绝了 `CONNECT`，返回上游响应的状态码
+pub fn upstream_status(error: &(dyn Error + Send + Sync + 'static)) -> Option<u16> {
+    error
+        .downcast_ref::<UpstreamRejected>()
+        .map(|rejected| rejected.status)
+}
+
+/// 上游代理以非 `200` 状态拒绝了 `CONNECT` 请求
+#[derive(Debug)]
+pub struct UpstreamRejected {
+    pub status: u16,
+    message: String,
+}
+
+impl fmt::Display for UpstreamRejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for UpstreamRejected {}
+
+/// [`BackendConnector::connect_http`] 得到的明文HTTP连接
+#[derive(Debug)]
+pub struct HttpConnection {
+    pub stream: TcpStream,
+    /// 连接实际连到的端点（目标或上游代理），响应结束后以它为键调用
+    /// [`BackendConnector::release`]；改连了备用目标时为 `None`，这类连接不放回连接池
+    pub endpoint: Option<(String, u16)>,
+    /// 是否为从连接池取出的空闲连接，这类连接可能在取出后才被源站关闭
+    pub reused: bool,
+}
+
+/// 目标解析到代理自身的监听地址，连接后请求会再次进入代理，形成无限转发
+#[derive(Debug)]
+struct LoopDetected {
+    host: String,
+    addr: SocketAddr,
+}
+
+impl fmt::Display for LoopDetected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "检测到转发回环：目标 {} ({}) 是代理自身的监听地址",
+            self.host, self.addr
+        )
+    }
+}
+
+impl Error for LoopDetected {}
+
+/// 默认的完整连接超时，包含域名解析
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 默认的连接尝试间隔，RFC 8305建议值
+pub const DEFAULT_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
 
 /// 后端连接器
 ///
 /// 负责使用代理IP连接到目标服务器，确保客户端IP匿名性
-pub struct BackendConnector;
+#[derive(Debug, Clone)]
+pub struct BackendConnector {
+    connect_timeout: Duration,
+    quick_check: Option<Duration>,
+    attempt_delay: Duration,
+    upstream: Option<UpstreamProxy>,
+    tls: Option<Arc<ClientConfig>>,
+    idle_timeout: Option<Duration>,
+    buffer_size: usize,
+    max_inflight: Option<usize>,
+    teardown_grace: Duration,
+    dial_limit: Option<DialLimit>,
+    sni_overrides: HashMap<String, String>,
+    fallback: Option<(String, u16)>,
+    access_rules: Arc<ArcSwapOption<AccessRules>>,
+    block_private: bool,
+    pool: Option<ConnectionPool>,
+    listen_addrs: Arc<ArcSwap<Vec<SocketAddr>>>,
+}
+
+impl Default for BackendConnector {
+    fn default() -> Self {
+        Self {
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            quick_check: None,
+            attempt_delay: DEFAULT_ATTEMPT_DELAY,
+            upstream: None,
+            tls: None,
+            idle_timeout: None,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            max_inflight: None,
+            teardown_grace: DEFAULT_TEARDOWN_GRACE,
+            dial_limit: None,
+            sni_overrides: HashMap::new(),
+            fallback: None,
+            access_rules: Arc::new(ArcSwapOption::empty()),
+            block_private: false,
+            pool: None,
+            listen_addrs: Arc::default(),
+        }
+    }
+}
 
 impl BackendConnector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 设置完整连接超时，域名解析与逐个地址的连接尝试共用该期限
+    pub fn with_connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    /// 启用快速可达性探测
+    ///
+    /// 每个地址的连接须在该期限内有结论（成功或被拒绝）；期限内没有应答的地址
+    /// 视为不可达，立即以超时失败，不再等待完整连接超时
+    pub fn with_quick_check(mut self, quick_check: Option<Duration>) -> Self {
+        self.quick_check = quick_check;
+        self
+    }
+
+    /// 设置连接尝试间隔，默认250毫秒
+    ///
+    /// 目标解析到多个地址时，上一个地址在该间隔内未连接成功即开始连接下一个地址，
+    /// 已开始的尝试继续进行，最先成功的连接胜出
+    pub fn with_attempt_delay(mut self, attempt_delay: Duration) -> Self {
+        self.attempt_delay = attempt_delay;
+        self
+    }
+
+    /// 设置转发的空闲超时，两个方向都没有数据流动超过该时长时关闭连接
+    pub fn with_idle_timeout(mut self, idle_timeout: Option<Duration>) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// 转发的空闲超时
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout
+    }
+
+    /// 设置转发缓冲区大小（每个方向）
+    pub fn with_buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    /// 转发缓冲区大小
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// 设置转发时每个方向在途字节数的上限
+    ///
+    /// 目标比客户端慢（或相反）时，快的一端在在途数据达到上限后暂停读取
+    pub fn with_max_inflight_bytes(mut self, max_inflight: Option<usize>) -> Self {
+        self.max_inflight = max_inflight;
+        self
+    }
+
+    /// 限制全局同时进行中的建立连接操作（解析+连接）数
+    ///
+    /// 超出上限的连接排队等待，等待时间计入连接超时
+    pub fn with_max_pending_dials(mut self, max_pending_dials: Option<usize>) -> Self {
+        self.dial_limit = max_pending_dials.map(|max| DialLimit {
+            semaphore: Arc::new(Semaphore::new(max)),
+            max,
+        });
+        self
+    }
+
+    /// 当前进行中的建立连接操作数，未设置上限时为 `None`
+    pub fn pending_dials(&self) -> Option<usize> {
+        self.dial_limit
+            .as_ref()
+            .map(|limit| limit.max - limit.semaphore.available_permits())
+    }
+
+    /// 设置转发异常关闭时刷新缓冲数据的宽限期
+    pub fn with_teardown_grace(mut self, teardown_grace: Duration) -> Self {
+        self.teardown_grace = teardown_grace;
+        self
+    }
+
+    /// 转发参数
+    pub fn relay_options(&self) -> RelayOptions {
+        RelayOptions {
+            idle_timeout: self.idle_timeout,
+            buffer_size: self.buffer_size,
+            max_inflight: self.max_inflight,
+            teardown_grace: self.teardown_grace,
+            websocket_close: false,
+        }
+    }
+
+    /// 设置备用目标，连接目标失败时改为连接该地址
+    pub fn with_fallback(mut self, fallback: Option<(String, u16)>) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    /// 设置上游代理，所有出站连接都经由其建立
+    pub fn with_upstream(mut self, upstream: Option<UpstreamProxy>) -> Self {
+        self.upstream = upstream;
+        self
+    }
+
+    /// 已配置的上游代理
+    pub fn upstream(&self) -> Option<&UpstreamProxy> {
+        self.upstream.as_ref()
+    }
+
+    /// 设置连接HTTPS源站使用的TLS客户端配置
+    ///
+    /// 设置后，目标为HTTPS的明文HTTP请求会经TLS转发到源站
+    pub fn with_tls(mut self, tls: Option<Arc<ClientConfig>>) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// 设置按目标主机覆盖的TLS SNI
+    ///
+    /// 连接到映射中的主机时，TLS握手发送并校验映射后的服务器名而不是连接地址
+    pub fn with_sni_overrides(mut self, sni_overrides: HashMap<String, String>) -> Self {
+        self.sni_overrides = sni_overrides;
+        self
+    }
+
+    /// 设置出站目标访问规则，被禁止的目标连接失败并返回
+    /// [`io::ErrorKind::PermissionDenied`]，可用 [`is_access_denied`] 判断
+    pub fn with_access_rules(mut self, access_rules: Option<Arc<AccessRules>>) -> Self {
+        self.access_rules = Arc::new(ArcSwapOption::new(access_rules));
+        self
+    }
+
+    /// 替换访问规则，所有克隆共享同一份规则
+    ///
+    /// 之后建立的连接按新规则检查，已建立的连接不受影响
+    pub fn set_access_rules(&self, access_rules: Option<Arc<AccessRules>>) {
+        self.access_rules.store(access_rules);
+    }
+
+    /// 是否拒绝连接回环、链路本地和私有网段的目标，域名解析后逐个地址检查，
+    /// 被拒绝时返回 [`io::ErrorKind::PermissionDenied`]
+    pub fn with_block_private(mut self, block_private: bool) -> Self {
+        self.block_private = block_private;
+        self
+    }
+
+    /// 设置明文HTTP源站连接的连接池，`None` 时每个请求都新建连接
+    pub fn with_pool(mut self, pool: Option<ConnectionPool>) -> Self {
+        self.pool = pool;
+        self
+    }
+
+    /// 登记代理自身的监听地址，所有克隆共享同一份地址
+    ///
+    /// 解析结果包含这些地址的目标以 [`io::ErrorKind::PermissionDenied`] 拒绝，
+    /// 可用 [`is_loop_detected`] 判断；监听在未指定地址上时，本机任一地址的同一端口都视为自身
+    pub fn add_listen_addr(&self, addr: SocketAddr) {
+        self.listen_addrs.rcu(|addrs| {
+            let mut addrs = Vec::clone(addrs);
+            if !addrs.contains(&addr) {
+                addrs.push(addr);
+            }
+            addrs
+        });
+    }
+
+    /// 地址是否指向代理自身的某个监听地址
+    fn is_listen_addr(&self, addr: SocketAddr) -> bool {
+        let ip = addr.ip().to_canonical();
+        self.listen_addrs.load().iter().any(|listen| {
+            listen.port() == addr.port()
+                && (listen.ip().to_canonical() == ip
+                    || ip.is_unspecified()
+                    || (listen.ip().is_unspecified() && is_local_ip(ip)))
+        })
+    }
+
+    /// 将响应结束后仍可复用的明文HTTP源站连接放回连接池，未启用连接池时关闭连接
+    pub fn release(&self, host: &str, port: u16, stream: TcpStream) {
+        if let Some(pool) = &self.pool {
+            pool.release(host, port, stream);
+        }
+    }
+
+    /// 是否启用了源站TLS
+    pub fn tls_enabled(&self) -> bool {
+        self.tls.is_some()
+    }
+
+    /// 连接到目标服务器并完成TLS握手
+    ///
+    /// 配置了上游代理时TLS建立在 `CONNECT` 隧道之上；SNI可通过
+    /// [`with_sni_overrides`](Self::with_sni_overrides) 按主机覆盖
+    pub async fn connect_tls(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> Result<TlsStream<TcpStream>, Box<dyn Error + Send + Sync>> {
+        let config = self.tls.clone().ok_or("未启用源站TLS")?;
+        let sni = self.sni_overrides.get(host).map_or(host, String::as_str);
+        let server_name = ServerName::try_from(sni.to_string())
+            .map_err(|e| format!("无效的TLS服务器名 {}: {}", sni, e))?;
+
+        let stream = self.connect(host, port).await?;
+        let handshake = TlsConnector::from(config).connect(server_name, stream);
+        let stream = match timeout(self.connect_timeout, handshake).await {
+            Ok(result) => result?,
+            Err(_) => return Err(timed_out(format!("与 {}:{} 的TLS握手超时", host, port)).into()),
+        };
+
+        debug!("与目标服务器 {}:{} 的TLS握手完成", host, port);
+        Ok(stream)
+    }
+
     /// 连接到目标服务器
     ///
+    /// 超过连接超时时返回 [`io::ErrorKind::TimedOut`]，可用 [`is_timeout`] 判断；
+    /// 配置了上游代理时先连接上游，再通过 `CONNECT` 建立到目标的隧道；
+    /// 连接失败且配置了备用目标时改为连接备用目标
+    ///
     /// # 参数
     /// * `host` - 目标主机名
     /// * `port` - 目标端口
     ///
     /// # 返回
     /// 返回与目标服务器的TCP连接
-    pub async fn connect(host: &str, port: u16) -> Result<TcpStream, Box<dyn Error + Send + Sync>> {
+    pub async fn connect(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> Result<TcpStream, Box<dyn Error + Send + Sync>> {
+        logging::record_target(host, port);
+        self.check_access(host)?;
+        let (stream, _) = self.connect_with_fallback(host, port).await?;
+        Ok(stream)
+    }
+
+    /// 连接到目标，失败且配置了备用目标时改为连接备用目标
+    ///
+    /// 返回连接及是否改连了备用目标
+    async fn connect_with_fallback(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> Result<(TcpStream, bool), Box<dyn Error + Send + Sync>> {
+        match self.connect_target(host, port, self.block_private).await {
+            Ok(stream) => Ok((stream, false)),
+            // 被策略禁止（包括回环）的目标不改连备用目标，否则这类请求反而被转发出去
+            Err(e) if is_access_denied(e.as_ref()) => Err(e),
+            Err(e) => match &self.fallback {
+                Some((fallback_host, fallback_port)) => {
+                    warn!(
+                        "连接 {}:{} 失败（{}），切换到备用目标 {}:{}",
+                        host, port, e, fallback_host, fallback_port
+                    );
+                    // 备用目标由运营者配置，通常就在内网，不受内部地址限制
+                    let stream = self
+                        .connect_target(fallback_host, *fallback_port, false)
+                        .await?;
+                    Ok((stream, true))
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    /// 为明文HTTP请求建立连接
+    ///
+    /// 配置了上游代理时直接返回到上游的连接，请求需以绝对URI形式发送；
+    /// 否则等同于 [`connect`](Self::connect)。启用了连接池时优先取出到同一端点的空闲连接
+    pub async fn connect_http(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> Result<HttpConnection, Box<dyn Error + Send + Sync>> {
+        self.open_http(host, port, true).await
+    }
+
+    /// 不经连接池新建明文HTTP连接，用于池中取出的连接失效后重试请求
+    pub async fn reconnect_http(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> Result<HttpConnection, Box<dyn Error + Send + Sync>> {
+        self.open_http(host, port, false).await
+    }
+
+    async fn open_http(
+        &self,
+        host: &str,
+        port: u16,
+        reuse: bool,
+    ) -> Result<HttpConnection, Box<dyn Error + Send + Sync>> {
+        logging::record_target(host, port);
+        self.check_access(host)?;
+        let endpoint = match &self.upstream {
+            Some(upstream) => (upstream.host.clone(), upstream.port),
+            None => (host.to_string(), port),
+        };
+        if let Some(stream) = self
+            .pool
+            .as_ref()
+            .filter(|_| reuse)
+            .and_then(|pool| pool.checkout(&endpoint.0, endpoint.1))
+        {
+            debug!("复用到 {}:{} 的空闲连接", endpoint.0, endpoint.1);
+            return Ok(HttpConnection {
+                stream,
+                endpoint: Some(endpoint),
+                reused: true,
+            });
+        }
+        let (stream, endpoint) = match &self.upstream {
+            Some(upstream) => {
+                let stream = self.dial(&upstream.host, upstream.port, false).await?;
+                (stream, Some(endpoint))
+            }
+            None => {
+                let (stream, fell_back) = self.connect_with_fallback(host, port).await?;
+                (stream, (!fell_back).then_some(endpoint))
+            }
+        };
+        Ok(HttpConnection {
+            stream,
+            endpoint,
+            reused: false,
+        })
+    }
+
+    /// 按访问规则检查目标主机
+    fn check_access(&self, host: &str) -> io::Result<()> {
+        if let Some(rules) = self.access_rules.load().as_ref() {
+            if !rules.is_allowed(host) {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("目标 {} 被访问规则禁止", host),
+                ));
+            }
+        }
+        // 经上游代理连接时由上游解析域名，这里只能检查IP形式的目标
+        let literal = host.trim_start_matches('[').trim_end_matches(']');
+        match literal.parse::<IpAddr>() {
+            Ok(ip) if self.block_private && is_private(ip) => Err(private_denied(host)),
+            _ => Ok(()),
+        }
+    }
+
+    /// 连接到单个目标，必要时经上游代理建立隧道
+    async fn connect_target(
+        &self,
+        host: &str,
+        port: u16,
+        block_private: bool,
+    ) -> Result<TcpStream, Box<dyn Error + Send + Sync>> {
+        match &self.upstream {
+            Some(upstream) => {
+                let mut stream = self.dial(&upstream.host, upstream.port, false).await?;
+                tunnel_through_upstream(&mut stream, upstream, host, port).await?;
+                info!("经上游代理 {} 连接到目标服务器 {}:{}", upstream, host, port);
+                Ok(stream)
+            }
+            None => self.dial(host, port, block_private).await,
+        }
+    }
+
+    /// 建立TCP连接
+    ///
+    /// 先在连接超时内解析目标地址，再按IPv6与IPv4交替的顺序错开发起连接
+    /// （见 [`race_connect`]），解析与所有尝试共用同一个连接超时。`block_private` 为真时跳过内部地址，
+    /// 解析结果全部为内部地址时按访问禁止处理
+    async fn dial(
+        &self,
+        host: &str,
+        port: u16,
+        block_private: bool,
+    ) -> Result<TcpStream, Box<dyn Error + Send + Sync>> {
         debug!("连接到目标服务器 {}:{}", host, port);
 
-        let stream = TcpStream::connect((host, port)).await?;
+        let deadline = Instant::now() + self.connect_timeout;
+        let _permit = match &self.dial_limit {
+            Some(limit) => match timeout_at(deadline, limit.semaphore.acquire()).await {
+                Ok(Ok(permit)) => Some(permit),
+                Ok(Err(e)) => return Err(e.into()),
+                Err(_) => {
+                    warn!("等待建立连接许可超时 {}:{}", host, port);
+                    return Err(timed_out(format!("等待连接 {}:{} 的许可超时", host, port)).into());
+                }
+            },
+            None => None,
+        };
+        let mut addrs: Vec<SocketAddr> = match timeout_at(deadline, lookup_host((host, port))).await
+        {
+            Ok(result) => result?.collect(),
+            Err(_) => {
+                return Err(timed_out(format!("解析 {} 超时", host)).into());
+            }
+        };
+        if let Some(&addr) = addrs.iter().find(|addr| self.is_listen_addr(**addr)) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                LoopDetected {
+                    host: host.to_string(),
+                    addr,
+                },
+            )
+            .into());
+        }
+        if block_private && !addrs.is_empty() {
+            addrs.retain(|addr| !is_private(addr.ip()));
+            if addrs.is_empty() {
+                return Err(private_denied(host).into());
+            }
+        }
 
-        info!("成功连接到目标服务器 {}:{}", host, port);
+        let addrs = interleave_families(addrs);
+        let result = race_connect(&addrs, self.attempt_delay, |addr| {
+            self.dial_addr(addr, deadline)
+        })
+        .await;
+        let last_error = match result {
+            Ok((addr, stream)) => {
+                info!("成功连接到目标服务器 {}:{} ({})", host, port, addr);
+                return Ok(stream);
+            }
+            Err(e) => e,
+        };
 
-        Ok(stream)
+        match last_error {
+            Some(e) if e.kind() == io::ErrorKind::TimedOut => {
+                Err(timed_out(format!("连接 {}:{} 超时", host, port)).into())
+            }
+            Some(e) => Err(e.into()),
+            None => Err(format!("无法解析 {}", host).into()),
+        }
+    }
+
+    /// 连接单个地址，整体不超过 `deadline`
+    ///
+    /// 启用快速探测时，探测期限内没有结论的地址按超时失败
+    async fn dial_addr(&self, addr: SocketAddr, deadline: Instant) -> io::Result<TcpStream> {
+        let connect = TcpStream::connect(addr);
+
+        if let Some(quick) = self.quick_check {
+            let quick_deadline = (Instant::now() + quick).min(deadline);
+            return match timeout_at(quick_deadline, connect).await {
+                Ok(result) => {
+                    if let Err(e) = &result {
+                        debug!("快速探测判定目标不可达 {}: {}", addr, e);
+                    }
+                    result
+                }
+                Err(_) => {
+                    debug!("快速探测期限内 {} 没有应答，视为不可达", addr);
+                    Err(timed_out(format!("快速探测 {} 没有应答", addr)))
+                }
+            };
+        }
+
+        match timeout_at(deadline, connect).await {
+            Ok(result) => result,
+            Err(_) => Err(timed_out(format!("连接 {} 超时", addr))),
+        }
+    }
+}
+
+/// 按RFC 8305交替排列IPv6和IPv4地址，以解析结果中第一个地址的协议族开头
+///
+/// 某一协议族的路径不通时，下一次尝试即换用另一协议族
+fn interleave_families(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let first_is_v6 = match addrs.first() {
+        Some(addr) => addr.is_ipv6(),
+        None => return addrs,
+    };
+    let (preferred, other): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .partition(|addr| addr.is_ipv6() == first_is_v6);
+    let mut interleaved = Vec::with_capacity(preferred.len() + other.len());
+    let mut preferred = preferred.into_iter();
+    let mut other = other.into_iter();
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => break,
+            (a, b) => interleaved.extend(a.into_iter().chain(b)),
+        }
+    }
+    interleaved
+}
+
+/// 错开启动各地址的连接尝试（Happy Eyeballs），返回最先成功的连接及其地址
+///
+/// 先连接第一个地址，`attempt_delay` 内未成功则开始连接下一个地址，某个尝试失败时
+/// 立即开始下一个；已开始的尝试并行进行，一个成功后其余尝试随之取消。
+/// 全部失败时返回最后一个错误，没有地址时返回 `None`
+async fn race_connect<T, F, Fut>(
+    addrs: &[SocketAddr],
+    attempt_delay: Duration,
+    connect: F,
+) -> Result<(SocketAddr, T), Option<io::Error>>
+where
+    F: Fn(SocketAddr) -> Fut,
+    Fut: Future<Output = io::Result<T>>,
+{
+    let mut attempts: Vec<(SocketAddr, Pin<Box<Fut>>)> = Vec::new();
+    let mut next = 0;
+    let mut launch = true;
+    let mut last_error = None;
+    let delay = tokio::time::sleep(attempt_delay);
+    tokio::pin!(delay);
+
+    poll_fn(|cx| loop {
+        if launch && next < addrs.len() {
+            let addr = addrs[next];
+            next += 1;
+            attempts.push((addr, Box::pin(connect(addr))));
+            delay.as_mut().reset(Instant::now() + attempt_delay);
+        }
+        launch = false;
+
+        let mut i = 0;
+        while i < attempts.len() {
+            match attempts[i].1.as_mut().poll(cx) {
+                Poll::Ready(Ok(stream)) => return Poll::Ready(Ok((attempts[i].0, stream))),
+                Poll::Ready(Err(e)) => {
+                    debug!("连接地址 {} 失败: {}", attempts[i].0, e);
+                    attempts.swap_remove(i);
+                    last_error = Some(e);
+                    launch = true;
+                }
+                Poll::Pending => i += 1,
+            }
+        }
+
+        if next < addrs.len() && (launch || delay.as_mut().poll(cx).is_ready()) {
+            launch = true;
+            continue;
+        }
+        if attempts.is_empty() {
+            return Poll::Ready(Err(last_error.take()));
+        }
+        return Poll::Pending;
+    })
+    .await
+}
+
+/// 全局建立连接数的上限
+#[derive(Debug, Clone)]
+struct DialLimit {
+    semaphore: Arc<Semaphore>,
+    max: usize,
+}
+
+/// 每个目标最多保留的空闲连接数，超出时关闭最早放回的连接
+const MAX_IDLE_PER_TARGET: usize = 8;
+
+/// 默认的空闲连接总数上限
+pub const DEFAULT_POOL_MAX_ENTRIES: usize = 1024;
+
+/// 明文HTTP源站的空闲连接池，按目标主机与端口分组
+///
+/// 只存放停在两个请求之间的连接；`CONNECT` 隧道与协议升级后的连接不会放回。
+/// 空闲超过 `idle_timeout` 的连接在下一次取出或放回时清理；
+/// 空闲连接总数超过 `max_entries` 时，无论属于哪个目标，关闭最久未使用的连接
+#[derive(Debug, Clone)]
+pub struct ConnectionPool {
+    idle: Arc<Mutex<IdleConnections>>,
+    idle_timeout: Duration,
+    max_entries: usize,
+    reused: Arc<AtomicU64>,
+    evicted: Arc<AtomicU64>,
+}
+
+/// 池中的空闲连接
+#[derive(Debug, Default)]
+struct IdleConnections {
+    /// 按目标主机与端口分组，每组中最近放回的连接在最后
+    by_target: HashMap<(String, u16), Vec<IdleConnection>>,
+    /// 所有空闲连接按放回顺序排列的序号及其目标，最久未使用的在最前
+    order: BTreeMap<u64, (String, u16)>,
+    next_seq: u64,
+}
+
+/// 池中的一个空闲连接
+#[derive(Debug)]
+struct IdleConnection {
+    stream: TcpStream,
+    since: Instant,
+    seq: u64,
+}
+
+impl ConnectionPool {
+    pub fn new(idle_timeout: Duration) -> Self {
+        Self {
+            idle: Arc::default(),
+            idle_timeout,
+            max_entries: DEFAULT_POOL_MAX_ENTRIES,
+            reused: Arc::new(AtomicU64::new(0)),
+            evicted: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// 设置所有目标合计的空闲连接数上限
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// 取出到 `host:port` 的一个可用空闲连接，优先最近放回的连接
+    ///
+    /// 对端已关闭或发来了多余数据的连接直接丢弃
+    pub fn checkout(&self, host: &str, port: u16) -> Option<TcpStream> {
+        let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        self.sweep(&mut idle);
+        let IdleConnections {
+            by_target, order, ..
+        } = &mut *idle;
+        let key = (host.to_string(), port);
+        let connections = by_target.get_mut(&key)?;
+        let mut found = None;
+        while let Some(connection) = connections.pop() {
+            order.remove(&connection.seq);
+            match connection.stream.try_read(&mut [0u8; 1]) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    self.reused.fetch_add(1, Ordering::Relaxed);
+                    found = Some(connection.stream);
+                    break;
+                }
+                _ => debug!("丢弃到 {}:{} 的失效空闲连接", host, port),
+            }
+        }
+        if connections.is_empty() {
+            by_target.remove(&key);
+        }
+        metrics().set_pool_idle(order.len());
+        found
+    }
+
+    /// 放回到 `host:port` 的空闲连接
+    pub fn release(&self, host: &str, port: u16, stream: TcpStream) {
+        let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        self.sweep(&mut idle);
+        let seq = idle.next_seq;
+        idle.next_seq += 1;
+        let IdleConnections {
+            by_target, order, ..
+        } = &mut *idle;
+        let key = (host.to_string(), port);
+        let connections = by_target.entry(key.clone()).or_default();
+        if connections.len() >= MAX_IDLE_PER_TARGET {
+            order.remove(&connections.remove(0).seq);
+        }
+        connections.push(IdleConnection {
+            stream,
+            since: Instant::now(),
+            seq,
+        });
+        order.insert(seq, key);
+
+        while order.len() > self.max_entries {
+            let (seq, key) = order.pop_first().unwrap();
+            evict(by_target, &key, seq);
+            self.evicted.fetch_add(1, Ordering::Relaxed);
+            metrics().record_pool_eviction();
+            debug!(
+                "空闲连接数达到上限，关闭到 {}:{} 最久未使用的连接",
+                key.0, key.1
+            );
+        }
+        metrics().set_pool_idle(order.len());
+    }
+
+    /// 从池中取出并复用的连接总数
+    pub fn reused(&self) -> u64 {
+        self.reused.load(Ordering::Relaxed)
+    }
+
+    /// 因空闲连接总数达到上限而关闭的连接总数
+    pub fn evicted(&self) -> u64 {
+        self.evicted.load(Ordering::Relaxed)
+    }
+
+    /// 当前池中的空闲连接数
+    pub fn idle_connections(&self) -> usize {
+        let idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        idle.order.len()
+    }
+
+    /// 关闭空闲超时的连接，放回顺序即空闲时长顺序，从最早放回的连接开始检查
+    fn sweep(&self, idle: &mut IdleConnections) {
+        let IdleConnections {
+            by_target, order, ..
+        } = idle;
+        while let Some(entry) = order.first_entry() {
+            let (seq, key) = (*entry.key(), entry.get());
+            let expired = by_target
+                .get(key)
+                .and_then(|connections| connections.iter().find(|c| c.seq == seq))
+                .is_none_or(|connection| connection.since.elapsed() >= self.idle_timeout);
+            if !expired {
+                break;
+            }
+            let key = entry.remove();
+            evict(by_target, &key, seq);
+        }
+    }
+}
+
+/// 从分组中移除序号为 `seq` 的空闲连接，分组为空时一并移除
+fn evict(
+    by_target: &mut HashMap<(String, u16), Vec<IdleConnection>>,
+    key: &(String, u16),
+    seq: u64,
+) {
+    if let Some(connections) = by_target.get_mut(key) {
+        connections.retain(|connection| connection.seq != seq);
+        if connections.is_empty() {
+            by_target.remove(key);
+        }
+    }
+}
+
+/// 地址是否属于本机：回环地址，或可以绑定的本机网卡地址
+fn is_local_ip(ip: IpAddr) -> bool {
+    ip.is_loopback() || UdpSocket::bind(SocketAddr::new(ip, 0)).is_ok()
+}
+
+/// 构造访问内部地址被禁止的错误，可用 [`is_access_denied`] 判断
+fn private_denied(host: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("目标 {} 为内部地址，已禁止访问", host),
+    )
+}
+
+/// 构造超时错误，可用 [`is_timeout`] 判断
+fn timed_out(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, message)
+}
+
+/// 解析 `host:port` 形式的地址，IPv6地址可用方括号包裹
+pub fn parse_host_port(target: &str) -> Result<(String, u16), String> {
+    let (host, port) = target
+        .rsplit_once(':')
+        .ok_or_else(|| format!("地址缺少端口: {}", target))?;
+    let port = port
+        .parse::<u16>()
+        .map_err(|_| format!("无效的端口: {}", target))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        return Err(format!("地址缺少主机: {}", target));
+    }
+    Ok((host.to_string(), port))
+}
+
+/// 上游代理 `CONNECT` 响应头部的最大字节数
+const MAX_UPSTREAM_RESPONSE_SIZE: usize = 8192;
+
+/// 通过上游代理发送 `CONNECT` 请求，收到 `200` 后隧道即可使用
+///
+/// 响应逐字节读取，确保不会吞掉目标服务器紧随其后发送的数据
+async fn tunnel_through_upstream(
+    stream: &mut TcpStream,
+    upstream: &UpstreamProxy,
+    host: &str,
+    port: u16,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let authority = if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    };
+    let mut request = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", authority, authority);
+    if let Some(auth) = upstream.auth_header() {
+        request.push_str(&format!("Proxy-Authorization: {}\r\n", auth));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut response = Vec::new();
+    let mut byte = [0u8; 1];
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_UPSTREAM_RESPONSE_SIZE {
+            return Err(format!("上游代理 {} 的响应头部过大", upstream).into());
+        }
+        if stream.read(&mut byte).await? == 0 {
+            return Err(format!("上游代理 {} 在响应前关闭了连接", upstream).into());
+        }
+        response.push(byte[0]);
+    }
+
+    let response = String::from_utf8_lossy(&response);
+    let status_line = response.lines().next().unwrap_or_default();
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse::<u16>().ok());
+    match status {
+        Some(200) => {}
+        Some(status) => {
+            return Err(UpstreamRejected {
+                status,
+                message: format!(
+                    "上游代理 {} 拒绝连接 {}: {}",
+                    upstream, authority, status_line
+                ),
+            }
+            .into())
+        }
+        None => return Err(format!("上游代理 {} 的响应无效: {}", upstream, status_line).into()),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pool_discards_closed_connections() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let pool = ConnectionPool::new(Duration::from_secs(30));
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (peer, _) = listener.accept().await.unwrap();
+        pool.release("127.0.0.1", addr.port(), stream);
+        assert!(pool.checkout("127.0.0.1", addr.port() + 1).is_none());
+        let stream = pool.checkout("127.0.0.1", addr.port()).unwrap();
+        assert_eq!(pool.reused(), 1);
+
+        // 对端关闭后的连接不再取出
+        pool.release("127.0.0.1", addr.port(), stream);
+        drop(peer);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(pool.checkout("127.0.0.1", addr.port()).is_none());
+        assert_eq!(pool.idle_connections(), 0);
+        assert_eq!(pool.reused(), 1);
+    }
+
+    /// 连接到一个新的本地目标，返回连接、目标端口及对端连接
+    async fn connect_new_target() -> (TcpStream, u16, TcpStream) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let (peer, _) = listener.accept().await.unwrap();
+        (stream, port, peer)
+    }
+
+    #[tokio::test]
+    async fn test_pool_evicts_least_recently_used_across_targets() {
+        let pool = ConnectionPool::new(Duration::from_secs(30)).with_max_entries(2);
+        let mut ports = Vec::new();
+        let mut peers = Vec::new();
+        for _ in 0..4 {
+            let (stream, port, peer) = connect_new_target().await;
+            pool.release("127.0.0.1", port, stream);
+            ports.push(port);
+            peers.push(peer);
+            assert!(pool.idle_connections() <= 2);
+        }
+
+        // 超出上限时依次关闭最久未使用的前两个目标的连接
+        assert_eq!(pool.idle_connections(), 2);
+        assert_eq!(pool.evicted(), 2);
+        assert!(pool.checkout("127.0.0.1", ports[0]).is_none());
+        assert!(pool.checkout("127.0.0.1", ports[1]).is_none());
+
+        // 取出后再放回的连接成为最近使用的连接，下一次淘汰另一个目标的连接
+        let stream = pool.checkout("127.0.0.1", ports[2]).unwrap();
+        pool.release("127.0.0.1", ports[2], stream);
+        let (stream, port, _peer) = connect_new_target().await;
+        pool.release("127.0.0.1", port, stream);
+        assert_eq!(pool.idle_connections(), 2);
+        assert_eq!(pool.evicted(), 3);
+        assert!(pool.checkout("127.0.0.1", ports[3]).is_none());
+        assert!(pool.checkout("127.0.0.1", ports[2]).is_some());
+        assert!(pool.checkout("127.0.0.1", port).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_unresolvable_host_fails_within_timeout() {
+        let connector = BackendConnector::new().with_connect_timeout(Duration::from_secs(2));
+
+        let started = std::time::Instant::now();
+        let result = tokio::time::timeout(
+            Duration::from_secs(5),
+            connector.connect("does-not-exist.invalid", 80),
+        )
+        .await
+        .expect("连接应在连接超时内失败而不是挂起");
+
+        assert!(result.is_err());
+        assert!(started.elapsed() < Duration::from_secs(3));
+    }
+
+    #[tokio::test]
+    async fn test_race_connect_skips_blackholed_address() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let live = listener.local_addr().unwrap();
+        let blackholed: SocketAddr = "[2001:db8::1]:80".parse().unwrap();
+
+        // 模拟解析结果：黑洞地址在前，连接它永远不会有结论
+        let started = std::time::Instant::now();
+        let (addr, _stream) = race_connect(
+            &[blackholed, live],
+            Duration::from_millis(50),
+            |addr| async move {
+                if addr == blackholed {
+                    std::future::pending::<()>().await;
+                }
+                TcpStream::connect(addr).await
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(addr, live);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_race_connect_reports_last_error() {
+        let started = std::time::Instant::now();
+        let result = race_connect(
+            &[
+                "127.0.0.1:1".parse().unwrap(),
+                "127.0.0.1:2".parse().unwrap(),
+            ],
+            Duration::from_secs(10),
+            |addr| async move {
+                Err::<(), _>(io::Error::new(
+                    io::ErrorKind::ConnectionRefused,
+                    addr.to_string(),
+                ))
+            },
+        )
+        .await;
+        // 失败后立即尝试下一个地址，不等待尝试间隔
+        assert_eq!(result.unwrap_err().unwrap().to_string(), "127.0.0.1:2");
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        let result = race_connect(&[], DEFAULT_ATTEMPT_DELAY, |_| async { Ok(()) }).await;
+        assert!(result.unwrap_err().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_listen_addr_loop_detected() {
+        let connector = BackendConnector::new();
+        connector.add_listen_addr("0.0.0.0:24975".parse().unwrap());
+        connector.add_listen_addr("192.0.2.10:8080".parse().unwrap());
+
+        // 未指定地址的监听覆盖本机各地址的同一端口
+        assert!(connector.is_listen_addr("127.0.0.1:24975".parse().unwrap()));
+        assert!(connector.is_listen_addr("[::ffff:127.0.0.1]:24975".parse().unwrap()));
+        assert!(connector.is_listen_addr("0.0.0.0:24975".parse().unwrap()));
+        assert!(!connector.is_listen_addr("127.0.0.1:24976".parse().unwrap()));
+        assert!(!connector.is_listen_addr("198.51.100.1:24975".parse().unwrap()));
+        // 指定地址的监听只匹配该地址
+        assert!(connector.is_listen_addr("192.0.2.10:8080".parse().unwrap()));
+        assert!(!connector.is_listen_addr("127.0.0.1:8080".parse().unwrap()));
+
+        let error = connector.connect("127.0.0.1", 24975).await.unwrap_err();
+        assert!(is_loop_detected(error.as_ref()));
+        assert!(is_access_denied(error.as_ref()));
+    }
+
+    #[test]
+    fn test_interleave_families() {
+        let addrs: Vec<SocketAddr> = [
+            "[::1]:80",
+            "[::2]:80",
+            "[::3]:80",
+            "10.0.0.1:80",
+            "10.0.0.2:80",
+        ]
+        .iter()
+        .map(|addr| addr.parse().unwrap())
+        .collect();
+        let expected: Vec<SocketAddr> = [
+            "[::1]:80",
+            "10.0.0.1:80",
+            "[::2]:80",
+            "10.0.0.2:80",
+            "[::3]:80",
+        ]
+        .iter()
+        .map(|addr| addr.parse().unwrap())
+        .collect();
+        assert_eq!(interleave_families(addrs), expected);
+    }
+
+    #[tokio::test]
+    async fn test_max_pending_dials_caps_in_flight() {
+        // 监听队列为0且已被占满的监听器丢弃新的SYN，连接会一直挂起到超时
+        let socket = tokio::net::TcpSocket::new_v4().unwrap();
+        socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let listener = socket.listen(0).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let _filler = TcpStream::connect(addr).await.unwrap();
+
+        let connector = Arc::new(
+            BackendConnector::new()
+                .with_connect_timeout(Duration::from_millis(500))
+                .with_max_pending_dials(Some(4)),
+        );
+        let dials: Vec<_> = (0..20)
+            .map(|_| {
+                let connector = connector.clone();
+                tokio::spawn(async move { connector.connect("127.0.0.1", addr.port()).await })
+            })
+            .collect();
+
+        let mut max_in_flight = 0;
+        for _ in 0..30 {
+            max_in_flight = max_in_flight.max(connector.pending_dials().unwrap());
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(max_in_flight, 4);
+
+        // 排队等待许可的连接同样在连接超时内失败
+        for dial in dials {
+            let error = dial.await.unwrap().unwrap_err();
+            assert!(is_timeout(error.as_ref()), "错误: {}", error);
+        }
+        assert_eq!(connector.pending_dials(), Some(0));
     }
 }
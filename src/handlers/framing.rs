@@ -0,0 +1,375 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// 分块编码中块大小行和尾部头部行的最大长度
+const MAX_CHUNK_LINE: u64 = 4096;
+
+/// HTTP/1.x消息体的长度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyLength {
+    /// 没有消息体
+    Empty,
+    /// `Content-Length` 给出的字节数
+    Fixed(u64),
+    /// 分块编码，到长度为0的块及其后的尾部头部为止
+    Chunked,
+    /// 到连接关闭为止，只用于响应
+    UntilClose,
+}
+
+/// 按请求头部确定请求体长度（RFC 7230 第3.3.3节）
+///
+/// 分块编码优先于 `Content-Length`，二者都没有时请求没有请求体
+pub fn request_body_length(head: &[u8]) -> BodyLength {
+    let text = String::from_utf8_lossy(head);
+    let mut content_length = None;
+    for line in text.lines().skip(1) {
+        let (name, value) = match line.split_once(':') {
+            Some(header) => header,
+            None => continue,
+        };
+        let name = name.trim();
+        if name.eq_ignore_ascii_case("transfer-encoding")
+            && value
+                .split(',')
+                .any(|coding| coding.trim().eq_ignore_ascii_case("chunked"))
+        {
+            return BodyLength::Chunked;
+        }
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value.trim().parse::<u64>().ok();
+        }
+    }
+    match content_length {
+        Some(0) | None => BodyLength::Empty,
+        Some(length) => BodyLength::Fixed(length),
+    }
+}
+
+/// 按响应头部确定响应体长度（RFC 7230 第3.3.3节）
+///
+/// 1xx/204/304响应没有响应体，分块编码优先于 `Content-Length`，二者都没有时响应体到连接关闭为止。
+/// 不考虑HEAD请求的响应
+pub fn response_body_length(head: &[u8]) -> BodyLength {
+    let status = String::from_utf8_lossy(head)
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .unwrap_or(200);
+    if (100..200).contains(&status) || status == 204 || status == 304 {
+        return BodyLength::Empty;
+    }
+    let has_length = String::from_utf8_lossy(head).lines().skip(1).any(|line| {
+        line.split_once(':')
+            .is_some_and(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+    });
+    match request_body_length(head) {
+        BodyLength::Empty if !has_length => BodyLength::UntilClose,
+        length => length,
+    }
+}
+
+/// 消息之后连接是否保持打开（RFC 7230 第6.3节）
+///
+/// `Connection: close` 时关闭；HTTP/1.0消息只有带 `Connection: keep-alive` 才保持
+pub fn keeps_alive(head: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(head);
+    let mut lines = text.lines();
+    let http10 = lines
+        .next()
+        .is_some_and(|line| line.split_whitespace().any(|part| part == "HTTP/1.0"));
+    let mut keep_alive = false;
+    for line in lines {
+        let (name, value) = match line.split_once(':') {
+            Some(header) => header,
+            None => continue,
+        };
+        if !name.trim().eq_ignore_ascii_case("connection") {
+            continue;
+        }
+        for token in value.split(',') {
+            let token = token.trim();
+            if token.eq_ignore_ascii_case("close") {
+                return false;
+            }
+            keep_alive |= token.eq_ignore_ascii_case("keep-alive");
+        }
+    }
+    !http10 || keep_alive
+}
+
+/// 读取一个完整的消息头部（含结尾的空行）
+///
+/// 请求行之前的空行按RFC 7230 第3.5节忽略。读到任何数据之前连接已关闭时返回 `None`，
+/// 头部超过 `max_size` 或不完整时返回错误
+pub async fn read_head<R>(reader: &mut R, max_size: usize) -> io::Result<Option<Vec<u8>>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut head = Vec::new();
+    loop {
+        let start = head.len();
+        let limit = (max_size + 1).saturating_sub(start) as u64;
+        let n = (&mut *reader)
+            .take(limit)
+            .read_until(b'\n', &mut head)
+            .await?;
+        if n == 0 {
+            if head.is_empty() {
+                return Ok(None);
+            }
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "消息头部不完整",
+            ));
+        }
+        if head.len() > max_size {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "消息头部过大"));
+        }
+        let line = &head[start..];
+        if line == b"\r\n" || line == b"\n" {
+            if start == 0 {
+                head.clear();
+                continue;
+            }
+            return Ok(Some(head));
+        }
+    }
+}
+
+/// 按 `length` 从 `reader` 复制一个消息体到 `writer`，分块编码的帧格式原样保留
+///
+/// 返回写入的字节数；消息体结束前连接关闭时返回 `UnexpectedEof`
+pub async fn copy_body<R, W>(reader: &mut R, writer: &mut W, length: BodyLength) -> io::Result<u64>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    match length {
+        BodyLength::Empty => Ok(0),
+        BodyLength::Fixed(length) => copy_exact(reader, writer, length).await,
+        BodyLength::UntilClose => tokio::io::copy_buf(reader, writer).await,
+        BodyLength::Chunked => copy_chunked(reader, writer).await,
+    }
+}
+
+async fn copy_exact<R, W>(reader: &mut R, writer: &mut W, length: u64) -> io::Result<u64>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let copied = tokio::io::copy_buf(&mut (&mut *reader).take(length), writer).await?;
+    if copied < length {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "消息体不完整"));
+    }
+    Ok(copied)
+}
+
+async fn copy_chunked<R, W>(reader: &mut R, writer: &mut W) -> io::Result<u64>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut written = 0;
+    loop {
+        let line = read_chunk_line(reader).await?;
+        writer.write_all(&line).await?;
+        written += line.len() as u64;
+
+        let size = String::from_utf8_lossy(&line);
+        let size = size.split(';').next().unwrap_or_default().trim();
+        let size = u64::from_str_radix(size, 16).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("无效的块大小: {}", size),
+            )
+        })?;
+        if size == 0 {
+            break;
+        }
+        // 块数据及其后的CRLF
+        written += copy_exact(reader, writer, size + 2).await?;
+    }
+
+    // 尾部头部，以空行结束
+    loop {
+        let line = read_chunk_line(reader).await?;
+        writer.write_all(&line).await?;
+        written += line.len() as u64;
+        if line == b"\r\n" || line == b"\n" {
+            return Ok(written);
+        }
+    }
+}
+
+async fn read_chunk_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut line = Vec::new();
+    (&mut *reader)
+        .take(MAX_CHUNK_LINE)
+        .read_until(b'\n', &mut line)
+        .await?;
+    if !line.ends_with(b"\n") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "分块编码的块大小行不完整或过长",
+        ));
+    }
+    Ok(line)
+}
+
+/// 统计写出字节数的写入端包装
+///
+/// 复制中途出错时 [`copy_body`] 不返回已写出的字节数，由 `written` 得知
+pub struct CountingWriter<'a, W> {
+    inner: W,
+    written: &'a AtomicU64,
+}
+
+impl<'a, W> CountingWriter<'a, W> {
+    pub fn new(inner: W, written: &'a AtomicU64) -> Self {
+        Self { inner, written }
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for CountingWriter<'_, W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = result {
+            self.written.fetch_add(n as u64, Ordering::Relaxed);
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::BufReader;
+
+    #[test]
+    fn test_request_body_length() {
+        assert_eq!(
+            request_body_length(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n"),
+            BodyLength::Empty
+        );
+        assert_eq!(
+            request_body_length(b"POST / HTTP/1.1\r\ncontent-length: 12\r\n\r\n"),
+            BodyLength::Fixed(12)
+        );
+        assert_eq!(
+            request_body_length(
+                b"POST / HTTP/1.1\r\nContent-Length: 12\r\nTransfer-Encoding: gzip, chunked\r\n\r\n"
+            ),
+            BodyLength::Chunked
+        );
+    }
+
+    #[test]
+    fn test_response_body_length() {
+        assert_eq!(
+            response_body_length(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n"),
+            BodyLength::Fixed(5)
+        );
+        assert_eq!(
+            response_body_length(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n"),
+            BodyLength::Empty
+        );
+        assert_eq!(
+            response_body_length(b"HTTP/1.1 304 Not Modified\r\nContent-Length: 5\r\n\r\n"),
+            BodyLength::Empty
+        );
+        assert_eq!(
+            response_body_length(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n"),
+            BodyLength::Chunked
+        );
+        assert_eq!(
+            response_body_length(b"HTTP/1.0 200 OK\r\n\r\n"),
+            BodyLength::UntilClose
+        );
+    }
+
+    #[test]
+    fn test_keeps_alive() {
+        assert!(keeps_alive(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n"));
+        assert!(!keeps_alive(b"GET / HTTP/1.1\r\nConnection: Close\r\n\r\n"));
+        assert!(!keeps_alive(
+            b"HTTP/1.0 200 OK\r\nContent-Length: 0\r\n\r\n"
+        ));
+        assert!(keeps_alive(
+            b"HTTP/1.0 200 OK\r\nConnection: keep-alive\r\nContent-Length: 0\r\n\r\n"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_read_pipelined_heads_and_bodies() {
+        let data: &[u8] = b"\r\nPOST /a HTTP/1.1\r\nContent-Length: 3\r\n\r\nabc\
+            POST /b HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3;ext=1\r\nxyz\r\n0\r\nX-Trailer: 1\r\n\r\n\
+            GET /c HTTP/1.1\r\n\r\n";
+        let mut reader = BufReader::new(data);
+
+        let head = read_head(&mut reader, 1024).await.unwrap().unwrap();
+        assert!(head.starts_with(b"POST /a "));
+        let mut body = Vec::new();
+        let length = request_body_length(&head);
+        assert_eq!(copy_body(&mut reader, &mut body, length).await.unwrap(), 3);
+        assert_eq!(body, b"abc");
+
+        let head = read_head(&mut reader, 1024).await.unwrap().unwrap();
+        assert!(head.starts_with(b"POST /b "));
+        let mut body = Vec::new();
+        copy_body(&mut reader, &mut body, request_body_length(&head))
+            .await
+            .unwrap();
+        assert_eq!(body, b"3;ext=1\r\nxyz\r\n0\r\nX-Trailer: 1\r\n\r\n");
+
+        let head = read_head(&mut reader, 1024).await.unwrap().unwrap();
+        assert_eq!(head, b"GET /c HTTP/1.1\r\n\r\n");
+        assert!(read_head(&mut reader, 1024).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_truncated_or_oversized_messages() {
+        let mut reader = BufReader::new(&b"GET / HTTP/1.1\r\nHost: a\r\n"[..]);
+        let error = read_head(&mut reader, 1024).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut reader = BufReader::new(&b"GET / HTTP/1.1\r\nHost: a\r\n\r\n"[..]);
+        let error = read_head(&mut reader, 16).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut reader = BufReader::new(&b"ab"[..]);
+        let error = copy_body(&mut reader, &mut Vec::new(), BodyLength::Fixed(3))
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[tokio::test]
+    async fn test_counting_writer_counts_partial_copy() {
+        let written = AtomicU64::new(0);
+        let mut output = Vec::new();
+        let mut writer = CountingWriter::new(&mut output, &written);
+        let mut reader = BufReader::new(&b"hello"[..]);
+        // 消息体不完整时返回错误，已写出的字节仍被统计
+        assert!(copy_body(&mut reader, &mut writer, BodyLength::Fixed(10))
+            .await
+            .is_err());
+        assert_eq!(written.load(Ordering::Relaxed), 5);
+        assert_eq!(output, b"hello");
+    }
+}
@@ -1,7 +1,33 @@
-use super::backend::BackendConnector;
-use crate::connection::send_error_response;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
+use super::backend::{forbidden_message, is_access_denied, is_timeout, BackendConnector};
+use super::framing::{
+    copy_body, keeps_alive, read_head, request_body_length, response_body_length, BodyLength,
+    CountingWriter,
+};
+use crate::access_log::{self, StatusSniffer};
+use crate::auth::{check_authentication, proxy_auth_username, AuthConfig};
+use crate::connection::{
+    deflected_path, extract_header, extract_proxy_auth, invalid_header_byte, read_http_head,
+    request_method, request_target, response_version, send_auth_required_response,
+    send_error_response, send_method_not_allowed_response, HeadRead, PrefetchedStream,
+    DEFAULT_INITIAL_READ_SIZE, DEFAULT_MAX_HEADER_SIZE,
+};
+use crate::logging::{self, PolicyViolation};
+use crate::metrics::metrics;
+use crate::parser::detector::{
+    bad_request_hint, detect_protocol, generic_upgrade_protocol, parse_authority, ProtocolType,
+};
+use crate::rejection::{RejectionCallback, RejectionReason};
+use crate::relay::{relay, IdleTracker};
+use crate::stream::ClientStream;
+use std::collections::HashSet;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
 use tracing::{debug, error, info};
 
 /// HTTP/1.x 请求详细信息
@@ -14,141 +40,1162 @@ pub struct HttpRequest {
     pub body: Vec<u8>,
 }
 
+/// HTTP/1.x转发选项
+#[derive(Clone)]
+pub struct Http1Options {
+    /// 按 `Connection: close` 语义转发单个请求和响应后关闭连接
+    pub force_close: bool,
+    /// 原样转发客户端的 `Proxy-Connection` 头，而不是将其语义改写为 `Connection`
+    pub preserve_proxy_connection: bool,
+    /// 不转发同一连接上的流水线请求，每个连接只处理一个请求
+    pub no_pipelining: bool,
+    /// 移除客户端发送的 `Via`、`X-Forwarded-For` 和 `Forwarded` 头，且不追加本代理的转发记录
+    pub strip_forwarded: bool,
+    /// 转发前移除的请求头部名称
+    pub remove_request_headers: Arc<Vec<String>>,
+    /// 转发前设置的请求头部，替换客户端发送的同名头部
+    pub add_request_headers: Arc<Vec<(String, String)>>,
+    /// 转发非WebSocket的协议升级请求，否则以501拒绝
+    pub allow_generic_upgrade: bool,
+    /// 同一连接上后续请求头部的最大字节数
+    pub max_header_size: usize,
+    /// 允许转发的请求方法（大写），`None` 表示不限制
+    pub allowed_methods: Option<Arc<HashSet<String>>>,
+    /// 拒绝头部包含控制字节等非法字节的请求
+    pub strict_headers: bool,
+    /// 拒绝缺少 `User-Agent` 的请求
+    pub require_user_agent: bool,
+    /// 扫描器常见路径由代理自身应答，不转发
+    pub deflect_scanners: bool,
+    /// 请求被拒绝时调用的回调：后续请求违反上述检查或目标被禁止
+    pub on_rejection: Option<RejectionCallback>,
+}
+
+impl Default for Http1Options {
+    fn default() -> Self {
+        Self {
+            force_close: false,
+            preserve_proxy_connection: false,
+            no_pipelining: false,
+            strip_forwarded: false,
+            remove_request_headers: Arc::default(),
+            add_request_headers: Arc::default(),
+            allow_generic_upgrade: false,
+            max_header_size: DEFAULT_MAX_HEADER_SIZE,
+            allowed_methods: None,
+            strict_headers: false,
+            require_user_agent: false,
+            deflect_scanners: false,
+            on_rejection: None,
+        }
+    }
+}
+
+/// 客户端连接上待转发的请求
+struct Requests<'a> {
+    /// 第一个请求的头部
+    head: &'a [u8],
+    /// 与第一个请求头部一同读到的后续数据
+    rest: &'a [u8],
+    /// 转发前改写请求头部
+    rewrite: &'a (dyn Fn(&[u8]) -> Vec<u8> + Sync),
+    /// 解析 `Host` 时的默认端口
+    default_port: u16,
+    /// 后续请求头部的最大字节数
+    max_header_size: usize,
+    /// 第一个请求为协议升级请求，之后的数据不再按HTTP报文划分
+    upgrade: bool,
+    /// 允许转发的请求方法，`None` 表示不限制
+    allowed_methods: Option<&'a HashSet<String>>,
+    /// 后续请求的逐请求检查
+    options: &'a Http1Options,
+}
+
+/// 需要换用其他源站连接处理的下一个请求
+struct NextRequest {
+    /// 客户端连接
+    stream: ClientStream,
+    /// 已从客户端读到的数据，以下一个请求的头部开始
+    buffer: Vec<u8>,
+}
+
 /// 处理HTTP/1.0和HTTP/1.1请求
+///
+/// 保持连接时逐个处理同一连接上的请求，复用源站连接的后续请求沿用第一个请求的认证结果；
+/// 需要连接其他目标的请求重新认证和检查后连接新的目标
 pub async fn handle_http1(
-    mut client_stream: TcpStream,
+    client_stream: ClientStream,
     client_addr: String,
-    _auth_config: &Option<crate::auth::AuthConfig>,
+    auth_config: &Option<AuthConfig>,
+    connector: &BackendConnector,
+    scheme: &str,
     buffer: &[u8],
+    options: Http1Options,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    // 解析HTTP请求
-    let request = match parse_http_request(buffer) {
-        Some(req) => req,
-        None => {
-            error!("[{}] 无法解析HTTP请求", client_addr);
-            send_error_response(&mut client_stream, "400 Bad Request", "无效的HTTP请求").await?;
+    let mut next = NextRequest {
+        stream: client_stream,
+        buffer: buffer.to_vec(),
+    };
+    loop {
+        let NextRequest {
+            stream: mut client_stream,
+            buffer,
+        } = next;
+        let buffer = &buffer[..];
+
+        // 请求目标为绝对形式的 https URI 时按HTTPS处理
+        let scheme = if is_https_target(buffer) {
+            "https"
+        } else {
+            scheme
+        };
+
+        // 解析HTTP请求，Host未指定端口时按原始协议取默认端口
+        let default_port = if scheme == "https" { 443 } else { 80 };
+        let request = match parse_http_request(buffer, default_port) {
+            Some(req) => req,
+            None => {
+                error!("[{}] 无法解析HTTP请求", client_addr);
+                send_error_response(
+                    &mut client_stream,
+                    "400 Bad Request",
+                    bad_request_hint(buffer),
+                    response_version(buffer),
+                )
+                .await?;
+                return Ok(());
+            }
+        };
+
+        // 无从确定目标主机时直接拒绝，不去连接空主机
+        if request.host.is_empty() {
+            error!("[{}] 请求没有Host头，也没有绝对URI", client_addr);
+            send_error_response(
+                &mut client_stream,
+                "400 Bad Request",
+                "没有Host头，也没有绝对URI",
+                response_version(buffer),
+            )
+            .await?;
             return Ok(());
         }
-    };
 
-    info!(
-        "[{}] HTTP/1.x 请求: {} {}://{}:{}{}",
-        client_addr,
-        request.method,
-        if request.port == 443 { "https" } else { "http" },
-        request.host,
-        request.port,
-        request.path
-    );
+        if reject_method(&mut client_stream, &client_addr, &request, buffer, &options).await?
+            || reject_upgrade(&mut client_stream, &client_addr, buffer, &options).await?
+        {
+            return Ok(());
+        }
+
+        info!(
+            "[{}] HTTP/1.x 请求: {} {}://{}:{}{}",
+            client_addr, request.method, scheme, request.host, request.port, request.path
+        );
+
+        next = match forward_to_target(
+            client_stream,
+            &client_addr,
+            connector,
+            scheme,
+            buffer,
+            options.clone(),
+            &request,
+        )
+        .await?
+        {
+            Some(next) => next,
+            None => return Ok(()),
+        };
 
-    // 连接到目标服务器
-    match BackendConnector::connect(&request.host, request.port).await {
-        Ok(target_stream) => {
+        // CONNECT、WebSocket等请求只能作为连接上的第一个请求处理
+        let protocol = detect_protocol(&next.buffer);
+        if protocol == ProtocolType::Unknown {
+            error!("[{}] 保持的连接上收到无法识别的请求", client_addr);
+            send_error_response(
+                &mut next.stream,
+                "400 Bad Request",
+                bad_request_hint(&next.buffer),
+                response_version(&next.buffer),
+            )
+            .await?;
+            return Ok(());
+        }
+        if !matches!(protocol, ProtocolType::Http10 | ProtocolType::Http11) {
             debug!(
-                "[{}] 成功连接到目标服务器 {}:{}",
-                client_addr, request.host, request.port
+                "[{}] 保持的连接上出现 {} 请求，关闭连接",
+                client_addr,
+                protocol.label()
             );
+            return Ok(());
+        }
 
-            // 转发原始请求
-            if let Err(e) =
-                forward_http_request(client_stream, target_stream, buffer, &client_addr).await
-            {
-                error!("[{}] HTTP/1.x转发失败: {}", client_addr, e);
-            }
+        // 换用新目标的请求重新经过第一个请求在建立连接时通过的检查；
+        // 访问代理自身的请求留给客户端在新连接上发送
+        if deflected_path(&next.stream, &next.buffer, options.deflect_scanners)
+            .await
+            .is_some()
+        {
+            debug!(
+                "[{}] 保持的连接上出现访问代理自身的请求，关闭连接",
+                client_addr
+            );
+            return Ok(());
+        }
+        if !reauthenticate(
+            &mut next.stream,
+            &client_addr,
+            auth_config,
+            &next.buffer,
+            &options,
+        )
+        .await?
+        {
+            return Ok(());
+        }
+    }
+}
 
-            Ok(())
+/// 换用新目标的后续请求按自身的 `Proxy-Authorization` 重新认证，失败时返回407
+///
+/// 通过时返回 `true`，并将请求携带的用户记入访问日志
+async fn reauthenticate(
+    client_stream: &mut ClientStream,
+    client_addr: &str,
+    auth_config: &Option<AuthConfig>,
+    buffer: &[u8],
+    options: &Http1Options,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let auth_header = extract_proxy_auth(buffer);
+    let method = request_method(buffer).unwrap_or_default();
+    let target = request_target(buffer).unwrap_or_default();
+    if check_authentication(auth_config, auth_header.as_deref(), method, target) {
+        if let Some(user) = auth_header.as_deref().and_then(proxy_auth_username) {
+            access_log::record_user(&user);
         }
-        Err(e) => {
-            error!(
-                "[{}] 连接目标服务器失败 {}:{}: {}",
-                client_addr, request.host, request.port, e
-            );
+        return Ok(true);
+    }
+
+    info!("[{}] 后续请求认证失败，需要代理认证", client_addr);
+    metrics().record_auth_failure();
+    report_rejection(options, client_addr, &RejectionReason::AuthFailed);
+    let challenge = auth_config
+        .as_ref()
+        .map(AuthConfig::challenge)
+        .unwrap_or_default();
+    send_auth_required_response(client_stream, response_version(buffer), &challenge).await?;
+    Ok(false)
+}
+
+/// 以反向代理方式处理HTTP/1.x请求
+///
+/// 请求原样（保留 `Host`）转发到路由匹配的后端，而不是按 `Host` 解析目标。
+/// 保持连接时只转发 `Host` 与第一个请求相同的后续请求
+pub async fn handle_reverse(
+    mut client_stream: ClientStream,
+    client_addr: String,
+    connector: &BackendConnector,
+    buffer: &[u8],
+    options: Http1Options,
+    backend: &(String, u16),
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut request = match parse_http_request(buffer, 80) {
+        Some(req) => req,
+        None => {
+            error!("[{}] 无法解析HTTP请求", client_addr);
             send_error_response(
                 &mut client_stream,
-                "502 Bad Gateway",
-                &format!("无法连接到 {}:{}", request.host, request.port),
+                "400 Bad Request",
+                bad_request_hint(buffer),
+                response_version(buffer),
             )
             .await?;
-            Ok(())
+            return Ok(());
         }
+    };
+
+    if reject_method(&mut client_stream, &client_addr, &request, buffer, &options).await?
+        || reject_upgrade(&mut client_stream, &client_addr, buffer, &options).await?
+    {
+        return Ok(());
+    }
+
+    info!(
+        "[{}] 反向代理请求: {} {}{} -> {}:{}",
+        client_addr, request.method, request.host, request.path, backend.0, backend.1
+    );
+
+    request.host = backend.0.clone();
+    request.port = backend.1;
+    let next = forward_to_target(
+        client_stream,
+        &client_addr,
+        connector,
+        "http",
+        buffer,
+        options,
+        &request,
+    )
+    .await?;
+    // 路由按连接上第一个请求的 `Host` 选定，`Host` 不同的后续请求需在新连接上重新路由
+    if next.is_some() {
+        debug!(
+            "[{}] 反向代理连接上的请求需要重新路由，关闭连接",
+            client_addr
+        );
+    }
+    Ok(())
+}
+
+/// 请求方法不在允许列表中时返回405，`Allow` 头列出允许的方法
+///
+/// 已拒绝时返回 `true`
+async fn reject_method(
+    client_stream: &mut ClientStream,
+    client_addr: &str,
+    request: &HttpRequest,
+    buffer: &[u8],
+    options: &Http1Options,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let allowed = match &options.allowed_methods {
+        Some(allowed) if !allowed.contains(&request.method) => allowed,
+        _ => return Ok(false),
+    };
+
+    logging::policy_violation(
+        PolicyViolation::DisallowedMethod,
+        &format!(
+            "[{}] {} {}:{}",
+            client_addr, request.method, request.host, request.port
+        ),
+    );
+    report_rejection(
+        options,
+        client_addr,
+        &RejectionReason::DisallowedMethod(request.method.clone()),
+    );
+    let mut allow: Vec<&str> = allowed.iter().map(String::as_str).collect();
+    allow.sort_unstable();
+    send_method_not_allowed_response(client_stream, response_version(buffer), &allow.join(", "))
+        .await?;
+    Ok(true)
+}
+
+/// 未启用 `allow_generic_upgrade` 时以501拒绝非WebSocket的协议升级请求
+///
+/// 已拒绝时返回 `true`
+async fn reject_upgrade(
+    client_stream: &mut ClientStream,
+    client_addr: &str,
+    buffer: &[u8],
+    options: &Http1Options,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    if options.allow_generic_upgrade {
+        return Ok(false);
     }
+    let protocol = match generic_upgrade_protocol(buffer) {
+        Some(protocol) => protocol,
+        None => return Ok(false),
+    };
+
+    info!("[{}] 拒绝协议升级请求: {}", client_addr, protocol);
+    send_error_response(
+        client_stream,
+        "501 Not Implemented",
+        &format!("不支持升级到 {}", protocol),
+        response_version(buffer),
+    )
+    .await?;
+    Ok(true)
 }
 
-/// 转发HTTP请求并建立双向数据传输
-async fn forward_http_request(
-    client_stream: TcpStream,
-    target_stream: TcpStream,
-    initial_buffer: &[u8],
+/// 连接到 `request` 指定的目标并转发请求，连接失败时向客户端返回错误响应
+///
+/// 保持的客户端连接上出现需要连接其他目标的请求时返回该请求
+async fn forward_to_target(
+    mut client_stream: ClientStream,
     client_addr: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // 先发送初始缓冲区（HTTP请求）
-    let (mut client_reader, mut client_writer) = client_stream.into_split();
-    let (mut target_reader, mut target_writer) = target_stream.into_split();
-
-    // 发送初始请求到目标服务器
-    target_writer.write_all(initial_buffer).await?;
-    debug!("[{}] HTTP请求已转发到目标服务器", client_addr);
-
-    // 双向转发
-    let client_to_target = async {
-        let mut buffer = [0u8; 8192];
+    connector: &BackendConnector,
+    scheme: &str,
+    buffer: &[u8],
+    options: Http1Options,
+    request: &HttpRequest,
+) -> Result<Option<NextRequest>, Box<dyn std::error::Error + Send + Sync>> {
+    // 协议升级请求保留升级相关头部，响应101后按升级后的协议原样转发，不强制关闭连接
+    let upgrade = options.allow_generic_upgrade && generic_upgrade_protocol(buffer).is_some();
+    let force_close = (options.force_close || options.no_pipelining) && !upgrade;
+    // 目标为HTTPS且启用了源站TLS时经TLS转发，否则使用明文连接
+    let use_tls = connector.tls_enabled() && (scheme == "https" || request.port == 443);
+
+    // 移除逐跳头部；明文经上游代理转发时改写为绝对URI形式并附加上游认证，
+    // 直接转发到源站时改写为源站形式
+    let upstream = if use_tls { None } else { connector.upstream() };
+    let upstream_auth = upstream.and_then(|upstream| upstream.auth_header());
+    let client_ip = client_addr.parse::<SocketAddr>().ok().map(|addr| addr.ip());
+    let rewrite = |head: &[u8]| {
+        let mut outgoing =
+            strip_hop_by_hop_headers(head, options.preserve_proxy_connection, upgrade);
+        if options.strip_forwarded {
+            outgoing = strip_forwarded_headers(&outgoing);
+        } else if let Some(client_ip) = client_ip {
+            outgoing = append_forwarded_headers(&outgoing, client_ip);
+        }
+        if !options.remove_request_headers.is_empty() || !options.add_request_headers.is_empty() {
+            outgoing = apply_header_rules(
+                &outgoing,
+                &options.remove_request_headers,
+                &options.add_request_headers,
+            );
+        }
+        if force_close {
+            outgoing = force_connection_close(&outgoing);
+        }
+        if upstream.is_some() {
+            outgoing = to_absolute_form(
+                &outgoing,
+                scheme,
+                &request.host,
+                request.port,
+                upstream_auth.as_deref(),
+            );
+        } else {
+            outgoing = to_origin_form(&outgoing);
+        }
+        outgoing
+    };
+    let head_end = buffer
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map_or(buffer.len(), |pos| pos + 4);
+    let requests = Requests {
+        head: &buffer[..head_end],
+        rest: &buffer[head_end..],
+        rewrite: &rewrite,
+        default_port: if scheme == "https" { 443 } else { 80 },
+        max_header_size: options.max_header_size,
+        upgrade,
+        allowed_methods: options.allowed_methods.as_deref(),
+        options: &options,
+    };
+
+    // 连接到目标服务器（或上游代理）
+    let connect_error = if use_tls {
+        match connector.connect_tls(&request.host, request.port).await {
+            Ok(target_stream) => {
+                debug!(
+                    "[{}] 成功通过TLS连接到目标服务器 {}:{}",
+                    client_addr, request.host, request.port
+                );
+                return match forward_http_request(
+                    client_stream,
+                    target_stream,
+                    &requests,
+                    connector,
+                    client_addr,
+                    force_close,
+                    false,
+                    drop,
+                )
+                .await
+                {
+                    Ok(Forwarded::Done(next)) => Ok(next),
+                    // 未请求重试时不会出现
+                    Ok(Forwarded::Stale(_)) => Ok(None),
+                    Err(e) => {
+                        error!("[{}] HTTP/1.x转发失败: {}", client_addr, e);
+                        Ok(None)
+                    }
+                };
+            }
+            Err(e) => e,
+        }
+    } else {
+        let mut connecting = connector.connect_http(&request.host, request.port).await;
         loop {
-            match client_reader.read(&mut buffer).await {
-                Ok(0) => {
-                    debug!("[{}] HTTP客户端到目标流结束", client_addr);
-                    break;
-                }
-                Ok(n) => {
-                    if let Err(e) = target_writer.write_all(&buffer[..n]).await {
-                        error!("[{}] HTTP客户端到目标写入失败: {}", client_addr, e);
-                        break;
+            let connection = match connecting {
+                Ok(connection) => connection,
+                Err(e) => break e,
+            };
+            debug!(
+                "[{}] 成功连接到目标服务器 {}:{}",
+                client_addr, request.host, request.port
+            );
+            // 池中取出的连接可能已被源站关闭，幂等且没有请求体的请求可在新连接上重试一次
+            let retry_stale = connection.reused && !force_close && retries_on_stale(requests.head);
+            let endpoint = connection.endpoint;
+            client_stream = match forward_http_request(
+                client_stream,
+                connection.stream,
+                &requests,
+                connector,
+                client_addr,
+                force_close,
+                retry_stale,
+                |stream| {
+                    if let Some((host, port)) = &endpoint {
+                        connector.release(host, *port, stream);
                     }
-                }
+                },
+            )
+            .await
+            {
+                Ok(Forwarded::Done(next)) => return Ok(next),
+                Ok(Forwarded::Stale(stream)) => stream,
                 Err(e) => {
-                    error!("[{}] 读取HTTP客户端数据失败: {}", client_addr, e);
-                    break;
+                    error!("[{}] HTTP/1.x转发失败: {}", client_addr, e);
+                    return Ok(None);
                 }
-            }
+            };
+            info!(
+                "[{}] 池中到 {}:{} 的连接已失效，在新连接上重试请求",
+                client_addr, request.host, request.port
+            );
+            connecting = connector.reconnect_http(&request.host, request.port).await;
         }
     };
 
-    let target_to_client = async {
-        let mut buffer = [0u8; 8192];
-        loop {
-            match target_reader.read(&mut buffer).await {
-                Ok(0) => {
-                    debug!("[{}] HTTP目标到客户端流结束", client_addr);
-                    break;
-                }
-                Ok(n) => {
-                    if let Err(e) = client_writer.write_all(&buffer[..n]).await {
-                        error!("[{}] HTTP目标到客户端写入失败: {}", client_addr, e);
-                        break;
+    if is_access_denied(connect_error.as_ref()) {
+        logging::policy_violation(
+            PolicyViolation::BlockedDestination,
+            &format!(
+                "[{}] {}:{}: {}",
+                client_addr, request.host, request.port, connect_error
+            ),
+        );
+        report_rejection(&options, client_addr, &RejectionReason::BlockedDestination);
+        send_error_response(
+            &mut client_stream,
+            "403 Forbidden",
+            &forbidden_message(connect_error.as_ref(), &request.host, request.port),
+            response_version(buffer),
+        )
+        .await?;
+        return Ok(None);
+    }
+    error!(
+        "[{}] 连接目标服务器失败 {}:{}: {}",
+        client_addr, request.host, request.port, connect_error
+    );
+    if is_timeout(connect_error.as_ref()) {
+        send_error_response(
+            &mut client_stream,
+            "504 Gateway Timeout",
+            &format!("连接 {}:{} 超时", request.host, request.port),
+            response_version(buffer),
+        )
+        .await?;
+        return Ok(None);
+    }
+    send_error_response(
+        &mut client_stream,
+        "502 Bad Gateway",
+        &format!("无法连接到 {}:{}", request.host, request.port),
+        response_version(buffer),
+    )
+    .await?;
+    Ok(None)
+}
+
+/// 请求目标是否为 `https://` 开头的绝对URI
+fn is_https_target(buffer: &[u8]) -> bool {
+    let line_end = buffer
+        .windows(2)
+        .position(|w| w == b"\r\n")
+        .unwrap_or(buffer.len());
+    String::from_utf8_lossy(&buffer[..line_end])
+        .split_whitespace()
+        .nth(1)
+        .and_then(|target| target.get(..8))
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case("https://"))
+}
+
+/// 转发HTTP请求及其响应
+///
+/// `force_close` 为真时只转发第一个请求及其响应，随后关闭客户端连接；否则保持客户端连接，
+/// 逐个转发同一连接上的请求（含流水线请求），目标相同的请求复用源站连接。
+/// 下一个请求需要连接其他目标时返回该请求，由调用方重新处理。
+/// 结束时源站连接停在两个请求之间的，交给 `release` 以便复用。
+///
+/// `retry_stale` 为真时，源站未对第一个请求返回任何数据即关闭连接的，
+/// 归还客户端连接（[`Forwarded::Stale`]）以便在新连接上重试；只用于保持连接且没有请求体的请求
+#[allow(clippy::too_many_arguments)]
+async fn forward_http_request<T>(
+    mut client_stream: ClientStream,
+    target_stream: T,
+    requests: &Requests<'_>,
+    connector: &BackendConnector,
+    client_addr: &str,
+    force_close: bool,
+    retry_stale: bool,
+    release: impl FnOnce(T),
+) -> Result<Forwarded, Box<dyn std::error::Error>>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let idle_timeout = connector.idle_timeout();
+
+    // 从经过的响应中记录状态码供访问日志使用
+    let mut target_stream = StatusSniffer::new(target_stream);
+
+    if requests.upgrade {
+        // 源站接受升级后双方改用新协议，第一个请求之后的数据原样双向转发；
+        // 源站拒绝升级时连接按普通HTTP继续，同样原样转发
+        let mut initial = (requests.rewrite)(requests.head);
+        initial.extend_from_slice(requests.rest);
+        let client_stream = PrefetchedStream::new(client_stream, initial);
+        let (sent, received) =
+            relay(client_stream, target_stream, connector.relay_options()).await?;
+        debug!(
+            "[{}] 协议升级连接结束，上行 {} 字节，下行 {} 字节",
+            client_addr, sent, received
+        );
+        return Ok(Forwarded::Done(None));
+    }
+
+    if force_close {
+        // 发送第一个请求头部到目标服务器
+        let head = (requests.rewrite)(requests.head);
+        target_stream.write_all(&head).await?;
+        debug!("[{}] HTTP请求已转发到目标服务器", client_addr);
+
+        let head_request = requests.head.starts_with(b"HEAD ");
+        let body_length = request_body_length(requests.head);
+        let body_written = AtomicU64::new(0);
+        let (sent, received, request_body_sent) = {
+            let (mut target_read, mut target_write) = tokio::io::split(&mut target_stream);
+            let (client_read, mut client_write) = tokio::io::split(&mut client_stream);
+            let mut client_read =
+                BufReader::new(PrefetchedStream::new(client_read, requests.rest.to_vec()));
+
+            // 请求体可能在头部之后继续到达，与响应并行转发；之后的流水线请求不再转发
+            let mut target_write = CountingWriter::new(&mut target_write, &body_written);
+            let upload = copy_body(&mut client_read, &mut target_write, body_length);
+            let download = forward_single_response(
+                &mut target_read,
+                &mut client_write,
+                head_request,
+                idle_timeout,
+                connector.buffer_size(),
+            );
+            tokio::pin!(upload, download);
+
+            // 请求体转发失败时源站仍可能给出响应，记录错误后继续转发响应
+            let mut upload_done = false;
+            let received = loop {
+                tokio::select! {
+                    result = &mut download => break result?,
+                    result = &mut upload, if !upload_done => {
+                        if let Err(e) = result {
+                            info!("[{}] 转发请求体失败: {}", client_addr, e);
+                        }
+                        upload_done = true;
                     }
                 }
-                Err(e) => {
-                    error!("[{}] 读取HTTP目标数据失败: {}", client_addr, e);
-                    break;
+            };
+            let body_sent = body_written.load(Ordering::Relaxed);
+            (head.len() as u64 + body_sent, received, body_sent)
+        };
+        access_log::record_body_bytes(request_body_sent, target_stream.body_bytes());
+        client_stream.shutdown().await?;
+        metrics().record_bytes(sent, received);
+        access_log::record_bytes(received);
+        logging::relay_finished(sent, received);
+        debug!(
+            "[{}] 响应已转发，关闭连接，上行 {} 字节，下行 {} 字节",
+            client_addr, sent, received
+        );
+        return Ok(Forwarded::Done(None));
+    }
+
+    // 保持连接：逐个转发请求及其响应，同一目标的后续请求复用源站连接
+    let tracker = IdleTracker::new();
+    let (client_read, mut client_write) = tokio::io::split(client_stream);
+    let mut client_read =
+        BufReader::new(tracker.track(PrefetchedStream::new(client_read, requests.rest.to_vec())));
+    let (target_read, mut target_write) = tokio::io::split(target_stream);
+    let mut target_read = BufReader::new(tracker.track(target_read));
+
+    let mut transferred = Transferred::default();
+    let result = tokio::select! {
+        result = serve_requests(
+            &mut client_read,
+            &mut client_write,
+            &mut target_read,
+            &mut target_write,
+            requests,
+            &mut transferred,
+            client_addr,
+            retry_stale,
+        ) => result,
+        _ = tracker.idle(idle_timeout) => {
+            info!("[{}] HTTP连接空闲超时，关闭连接", client_addr);
+            Ok(Served::Closed)
+        }
+    };
+    if let Ok(Served::Stale) = result {
+        // 客户端连接还没有读写过，原样归还
+        let (client_read, _) = client_read.into_inner().into_inner().into_parts();
+        return Ok(Forwarded::Stale(client_read.unsplit(client_write)));
+    }
+    metrics().record_bytes(transferred.sent, transferred.received);
+    logging::relay_finished(transferred.sent, transferred.received);
+    debug!(
+        "[{}] HTTP连接结束，上行 {} 字节，下行 {} 字节",
+        client_addr, transferred.sent, transferred.received
+    );
+
+    let served = result?;
+    if !matches!(served, Served::Closed) && target_read.buffer().is_empty() {
+        let target_read = target_read.into_inner().into_inner();
+        release(target_read.unsplit(target_write).into_inner());
+    }
+    let head = match served {
+        Served::Handoff(head) => head,
+        Served::Closed | Served::ClientClosed | Served::Stale => {
+            let _ = client_write.shutdown().await;
+            return Ok(Forwarded::Done(None));
+        }
+    };
+    // 下一个请求交给调用方处理，连同已读到的数据一起归还客户端连接
+    let mut buffer = head;
+    buffer.extend_from_slice(client_read.buffer());
+    let (client_read, prefetched) = client_read.into_inner().into_inner().into_parts();
+    buffer.extend_from_slice(&prefetched);
+    Ok(Forwarded::Done(Some(NextRequest {
+        stream: client_read.unsplit(client_write),
+        buffer,
+    })))
+}
+
+/// 转发一个客户端连接上请求的结果
+enum Forwarded {
+    /// 转发结束，可能带有需要换用其他源站连接处理的下一个请求
+    Done(Option<NextRequest>),
+    /// 池中取出的源站连接已失效，第一个请求没有得到任何响应，归还尚未读写的客户端连接
+    Stale(ClientStream),
+}
+
+/// 池中取出的源站连接失效时能否在新连接上重试：幂等方法且没有请求体
+///
+/// 请求体转发时即从客户端读出，无法重放，因此带请求体的请求不重试
+fn retries_on_stale(head: &[u8]) -> bool {
+    const IDEMPOTENT: [&str; 6] = ["GET", "HEAD", "PUT", "DELETE", "OPTIONS", "TRACE"];
+    request_method(head).is_some_and(|method| IDEMPOTENT.contains(&method))
+        && request_body_length(head) == BodyLength::Empty
+}
+
+/// 源站连接在请求发出后出现的、表明连接早已被对端关闭的错误
+fn is_stale_error(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe
+    )
+}
+
+/// 保持连接时双向转发的字节数
+#[derive(Debug, Default)]
+struct Transferred {
+    /// 写往源站的字节数
+    sent: u64,
+    /// 写给客户端的字节数
+    received: u64,
+}
+
+/// 保持的客户端连接上转发结束的方式
+enum Served {
+    /// 连接需要关闭，源站连接不再复用
+    Closed,
+    /// 客户端在两个请求之间关闭了连接
+    ClientClosed,
+    /// 下一个请求需要连接其他目标，返回其头部
+    Handoff(Vec<u8>),
+    /// 源站未对第一个请求返回任何数据即关闭连接，请求可在新连接上重试
+    Stale,
+}
+
+/// 在保持的客户端连接上逐个转发请求及其响应
+///
+/// 从第一个请求开始，请求体与响应并行转发；响应结束后读取下一个请求，能在当前源站连接上
+/// 转发时（见 [`continues_on_target`]）继续，否则返回其头部交由调用方处理。
+/// 除客户端关闭连接与交由调用方处理外，源站连接都不再复用。
+///
+/// `retry_stale` 为真时先等到源站对第一个请求返回数据再开始转发，
+/// 源站未返回任何数据即关闭连接时返回 [`Served::Stale`]，此时客户端连接还没有读写过
+#[allow(clippy::too_many_arguments)]
+async fn serve_requests<CR, CW, TR, TW>(
+    client_read: &mut CR,
+    client_write: &mut CW,
+    target_read: &mut TR,
+    target_write: &mut TW,
+    requests: &Requests<'_>,
+    transferred: &mut Transferred,
+    client_addr: &str,
+    mut retry_stale: bool,
+) -> io::Result<Served>
+where
+    CR: AsyncBufRead + Unpin,
+    CW: AsyncWrite + Unpin,
+    TR: AsyncBufRead + Unpin,
+    TW: AsyncWrite + Unpin,
+{
+    let target = pipelined_target(requests.head, requests.default_port);
+    let mut head = requests.head.to_vec();
+    loop {
+        let outgoing = (requests.rewrite)(&head);
+        if retry_stale {
+            retry_stale = false;
+            let answered = async {
+                target_write.write_all(&outgoing).await?;
+                target_write.flush().await?;
+                Ok::<_, io::Error>(!target_read.fill_buf().await?.is_empty())
+            };
+            match answered.await {
+                Ok(true) => {}
+                Ok(false) => return Ok(Served::Stale),
+                Err(e) if is_stale_error(&e) => return Ok(Served::Stale),
+                Err(e) => return Err(e),
+            }
+        } else {
+            target_write.write_all(&outgoing).await?;
+        }
+        transferred.sent += outgoing.len() as u64;
+        debug!("[{}] HTTP请求已转发到目标服务器", client_addr);
+
+        let body_length = request_body_length(&head);
+        let head_request = head.starts_with(b"HEAD ");
+        let (response, uploaded) = {
+            let upload = async {
+                let copied = copy_body(client_read, target_write, body_length).await?;
+                target_write.flush().await?;
+                Ok::<_, io::Error>(copied)
+            };
+            let download = forward_response(target_read, client_write, head_request);
+            tokio::pin!(upload, download);
+
+            let mut uploaded = None;
+            loop {
+                tokio::select! {
+                    result = &mut download => break (result?, uploaded),
+                    result = &mut upload, if uploaded.is_none() => uploaded = Some(result?),
                 }
             }
+        };
+        transferred.received += response.head_bytes + response.body_bytes;
+        access_log::record_status(response.status);
+        access_log::record_bytes(response.head_bytes + response.body_bytes);
+        access_log::record_body_bytes(uploaded.unwrap_or(0), response.body_bytes);
+        client_write.flush().await?;
+        let uploaded = match uploaded {
+            Some(uploaded) => uploaded,
+            None => {
+                // 源站未读完请求体就已响应，无从确定下一个请求的起点
+                debug!("[{}] 响应先于请求体结束，关闭连接", client_addr);
+                return Ok(Served::Closed);
+            }
+        };
+        transferred.sent += uploaded;
+        // 按发往源站的头部判断，`Proxy-Connection` 已改写为 `Connection`
+        if !response.keep_alive || !keeps_alive(&outgoing) {
+            return Ok(Served::Closed);
+        }
+
+        // 等待下一个请求；源站在此期间关闭连接或发来多余数据时不再复用
+        let next = tokio::select! {
+            biased;
+            next = read_head(client_read, requests.max_header_size) => next?,
+            _ = target_read.fill_buf() => {
+                debug!("[{}] 源站关闭了空闲连接", client_addr);
+                return Ok(Served::Closed);
+            }
+        };
+        head = match next {
+            Some(head) => head,
+            None => return Ok(Served::ClientClosed),
+        };
+        // 每个请求单独写一条访问记录，复用源站连接时沿用上一个请求的目标
+        let continues = continues_on_target(&head, requests, &target);
+        access_log::next_request(continues);
+        if let Some(method) = request_method(&head) {
+            access_log::record_method(method);
+        }
+        // 每个请求都要通过逐请求检查，无论是否复用源站连接
+        if let Some((reason, status, message)) = request_policy_violation(&head, requests.options) {
+            info!("[{}] 后续请求被拒绝: {}", client_addr, reason);
+            report_rejection(requests.options, client_addr, &reason);
+            if send_error_response(client_write, status, message, response_version(&head))
+                .await
+                .is_ok()
+            {
+                client_write.flush().await?;
+            }
+            return Ok(Served::Closed);
+        }
+        if !continues {
+            return Ok(Served::Handoff(head));
+        }
+        debug!("[{}] 复用源站连接转发下一个请求", client_addr);
+    }
+}
+
+/// 转发的一个响应
+struct Response {
+    /// 最终响应（1xx中间响应之后）的状态码
+    status: u16,
+    head_bytes: u64,
+    body_bytes: u64,
+    /// 响应之后源站连接可以继续使用
+    keep_alive: bool,
+}
+
+/// 转发一个响应及其之前的1xx中间响应
+async fn forward_response<R, W>(
+    target: &mut R,
+    client: &mut W,
+    head_request: bool,
+) -> io::Result<Response>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut head_bytes = 0;
+    loop {
+        let head = match read_head(target, DEFAULT_MAX_HEADER_SIZE).await? {
+            Some(head) => head,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "源站未返回响应即关闭连接",
+                ))
+            }
+        };
+        client.write_all(&head).await?;
+        head_bytes += head.len() as u64;
+
+        let status = access_log::parse_status(&head).unwrap_or(200);
+        if (100..200).contains(&status) && status != 101 {
+            continue;
+        }
+        let length = if head_request {
+            BodyLength::Empty
+        } else {
+            response_body_length(&head)
+        };
+        let body_bytes = copy_body(target, client, length).await?;
+        return Ok(Response {
+            status,
+            head_bytes,
+            body_bytes,
+            keep_alive: status != 101 && length != BodyLength::UntilClose && keeps_alive(&head),
+        });
+    }
+}
+
+/// 通知 [`Http1Options::on_rejection`] 回调请求被拒绝
+fn report_rejection(options: &Http1Options, client_addr: &str, reason: &RejectionReason) {
+    if let (Some(callback), Ok(client)) = (&options.on_rejection, client_addr.parse()) {
+        callback(client, reason);
+    }
+}
+
+/// 按 `strict_headers` 与 `require_user_agent` 检查一个请求的头部
+///
+/// 连接上的每个请求都经过该检查；违反时返回拒绝原因、响应状态和提示
+pub(crate) fn request_policy_violation(
+    head: &[u8],
+    options: &Http1Options,
+) -> Option<(RejectionReason, &'static str, &'static str)> {
+    if options.strict_headers && invalid_header_byte(head).is_some() {
+        let message = "请求头包含非法字节";
+        return Some((
+            RejectionReason::BadRequest(message.to_string()),
+            "400 Bad Request",
+            message,
+        ));
+    }
+    if options.require_user_agent
+        && extract_header(head, "User-Agent").is_none_or(|ua| ua.is_empty())
+    {
+        return Some((
+            RejectionReason::MissingUserAgent,
+            "403 Forbidden",
+            "缺少User-Agent",
+        ));
+    }
+    None
+}
+
+/// 后续请求能否在当前源站连接上转发：目标与第一个请求相同、方法允许且不是协议升级
+fn continues_on_target(
+    head: &[u8],
+    requests: &Requests<'_>,
+    target: &Option<(String, u16)>,
+) -> bool {
+    if target.is_none() || pipelined_target(head, requests.default_port) != *target {
+        return false;
+    }
+    if extract_header(head, "upgrade").is_some() {
+        return false;
+    }
+    let method = head.split(|&b| b == b' ').next().unwrap_or_default();
+    requests
+        .allowed_methods
+        .is_none_or(|allowed| allowed.contains(String::from_utf8_lossy(method).as_ref()))
+}
+
+/// 流水线请求的目标主机和端口，CONNECT和无法确定目标的请求返回 `None`
+fn pipelined_target(head: &[u8], default_port: u16) -> Option<(String, u16)> {
+    let request = parse_http_request(head, default_port)?;
+    if request.method.eq_ignore_ascii_case("CONNECT") || request.host.is_empty() {
+        return None;
+    }
+    Some((request.host, request.port))
+}
+
+/// 转发单个响应并改写为连接关闭语义
+///
+/// 响应体按 `Content-Length` 转发；未声明长度或使用分块编码时转发到源站关闭为止。
+/// 返回写给客户端的字节数
+async fn forward_single_response<R, W>(
+    target: &mut R,
+    client: &mut W,
+    head_request: bool,
+    idle_timeout: Option<Duration>,
+    buffer_size: usize,
+) -> io::Result<u64>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let head = match idle_timeout {
+        Some(limit) => tokio::time::timeout(
+            limit,
+            read_http_head(target, DEFAULT_MAX_HEADER_SIZE, DEFAULT_INITIAL_READ_SIZE),
+        )
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "连接空闲超时"))??,
+        None => read_http_head(target, DEFAULT_MAX_HEADER_SIZE, DEFAULT_INITIAL_READ_SIZE).await?,
+    };
+    let (head, rest) = match head {
+        HeadRead::Complete { head, rest } => (head, rest),
+        HeadRead::TooLarge => {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "响应头部过大"))
+        }
+        HeadRead::Closed(data) => {
+            client.write_all(&data).await?;
+            return Ok(data.len() as u64);
         }
     };
 
-    tokio::select! {
-        _ = client_to_target => {
-            debug!("[{}] HTTP客户端到目标连接结束", client_addr);
+    let (head, body_length) = close_delimited_head(&head);
+    let body_length = if head_request { Some(0) } else { body_length };
+    client.write_all(&head).await?;
+
+    let rest = match body_length {
+        Some(length) => &rest[..rest.len().min(length as usize)],
+        None => &rest[..],
+    };
+    client.write_all(rest).await?;
+    let mut written = (head.len() + rest.len()) as u64;
+    let mut remaining = body_length.map(|length| length - rest.len() as u64);
+
+    let mut buffer = vec![0u8; buffer_size];
+    while remaining != Some(0) {
+        let limit = remaining.map_or(buffer.len(), |r| r.min(buffer.len() as u64) as usize);
+        let read = target.read(&mut buffer[..limit]);
+        let n = match idle_timeout {
+            Some(limit) => tokio::time::timeout(limit, read)
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "连接空闲超时"))??,
+            None => read.await?,
+        };
+        if n == 0 {
+            break;
         }
-        _ = target_to_client => {
-            debug!("[{}] HTTP目标到客户端连接结束", client_addr);
+        client.write_all(&buffer[..n]).await?;
+        written += n as u64;
+        if let Some(r) = remaining.as_mut() {
+            *r -= n as u64;
         }
     }
 
-    Ok(())
+    Ok(written)
+}
+
+/// 将响应头部的连接管理头部替换为 `Connection: close`
+///
+/// 返回改写后的头部以及响应体长度，1xx/204/304无响应体，
+/// 分块编码或未声明长度时返回 `None`
+fn close_delimited_head(head: &[u8]) -> (Vec<u8>, Option<u64>) {
+    let text = String::from_utf8_lossy(head);
+    let mut lines = text.trim_end_matches("\r\n").split("\r\n");
+    let status_line = lines.next().unwrap_or_default();
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .unwrap_or(200);
+
+    let mut output = String::with_capacity(head.len() + 19);
+    output.push_str(status_line);
+    output.push_str("\r\n");
+
+    let mut content_length = None;
+    let mut chunked = false;
+    for line in lines {
+        let (name, value) = line.split_once(':').unwrap_or((line, ""));
+        let name = name.trim().to_ascii_lowercase();
+        match name.as_str() {
+            "connection" | "keep-alive" | "proxy-connection" => continue,
+            "content-length" => content_length = value.trim().parse::<u64>().ok(),
+            "transfer-encoding" => chunked = true,
+            _ => {}
+        }
+        output.push_str(line);
+        output.push_str("\r\n");
+    }
+    output.push_str("Connection: close\r\n\r\n");
+
+    let body_length = if (100..200).contains(&status) || status == 204 || status == 304 {
+        Some(0)
+    } else if chunked {
+        None
+    } else {
+        content_length
+    };
+    (output.into_bytes(), body_length)
+}
+
+/// 将请求的连接管理头部替换为 `Connection: close`
+fn force_connection_close(buffer: &[u8]) -> Vec<u8> {
+    let head_end = match buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(pos) => pos + 2,
+        None => return buffer.to_vec(),
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..head_end]);
+    let mut output = String::with_capacity(head_end + 19);
+    for line in head.split_terminator("\r\n") {
+        let name = line.split_once(':').map(|(key, _)| key.trim());
+        if name.is_some_and(|name| name.eq_ignore_ascii_case("connection")) {
+            continue;
+        }
+        output.push_str(line);
+        output.push_str("\r\n");
+    }
+    output.push_str("Connection: close\r\n");
+
+    let mut request = output.into_bytes();
+    request.extend_from_slice(&buffer[head_end..]);
+    request
 }
 
 /// 解析HTTP请求
-fn parse_http_request(buffer: &[u8]) -> Option<HttpRequest> {
-    let request = String::from_utf8_lossy(buffer);
+///
+/// 请求头按原始字节中的 `\r\n\r\n` 划分，之后的字节原样作为body，不受body内容影响
+fn parse_http_request(buffer: &[u8], default_port: u16) -> Option<HttpRequest> {
+    let body_start = buffer.windows(4).position(|w| w == b"\r\n\r\n")? + 4;
+    let request = String::from_utf8_lossy(&buffer[..body_start]);
     let lines: Vec<&str> = request.lines().collect();
 
     if lines.is_empty() {
@@ -167,24 +1214,31 @@ fn parse_http_request(buffer: &[u8]) -> Option<HttpRequest> {
 
     // 提取Host头
     let mut host = String::new();
-    let mut port = 80u16;
+    let mut port = default_port;
 
     for line in &lines {
         if line.to_lowercase().starts_with("host:") {
             let host_value = line[5..].trim();
-            if let Some(colon_pos) = host_value.find(':') {
-                host = host_value[..colon_pos].to_string();
-                port = host_value[colon_pos + 1..]
-                    .trim()
-                    .parse::<u16>()
-                    .unwrap_or(80);
-            } else {
-                host = host_value.to_string();
+            if let Some((h, p)) = parse_authority(host_value, Some(default_port)) {
+                host = h;
+                port = p;
             }
             break;
         }
     }
 
+    // 没有Host头时从绝对URI中取目标
+    if host.is_empty() {
+        let authority = full_path
+            .strip_prefix("http://")
+            .or_else(|| full_path.strip_prefix("https://"))
+            .map(|rest| rest.split('/').next().unwrap_or(rest));
+        if let Some((h, p)) = authority.and_then(|a| parse_authority(a, Some(default_port))) {
+            host = h;
+            port = p;
+        }
+    }
+
     // 解析路径（如果有完整URL则提取路径部分）
     let path = if full_path.starts_with("http://") || full_path.starts_with("https://") {
         let start_idx = if full_path.starts_with("http://") {
@@ -214,13 +1268,6 @@ fn parse_http_request(buffer: &[u8]) -> Option<HttpRequest> {
         }
     }
 
-    // 提取body
-    let empty_line_idx = lines.iter().position(|l| l.is_empty())?;
-    let body_start = request
-        .lines()
-        .take(empty_line_idx + 1)
-        .map(|l| l.len() + 2)
-        .sum::<usize>();
     let body: Vec<u8> = buffer[body_start..].to_vec();
 
     Some(HttpRequest {
@@ -232,3 +1279,565 @@ fn parse_http_request(buffer: &[u8]) -> Option<HttpRequest> {
         body,
     })
 }
+
+/// 转发前需要移除的逐跳头部（RFC 7230 第6.1节）
+///
+/// `Transfer-Encoding` 虽属逐跳头部，但请求体按原样转发，必须保留以维持分块编码的帧格式
+const HOP_BY_HOP_HEADERS: &[&str] = &[
+    "proxy-authorization",
+    "proxy-connection",
+    "keep-alive",
+    "te",
+    "trailer",
+    "upgrade",
+];
+
+/// 移除请求头部中的逐跳头部
+///
+/// 除固定列表外，`Connection` 头中列出的字段也会被移除；`Connection` 本身仅保留
+/// `close`/`keep-alive` 语义。旧客户端只发送非标准的 `Proxy-Connection` 时，
+/// 其 `close`/`keep-alive` 语义改写为 `Connection` 发往源站；`preserve_proxy_connection`
+/// 为真时改为原样转发 `Proxy-Connection`。`upgrade` 为真时保留 `Upgrade` 头、`Connection`
+/// 头中的全部字段及其列出的头部，使协议升级请求到达源站。头部之后的数据原样保留。
+pub(super) fn strip_hop_by_hop_headers(
+    buffer: &[u8],
+    preserve_proxy_connection: bool,
+    upgrade: bool,
+) -> Vec<u8> {
+    let head_end = match buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(pos) => pos + 4,
+        None => return buffer.to_vec(),
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..head_end - 4]);
+    let mut lines = head.split("\r\n");
+    let request_line = lines.next().unwrap_or_default();
+    let headers: Vec<&str> = lines.collect();
+
+    // 收集Connection（或Proxy-Connection）头中列出的字段
+    let header_tokens = |header: &str| -> Vec<String> {
+        headers
+            .iter()
+            .filter_map(|line| line.split_once(':'))
+            .filter(|(key, _)| key.trim().eq_ignore_ascii_case(header))
+            .flat_map(|(_, value)| value.split(','))
+            .map(|token| token.trim().to_ascii_lowercase())
+            .filter(|token| !token.is_empty())
+            .collect()
+    };
+    let connection_tokens = header_tokens("connection");
+    let is_connection_option = |token: &&String| *token == "close" || *token == "keep-alive";
+
+    // 没有Connection头时沿用Proxy-Connection表达的连接语义
+    let connection_option = match connection_tokens.iter().find(is_connection_option) {
+        Some(token) => Some(token.clone()),
+        None if !preserve_proxy_connection => header_tokens("proxy-connection")
+            .iter()
+            .find(is_connection_option)
+            .cloned(),
+        None => None,
+    };
+
+    let mut output = String::with_capacity(head_end);
+    output.push_str(request_line);
+    output.push_str("\r\n");
+
+    for line in headers {
+        let name = line
+            .split_once(':')
+            .map(|(key, _)| key.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+
+        if name == "connection" {
+            continue;
+        }
+        if preserve_proxy_connection && name == "proxy-connection" {
+            output.push_str(line);
+            output.push_str("\r\n");
+            continue;
+        }
+        if upgrade && (name == "upgrade" || connection_tokens.contains(&name)) {
+            output.push_str(line);
+            output.push_str("\r\n");
+            continue;
+        }
+        if HOP_BY_HOP_HEADERS.contains(&name.as_str()) || connection_tokens.contains(&name) {
+            continue;
+        }
+
+        output.push_str(line);
+        output.push_str("\r\n");
+    }
+
+    // 保留连接管理语义
+    if upgrade {
+        output.push_str("Connection: ");
+        output.push_str(&connection_tokens.join(", "));
+        output.push_str("\r\n");
+    } else if let Some(token) = connection_option {
+        output.push_str("Connection: ");
+        output.push_str(&token);
+        output.push_str("\r\n");
+    }
+    output.push_str("\r\n");
+
+    let mut request = output.into_bytes();
+    request.extend_from_slice(&buffer[head_end..]);
+    request
+}
+
+/// 记录客户端之前经过的代理的头部
+const FORWARDED_HEADERS: &[&str] = &["via", "x-forwarded-for", "forwarded"];
+
+/// 移除请求头部中记录之前代理跳数的头部，使源站无从得知客户端经过的代理链
+fn strip_forwarded_headers(buffer: &[u8]) -> Vec<u8> {
+    let head_end = match buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(pos) => pos + 2,
+        None => return buffer.to_vec(),
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..head_end]);
+    let mut output = String::with_capacity(head_end);
+    for line in head.split_terminator("\r\n") {
+        let name = line
+            .split_once(':')
+            .map(|(key, _)| key.trim().to_ascii_lowercase());
+        if name.is_some_and(|name| FORWARDED_HEADERS.contains(&name.as_str())) {
+            continue;
+        }
+        output.push_str(line);
+        output.push_str("\r\n");
+    }
+
+    let mut request = output.into_bytes();
+    request.extend_from_slice(&buffer[head_end..]);
+    request
+}
+
+/// 写入 `Via` 头的本代理名称
+const VIA_PSEUDONYM: &str = "rust-proxy";
+
+/// 追加本代理的转发记录：`X-Forwarded-For` 末尾加上客户端IP，`Via` 末尾加上本跳
+///
+/// 客户端发送的多个同名头部按顺序合并为一个，保留之前经过的代理链
+fn append_forwarded_headers(buffer: &[u8], client_ip: IpAddr) -> Vec<u8> {
+    edit_headers(buffer, |request_line, headers| {
+        let version = request_line
+            .rsplit_once("HTTP/")
+            .map_or("1.1", |(_, version)| version.trim());
+        let forwarded_for = take_header(headers, "x-forwarded-for");
+        let via = take_header(headers, "via");
+        headers.push(format!(
+            "X-Forwarded-For: {}",
+            append_hop(forwarded_for, &client_ip.to_string())
+        ));
+        headers.push(format!(
+            "Via: {}",
+            append_hop(via, &format!("{} {}", version, VIA_PSEUDONYM))
+        ));
+    })
+}
+
+/// 移除 `remove` 中列出的请求头部，再设置 `add` 中的头部，名称不区分大小写
+///
+/// 设置的头部替换客户端发送的同名头部
+fn apply_header_rules(buffer: &[u8], remove: &[String], add: &[(String, String)]) -> Vec<u8> {
+    edit_headers(buffer, |_, headers| {
+        headers.retain(|line| {
+            let name = header_name(line);
+            !remove
+                .iter()
+                .any(|removed| removed.eq_ignore_ascii_case(&name))
+                && !add
+                    .iter()
+                    .any(|(added, _)| added.eq_ignore_ascii_case(&name))
+        });
+        for (name, value) in add {
+            headers.push(format!("{}: {}", name, value));
+        }
+    })
+}
+
+/// 将请求头部拆分为请求行与各头部行，交给 `edit` 修改头部行后重新拼接
+///
+/// 头部之后的数据保持不变；找不到头部结束符时原样返回
+fn edit_headers(buffer: &[u8], edit: impl FnOnce(&str, &mut Vec<String>)) -> Vec<u8> {
+    let head_end = match buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(pos) => pos + 2,
+        None => return buffer.to_vec(),
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..head_end]);
+    let mut lines = head.split_terminator("\r\n");
+    let request_line = lines.next().unwrap_or_default();
+    let mut headers: Vec<String> = lines.map(str::to_string).collect();
+    edit(request_line, &mut headers);
+
+    let mut output = String::with_capacity(head_end);
+    output.push_str(request_line);
+    output.push_str("\r\n");
+    for line in &headers {
+        output.push_str(line);
+        output.push_str("\r\n");
+    }
+    let mut request = output.into_bytes();
+    request.extend_from_slice(&buffer[head_end..]);
+    request
+}
+
+/// 头部行的小写名称，不是 `名称: 值` 形式时为空
+fn header_name(line: &str) -> String {
+    line.split_once(':')
+        .map(|(key, _)| key.trim().to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+/// 移除名称为 `name`（小写）的全部头部，返回以 `, ` 连接的非空值
+fn take_header(headers: &mut Vec<String>, name: &str) -> String {
+    let mut values = Vec::new();
+    headers.retain(|line| {
+        if header_name(line) != name {
+            return true;
+        }
+        if let Some((_, value)) = line.split_once(':') {
+            if !value.trim().is_empty() {
+                values.push(value.trim().to_string());
+            }
+        }
+        false
+    });
+    values.join(", ")
+}
+
+/// 在以逗号分隔的转发链末尾加上一跳
+fn append_hop(chain: String, hop: &str) -> String {
+    if chain.is_empty() {
+        hop.to_string()
+    } else {
+        format!("{}, {}", chain, hop)
+    }
+}
+
+/// 将请求行改写为绝对URI形式，并按需附加 `Proxy-Authorization` 头
+///
+/// 用于把明文HTTP请求转发给上游代理；已是绝对形式的请求目标保持不变
+fn to_absolute_form(
+    buffer: &[u8],
+    scheme: &str,
+    host: &str,
+    port: u16,
+    proxy_auth: Option<&str>,
+) -> Vec<u8> {
+    let line_end = match buffer.windows(2).position(|w| w == b"\r\n") {
+        Some(pos) => pos,
+        None => return buffer.to_vec(),
+    };
+
+    let request_line = String::from_utf8_lossy(&buffer[..line_end]);
+    let parts: Vec<&str> = request_line.split_whitespace().collect();
+    let mut output = if parts.len() == 3 && parts[1].starts_with('/') {
+        let default_port = if scheme == "https" { 443 } else { 80 };
+        // IPv6地址需要加方括号才能构成合法的authority
+        let host = if host.contains(':') {
+            format!("[{}]", host)
+        } else {
+            host.to_string()
+        };
+        let authority = if port == default_port {
+            host
+        } else {
+            format!("{}:{}", host, port)
+        };
+        format!(
+            "{} {}://{}{} {}",
+            parts[0], scheme, authority, parts[1], parts[2]
+        )
+        .into_bytes()
+    } else {
+        buffer[..line_end].to_vec()
+    };
+    output.extend_from_slice(b"\r\n");
+
+    if let Some(auth) = proxy_auth {
+        output.extend_from_slice(format!("Proxy-Authorization: {}\r\n", auth).as_bytes());
+    }
+    output.extend_from_slice(&buffer[line_end + 2..]);
+    output
+}
+
+/// 将绝对形式的请求目标改写为源站形式（RFC 7230 第5.3.1节）
+///
+/// 支持 `http`/`https` 以及WebSocket的 `ws`/`wss` 方案。请求没有 `Host` 头时按URI中的
+/// authority补上；源站形式、`*` 等其他形式保持不变
+pub(super) fn to_origin_form(buffer: &[u8]) -> Vec<u8> {
+    let line_end = match buffer.windows(2).position(|w| w == b"\r\n") {
+        Some(pos) => pos,
+        None => return buffer.to_vec(),
+    };
+
+    let request_line = String::from_utf8_lossy(&buffer[..line_end]);
+    let parts: Vec<&str> = request_line.split_whitespace().collect();
+    if parts.len() != 3 {
+        return buffer.to_vec();
+    }
+    let rest = match ["http://", "https://", "ws://", "wss://"]
+        .iter()
+        .find_map(|prefix| {
+            parts[1]
+                .get(..prefix.len())
+                .filter(|scheme| scheme.eq_ignore_ascii_case(prefix))
+                .map(|_| &parts[1][prefix.len()..])
+        }) {
+        Some(rest) => rest,
+        None => return buffer.to_vec(),
+    };
+
+    let (authority, path) = match rest.find(['/', '?']) {
+        Some(pos) => rest.split_at(pos),
+        None => (rest, ""),
+    };
+    let authority = authority.rsplit('@').next().unwrap_or(authority);
+    let path = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("/{}", path)
+    };
+
+    let mut output = format!("{} {} {}\r\n", parts[0], path, parts[2]).into_bytes();
+    let headers = &buffer[line_end + 2..];
+    let has_host = String::from_utf8_lossy(headers)
+        .split("\r\n")
+        .take_while(|line| !line.is_empty())
+        .any(|line| {
+            line.split_once(':')
+                .is_some_and(|(name, _)| name.trim().eq_ignore_ascii_case("host"))
+        });
+    if !has_host {
+        output.extend_from_slice(format!("Host: {}\r\n", authority).as_bytes());
+    }
+    output.extend_from_slice(headers);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_hop_by_hop_headers() {
+        let buffer = b"POST http://example.com/upload HTTP/1.1\r\n\
+            Host: example.com\r\n\
+            Proxy-Authorization: Basic dGVzdDp0ZXN0\r\n\
+            Proxy-Connection: keep-alive\r\n\
+            Connection: X-Custom, close\r\n\
+            X-Custom: secret\r\n\
+            Keep-Alive: timeout=5\r\n\
+            TE: trailers\r\n\
+            Transfer-Encoding: chunked\r\n\
+            Accept: */*\r\n\r\n\
+            5\r\nhello\r\n0\r\n\r\n";
+
+        let stripped = strip_hop_by_hop_headers(buffer, false, false);
+        assert_eq!(
+            String::from_utf8(stripped).unwrap(),
+            "POST http://example.com/upload HTTP/1.1\r\n\
+            Host: example.com\r\n\
+            Transfer-Encoding: chunked\r\n\
+            Accept: */*\r\n\
+            Connection: close\r\n\r\n\
+            5\r\nhello\r\n0\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_proxy_connection_rewritten() {
+        let buffer = b"GET http://example.com/ HTTP/1.0\r\n\
+            Host: example.com\r\n\
+            Proxy-Connection: Keep-Alive\r\n\r\n";
+
+        assert_eq!(
+            String::from_utf8(strip_hop_by_hop_headers(buffer, false, false)).unwrap(),
+            "GET http://example.com/ HTTP/1.0\r\nHost: example.com\r\nConnection: keep-alive\r\n\r\n"
+        );
+        assert_eq!(
+            String::from_utf8(strip_hop_by_hop_headers(buffer, true, false)).unwrap(),
+            String::from_utf8_lossy(buffer)
+        );
+    }
+
+    #[test]
+    fn test_strip_hop_by_hop_keeps_upgrade() {
+        let buffer = b"GET / HTTP/1.1\r\n\
+            Host: example.com\r\n\
+            Proxy-Authorization: Basic dGVzdDp0ZXN0\r\n\
+            Connection: Upgrade, HTTP2-Settings\r\n\
+            Upgrade: h2c\r\n\
+            HTTP2-Settings: AAMAAABkAAQAAP__\r\n\
+            \r\n";
+        assert_eq!(
+            String::from_utf8(strip_hop_by_hop_headers(buffer, false, true)).unwrap(),
+            "GET / HTTP/1.1\r\nHost: example.com\r\nUpgrade: h2c\r\n\
+             HTTP2-Settings: AAMAAABkAAQAAP__\r\nConnection: upgrade, http2-settings\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_strip_forwarded_headers() {
+        let buffer = b"GET / HTTP/1.1\r\n\
+            Host: example.com\r\n\
+            Via: 1.1 edge\r\n\
+            x-forwarded-for: 10.0.0.1\r\n\
+            Forwarded: for=10.0.0.1\r\n\
+            X-Forwarded-Proto: https\r\n\
+            \r\nbody";
+        let stripped = strip_forwarded_headers(buffer);
+        assert_eq!(
+            String::from_utf8(stripped).unwrap(),
+            "GET / HTTP/1.1\r\nHost: example.com\r\nX-Forwarded-Proto: https\r\n\r\nbody"
+        );
+    }
+
+    #[test]
+    fn test_append_forwarded_headers() {
+        let buffer = b"GET / HTTP/1.0\r\n\
+            Host: example.com\r\n\
+            X-Forwarded-For: 10.0.0.1\r\n\
+            Via: 1.1 edge\r\n\
+            x-forwarded-for: 10.0.0.2\r\n\
+            \r\nbody";
+        let appended = append_forwarded_headers(buffer, "192.0.2.7".parse().unwrap());
+        assert_eq!(
+            String::from_utf8(appended).unwrap(),
+            "GET / HTTP/1.0\r\nHost: example.com\r\n\
+             X-Forwarded-For: 10.0.0.1, 10.0.0.2, 192.0.2.7\r\n\
+             Via: 1.1 edge, 1.0 rust-proxy\r\n\r\nbody"
+        );
+
+        let appended = append_forwarded_headers(
+            b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n",
+            "2001:db8::1".parse().unwrap(),
+        );
+        assert_eq!(
+            String::from_utf8(appended).unwrap(),
+            "GET / HTTP/1.1\r\nHost: example.com\r\n\
+             X-Forwarded-For: 2001:db8::1\r\nVia: 1.1 rust-proxy\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_apply_header_rules() {
+        let buffer = b"GET / HTTP/1.1\r\n\
+            Host: example.com\r\n\
+            Cookie: a=1\r\n\
+            x-env: prod\r\n\
+            Accept: */*\r\n\
+            \r\nbody";
+        let rewritten = apply_header_rules(
+            buffer,
+            &["COOKIE".to_string()],
+            &[("X-Env".to_string(), "staging".to_string())],
+        );
+        assert_eq!(
+            String::from_utf8(rewritten).unwrap(),
+            "GET / HTTP/1.1\r\nHost: example.com\r\nAccept: */*\r\nX-Env: staging\r\n\r\nbody"
+        );
+    }
+
+    #[test]
+    fn test_to_origin_form() {
+        let rewritten = to_origin_form(
+            b"GET http://example.com:8080/path?q=1 HTTP/1.1\r\nHost: example.com:8080\r\n\r\nbody",
+        );
+        assert_eq!(
+            String::from_utf8(rewritten).unwrap(),
+            "GET /path?q=1 HTTP/1.1\r\nHost: example.com:8080\r\n\r\nbody"
+        );
+
+        // 没有Host头的HTTP/1.0请求按URI补上Host
+        let rewritten = to_origin_form(b"GET HTTPS://user@example.com?q=1 HTTP/1.0\r\n\r\n");
+        assert_eq!(
+            String::from_utf8(rewritten).unwrap(),
+            "GET /?q=1 HTTP/1.0\r\nHost: example.com\r\n\r\n"
+        );
+
+        // WebSocket升级请求的ws/wss目标
+        let rewritten =
+            to_origin_form(b"GET wss://example.com/chat HTTP/1.1\r\nHost: example.com\r\n\r\n");
+        assert_eq!(
+            String::from_utf8(rewritten).unwrap(),
+            "GET /chat HTTP/1.1\r\nHost: example.com\r\n\r\n"
+        );
+
+        let origin_form = b"OPTIONS * HTTP/1.1\r\nHost: example.com\r\n\r\n";
+        assert_eq!(to_origin_form(origin_form), origin_form);
+    }
+
+    #[test]
+    fn test_force_connection_close() {
+        let buffer = b"GET / HTTP/1.0\r\nHost: example.com\r\nConnection: keep-alive\r\n\r\nbody";
+        assert_eq!(
+            String::from_utf8(force_connection_close(buffer)).unwrap(),
+            "GET / HTTP/1.0\r\nHost: example.com\r\nConnection: close\r\n\r\nbody"
+        );
+    }
+
+    #[test]
+    fn test_close_delimited_head() {
+        let head = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: keep-alive\r\nKeep-Alive: timeout=5\r\n\r\n";
+        let (rewritten, body_length) = close_delimited_head(head);
+        assert_eq!(
+            String::from_utf8(rewritten).unwrap(),
+            "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\n"
+        );
+        assert_eq!(body_length, Some(5));
+
+        let head = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n";
+        assert_eq!(close_delimited_head(head).1, None);
+
+        let head = b"HTTP/1.1 304 Not Modified\r\nContent-Length: 10\r\n\r\n";
+        assert_eq!(close_delimited_head(head).1, Some(0));
+    }
+
+    #[test]
+    fn test_parse_request_body_intact() {
+        let mut buffer =
+            b"POST /upload HTTP/1.1\r\nHost: example.com\r\nContent-Length: 24\r\n\r\n".to_vec();
+        let body = b"line1\nline2\n\nhost: x\n\xff\r\n";
+        buffer.extend_from_slice(body);
+
+        let request = parse_http_request(&buffer, 80).unwrap();
+        assert_eq!(request.body, body);
+        assert_eq!(request.host, "example.com");
+        assert_eq!(request.headers.len(), 2);
+
+        // 头部未结束时无法确定body的起点
+        assert!(parse_http_request(b"POST / HTTP/1.1\r\nHost: example.com\r\n", 80).is_none());
+    }
+
+    #[test]
+    fn test_to_absolute_form() {
+        let buffer = b"GET /index.html HTTP/1.1\r\nHost: example.com:8080\r\n\r\n";
+        let rewritten = to_absolute_form(buffer, "http", "example.com", 8080, Some("Basic dTpw"));
+        assert_eq!(
+            String::from_utf8(rewritten).unwrap(),
+            "GET http://example.com:8080/index.html HTTP/1.1\r\n\
+            Proxy-Authorization: Basic dTpw\r\n\
+            Host: example.com:8080\r\n\r\n"
+        );
+
+        // 已是绝对形式时不改写
+        let buffer = b"GET http://example.com/ HTTP/1.1\r\nHost: example.com\r\n\r\n";
+        assert_eq!(
+            to_absolute_form(buffer, "http", "example.com", 80, None),
+            buffer.to_vec()
+        );
+
+        // IPv6目标加方括号
+        let buffer = b"GET / HTTP/1.1\r\nHost: [::1]:8080\r\n\r\n";
+        let rewritten = to_absolute_form(buffer, "http", "::1", 8080, None);
+        assert!(rewritten.starts_with(b"GET http://[::1]:8080/ HTTP/1.1\r\n"));
+        let buffer = b"GET / HTTP/1.1\r\nHost: [::1]\r\n\r\n";
+        let rewritten = to_absolute_form(buffer, "http", "::1", 80, None);
+        assert!(rewritten.starts_with(b"GET http://[::1]/ HTTP/1.1\r\n"));
+    }
+}
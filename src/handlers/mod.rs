@@ -1,4 +1,7 @@
 pub mod backend;
+pub mod framing;
 pub mod http1;
 pub mod http2;
+pub mod socks4;
+pub mod socks5;
 pub mod websocket;
@@ -0,0 +1,211 @@
+use super::backend::{is_access_denied, BackendConnector};
+use crate::auth::AuthConfig;
+use crate::relay::relay;
+use crate::stream::ClientStream;
+use std::io;
+use std::net::Ipv4Addr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing::{debug, error, info, warn};
+
+const SOCKS_VERSION: u8 = 0x04;
+
+/// 命令
+const CMD_CONNECT: u8 = 0x01;
+
+/// 应答版本号，SOCKS4应答首字节固定为0
+const REPLY_VERSION: u8 = 0x00;
+
+/// 应答码
+pub const REPLY_GRANTED: u8 = 0x5a;
+pub const REPLY_REJECTED: u8 = 0x5b;
+
+/// USERID和主机名字段的最大长度
+const MAX_FIELD_LEN: usize = 255;
+
+/// 处理SOCKS4/SOCKS4a连接
+///
+/// 仅支持CONNECT命令；目标地址为 0.0.0.x (x≠0) 时按SOCKS4a读取主机名。
+/// SOCKS4只能携带USERID而无法携带密码，因此启用认证时拒绝所有SOCKS4请求
+pub async fn handle_socks4(
+    mut client_stream: ClientStream,
+    client_addr: String,
+    auth_config: &Option<AuthConfig>,
+    connector: &BackendConnector,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // 请求: VER CMD DSTPORT DSTIP USERID NULL
+    let mut request = [0u8; 8];
+    client_stream.read_exact(&mut request).await?;
+    if request[0] != SOCKS_VERSION {
+        return Err(format!("不支持的SOCKS版本: {}", request[0]).into());
+    }
+    let command = request[1];
+    let port = u16::from_be_bytes([request[2], request[3]]);
+    let ip = Ipv4Addr::new(request[4], request[5], request[6], request[7]);
+    let _user_id = read_null_terminated(&mut client_stream).await?;
+
+    // SOCKS4a: 0.0.0.x 表示其后跟随以NULL结尾的主机名
+    let octets = ip.octets();
+    let host = if octets[..3] == [0, 0, 0] && octets[3] != 0 {
+        let domain = read_null_terminated(&mut client_stream).await?;
+        String::from_utf8_lossy(&domain).to_string()
+    } else {
+        ip.to_string()
+    };
+
+    if auth_config.is_some() {
+        warn!("[{}] 已启用认证，拒绝无法携带密码的SOCKS4请求", client_addr);
+        send_reply(&mut client_stream, REPLY_REJECTED).await?;
+        return Ok(());
+    }
+
+    if command != CMD_CONNECT {
+        warn!("[{}] 不支持的SOCKS4命令: {}", client_addr, command);
+        send_reply(&mut client_stream, REPLY_REJECTED).await?;
+        return Ok(());
+    }
+
+    info!("[{}] SOCKS4 CONNECT {}:{}", client_addr, host, port);
+
+    match connector.connect(&host, port).await {
+        Ok(target_stream) => {
+            send_reply(&mut client_stream, REPLY_GRANTED).await?;
+
+            let (sent, received) =
+                relay(client_stream, target_stream, connector.relay_options()).await?;
+            debug!(
+                "[{}] SOCKS4连接结束，上行 {} 字节，下行 {} 字节",
+                client_addr, sent, received
+            );
+            Ok(())
+        }
+        Err(e) => {
+            error!(
+                "[{}] SOCKS4连接目标失败 {}:{}: {}",
+                client_addr, host, port, e
+            );
+            send_reply(&mut client_stream, REPLY_REJECTED).await?;
+            // 目标被禁止时交由调用方报告拒绝原因
+            if is_access_denied(e.as_ref()) {
+                return Err(e);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// 读取以NULL结尾的字段
+async fn read_null_terminated(stream: &mut ClientStream) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    loop {
+        let byte = stream.read_u8().await?;
+        if byte == 0 {
+            return Ok(data);
+        }
+        if data.len() >= MAX_FIELD_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "SOCKS4字段过长"));
+        }
+        data.push(byte);
+    }
+}
+
+/// 发送SOCKS4应答，DSTPORT和DSTIP字段被客户端忽略，填0
+async fn send_reply(stream: &mut ClientStream, reply: u8) -> io::Result<()> {
+    stream
+        .write_all(&[REPLY_VERSION, reply, 0, 0, 0, 0, 0, 0])
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// 启动本地回显服务器
+    async fn start_echo_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let (mut reader, mut writer) = stream.split();
+                    let _ = tokio::io::copy(&mut reader, &mut writer).await;
+                });
+            }
+        });
+        addr
+    }
+
+    /// 启动运行SOCKS4处理器的服务端，返回已连接的客户端
+    async fn connect_socks4() -> TcpStream {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, client_addr) = listener.accept().await.unwrap();
+            let _ = handle_socks4(
+                stream.into(),
+                client_addr.to_string(),
+                &None,
+                &BackendConnector::new(),
+            )
+            .await;
+        });
+        TcpStream::connect(addr).await.unwrap()
+    }
+
+    async fn read_reply(stream: &mut TcpStream) -> [u8; 8] {
+        let mut reply = [0u8; 8];
+        stream.read_exact(&mut reply).await.unwrap();
+        reply
+    }
+
+    #[tokio::test]
+    async fn test_socks4_connect_ipv4() {
+        let echo = start_echo_server().await;
+        let mut client = connect_socks4().await;
+
+        let mut request = vec![0x04, 0x01];
+        request.extend_from_slice(&echo.port().to_be_bytes());
+        request.extend_from_slice(&[127, 0, 0, 1]);
+        request.extend_from_slice(b"user\0");
+        client.write_all(&request).await.unwrap();
+        let reply = read_reply(&mut client).await;
+        assert_eq!(&reply[..2], &[0x00, REPLY_GRANTED]);
+
+        client.write_all(b"ping").await.unwrap();
+        let mut echoed = [0u8; 4];
+        client.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(&echoed, b"ping");
+    }
+
+    #[tokio::test]
+    async fn test_socks4a_connect_domain() {
+        let echo = start_echo_server().await;
+        let mut client = connect_socks4().await;
+
+        let mut request = vec![0x04, 0x01];
+        request.extend_from_slice(&echo.port().to_be_bytes());
+        request.extend_from_slice(&[0, 0, 0, 1]);
+        request.extend_from_slice(b"\0localhost\0");
+        client.write_all(&request).await.unwrap();
+        let reply = read_reply(&mut client).await;
+        assert_eq!(&reply[..2], &[0x00, REPLY_GRANTED]);
+
+        client.write_all(b"hello").await.unwrap();
+        let mut echoed = [0u8; 5];
+        client.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(&echoed, b"hello");
+    }
+
+    #[tokio::test]
+    async fn test_socks4_rejects_bind() {
+        let mut client = connect_socks4().await;
+
+        client
+            .write_all(&[0x04, 0x02, 0x00, 0x50, 127, 0, 0, 1, 0])
+            .await
+            .unwrap();
+        let reply = read_reply(&mut client).await;
+        assert_eq!(&reply[..2], &[0x00, REPLY_REJECTED]);
+    }
+}
@@ -0,0 +1,324 @@
+use super::backend::BackendConnector;
+use crate::auth::AuthConfig;
+use crate::metrics::metrics;
+use crate::relay::relay;
+use crate::stream::ClientStream;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing::{debug, error, info, warn};
+
+const SOCKS_VERSION: u8 = 0x05;
+
+/// 认证方法
+const METHOD_NO_AUTH: u8 = 0x00;
+const METHOD_USERNAME_PASSWORD: u8 = 0x02;
+const METHOD_NO_ACCEPTABLE: u8 = 0xFF;
+
+/// 用户名/密码子协商版本（RFC 1929）
+const AUTH_VERSION: u8 = 0x01;
+
+/// 命令
+const CMD_CONNECT: u8 = 0x01;
+
+/// 地址类型
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+/// 应答码（RFC 1928 第6节）
+pub const REPLY_SUCCEEDED: u8 = 0x00;
+pub const REPLY_GENERAL_FAILURE: u8 = 0x01;
+pub const REPLY_NOT_ALLOWED: u8 = 0x02;
+pub const REPLY_HOST_UNREACHABLE: u8 = 0x04;
+pub const REPLY_CONNECTION_REFUSED: u8 = 0x05;
+pub const REPLY_COMMAND_NOT_SUPPORTED: u8 = 0x07;
+pub const REPLY_ADDRESS_TYPE_NOT_SUPPORTED: u8 = 0x08;
+
+/// 处理SOCKS5连接
+///
+/// 完成方法协商、可选的用户名/密码认证以及CONNECT命令后进行双向转发
+pub async fn handle_socks5(
+    mut client_stream: ClientStream,
+    client_addr: String,
+    auth_config: &Option<AuthConfig>,
+    connector: &BackendConnector,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // 方法协商: VER NMETHODS METHODS
+    let mut header = [0u8; 2];
+    client_stream.read_exact(&mut header).await?;
+    if header[0] != SOCKS_VERSION {
+        return Err(format!("不支持的SOCKS版本: {}", header[0]).into());
+    }
+    let mut methods = vec![0u8; header[1] as usize];
+    client_stream.read_exact(&mut methods).await?;
+
+    let method = if auth_config.is_some() {
+        METHOD_USERNAME_PASSWORD
+    } else {
+        METHOD_NO_AUTH
+    };
+    if !methods.contains(&method) {
+        warn!("[{}] SOCKS5客户端未提供可接受的认证方法", client_addr);
+        client_stream
+            .write_all(&[SOCKS_VERSION, METHOD_NO_ACCEPTABLE])
+            .await?;
+        return Ok(());
+    }
+    client_stream.write_all(&[SOCKS_VERSION, method]).await?;
+
+    // 用户名/密码子协商: VER ULEN UNAME PLEN PASSWD
+    if let Some(auth) = auth_config {
+        let mut version = [0u8; 1];
+        client_stream.read_exact(&mut version).await?;
+        if version[0] != AUTH_VERSION {
+            return Err(format!("不支持的SOCKS5认证版本: {}", version[0]).into());
+        }
+        let username = read_length_prefixed(&mut client_stream).await?;
+        let password = read_length_prefixed(&mut client_stream).await?;
+
+        let username = String::from_utf8_lossy(&username);
+        let password = String::from_utf8_lossy(&password);
+        if !auth.validate_credentials(&username, &password) {
+            info!("[{}] SOCKS5认证失败", client_addr);
+            metrics().record_auth_failure();
+            client_stream.write_all(&[AUTH_VERSION, 0x01]).await?;
+            return Ok(());
+        }
+        client_stream.write_all(&[AUTH_VERSION, 0x00]).await?;
+    }
+
+    // 请求: VER CMD RSV ATYP DST.ADDR DST.PORT
+    let mut request = [0u8; 4];
+    client_stream.read_exact(&mut request).await?;
+    if request[0] != SOCKS_VERSION {
+        return Err(format!("不支持的SOCKS版本: {}", request[0]).into());
+    }
+
+    let host = match request[3] {
+        ATYP_IPV4 => {
+            let mut addr = [0u8; 4];
+            client_stream.read_exact(&mut addr).await?;
+            Ipv4Addr::from(addr).to_string()
+        }
+        ATYP_IPV6 => {
+            let mut addr = [0u8; 16];
+            client_stream.read_exact(&mut addr).await?;
+            Ipv6Addr::from(addr).to_string()
+        }
+        ATYP_DOMAIN => {
+            let domain = read_length_prefixed(&mut client_stream).await?;
+            String::from_utf8_lossy(&domain).to_string()
+        }
+        atyp => {
+            warn!("[{}] 不支持的SOCKS5地址类型: {}", client_addr, atyp);
+            send_reply(&mut client_stream, REPLY_ADDRESS_TYPE_NOT_SUPPORTED, None).await?;
+            return Ok(());
+        }
+    };
+    let mut port = [0u8; 2];
+    client_stream.read_exact(&mut port).await?;
+    let port = u16::from_be_bytes(port);
+
+    if request[1] != CMD_CONNECT {
+        warn!("[{}] 不支持的SOCKS5命令: {}", client_addr, request[1]);
+        send_reply(&mut client_stream, REPLY_COMMAND_NOT_SUPPORTED, None).await?;
+        return Ok(());
+    }
+
+    info!("[{}] SOCKS5 CONNECT {}:{}", client_addr, host, port);
+
+    match connector.connect(&host, port).await {
+        Ok(target_stream) => {
+            send_reply(
+                &mut client_stream,
+                REPLY_SUCCEEDED,
+                target_stream.local_addr().ok(),
+            )
+            .await?;
+
+            let (sent, received) =
+                relay(client_stream, target_stream, connector.relay_options()).await?;
+            debug!(
+                "[{}] SOCKS5连接结束，上行 {} 字节，下行 {} 字节",
+                client_addr, sent, received
+            );
+            Ok(())
+        }
+        Err(e) => {
+            error!(
+                "[{}] SOCKS5连接目标失败 {}:{}: {}",
+                client_addr, host, port, e
+            );
+            let reply = match e.downcast_ref::<io::Error>().map(|e| e.kind()) {
+                Some(io::ErrorKind::PermissionDenied) => REPLY_NOT_ALLOWED,
+                Some(io::ErrorKind::ConnectionRefused) => REPLY_CONNECTION_REFUSED,
+                Some(_) => REPLY_HOST_UNREACHABLE,
+                None => REPLY_GENERAL_FAILURE,
+            };
+            send_reply(&mut client_stream, reply, None).await?;
+            // 目标被禁止时交由调用方报告拒绝原因
+            if reply == REPLY_NOT_ALLOWED {
+                return Err(e);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// 读取以单字节长度为前缀的字段
+async fn read_length_prefixed(stream: &mut ClientStream) -> io::Result<Vec<u8>> {
+    let mut len = [0u8; 1];
+    stream.read_exact(&mut len).await?;
+    let mut data = vec![0u8; len[0] as usize];
+    stream.read_exact(&mut data).await?;
+    Ok(data)
+}
+
+/// 发送SOCKS5应答，未提供绑定地址时使用 0.0.0.0:0
+pub async fn send_reply(
+    stream: &mut ClientStream,
+    reply: u8,
+    bound: Option<SocketAddr>,
+) -> io::Result<()> {
+    let mut response = vec![SOCKS_VERSION, reply, 0x00];
+    match bound {
+        Some(SocketAddr::V6(addr)) => {
+            response.push(ATYP_IPV6);
+            response.extend_from_slice(&addr.ip().octets());
+            response.extend_from_slice(&addr.port().to_be_bytes());
+        }
+        Some(SocketAddr::V4(addr)) => {
+            response.push(ATYP_IPV4);
+            response.extend_from_slice(&addr.ip().octets());
+            response.extend_from_slice(&addr.port().to_be_bytes());
+        }
+        None => {
+            response.push(ATYP_IPV4);
+            response.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        }
+    }
+    stream.write_all(&response).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// 启动本地回显服务器
+    async fn start_echo_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let (mut reader, mut writer) = stream.split();
+                    let _ = tokio::io::copy(&mut reader, &mut writer).await;
+                });
+            }
+        });
+        addr
+    }
+
+    /// 启动运行SOCKS5处理器的服务端，返回已连接的客户端
+    async fn connect_socks5(auth_config: Option<AuthConfig>) -> TcpStream {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, client_addr) = listener.accept().await.unwrap();
+            let _ = handle_socks5(
+                stream.into(),
+                client_addr.to_string(),
+                &auth_config,
+                &BackendConnector::new(),
+            )
+            .await;
+        });
+        TcpStream::connect(addr).await.unwrap()
+    }
+
+    async fn read_reply(stream: &mut TcpStream) -> [u8; 10] {
+        let mut reply = [0u8; 10];
+        stream.read_exact(&mut reply).await.unwrap();
+        reply
+    }
+
+    #[tokio::test]
+    async fn test_socks5_connect_ipv4_no_auth() {
+        let echo = start_echo_server().await;
+        let mut client = connect_socks5(None).await;
+
+        client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+        let mut method = [0u8; 2];
+        client.read_exact(&mut method).await.unwrap();
+        assert_eq!(method, [0x05, 0x00]);
+
+        let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+        request.extend_from_slice(&echo.port().to_be_bytes());
+        client.write_all(&request).await.unwrap();
+        let reply = read_reply(&mut client).await;
+        assert_eq!(&reply[..4], &[0x05, REPLY_SUCCEEDED, 0x00, 0x01]);
+
+        client.write_all(b"ping").await.unwrap();
+        let mut echoed = [0u8; 4];
+        client.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(&echoed, b"ping");
+    }
+
+    #[tokio::test]
+    async fn test_socks5_connect_domain_with_auth() {
+        let echo = start_echo_server().await;
+        let auth = AuthConfig::new("user".to_string(), "pass".to_string());
+        let mut client = connect_socks5(Some(auth)).await;
+
+        client.write_all(&[0x05, 0x02, 0x00, 0x02]).await.unwrap();
+        let mut method = [0u8; 2];
+        client.read_exact(&mut method).await.unwrap();
+        assert_eq!(method, [0x05, 0x02]);
+
+        client
+            .write_all(&[0x01, 4, b'u', b's', b'e', b'r', 4, b'p', b'a', b's', b's'])
+            .await
+            .unwrap();
+        let mut status = [0u8; 2];
+        client.read_exact(&mut status).await.unwrap();
+        assert_eq!(status, [0x01, 0x00]);
+
+        let mut request = vec![0x05, 0x01, 0x00, 0x03, 9];
+        request.extend_from_slice(b"localhost");
+        request.extend_from_slice(&echo.port().to_be_bytes());
+        client.write_all(&request).await.unwrap();
+        let reply = read_reply(&mut client).await;
+        assert_eq!(reply[1], REPLY_SUCCEEDED);
+
+        client.write_all(b"hello").await.unwrap();
+        let mut echoed = [0u8; 5];
+        client.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(&echoed, b"hello");
+    }
+
+    #[tokio::test]
+    async fn test_socks5_rejects_bad_credentials_and_missing_method() {
+        let auth = AuthConfig::new("user".to_string(), "pass".to_string());
+
+        // 需要认证时只提供无认证方法
+        let mut client = connect_socks5(Some(auth.clone())).await;
+        client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+        let mut method = [0u8; 2];
+        client.read_exact(&mut method).await.unwrap();
+        assert_eq!(method, [0x05, METHOD_NO_ACCEPTABLE]);
+
+        // 错误的密码
+        let mut client = connect_socks5(Some(auth)).await;
+        client.write_all(&[0x05, 0x01, 0x02]).await.unwrap();
+        client.read_exact(&mut method).await.unwrap();
+        client
+            .write_all(&[0x01, 4, b'u', b's', b'e', b'r', 3, b'b', b'a', b'd'])
+            .await
+            .unwrap();
+        let mut status = [0u8; 2];
+        client.read_exact(&mut status).await.unwrap();
+        assert_eq!(status, [0x01, 0x01]);
+    }
+}
@@ -1,6 +1,17 @@
-use super::backend::BackendConnector;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
+use super::backend::{forbidden_message, is_access_denied, is_timeout, BackendConnector};
+use super::http1::{strip_hop_by_hop_headers, to_origin_form};
+use crate::access_log;
+use crate::connection::{
+    read_http_head, response_version, send_error_response, HeadRead, DEFAULT_INITIAL_READ_SIZE,
+    DEFAULT_MAX_HEADER_SIZE,
+};
+use crate::parser::detector::{
+    default_websocket_port, is_secure_websocket_target, parse_authority,
+};
+use crate::relay::{relay_until, RelayOptions};
+use crate::stream::ClientStream;
+use std::future::Future;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
 use tracing::{debug, error, info};
 
 /// WebSocket升级请求详细信息
@@ -9,13 +20,27 @@ pub struct WebSocketUpgrade {
     pub host: String,
     pub port: u16,
     pub path: String,
+    /// 是否为安全连接（wss）
+    pub secure: bool,
 }
 
 /// 处理WebSocket连接升级和代理
+///
+/// `request` 为客户端的原始升级请求（可带头部之后已读到的数据），移除逐跳头部并改写为
+/// 源站形式后转发，子协议、扩展、Cookie、Origin等头部原样到达源站；
+/// 源站的完整升级响应原样返回给客户端。安全连接（wss）经TLS连接源站，
+/// 未启用源站TLS时返回502。
+///
+/// 升级后的转发在 `shutdown` 完成时结束，`close_frame` 为真时空闲超时或结束前向两端发送关闭帧
+#[allow(clippy::too_many_arguments)]
 pub async fn handle_websocket(
-    mut client_stream: TcpStream,
+    mut client_stream: ClientStream,
     client_addr: String,
+    connector: &BackendConnector,
     upgrade: WebSocketUpgrade,
+    request: &[u8],
+    close_frame: bool,
+    shutdown: impl Future<Output = ()>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     info!(
         "[{}] WebSocket升级请求: {}:{}{}",
@@ -23,157 +48,220 @@ pub async fn handle_websocket(
     );
 
     // 连接到目标服务器
-    match BackendConnector::connect(&upgrade.host, upgrade.port).await {
-        Ok(mut target_stream) => {
-            debug!(
-                "[{}] 成功连接到WebSocket目标服务器 {}:{}",
-                client_addr, upgrade.host, upgrade.port
-            );
-
-            // 转发原始升级请求到目标服务器
-            let upgrade_request = format!(
-                "GET {} HTTP/1.1\r\n\
-                 Host: {}:{}\r\n\
-                 Upgrade: websocket\r\n\
-                 Connection: Upgrade\r\n\
-                 Sec-WebSocket-Key: {}\r\n\
-                 Sec-WebSocket-Version: 13\r\n\
-                 \r\n",
-                upgrade.path, upgrade.host, upgrade.port, upgrade.key
-            );
-
-            if let Err(e) = target_stream.write_all(upgrade_request.as_bytes()).await {
-                error!("[{}] 发送WebSocket升级请求失败: {}", client_addr, e);
-                send_websocket_error(&mut client_stream, "502 Bad Gateway").await?;
-                return Err(e.into());
+    let connect_error = if upgrade.secure {
+        match connector.connect_tls(&upgrade.host, upgrade.port).await {
+            Ok(target_stream) => {
+                debug!(
+                    "[{}] 成功通过TLS连接到WebSocket目标服务器 {}:{}",
+                    client_addr, upgrade.host, upgrade.port
+                );
+                return upgrade_and_relay(
+                    client_stream,
+                    target_stream,
+                    &client_addr,
+                    connector,
+                    request,
+                    close_frame,
+                    shutdown,
+                )
+                .await;
             }
-
-            debug!("[{}] WebSocket升级请求已发送，等待目标响应", client_addr);
-
-            // 读取目标服务器的升级响应
-            let mut response_buffer = [0u8; 4096];
-            let n = match target_stream.read(&mut response_buffer).await {
-                Ok(0) => {
-                    error!("[{}] 目标服务器关闭连接", client_addr);
-                    send_websocket_error(&mut client_stream, "502 Bad Gateway").await?;
-                    return Ok(());
-                }
-                Ok(n) => n,
-                Err(e) => {
-                    error!("[{}] 读取目标响应失败: {}", client_addr, e);
-                    send_websocket_error(&mut client_stream, "502 Bad Gateway").await?;
-                    return Err(e.into());
-                }
-            };
-
-            let response = String::from_utf8_lossy(&response_buffer[..n]);
-
-            // 检查目标服务器是否同意升级
-            if !response.contains("HTTP/1.1 101") && !response.contains("HTTP/1.0 101") {
-                error!(
-                    "[{}] 目标服务器拒绝WebSocket升级: {}",
-                    client_addr,
-                    response.lines().next().unwrap_or("未知响应")
+            Err(e) => e,
+        }
+    } else {
+        match connector.connect(&upgrade.host, upgrade.port).await {
+            Ok(target_stream) => {
+                debug!(
+                    "[{}] 成功连接到WebSocket目标服务器 {}:{}",
+                    client_addr, upgrade.host, upgrade.port
                 );
-
-                // 将错误响应转发给客户端
-                if let Err(e) = client_stream.write_all(&response_buffer[..n]).await {
-                    error!("[{}] 转发错误响应失败: {}", client_addr, e);
-                }
-                return Ok(());
+                return upgrade_and_relay(
+                    client_stream,
+                    target_stream,
+                    &client_addr,
+                    connector,
+                    request,
+                    close_frame,
+                    shutdown,
+                )
+                .await;
             }
+            Err(e) => e,
+        }
+    };
 
-            debug!(
-                "[{}] 目标服务器接受WebSocket升级，转发响应给客户端",
-                client_addr
-            );
+    error!(
+        "[{}] WebSocket连接目标失败 {}:{}: {}",
+        client_addr, upgrade.host, upgrade.port, connect_error
+    );
+    let (status, message) = if is_access_denied(connect_error.as_ref()) {
+        (
+            "403 Forbidden",
+            forbidden_message(connect_error.as_ref(), &upgrade.host, upgrade.port),
+        )
+    } else if is_timeout(connect_error.as_ref()) {
+        (
+            "504 Gateway Timeout",
+            format!("连接 {}:{} 超时", upgrade.host, upgrade.port),
+        )
+    } else {
+        (
+            "502 Bad Gateway",
+            format!("无法连接到 {}:{}", upgrade.host, upgrade.port),
+        )
+    };
+    send_error_response(
+        &mut client_stream,
+        status,
+        &message,
+        response_version(request),
+    )
+    .await?;
+    Err(connect_error)
+}
 
-            // 转发升级响应给客户端
-            if let Err(e) = client_stream.write_all(&response_buffer[..n]).await {
-                error!("[{}] 发送WebSocket升级响应失败: {}", client_addr, e);
-                return Err(e.into());
-            }
+/// 向已连接的源站发送升级请求，源站接受升级后双向透明转发
+async fn upgrade_and_relay<T>(
+    mut client_stream: ClientStream,
+    mut target_stream: T,
+    client_addr: &str,
+    connector: &BackendConnector,
+    request: &[u8],
+    close_frame: bool,
+    shutdown: impl Future<Output = ()>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    // 错误响应使用客户端请求的HTTP版本
+    let version = response_version(request);
 
-            if let Err(e) = client_stream.flush().await {
-                error!("[{}] 刷新WebSocket升级响应失败: {}", client_addr, e);
-                return Err(e.into());
-            }
+    // 转发原始升级请求到目标服务器，保留 `Upgrade` 与 `Connection` 列出的头部
+    let upgrade_request = to_origin_form(&strip_hop_by_hop_headers(request, false, true));
 
-            debug!("[{}] WebSocket连接建立成功，开始透明转发", client_addr);
-
-            // 建立双向透明转发
-            let (mut client_reader, mut client_writer) = client_stream.into_split();
-            let (mut target_reader, mut target_writer) = target_stream.into_split();
-
-            let client_to_target = async {
-                let mut buffer = [0u8; 8192];
-                loop {
-                    match client_reader.read(&mut buffer).await {
-                        Ok(0) => {
-                            debug!("[{}] WebSocket客户端流结束", client_addr);
-                            break;
-                        }
-                        Ok(n) => {
-                            if let Err(e) = target_writer.write_all(&buffer[..n]).await {
-                                error!("[{}] WebSocket转发到目标失败: {}", client_addr, e);
-                                break;
-                            }
-                        }
-                        Err(e) => {
-                            error!("[{}] 读取WebSocket客户端数据失败: {}", client_addr, e);
-                            break;
-                        }
-                    }
-                }
-            };
-
-            let target_to_client = async {
-                let mut buffer = [0u8; 8192];
-                loop {
-                    match target_reader.read(&mut buffer).await {
-                        Ok(0) => {
-                            debug!("[{}] WebSocket目标流结束", client_addr);
-                            break;
-                        }
-                        Ok(n) => {
-                            if let Err(e) = client_writer.write_all(&buffer[..n]).await {
-                                error!("[{}] WebSocket转发到客户端失败: {}", client_addr, e);
-                                break;
-                            }
-                        }
-                        Err(e) => {
-                            error!("[{}] 读取WebSocket目标数据失败: {}", client_addr, e);
-                            break;
-                        }
-                    }
-                }
-            };
-
-            tokio::select! {
-                _ = client_to_target => {
-                    debug!("[{}] WebSocket客户端到目标连接结束", client_addr);
-                }
-                _ = target_to_client => {
-                    debug!("[{}] WebSocket目标到客户端连接结束", client_addr);
-                }
-            }
+    if let Err(e) = target_stream.write_all(&upgrade_request).await {
+        error!("[{}] 发送WebSocket升级请求失败: {}", client_addr, e);
+        send_error_response(
+            &mut client_stream,
+            "502 Bad Gateway",
+            "发送升级请求到目标服务器失败",
+            version,
+        )
+        .await?;
+        return Err(e.into());
+    }
+
+    debug!("[{}] WebSocket升级请求已发送，等待目标响应", client_addr);
 
-            Ok(())
+    // 读取目标服务器完整的升级响应头部，头部可能分多次到达
+    let response = match read_http_head(
+        &mut target_stream,
+        DEFAULT_MAX_HEADER_SIZE,
+        DEFAULT_INITIAL_READ_SIZE,
+    )
+    .await
+    {
+        Ok(HeadRead::Complete { mut head, rest }) => {
+            head.extend_from_slice(&rest);
+            head
+        }
+        Ok(HeadRead::TooLarge) => {
+            error!("[{}] 目标服务器的升级响应头部过大", client_addr);
+            send_error_response(
+                &mut client_stream,
+                "502 Bad Gateway",
+                "目标服务器的升级响应头部过大",
+                version,
+            )
+            .await?;
+            return Ok(());
+        }
+        Ok(HeadRead::Closed(_)) => {
+            error!("[{}] 目标服务器关闭连接", client_addr);
+            send_error_response(
+                &mut client_stream,
+                "502 Bad Gateway",
+                "目标服务器未响应升级请求即关闭连接",
+                version,
+            )
+            .await?;
+            return Ok(());
         }
         Err(e) => {
-            error!(
-                "[{}] WebSocket连接目标失败 {}:{}: {}",
-                client_addr, upgrade.host, upgrade.port, e
-            );
-            send_websocket_error(&mut client_stream, "502 Bad Gateway").await?;
-            Err(format!("Connection failed: {}", e).into())
+            error!("[{}] 读取目标响应失败: {}", client_addr, e);
+            send_error_response(
+                &mut client_stream,
+                "502 Bad Gateway",
+                "读取目标服务器的升级响应失败",
+                version,
+            )
+            .await?;
+            return Err(e.into());
+        }
+    };
+
+    let status = access_log::parse_status(&response);
+    if let Some(status) = status {
+        access_log::record_status(status);
+    }
+
+    // 检查目标服务器是否同意升级
+    if status != Some(101) {
+        error!(
+            "[{}] 目标服务器拒绝WebSocket升级: {}",
+            client_addr,
+            String::from_utf8_lossy(&response)
+                .lines()
+                .next()
+                .unwrap_or("未知响应")
+        );
+
+        // 将错误响应转发给客户端
+        if let Err(e) = client_stream.write_all(&response).await {
+            error!("[{}] 转发错误响应失败: {}", client_addr, e);
         }
+        return Ok(());
+    }
+
+    debug!(
+        "[{}] 目标服务器接受WebSocket升级，转发响应给客户端",
+        client_addr
+    );
+
+    // 转发升级响应给客户端
+    if let Err(e) = client_stream.write_all(&response).await {
+        error!("[{}] 发送WebSocket升级响应失败: {}", client_addr, e);
+        return Err(e.into());
+    }
+
+    if let Err(e) = client_stream.flush().await {
+        error!("[{}] 刷新WebSocket升级响应失败: {}", client_addr, e);
+        return Err(e.into());
     }
+
+    debug!("[{}] WebSocket连接建立成功，开始透明转发", client_addr);
+
+    // 建立双向透明转发
+    let options = RelayOptions {
+        websocket_close: close_frame,
+        ..connector.relay_options()
+    };
+    let (sent, received) = relay_until(client_stream, target_stream, options, shutdown).await?;
+    debug!(
+        "[{}] WebSocket连接结束，上行 {} 字节，下行 {} 字节",
+        client_addr, sent, received
+    );
+
+    Ok(())
 }
 
 /// 解析WebSocket升级请求
+///
+/// 请求目标为 `wss://`/`https://`，或原始请求协议 `scheme` 为 `https`（如受信任的
+/// `X-Forwarded-Proto`）时视为安全连接，Host未指定端口时默认443，否则默认80
 pub fn parse_websocket_upgrade(
     buffer: &[u8],
+    scheme: &str,
 ) -> Result<Option<WebSocketUpgrade>, Box<dyn std::error::Error + Send + Sync>> {
     let request = String::from_utf8_lossy(buffer);
     let lines: Vec<&str> = request.lines().collect();
@@ -190,10 +278,13 @@ pub fn parse_websocket_upgrade(
     }
     let path = parts[1].to_string();
 
+    let secure = scheme == "https" || is_secure_websocket_target(buffer);
+    let default_port = default_websocket_port(secure);
+
     // 提取必需的头部
     let mut key = None;
     let mut host = String::new();
-    let mut port = 80u16;
+    let mut port = default_port;
 
     for line in &lines {
         let line_lower = line.to_lowercase();
@@ -202,14 +293,9 @@ pub fn parse_websocket_upgrade(
             key = Some(line[18..].trim().to_string());
         } else if line_lower.starts_with("host:") {
             let host_value = line[5..].trim();
-            if let Some(colon_pos) = host_value.find(':') {
-                host = host_value[..colon_pos].to_string();
-                port = host_value[colon_pos + 1..]
-                    .trim()
-                    .parse::<u16>()
-                    .unwrap_or(80);
-            } else {
-                host = host_value.to_string();
+            if let Some((h, p)) = parse_authority(host_value, Some(default_port)) {
+                host = h;
+                port = p;
             }
         }
     }
@@ -225,21 +311,45 @@ pub fn parse_websocket_upgrade(
         host,
         port,
         path,
+        secure,
     }))
 }
 
-/// 发送WebSocket错误响应
-async fn send_websocket_error(
-    stream: &mut TcpStream,
-    status: &str,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let response = format!(
-        "HTTP/1.1 {}\r\n\
-         Content-Type: text/plain\r\n\
-         Connection: close\r\n\
-         \r\n",
-        status
-    );
-    stream.write_all(response.as_bytes()).await?;
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upgrade_request(target: &str) -> String {
+        format!(
+            "GET {} HTTP/1.1\r\n\
+            Host: example.com\r\n\
+            Upgrade: websocket\r\n\
+            Connection: Upgrade\r\n\
+            Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+            target
+        )
+    }
+
+    #[test]
+    fn test_parse_websocket_upgrade_default_port() {
+        let ws =
+            parse_websocket_upgrade(upgrade_request("ws://example.com/chat").as_bytes(), "http")
+                .unwrap()
+                .unwrap();
+        assert_eq!(ws.port, 80);
+        assert!(!ws.secure);
+
+        let wss =
+            parse_websocket_upgrade(upgrade_request("wss://example.com/chat").as_bytes(), "http")
+                .unwrap()
+                .unwrap();
+        assert_eq!(wss.port, 443);
+        assert!(wss.secure);
+
+        // 前置TLS终结时由受信任的X-Forwarded-Proto得出https
+        let forwarded = parse_websocket_upgrade(upgrade_request("/chat").as_bytes(), "https")
+            .unwrap()
+            .unwrap();
+        assert_eq!(forwarded.port, 443);
+    }
 }
@@ -0,0 +1,112 @@
+use crate::handlers::backend::BackendConnector;
+use crate::metrics::{metrics, read_request_path};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, error, info, warn};
+
+/// 就绪状态
+///
+/// 后台任务按固定间隔连接探测目标并缓存结果，`/readyz` 只读取缓存，
+/// 不会为每次探测请求增加连接延迟
+#[derive(Debug, Clone)]
+pub struct Readiness {
+    ready: Arc<AtomicBool>,
+}
+
+impl Readiness {
+    /// 启动后台探测任务
+    ///
+    /// 首次探测完成前视为未就绪；所有 `Readiness` 实例被释放后任务自动结束
+    pub fn spawn(connector: BackendConnector, host: String, port: u16, interval: Duration) -> Self {
+        let ready = Arc::new(AtomicBool::new(false));
+        let weak = Arc::downgrade(&ready);
+
+        tokio::spawn(async move {
+            loop {
+                let reachable = match connector.connect(&host, port).await {
+                    Ok(_) => true,
+                    Err(e) => {
+                        warn!("就绪探测失败 {}:{}: {}", host, port, e);
+                        false
+                    }
+                };
+
+                match weak.upgrade() {
+                    Some(ready) => {
+                        if ready.swap(reachable, Ordering::Relaxed) != reachable {
+                            debug!("就绪状态变更为 {}", reachable);
+                        }
+                    }
+                    None => break,
+                }
+
+                tokio::time::sleep(interval).await;
+            }
+        });
+
+        Readiness { ready }
+    }
+
+    /// 最近一次探测是否成功
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Relaxed)
+    }
+}
+
+/// 在独立端口上提供 `/healthz` 存活检查端点
+///
+/// 不经过代理的协议识别和认证，供负载均衡器和k8s探针使用。
+/// 响应为JSON：运行时长（秒，自端点启动起）、活跃连接数和版本号
+pub async fn serve(listener: TcpListener) {
+    if let Ok(addr) = listener.local_addr() {
+        info!("💓 健康检查端点: http://{}/healthz", addr);
+    }
+    let started = Instant::now();
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, addr)) => {
+                tokio::spawn(async move {
+                    if let Err(e) = serve_healthz(stream, started).await {
+                        debug!("[{}] 健康检查请求处理失败: {}", addr, e);
+                    }
+                });
+            }
+            Err(e) => {
+                error!("健康检查端点接受连接失败: {}", e);
+            }
+        }
+    }
+}
+
+async fn serve_healthz(mut stream: TcpStream, started: Instant) -> std::io::Result<()> {
+    let path = match read_request_path(&mut stream).await? {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+
+    let (status, body) = if path == "/healthz" {
+        (
+            "200 OK",
+            format!(
+                "{{\"status\":\"ok\",\"uptime_secs\":{},\"active_connections\":{},\"version\":\"{}\"}}",
+                started.elapsed().as_secs(),
+                metrics().active_connections(),
+                env!("CARGO_PKG_VERSION")
+            ),
+        )
+    } else {
+        ("404 Not Found", "{\"status\":\"not found\"}".to_string())
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
@@ -1,6 +1,23 @@
+pub mod access_log;
+pub mod access_rules;
+pub mod admission;
 pub mod auth;
+pub mod check;
+pub mod cidr;
 pub mod config;
 pub mod connection;
+pub mod error;
 pub mod handlers;
+pub mod health;
+pub mod logging;
+pub mod metrics;
 pub mod parser;
 pub mod proxy;
+pub mod rejection;
+pub mod relay;
+pub mod reload;
+pub mod selftest;
+pub mod stream;
+pub mod tls;
+pub mod upstream;
+pub mod watchdog;
@@ -0,0 +1,310 @@
+use crate::access_log;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::hash::BuildHasher;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::field::Empty;
+use tracing::{info, info_span, warn, Span};
+
+/// 日志输出格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// 便于阅读的文本
+    #[default]
+    Text,
+    /// 每行一个JSON对象，便于日志聚合
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("日志格式应为 text 或 json: {}", value)),
+        }
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogFormat::Text => write!(f, "text"),
+            LogFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// 按指定格式初始化全局日志
+pub fn init(format: LogFormat) {
+    match format {
+        LogFormat::Text => tracing_subscriber::fmt::init(),
+        LogFormat::Json => tracing_subscriber::fmt().json().init(),
+    }
+}
+
+tokio::task_local! {
+    /// 当前连接的请求ID
+    static REQUEST_ID: String;
+}
+
+/// 生成一个新的请求ID（12位十六进制）
+///
+/// 由进程内递增序号经随机种子散列得到，不同进程之间也不易重复
+pub fn next_request_id() -> String {
+    static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+    static STATE: OnceLock<RandomState> = OnceLock::new();
+
+    let sequence = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+    let hash = STATE.get_or_init(RandomState::new).hash_one(sequence);
+    format!("{:012x}", hash >> 16)
+}
+
+/// 在请求ID作用域内运行连接任务，作用域内可通过 [`current_request_id`] 读取
+pub async fn request_scope<F: Future>(request_id: String, future: F) -> F::Output {
+    REQUEST_ID.scope(request_id, future).await
+}
+
+/// 当前连接的请求ID，不在 [`request_scope`] 内时返回 `None`
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// 携带当前请求ID的 `X-Proxy-Request-Id` 响应头行，没有请求ID时为空
+pub fn request_id_header() -> String {
+    match current_request_id() {
+        Some(id) => format!("X-Proxy-Request-Id: {}\r\n", id),
+        None => String::new(),
+    }
+}
+
+/// 创建单个连接的日志span
+///
+/// 连接内的所有日志都带有该span的字段；协议和目标在识别后通过
+/// [`record_protocol`] 与 [`record_target`] 补充
+pub fn connection_span(client_addr: SocketAddr, request_id: &str) -> Span {
+    info_span!(
+        "connection",
+        request_id = %request_id,
+        client_addr = %client_addr,
+        protocol = Empty,
+        target_host = Empty,
+        target_port = Empty,
+    )
+}
+
+/// 在当前连接span上记录协议
+pub fn record_protocol(protocol: &str) {
+    Span::current().record("protocol", protocol);
+}
+
+/// 在当前连接span上记录目标地址，同时计入访问日志
+pub fn record_target(host: &str, port: u16) {
+    access_log::record_target(host, port);
+    let span = Span::current();
+    span.record("target_host", host);
+    span.record("target_port", port);
+}
+
+/// 记录一次转发结束及双向字节数
+pub fn relay_finished(bytes_in: u64, bytes_out: u64) {
+    info!(bytes_in, bytes_out, "转发结束");
+}
+
+/// 策略拒绝的类别，限速日志按类别分别计数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PolicyViolation {
+    /// CONNECT到未允许的端口
+    DisallowedPort,
+    /// 目标被访问规则禁止或为内部地址
+    BlockedDestination,
+    /// 请求方法不在允许列表中
+    DisallowedMethod,
+    /// CONNECT隧道的ClientHello没有SNI
+    MissingSni,
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyViolation::DisallowedPort => write!(f, "CONNECT到未允许的端口"),
+            PolicyViolation::BlockedDestination => write!(f, "访问被禁止的目标"),
+            PolicyViolation::DisallowedMethod => write!(f, "不允许的请求方法"),
+            PolicyViolation::MissingSni => write!(f, "CONNECT隧道缺少SNI"),
+        }
+    }
+}
+
+/// 策略拒绝日志的汇总周期
+pub const POLICY_LOG_WINDOW: Duration = Duration::from_secs(60);
+
+/// 限速的策略拒绝日志
+///
+/// 每个周期内每类拒绝只以warn级别记录第一次的详情，其余只计数；
+/// 周期结束后再出现同类拒绝时，先输出上一周期的总次数。
+/// 扫描器大量触发拒绝时，日志行数与周期数而不是请求数成正比
+#[derive(Debug)]
+pub struct PolicyLog {
+    window: Duration,
+    windows: Mutex<HashMap<PolicyViolation, PolicyWindow>>,
+}
+
+#[derive(Debug)]
+struct PolicyWindow {
+    started: Instant,
+    count: u64,
+}
+
+impl PolicyLog {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 记录一次策略拒绝，`detail` 描述客户端和目标
+    pub fn record(&self, violation: PolicyViolation, detail: &str) {
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        if let Some(current) = windows.get_mut(&violation) {
+            let elapsed = now.duration_since(current.started);
+            if elapsed < self.window {
+                current.count += 1;
+                return;
+            }
+            if current.count > 1 {
+                warn!(
+                    "过去 {} 秒内共拒绝 {} 次{}",
+                    elapsed.as_secs(),
+                    current.count,
+                    violation
+                );
+            }
+        }
+        warn!(
+            "拒绝{}: {}（同类拒绝在汇总周期内不再逐条记录）",
+            violation, detail
+        );
+        windows.insert(
+            violation,
+            PolicyWindow {
+                started: now,
+                count: 1,
+            },
+        );
+    }
+}
+
+/// 按全局限速日志记录一次策略拒绝，见 [`PolicyLog`]
+pub fn policy_violation(violation: PolicyViolation, detail: &str) {
+    static POLICY_LOG: OnceLock<PolicyLog> = OnceLock::new();
+    POLICY_LOG
+        .get_or_init(|| PolicyLog::new(POLICY_LOG_WINDOW))
+        .record(violation, detail);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_log_carries_connection_fields() {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .with_writer(move || writer.clone())
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = connection_span("127.0.0.1:5000".parse().unwrap(), "0123456789ab");
+            let _entered = span.enter();
+            record_protocol("connect");
+            record_target("example.com", 443);
+            relay_finished(10, 20);
+        });
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let line: serde_json::Value = serde_json::from_str(output.lines().last().unwrap()).unwrap();
+        assert_eq!(line["fields"]["bytes_in"], 10);
+        assert_eq!(line["fields"]["bytes_out"], 20);
+        assert_eq!(line["span"]["request_id"], "0123456789ab");
+        assert_eq!(line["span"]["client_addr"], "127.0.0.1:5000");
+        assert_eq!(line["span"]["protocol"], "connect");
+        assert_eq!(line["span"]["target_host"], "example.com");
+        assert_eq!(line["span"]["target_port"], 443);
+    }
+
+    #[test]
+    fn test_policy_violations_are_aggregated() {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+
+        let log = PolicyLog::new(Duration::from_millis(200));
+        tracing::subscriber::with_default(subscriber, || {
+            for port in 0..100 {
+                log.record(
+                    PolicyViolation::DisallowedPort,
+                    &format!("127.0.0.1:{}", port),
+                );
+            }
+            log.record(PolicyViolation::BlockedDestination, "10.0.0.1:80");
+            std::thread::sleep(Duration::from_millis(250));
+            log.record(PolicyViolation::DisallowedPort, "127.0.0.1:100");
+        });
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 4, "日志: {}", output);
+        assert!(lines[0].contains("127.0.0.1:0"));
+        assert!(lines[1].contains("10.0.0.1:80"));
+        assert!(lines[2].contains("共拒绝 100 次CONNECT到未允许的端口"));
+        assert!(lines[3].contains("127.0.0.1:100"));
+    }
+
+    #[test]
+    fn test_request_ids_are_short_and_distinct() {
+        let first = next_request_id();
+        let second = next_request_id();
+        assert_eq!(first.len(), 12);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_parse_log_format() {
+        assert_eq!("JSON".parse::<LogFormat>(), Ok(LogFormat::Json));
+        assert_eq!("text".parse::<LogFormat>(), Ok(LogFormat::Text));
+        assert!("xml".parse::<LogFormat>().is_err());
+    }
+}
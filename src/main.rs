@@ -1,78 +1,351 @@
-use rust_proxy::auth::AuthConfig;
+use rust_proxy::access_log::AccessLog;
+use rust_proxy::check;
 use rust_proxy::config::Config;
-use rust_proxy::proxy::Proxy;
+use rust_proxy::handlers::backend::{self, BackendConnector, ConnectionPool};
+use rust_proxy::health::{self, Readiness};
+use rust_proxy::logging;
+use rust_proxy::metrics;
+use rust_proxy::proxy::{self, Proxy};
+use rust_proxy::reload;
+#[cfg(unix)]
+use rust_proxy::reload::Reloader;
+use rust_proxy::selftest;
+use rust_proxy::tls;
+use std::collections::HashMap;
 use std::error::Error;
 use std::net::SocketAddr;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
 use tokio::sync::Semaphore;
 use tracing::{error, info};
-use tracing_subscriber;
 
 #[tokio::main]
 async fn std_main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    // 解析命令行参数
+    let config = Config::from_args()?;
+
     // 初始化日志
-    tracing_subscriber::fmt::init();
+    logging::init(config.log_format);
 
-    // 解析命令行参数
-    let config = Config::from_args();
-
-    // 创建认证配置
-    let auth_config = if config.auth_enabled() {
-        Some(AuthConfig::new(
-            config.username.clone().unwrap(),
-            config.password.clone().unwrap(),
-        ))
+    if let Some(bytes) = config.self_test {
+        info!("🧪 运行吞吐量自检，传输 {} 字节", bytes);
+        let connector = BackendConnector::new()
+            .with_idle_timeout(config.idle_timeout_secs.map(Duration::from_secs));
+        let proxy = Proxy::new(None)
+            .with_connector(connector)
+            .with_max_header_size(config.max_header_size);
+        let report = selftest::run(proxy, bytes).await?;
+        info!("✅ 自检完成: {}", report);
+        return Ok(());
+    }
+
+    if config.check {
+        info!("🔍 检查配置");
+        match check::run(&config).await {
+            Ok(report) => {
+                info!("✅ 配置检查通过\n{}", report);
+                return Ok(());
+            }
+            Err(e) => {
+                error!("❌ 配置检查失败: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // 创建认证配置，用户文件与命令行账号可同时使用
+    let auth_config = reload::load_auth_config(&config)?;
+    if let Some(auth) = &auth_config {
+        info!("已加载 {} 个认证账号", auth.user_count());
+    }
+
+    // 创建代理服务器
+    let backend_tls = if config.backend_tls {
+        Some(tls::client_config(
+            config.backend_tls_ca.as_deref(),
+            config.backend_tls_insecure,
+        )?)
     } else {
         None
     };
-
-    // 创建代理服务器
-    let proxy = Proxy::new(auth_config);
-    let addr = SocketAddr::new(config.ip, config.port);
-    // 绑定监听端口
-    let listener = TcpListener::bind(addr).await?;
+    let listener_tls = match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => Some(tls::server_config(
+            cert,
+            key,
+            &config.tls_alpn,
+            config.min_tls_version,
+        )?),
+        (None, None) => None,
+        _ => return Err("--tls-cert 与 --tls-key 必须同时指定".into()),
+    };
+    let fallback = match &config.fallback_dest {
+        Some(target) => Some(backend::parse_host_port(target)?),
+        None => None,
+    };
+    let access_rules = reload::load_access_rules(&config)?;
+    if let Some(rules) = &access_rules {
+        info!(
+            "已加载访问规则：白名单 {} 条，黑名单 {} 条",
+            rules.allow_count(),
+            rules.block_count()
+        );
+    }
+    let connector = BackendConnector::new()
+        .with_connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+        .with_buffer_size(config.relay_buffer_size)
+        .with_max_inflight_bytes(config.max_inflight_bytes)
+        .with_quick_check(config.connect_quick_check_ms.map(Duration::from_millis))
+        .with_attempt_delay(Duration::from_millis(config.connect_attempt_delay_ms))
+        .with_max_pending_dials(config.max_pending_dials)
+        .with_upstream(config.upstream.clone())
+        .with_tls(backend_tls)
+        .with_idle_timeout(config.idle_timeout_secs.map(Duration::from_secs))
+        .with_pool(config.pool_idle_timeout_secs.map(|secs| {
+            ConnectionPool::new(Duration::from_secs(secs))
+                .with_max_entries(config.backend_pool_max_entries)
+        }))
+        .with_teardown_grace(Duration::from_millis(config.teardown_grace_ms))
+        .with_sni_overrides(config.outbound_sni.clone())
+        .with_fallback(fallback)
+        .with_access_rules(access_rules)
+        .with_block_private(config.block_private);
+    let readiness = match &config.health_target {
+        Some(target) => {
+            let (host, port) = backend::parse_host_port(target)?;
+            // 就绪探测只反映探测目标本身是否可达，不切换到备用目标
+            Some(Readiness::spawn(
+                connector.clone().with_fallback(None),
+                host,
+                port,
+                Duration::from_secs(config.health_interval_secs),
+            ))
+        }
+        None => None,
+    };
+    let landing_page = match &config.landing_page {
+        Some(path) => Some(std::fs::read_to_string(path)?),
+        None => None,
+    };
+    let routes = config
+        .routes
+        .iter()
+        .map(|(host, addr)| Ok((host.clone(), backend::parse_host_port(addr)?)))
+        .collect::<Result<HashMap<_, _>, String>>()?;
+    if !routes.is_empty() {
+        info!("🔀 反向代理模式，已加载 {} 条路由", routes.len());
+    }
+    let scanner_body = match &config.scanner_body {
+        Some(path) => Some(std::fs::read_to_string(path)?),
+        None => None,
+    };
+    let access_log = match &config.access_log {
+        Some(path) => {
+            info!("📝 访问日志: {}", path.display());
+            Some(AccessLog::open(path).await?)
+        }
+        None => None,
+    };
+    let proxy = Proxy::new(auth_config)
+        .with_connector(connector)
+        .with_landing_page(landing_page)
+        .with_deflect_scanners(config.deflect_scanners)
+        .with_scanner_body(scanner_body)
+        .with_max_header_size(config.max_header_size)
+        .with_initial_read_size(config.initial_read_size)
+        .with_trusted_proxies(config.trusted_proxies.clone())
+        .with_no_auth_for_localhost(config.no_auth_for_localhost)
+        .with_auth_exempt(config.auth_exempt.clone())
+        .with_trust_forwarded_proto(config.trust_forwarded_proto)
+        .with_strict_headers(config.strict_headers)
+        .with_require_user_agent(config.require_user_agent)
+        .with_http10_close(!config.http10_keep_alive)
+        .with_preserve_proxy_connection(config.preserve_proxy_connection)
+        .with_no_pipelining(config.no_pipelining)
+        .with_strip_forwarded(config.strip_forwarded)
+        .with_allow_generic_upgrade(config.allow_generic_upgrade)
+        .with_allowed_methods(config.allowed_methods.clone())
+        .with_remove_request_headers(config.remove_request_headers.clone())
+        .with_add_request_headers(config.add_request_headers.clone())
+        .with_max_websocket_sessions(config.max_websocket_sessions)
+        .with_websocket_close_frame(config.websocket_close_frame)
+        .with_handshake_timeout(Duration::from_secs(config.handshake_timeout_secs))
+        .with_allowed_connect_ports(config.allowed_connect_ports.clone())
+        .with_require_sni(config.require_sni)
+        .with_accept_proxy_protocol(config.accept_proxy_protocol)
+        .with_routes(routes)
+        .with_accept_watchdog(config.accept_watchdog_secs.map(Duration::from_secs))
+        .with_accept_workers(config.accept_workers)
+        .with_access_log(access_log.clone())
+        .with_tls(listener_tls)
+        .with_readiness(readiness);
+    // 配置了Unix域套接字时只在该套接字上提供服务，否则绑定TCP监听地址，
+    // 部分地址失败时继续在其余地址上提供服务
+    let (listeners, addrs) = match &config.unix_socket {
+        Some(path) => (bind_unix_socket(path)?, format!("unix:{}", path.display())),
+        None => {
+            let listeners = proxy::bind_listeners(&config.listen_addrs()).await;
+            if listeners.is_empty() {
+                return Err("所有监听地址均绑定失败".into());
+            }
+            let addrs = listeners
+                .iter()
+                .filter_map(|listener| listener.local_addr().ok())
+                .map(|addr| {
+                    if proxy::is_dual_stack(addr) {
+                        format!("{} (IPv4/IPv6双栈)", addr)
+                    } else {
+                        addr.to_string()
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            (Listeners::Tcp(listeners), addrs)
+        }
+    };
 
     if config.auth_enabled() {
         info!(
-            "🔒 代理服务器: {}:{} (最大连接数: {})",
-            config.ip, config.port, config.max_connections
+            "🔒 代理服务器: {} (最大连接数: {})",
+            addrs, config.max_connections
         );
     } else {
         info!(
-            "🔓 代理服务器: {}:{} (最大连接数: {})",
-            config.ip, config.port, config.max_connections
+            "🔓 代理服务器: {} (最大连接数: {})",
+            addrs, config.max_connections
         );
     }
 
+    if let Some(upstream) = &config.upstream {
+        info!("🔗 上游代理: {}", upstream);
+    }
+
+    // 在独立端口上提供指标端点
+    if let Some(metrics_port) = config.metrics_port {
+        let metrics_listener = proxy::bind_listener(SocketAddr::new(config.ip, metrics_port))?;
+        tokio::spawn(metrics::serve(metrics_listener));
+    }
+    if let Some(health_port) = config.health_port {
+        let health_listener = proxy::bind_listener(SocketAddr::new(config.ip, health_port))?;
+        tokio::spawn(health::serve(health_listener));
+    }
+
+    // 收到SIGHUP时重新加载认证账号与访问规则，不影响已建立的连接
+    #[cfg(unix)]
+    tokio::spawn(Reloader::new(config.clone(), proxy.clone()).watch_sighup());
+
     // 创建信号量来限制并发连接数
     let semaphore = Arc::new(Semaphore::new(config.max_connections));
 
-    loop {
-        match listener.accept().await {
-            Ok((stream, remote_addr)) => {
-                info!("接受新连接来自: {}", remote_addr);
-
-                // 获取信号量许可
-                let permit = match semaphore.clone().acquire_owned().await {
-                    Ok(permit) => permit,
-                    Err(e) => {
-                        error!("获取连接许可失败: {}", e);
-                        continue;
-                    }
-                };
-
-                let proxy_clone = proxy.clone();
-                tokio::spawn(async move {
-                    proxy_clone.handle_connection(stream, remote_addr).await;
-                    // 释放许可
-                    drop(permit);
-                });
+    // 收到第一个终止信号后停止接受新连接，返回时监听器已关闭
+    match listeners {
+        Listeners::Tcp(listeners) => {
+            proxy
+                .serve_listeners_with_shutdown(listeners, semaphore.clone(), shutdown_signal())
+                .await?
+        }
+        #[cfg(unix)]
+        Listeners::Unix(listener) => {
+            proxy
+                .serve_unix_with_shutdown(listener, semaphore.clone(), shutdown_signal())
+                .await?
+        }
+    }
+
+    // 在宽限期内等待活跃连接结束：所有许可都归还即表示连接已全部结束
+    let active = config.max_connections - semaphore.available_permits();
+    let grace = Duration::from_secs(config.shutdown_grace_secs);
+    info!(
+        "🛑 停止接受新连接，等待 {} 个活跃连接结束（最长 {:?}），再次发送信号立即退出",
+        active, grace
+    );
+
+    let drain = semaphore.acquire_many(config.max_connections as u32);
+    tokio::select! {
+        _ = tokio::time::timeout(grace, drain) => {}
+        _ = shutdown_signal() => {
+            info!("收到第二次终止信号，立即退出");
+        }
+    }
+
+    let remaining = config.max_connections - semaphore.available_permits();
+    info!(
+        "服务器已关闭：{} 个连接正常结束，{} 个连接被强制关闭",
+        active.saturating_sub(remaining),
+        remaining
+    );
+
+    // 确保已结束连接的访问记录全部写入文件
+    if let Some(access_log) = &access_log {
+        access_log.flush().await;
+    }
+
+    if let Some(path) = &config.unix_socket {
+        let _ = std::fs::remove_file(path);
+    }
+
+    Ok(())
+}
+
+/// 代理接受连接的监听器
+enum Listeners {
+    Tcp(Vec<TcpListener>),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+/// 绑定Unix域套接字，先删除上次运行遗留的套接字文件
+#[cfg(unix)]
+fn bind_unix_socket(path: &Path) -> Result<Listeners, Box<dyn Error + Send + Sync>> {
+    use std::os::unix::fs::FileTypeExt;
+
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(format!("{} 已存在且不是套接字文件", path.display()).into());
+        }
+        std::fs::remove_file(path)?;
+    }
+    match UnixListener::bind(path) {
+        Ok(listener) => Ok(Listeners::Unix(listener)),
+        Err(e) => Err(format!("绑定Unix域套接字 {} 失败: {}", path.display(), e).into()),
+    }
+}
+
+#[cfg(not(unix))]
+fn bind_unix_socket(_path: &Path) -> Result<Listeners, Box<dyn Error + Send + Sync>> {
+    Err("当前平台不支持Unix域套接字".into())
+}
+
+/// 等待 SIGINT（Ctrl+C）或 SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("监听Ctrl+C信号失败: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
             }
             Err(e) => {
-                error!("接受连接失败: {}", e);
+                error!("监听SIGTERM信号失败: {}", e);
+                std::future::pending::<()>().await;
             }
         }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
     }
 }
 
@@ -0,0 +1,412 @@
+use crate::parser::detector::ProtocolType;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::future::Future;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, error, info};
+
+/// 按协议统计请求数时使用的标签
+const PROTOCOLS: [&str; 7] = [
+    "http1",
+    "http2",
+    "websocket",
+    "connect",
+    "socks4",
+    "socks5",
+    "unknown",
+];
+
+/// 单独统计的租户数上限，超过后新租户不再计入按租户的指标
+const MAX_TENANTS: usize = 1024;
+
+tokio::task_local! {
+    /// 当前连接所属的租户
+    static TENANT: RefCell<Option<String>>;
+}
+
+/// 在租户作用域内运行连接任务
+///
+/// 作用域内通过 [`set_tenant`] 标记租户后，转发的字节数同时计入该租户
+pub async fn tenant_scope<F: Future>(future: F) -> F::Output {
+    TENANT.scope(RefCell::new(None), future).await
+}
+
+/// 标记当前连接所属的租户，不在 [`tenant_scope`] 内时忽略
+pub fn set_tenant(tenant: &str) {
+    let _ = TENANT.try_with(|current| *current.borrow_mut() = Some(tenant.to_string()));
+}
+
+fn current_tenant() -> Option<String> {
+    TENANT
+        .try_with(|current| current.borrow().clone())
+        .ok()
+        .flatten()
+}
+
+/// 单个租户的累计指标
+#[derive(Debug, Default, Clone, Copy)]
+struct TenantStats {
+    requests: u64,
+    bytes_in: u64,
+    bytes_out: u64,
+}
+
+/// 全局指标
+static METRICS: Metrics = Metrics::new();
+
+/// 获取全局指标
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+/// 代理运行指标，以Prometheus文本格式导出
+pub struct Metrics {
+    connections_total: AtomicU64,
+    active_connections: AtomicI64,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    auth_failures: AtomicU64,
+    requests: [AtomicU64; PROTOCOLS.len()],
+    permit_wait_micros: AtomicU64,
+    permit_waits: AtomicU64,
+    accept_stalls: AtomicU64,
+    pool_idle: AtomicU64,
+    pool_evictions: AtomicU64,
+    tenants: Mutex<BTreeMap<String, TenantStats>>,
+}
+
+/// 活跃连接计数守卫，释放时活跃连接数减一
+pub struct ConnectionGuard<'a>(&'a Metrics);
+
+impl Drop for ConnectionGuard<'_> {
+    fn drop(&mut self) {
+        self.0.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            connections_total: AtomicU64::new(0),
+            active_connections: AtomicI64::new(0),
+            bytes_in: AtomicU64::new(0),
+            bytes_out: AtomicU64::new(0),
+            auth_failures: AtomicU64::new(0),
+            requests: [
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+            ],
+            permit_wait_micros: AtomicU64::new(0),
+            permit_waits: AtomicU64::new(0),
+            accept_stalls: AtomicU64::new(0),
+            pool_idle: AtomicU64::new(0),
+            pool_evictions: AtomicU64::new(0),
+            tenants: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// 当前活跃连接数
+    pub fn active_connections(&self) -> i64 {
+        self.active_connections.load(Ordering::Relaxed)
+    }
+
+    /// 记录新连接，返回的守卫在连接结束时释放
+    pub fn connection_opened(&self) -> ConnectionGuard<'_> {
+        self.connections_total.fetch_add(1, Ordering::Relaxed);
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard(self)
+    }
+
+    /// 记录转发的字节数
+    ///
+    /// # 参数
+    /// * `bytes_in` - 客户端→目标
+    /// * `bytes_out` - 目标→客户端
+    pub fn record_bytes(&self, bytes_in: u64, bytes_out: u64) {
+        self.bytes_in.fetch_add(bytes_in, Ordering::Relaxed);
+        self.bytes_out.fetch_add(bytes_out, Ordering::Relaxed);
+        if let Some(tenant) = current_tenant() {
+            self.update_tenant(&tenant, |stats| {
+                stats.bytes_in += bytes_in;
+                stats.bytes_out += bytes_out;
+            });
+        }
+    }
+
+    /// 记录租户的一次请求
+    pub fn record_tenant_request(&self, tenant: &str) {
+        self.update_tenant(tenant, |stats| stats.requests += 1);
+    }
+
+    fn update_tenant(&self, tenant: &str, update: impl FnOnce(&mut TenantStats)) {
+        let mut tenants = self.tenants.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(stats) = tenants.get_mut(tenant) {
+            update(stats);
+        } else if tenants.len() < MAX_TENANTS {
+            update(tenants.entry(tenant.to_string()).or_default());
+        }
+    }
+
+    /// 记录认证失败
+    pub fn record_auth_failure(&self) {
+        self.auth_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 记录等待连接许可的时间
+    pub fn record_permit_wait(&self, waited: Duration) {
+        self.permit_wait_micros
+            .fetch_add(waited.as_micros() as u64, Ordering::Relaxed);
+        self.permit_waits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 记录接受循环停滞
+    pub fn record_accept_stall(&self) {
+        self.accept_stalls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 更新源站连接池中的空闲连接数
+    pub fn set_pool_idle(&self, idle: usize) {
+        self.pool_idle.store(idle as u64, Ordering::Relaxed);
+    }
+
+    /// 记录源站连接池因达到上限关闭的一个空闲连接
+    pub fn record_pool_eviction(&self) {
+        self.pool_evictions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 按协议类型记录请求
+    pub fn record_request(&self, protocol: &ProtocolType) {
+        let label = protocol.label();
+        if let Some(index) = PROTOCOLS.iter().position(|p| *p == label) {
+            self.requests[index].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// 以Prometheus文本格式输出所有指标
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: String| {
+            let _ = writeln!(output, "# HELP {} {}", name, help);
+            let _ = writeln!(output, "# TYPE {} {}", name, kind);
+            let _ = writeln!(output, "{} {}", name, value);
+        };
+
+        metric(
+            "rust_proxy_connections_total",
+            "counter",
+            "接受的连接总数",
+            self.connections_total.load(Ordering::Relaxed).to_string(),
+        );
+        metric(
+            "rust_proxy_active_connections",
+            "gauge",
+            "当前活跃的连接数",
+            self.active_connections.load(Ordering::Relaxed).to_string(),
+        );
+        metric(
+            "rust_proxy_bytes_in_total",
+            "counter",
+            "客户端发往目标的字节数",
+            self.bytes_in.load(Ordering::Relaxed).to_string(),
+        );
+        metric(
+            "rust_proxy_bytes_out_total",
+            "counter",
+            "目标发往客户端的字节数",
+            self.bytes_out.load(Ordering::Relaxed).to_string(),
+        );
+        metric(
+            "rust_proxy_auth_failures_total",
+            "counter",
+            "认证失败次数",
+            self.auth_failures.load(Ordering::Relaxed).to_string(),
+        );
+        metric(
+            "rust_proxy_accept_stalls_total",
+            "counter",
+            "接受循环超过看门狗间隔未接受连接的次数",
+            self.accept_stalls.load(Ordering::Relaxed).to_string(),
+        );
+        metric(
+            "rust_proxy_backend_pool_idle_connections",
+            "gauge",
+            "源站连接池中的空闲连接数",
+            self.pool_idle.load(Ordering::Relaxed).to_string(),
+        );
+        metric(
+            "rust_proxy_backend_pool_evictions_total",
+            "counter",
+            "源站连接池因空闲连接数达到上限关闭的连接数",
+            self.pool_evictions.load(Ordering::Relaxed).to_string(),
+        );
+
+        let _ = writeln!(
+            output,
+            "# HELP rust_proxy_requests_total 按协议统计的请求数"
+        );
+        let _ = writeln!(output, "# TYPE rust_proxy_requests_total counter");
+        for (label, count) in PROTOCOLS.iter().zip(&self.requests) {
+            let _ = writeln!(
+                output,
+                "rust_proxy_requests_total{{protocol=\"{}\"}} {}",
+                label,
+                count.load(Ordering::Relaxed)
+            );
+        }
+
+        let _ = writeln!(
+            output,
+            "# HELP rust_proxy_permit_wait_seconds 接受连接后等待并发许可的时间"
+        );
+        let _ = writeln!(output, "# TYPE rust_proxy_permit_wait_seconds summary");
+        let _ = writeln!(
+            output,
+            "rust_proxy_permit_wait_seconds_sum {}",
+            self.permit_wait_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0
+        );
+        let _ = writeln!(
+            output,
+            "rust_proxy_permit_wait_seconds_count {}",
+            self.permit_waits.load(Ordering::Relaxed)
+        );
+
+        let tenants = self
+            .tenants
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        type Field = fn(&TenantStats) -> u64;
+        let tenant_metrics: [(&str, &str, Field); 3] = [
+            (
+                "rust_proxy_tenant_requests_total",
+                "按租户统计的请求数",
+                |stats| stats.requests,
+            ),
+            (
+                "rust_proxy_tenant_bytes_in_total",
+                "按租户统计的客户端发往目标的字节数",
+                |stats| stats.bytes_in,
+            ),
+            (
+                "rust_proxy_tenant_bytes_out_total",
+                "按租户统计的目标发往客户端的字节数",
+                |stats| stats.bytes_out,
+            ),
+        ];
+        for (name, help, value) in tenant_metrics {
+            let _ = writeln!(output, "# HELP {} {}", name, help);
+            let _ = writeln!(output, "# TYPE {} counter", name);
+            for (tenant, stats) in &tenants {
+                let _ = writeln!(output, "{}{{tenant=\"{}\"}} {}", name, tenant, value(stats));
+            }
+        }
+
+        output
+    }
+}
+
+/// 在独立端口上提供 `/metrics` 端点
+pub async fn serve(listener: TcpListener) {
+    if let Ok(addr) = listener.local_addr() {
+        info!("📈 指标端点: http://{}/metrics", addr);
+    }
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, addr)) => {
+                tokio::spawn(async move {
+                    if let Err(e) = serve_scrape(stream).await {
+                        debug!("[{}] 指标请求处理失败: {}", addr, e);
+                    }
+                });
+            }
+            Err(e) => {
+                error!("指标端点接受连接失败: {}", e);
+            }
+        }
+    }
+}
+
+/// 读取管理端点的请求头，返回请求路径；请求头结束前连接关闭时返回 `None`
+pub(crate) async fn read_request_path(stream: &mut TcpStream) -> std::io::Result<Option<String>> {
+    let mut buffer = vec![0u8; 4096];
+    let mut len = 0;
+    while !buffer[..len].windows(4).any(|w| w == b"\r\n\r\n") && len < buffer.len() {
+        let n = stream.read(&mut buffer[len..]).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        len += n;
+    }
+
+    let request = String::from_utf8_lossy(&buffer[..len]);
+    let path = request
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .unwrap_or_default();
+    Ok(Some(path.to_string()))
+}
+
+async fn serve_scrape(mut stream: TcpStream) -> std::io::Result<()> {
+    let path = match read_request_path(&mut stream).await? {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+
+    let (status, body) = if path == "/metrics" {
+        ("200 OK", metrics().render())
+    } else {
+        ("404 Not Found", "Not Found".to_string())
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_prometheus_format() {
+        let metrics = Metrics::new();
+        let guard = metrics.connection_opened();
+        metrics.record_bytes(10, 20);
+        metrics.record_request(&ProtocolType::Http11);
+        metrics.record_request(&ProtocolType::Socks5);
+        metrics.record_permit_wait(Duration::from_millis(1500));
+
+        let output = metrics.render();
+        assert!(output.contains("# TYPE rust_proxy_connections_total counter\n"));
+        assert!(output.contains("rust_proxy_connections_total 1\n"));
+        assert!(output.contains("rust_proxy_bytes_in_total 10\n"));
+        assert!(output.contains("rust_proxy_bytes_out_total 20\n"));
+        assert!(output.contains("rust_proxy_requests_total{protocol=\"http1\"} 1\n"));
+        assert!(output.contains("rust_proxy_requests_total{protocol=\"socks5\"} 1\n"));
+        assert!(output.contains("rust_proxy_requests_total{protocol=\"http2\"} 0\n"));
+        assert!(output.contains("rust_proxy_active_connections 1\n"));
+        assert!(output.contains("rust_proxy_permit_wait_seconds_sum 1.5\n"));
+        assert!(output.contains("rust_proxy_permit_wait_seconds_count 1\n"));
+
+        drop(guard);
+        assert!(metrics
+            .render()
+            .contains("rust_proxy_active_connections 0\n"));
+    }
+}
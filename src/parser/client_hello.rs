@@ -0,0 +1,192 @@
+/// 缓冲ClientHello的最大字节数（含TLS记录头），超过后按无效处理
+pub const MAX_CLIENT_HELLO: usize = 16 * 1024;
+
+/// TLS记录类型：握手
+const CONTENT_HANDSHAKE: u8 = 0x16;
+/// 握手消息类型：ClientHello
+const HANDSHAKE_CLIENT_HELLO: u8 = 0x01;
+/// 扩展类型：server_name（RFC 6066）
+const EXTENSION_SERVER_NAME: u16 = 0x0000;
+/// server_name扩展中的名称类型：host_name
+const NAME_TYPE_HOST_NAME: u8 = 0x00;
+
+/// 解析ClientHello的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientHello {
+    /// 数据不足，ClientHello尚未完整到达
+    Incomplete,
+    /// 完整的ClientHello，携带SNI主机名，没有SNI时为 `None`
+    Complete(Option<String>),
+    /// 不是TLS ClientHello或格式错误
+    Invalid,
+}
+
+/// 从客户端发出的首批数据中解析TLS ClientHello及其SNI
+///
+/// ClientHello可能拆分在多个TLS记录中，按顺序拼接各记录的握手数据后再解析
+pub fn parse_client_hello(data: &[u8]) -> ClientHello {
+    let mut handshake = Vec::new();
+    let mut records = data;
+    loop {
+        if handshake.len() >= 4 {
+            let length = u32::from_be_bytes([0, handshake[1], handshake[2], handshake[3]]) as usize;
+            if handshake[0] != HANDSHAKE_CLIENT_HELLO || length + 9 > MAX_CLIENT_HELLO {
+                return ClientHello::Invalid;
+            }
+            if handshake.len() >= length + 4 {
+                return match server_name(&handshake[4..length + 4]) {
+                    Some(name) => ClientHello::Complete(name),
+                    None => ClientHello::Invalid,
+                };
+            }
+        }
+        if records.len() < 5 {
+            return ClientHello::Incomplete;
+        }
+        if records[0] != CONTENT_HANDSHAKE || records[1] != 0x03 {
+            return ClientHello::Invalid;
+        }
+        let length = u16::from_be_bytes([records[3], records[4]]) as usize;
+        if length == 0 {
+            return ClientHello::Invalid;
+        }
+        if records.len() < length + 5 {
+            return ClientHello::Incomplete;
+        }
+        handshake.extend_from_slice(&records[5..length + 5]);
+        records = &records[length + 5..];
+    }
+}
+
+/// 从ClientHello消息体中取出SNI主机名，格式错误时返回 `None`
+fn server_name(body: &[u8]) -> Option<Option<String>> {
+    let mut reader = Reader(body);
+    // 协议版本与随机数
+    reader.take(2 + 32)?;
+    let session_id = reader.u8()? as usize;
+    reader.take(session_id)?;
+    let cipher_suites = reader.u16()? as usize;
+    reader.take(cipher_suites)?;
+    let compression = reader.u8()? as usize;
+    reader.take(compression)?;
+    if reader.0.is_empty() {
+        // 没有扩展
+        return Some(None);
+    }
+
+    let extensions = reader.u16()? as usize;
+    let mut extensions = Reader(reader.take(extensions)?);
+    while !extensions.0.is_empty() {
+        let kind = extensions.u16()?;
+        let length = extensions.u16()? as usize;
+        let data = extensions.take(length)?;
+        if kind != EXTENSION_SERVER_NAME {
+            continue;
+        }
+        let mut data = Reader(data);
+        let list = data.u16()? as usize;
+        let mut list = Reader(data.take(list)?);
+        while !list.0.is_empty() {
+            let name_type = list.u8()?;
+            let length = list.u16()? as usize;
+            let name = list.take(length)?;
+            if name_type == NAME_TYPE_HOST_NAME && !name.is_empty() {
+                return Some(Some(String::from_utf8_lossy(name).into_owned()));
+            }
+        }
+        return Some(None);
+    }
+    Some(None)
+}
+
+/// 按大端序读取定长字段的游标
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 构造携带可选SNI的ClientHello，按 `fragment` 字节拆分为多个TLS记录
+    fn client_hello(sni: Option<&str>, fragment: usize) -> Vec<u8> {
+        let mut extensions = Vec::new();
+        if let Some(name) = sni {
+            let name = name.as_bytes();
+            extensions.extend_from_slice(&[0x00, 0x00]);
+            extensions.extend_from_slice(&(name.len() as u16 + 5).to_be_bytes());
+            extensions.extend_from_slice(&(name.len() as u16 + 3).to_be_bytes());
+            extensions.push(NAME_TYPE_HOST_NAME);
+            extensions.extend_from_slice(&(name.len() as u16).to_be_bytes());
+            extensions.extend_from_slice(name);
+        }
+        // supported_versions: TLS 1.3
+        extensions.extend_from_slice(&[0x00, 0x2b, 0x00, 0x03, 0x02, 0x03, 0x04]);
+
+        let mut body = vec![0x03, 0x03];
+        body.extend_from_slice(&[0x11; 32]);
+        body.push(0);
+        body.extend_from_slice(&[0x00, 0x02, 0x13, 0x01]);
+        body.extend_from_slice(&[0x01, 0x00]);
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(&extensions);
+
+        let mut handshake = vec![HANDSHAKE_CLIENT_HELLO];
+        handshake.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        handshake.extend_from_slice(&body);
+
+        let mut records = Vec::new();
+        for chunk in handshake.chunks(fragment) {
+            records.extend_from_slice(&[CONTENT_HANDSHAKE, 0x03, 0x01]);
+            records.extend_from_slice(&(chunk.len() as u16).to_be_bytes());
+            records.extend_from_slice(chunk);
+        }
+        records
+    }
+
+    #[test]
+    fn test_parse_sni() {
+        let hello = client_hello(Some("example.com"), 1024);
+        assert_eq!(
+            parse_client_hello(&hello),
+            ClientHello::Complete(Some("example.com".to_string()))
+        );
+        assert_eq!(
+            parse_client_hello(&client_hello(None, 1024)),
+            ClientHello::Complete(None)
+        );
+        assert_eq!(
+            parse_client_hello(b"GET / HTTP/1.1\r\n\r\n"),
+            ClientHello::Invalid
+        );
+    }
+
+    #[test]
+    fn test_fragmented_client_hello() {
+        let hello = client_hello(Some("example.com"), 10);
+        for end in 0..hello.len() {
+            assert_eq!(parse_client_hello(&hello[..end]), ClientHello::Incomplete);
+        }
+        assert_eq!(
+            parse_client_hello(&hello),
+            ClientHello::Complete(Some("example.com".to_string()))
+        );
+    }
+}
@@ -15,14 +15,40 @@ pub enum ProtocolType {
     },
     /// CONNECT 隧道 (HTTPS)
     ConnectTunnel { host: String, port: u16 },
+    /// SOCKS4/SOCKS4a
+    Socks4,
+    /// SOCKS5
+    Socks5,
     /// 未知协议
     Unknown,
 }
 
+impl ProtocolType {
+    /// 协议的简短标签，用于指标和日志
+    pub fn label(&self) -> &'static str {
+        match self {
+            ProtocolType::Http10 | ProtocolType::Http11 => "http1",
+            ProtocolType::Http2 => "http2",
+            ProtocolType::WebSocketUpgrade { .. } => "websocket",
+            ProtocolType::ConnectTunnel { .. } => "connect",
+            ProtocolType::Socks4 => "socks4",
+            ProtocolType::Socks5 => "socks5",
+            ProtocolType::Unknown => "unknown",
+        }
+    }
+}
+
 /// 检测协议类型
 ///
 /// 根据初始字节流判断客户端使用的协议类型
 pub fn detect_protocol(buffer: &[u8]) -> ProtocolType {
+    // SOCKS以版本号开头: SOCKS4为0x04，SOCKS5为0x05
+    match buffer.first() {
+        Some(0x04) => return ProtocolType::Socks4,
+        Some(0x05) => return ProtocolType::Socks5,
+        _ => {}
+    }
+
     // 检查HTTP/2 preface
     if is_http2_preface(buffer) {
         return ProtocolType::Http2;
@@ -30,18 +56,20 @@ pub fn detect_protocol(buffer: &[u8]) -> ProtocolType {
 
     // 尝试解析为HTTP/1.x请求
     if let Some(method) = parse_http_method(buffer) {
-        // 检查是否是CONNECT请求
+        // 检查是否是CONNECT请求，目标无效时不按普通请求转发
         if method == "CONNECT" {
-            if let Some((host, port)) = parse_connect_target(buffer) {
-                return ProtocolType::ConnectTunnel { host, port };
-            }
+            return match parse_connect_target(buffer) {
+                Some((host, port)) => ProtocolType::ConnectTunnel { host, port },
+                None => ProtocolType::Unknown,
+            };
         }
 
-        // 检查是否是WebSocket升级请求
+        // 检查是否是WebSocket升级请求，缺少握手所需头部时同样无法处理
         if is_websocket_upgrade(buffer) {
-            if let Some((host, port, key)) = parse_websocket_details(buffer) {
-                return ProtocolType::WebSocketUpgrade { key, host, port };
-            }
+            return match parse_websocket_details(buffer) {
+                Some((host, port, key)) => ProtocolType::WebSocketUpgrade { key, host, port },
+                None => ProtocolType::Unknown,
+            };
         }
 
         // 检查HTTP版本
@@ -49,6 +77,7 @@ pub fn detect_protocol(buffer: &[u8]) -> ProtocolType {
             match version.as_str() {
                 "HTTP/1.0" => return ProtocolType::Http10,
                 "HTTP/1.1" => return ProtocolType::Http11,
+                version if !version.starts_with("HTTP/") => return ProtocolType::Unknown,
                 _ => {}
             }
         }
@@ -60,6 +89,31 @@ pub fn detect_protocol(buffer: &[u8]) -> ProtocolType {
     ProtocolType::Unknown
 }
 
+/// 无法处理请求时返回给客户端的提示，说明期望的请求格式
+///
+/// 只根据请求的类别给出固定文本，不回显请求内容
+pub fn bad_request_hint(buffer: &[u8]) -> &'static str {
+    match parse_http_method(buffer).as_deref() {
+        Some("CONNECT") => "无效的CONNECT请求：请求目标应为 host:port",
+        Some(_) if is_websocket_upgrade(buffer) => {
+            "无效的WebSocket升级请求：需要Host头和Sec-WebSocket-Key头"
+        }
+        _ => "无法识别的请求：应为HTTP/1.x请求、CONNECT请求或SOCKS握手",
+    }
+}
+
+/// 字节能否作为请求的第一个字节：HTTP方法的token字符、请求行前的空行或SOCKS版本号
+///
+/// 不满足时无需等待完整的请求头部即可判定为无效请求
+pub fn is_request_start(byte: u8) -> bool {
+    matches!(byte, 0x04 | 0x05 | b'\r' | b'\n') || is_token_byte(byte)
+}
+
+/// RFC 9110 token字符
+pub(crate) fn is_token_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte)
+}
+
 /// 检查是否是HTTP/2 preface
 /// HTTP/2 preface: "PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n"
 fn is_http2_preface(buffer: &[u8]) -> bool {
@@ -78,6 +132,9 @@ fn parse_http_method(buffer: &[u8]) -> Option<String> {
     let first_line = request.lines().next()?;
 
     let method = first_line.split_whitespace().next()?;
+    if !method.bytes().all(is_token_byte) {
+        return None;
+    }
     Some(method.to_uppercase())
 }
 
@@ -104,12 +161,38 @@ fn parse_connect_target(buffer: &[u8]) -> Option<(String, u16)> {
         return None;
     }
 
-    let host_port = parts[1];
-    let mut parts = host_port.split(':');
-    let host = parts.next()?.to_string();
-    let port = parts.next()?.parse::<u16>().ok()?;
+    parse_authority(parts[1], None)
+}
 
-    Some((host, port))
+/// 解析 `host[:port]` 形式的authority
+///
+/// IPv6字面量需用方括号包裹（如 `[2001:db8::1]:443`），返回的主机不含方括号；
+/// 未指定端口时使用 `default_port`，为 `None` 时端口必须存在
+pub fn parse_authority(authority: &str, default_port: Option<u16>) -> Option<(String, u16)> {
+    let authority = authority.trim();
+    let (host, port) = match authority.strip_prefix('[') {
+        Some(rest) => {
+            let (host, rest) = rest.split_once(']')?;
+            match rest {
+                "" => (host, None),
+                _ => (host, Some(rest.strip_prefix(':')?)),
+            }
+        }
+        None => match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        },
+    };
+
+    if host.is_empty() {
+        return None;
+    }
+    let port = match port {
+        Some(port) => port.parse::<u16>().ok()?,
+        None => default_port?,
+    };
+
+    Some((host.to_string(), port))
 }
 
 /// 检查是否是WebSocket升级请求
@@ -119,7 +202,43 @@ fn is_websocket_upgrade(buffer: &[u8]) -> bool {
     request.contains("upgrade: websocket") && request.contains("connection: upgrade")
 }
 
+/// 非WebSocket协议升级请求（如 `Upgrade: h2c`）的 `Upgrade` 头
+///
+/// 只有 `Connection` 头列出 `upgrade` 时才视为升级请求；WebSocket升级及普通请求返回 `None`
+pub fn generic_upgrade_protocol(buffer: &[u8]) -> Option<String> {
+    let head_end = buffer
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .unwrap_or(buffer.len());
+    let head = String::from_utf8_lossy(&buffer[..head_end]);
+
+    let mut connection_upgrade = false;
+    let mut protocol = None;
+    for line in head.lines().skip(1) {
+        let (name, value) = match line.split_once(':') {
+            Some(header) => header,
+            None => continue,
+        };
+        let name = name.trim();
+        if name.eq_ignore_ascii_case("connection") {
+            connection_upgrade |= value
+                .split(',')
+                .any(|token| token.trim().eq_ignore_ascii_case("upgrade"));
+        } else if name.eq_ignore_ascii_case("upgrade") {
+            protocol = Some(value.trim().to_string());
+        }
+    }
+
+    let protocol = protocol.filter(|protocol| !protocol.is_empty())?;
+    let websocket = protocol
+        .split(',')
+        .any(|token| token.trim().eq_ignore_ascii_case("websocket"));
+    (connection_upgrade && !websocket).then_some(protocol)
+}
+
 /// 解析WebSocket握手详细信息
+///
+/// Host未指定端口时，`wss://`（或 `https://`）请求目标默认443，否则默认80
 fn parse_websocket_details(buffer: &[u8]) -> Option<(String, u16, String)> {
     let request = String::from_utf8_lossy(buffer);
 
@@ -134,23 +253,35 @@ fn parse_websocket_details(buffer: &[u8]) -> Option<(String, u16, String)> {
     let host_line = request
         .lines()
         .find(|line| line.to_lowercase().starts_with("host:"))?;
-
-    let host_value = host_line.split(':').nth(1)?.trim();
+    let host_value = host_line[5..].trim();
 
     // 解析host:port
-    let mut parts = host_value.split(':');
-    let host = parts.next()?.to_string();
-    let port = if let Some(port_str) = parts.next() {
-        port_str.parse::<u16>().ok()?
-    } else {
-        // WebSocket默认端口: ws=80, wss=443
-        // 这里简化处理，默认80
-        80
-    };
+    let default_port = default_websocket_port(is_secure_websocket_target(buffer));
+    let (host, port) = parse_authority(host_value, Some(default_port))?;
 
     Some((host, port, ws_key))
 }
 
+/// 请求目标是否为安全的WebSocket地址（`wss://` 或 `https://`）
+pub fn is_secure_websocket_target(buffer: &[u8]) -> bool {
+    let request = String::from_utf8_lossy(buffer);
+    request
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .map(|target| target.to_ascii_lowercase())
+        .is_some_and(|target| target.starts_with("wss://") || target.starts_with("https://"))
+}
+
+/// WebSocket默认端口: ws=80, wss=443
+pub fn default_websocket_port(secure: bool) -> u16 {
+    if secure {
+        443
+    } else {
+        80
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -185,6 +316,50 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_connect_ipv6_detection() {
+        let buffer = b"CONNECT [2001:db8::1]:443 HTTP/1.1\r\n\r\n";
+        assert_eq!(
+            detect_protocol(buffer),
+            ProtocolType::ConnectTunnel {
+                host: "2001:db8::1".to_string(),
+                port: 443
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_authority() {
+        assert_eq!(
+            parse_authority("[::1]:8080", None),
+            Some(("::1".to_string(), 8080))
+        );
+        assert_eq!(
+            parse_authority("[::1]", Some(80)),
+            Some(("::1".to_string(), 80))
+        );
+        assert_eq!(
+            parse_authority("example.com:443", Some(80)),
+            Some(("example.com".to_string(), 443))
+        );
+        assert_eq!(parse_authority("[::1]", None), None);
+        assert_eq!(parse_authority("[::1]x", Some(80)), None);
+    }
+
+    #[test]
+    fn test_generic_upgrade_protocol() {
+        let h2c = b"GET / HTTP/1.1\r\nHost: example.com\r\n\
+            Connection: Upgrade, HTTP2-Settings\r\nUpgrade: h2c\r\nHTTP2-Settings: AAMAAABkAAQAAP__\r\n\r\n";
+        assert_eq!(generic_upgrade_protocol(h2c), Some("h2c".to_string()));
+
+        // 没有 Connection: upgrade 时Upgrade头无效，WebSocket升级另行处理
+        let stray = b"GET / HTTP/1.1\r\nHost: example.com\r\nUpgrade: h2c\r\n\r\n";
+        assert_eq!(generic_upgrade_protocol(stray), None);
+        let websocket = b"GET / HTTP/1.1\r\nHost: example.com\r\n\
+            Connection: upgrade\r\nUpgrade: WebSocket\r\n\r\n";
+        assert_eq!(generic_upgrade_protocol(websocket), None);
+    }
+
     #[test]
     fn test_websocket_upgrade_detection() {
         let buffer = b"GET /chat HTTP/1.1\r\n\
@@ -202,4 +377,71 @@ mod tests {
             _ => panic!("Expected WebSocket upgrade"),
         }
     }
+
+    #[test]
+    fn test_websocket_default_port_by_scheme() {
+        let upgrade = |target: &str| {
+            let buffer = format!(
+                "GET {} HTTP/1.1\r\n\
+                Host: example.com\r\n\
+                Upgrade: websocket\r\n\
+                Connection: Upgrade\r\n\
+                Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+                target
+            );
+            match detect_protocol(buffer.as_bytes()) {
+                ProtocolType::WebSocketUpgrade { port, .. } => port,
+                other => panic!("Expected WebSocket upgrade, got {:?}", other),
+            }
+        };
+
+        assert_eq!(upgrade("ws://example.com/chat"), 80);
+        assert_eq!(upgrade("wss://example.com/chat"), 443);
+    }
+
+    #[test]
+    fn test_http1_without_version_defaults_to_http11() {
+        let buffer = b"GET /\r\n\r\n";
+        assert_eq!(detect_protocol(buffer), ProtocolType::Http11);
+    }
+
+    #[test]
+    fn test_socks5_detection() {
+        assert_eq!(detect_protocol(&[0x05, 0x01, 0x00]), ProtocolType::Socks5);
+    }
+
+    #[test]
+    fn test_socks4_detection() {
+        assert_eq!(
+            detect_protocol(&[0x04, 0x01, 0x00, 0x50, 127, 0, 0, 1, 0]),
+            ProtocolType::Socks4
+        );
+    }
+
+    #[test]
+    fn test_unknown_detection() {
+        assert_eq!(detect_protocol(b""), ProtocolType::Unknown);
+        assert_eq!(detect_protocol(b"\r\n\r\n"), ProtocolType::Unknown);
+        assert_eq!(
+            detect_protocol(b"\x16\x03\x01\x00\xa5\r\n\r\n"),
+            ProtocolType::Unknown
+        );
+        assert_eq!(
+            detect_protocol(b"GET / SPDY/3\r\n\r\n"),
+            ProtocolType::Unknown
+        );
+        assert!(!is_request_start(0x16));
+        assert!(is_request_start(b'G'));
+    }
+
+    #[test]
+    fn test_malformed_connect_and_websocket_are_unknown() {
+        let connect = b"CONNECT example.com HTTP/1.1\r\n\r\n";
+        assert_eq!(detect_protocol(connect), ProtocolType::Unknown);
+        assert!(bad_request_hint(connect).contains("host:port"));
+
+        let websocket = b"GET /chat HTTP/1.1\r\nHost: example.com\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\r\n";
+        assert_eq!(detect_protocol(websocket), ProtocolType::Unknown);
+        assert!(bad_request_hint(websocket).contains("Sec-WebSocket-Key"));
+    }
 }
@@ -1 +1,3 @@
+pub mod client_hello;
 pub mod detector;
+pub mod proxy_protocol;
@@ -0,0 +1,251 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// PROXY协议v2头部的固定签名
+pub const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// PROXY协议v1头部的最大字节数（含结尾的 `\r\n`）
+pub const MAX_V1_HEADER: usize = 107;
+
+/// v1头部的固定前缀
+const V1_PREFIX: &[u8] = b"PROXY ";
+/// v2命令：LOCAL，连接由前置代理自身发起（如健康检查）
+const V2_COMMAND_LOCAL: u8 = 0x0;
+/// v2命令：PROXY，代为转发的客户端连接
+const V2_COMMAND_PROXY: u8 = 0x1;
+/// v2地址族
+const V2_FAMILY_UNSPEC: u8 = 0x0;
+const V2_FAMILY_INET: u8 = 0x1;
+const V2_FAMILY_INET6: u8 = 0x2;
+const V2_FAMILY_UNIX: u8 = 0x3;
+
+/// 解析PROXY协议头部的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProxyHeader {
+    /// 数据不足，至少还需要读取的字节数
+    ///
+    /// 按该字节数读取不会越过头部，头部之后的客户端数据保留在连接中
+    Incomplete(usize),
+    /// 完整的头部，携带原始客户端地址
+    ///
+    /// LOCAL命令、`UNKNOWN` 或不含网络地址的地址族为 `None`，此时沿用连接的对端地址
+    Complete(Option<SocketAddr>),
+    /// 不是PROXY协议头部或格式错误
+    Invalid,
+}
+
+/// 从连接开头的数据中解析PROXY协议v1（文本）或v2（二进制）头部
+///
+/// `data` 应只包含头部本身，头部之后出现多余字节时按无效处理
+pub fn parse_proxy_header(data: &[u8]) -> ProxyHeader {
+    // v1最短的头部 `PROXY UNKNOWN\r\n` 与v2签名都不短于12字节
+    if data.len() < V2_SIGNATURE.len() {
+        let prefix_matches = V2_SIGNATURE.starts_with(data)
+            || data.starts_with(V1_PREFIX)
+            || V1_PREFIX.starts_with(data);
+        return if prefix_matches {
+            ProxyHeader::Incomplete(V2_SIGNATURE.len() - data.len())
+        } else {
+            ProxyHeader::Invalid
+        };
+    }
+    if data.starts_with(&V2_SIGNATURE) {
+        parse_v2(data)
+    } else if data.starts_with(V1_PREFIX) {
+        parse_v1(data)
+    } else {
+        ProxyHeader::Invalid
+    }
+}
+
+/// 解析v1头部：`PROXY TCP4 源地址 目标地址 源端口 目标端口\r\n`
+fn parse_v1(data: &[u8]) -> ProxyHeader {
+    let end = match data.windows(2).position(|w| w == b"\r\n") {
+        Some(end) => end,
+        None if data.len() < MAX_V1_HEADER => return ProxyHeader::Incomplete(1),
+        None => return ProxyHeader::Invalid,
+    };
+    if end + 2 != data.len() {
+        return ProxyHeader::Invalid;
+    }
+    let line = match std::str::from_utf8(&data[V1_PREFIX.len()..end]) {
+        Ok(line) => line,
+        Err(_) => return ProxyHeader::Invalid,
+    };
+
+    let fields: Vec<&str> = line.split(' ').collect();
+    match fields.as_slice() {
+        ["UNKNOWN", ..] => ProxyHeader::Complete(None),
+        ["TCP4", source, _, port, _] => match (source.parse::<Ipv4Addr>(), port.parse()) {
+            (Ok(ip), Ok(port)) if valid_v1_fields(&fields) => {
+                ProxyHeader::Complete(Some(SocketAddr::new(IpAddr::V4(ip), port)))
+            }
+            _ => ProxyHeader::Invalid,
+        },
+        ["TCP6", source, _, port, _] => match (source.parse::<Ipv6Addr>(), port.parse()) {
+            (Ok(ip), Ok(port)) if valid_v1_fields(&fields) => {
+                ProxyHeader::Complete(Some(SocketAddr::new(IpAddr::V6(ip), port)))
+            }
+            _ => ProxyHeader::Invalid,
+        },
+        _ => ProxyHeader::Invalid,
+    }
+}
+
+/// 检查v1头部的目标地址与两个端口，地址族须与协议一致
+fn valid_v1_fields(fields: &[&str]) -> bool {
+    let destination = match fields[0] {
+        "TCP4" => fields[2].parse::<Ipv4Addr>().is_ok(),
+        _ => fields[2].parse::<Ipv6Addr>().is_ok(),
+    };
+    destination
+        && [fields[3], fields[4]]
+            .iter()
+            .all(|port| port.parse::<u16>().is_ok() && (*port == "0" || !port.starts_with('0')))
+}
+
+/// 解析v2头部：12字节签名、版本与命令、地址族与协议、2字节长度，随后为地址块
+fn parse_v2(data: &[u8]) -> ProxyHeader {
+    if data.len() < 16 {
+        return ProxyHeader::Incomplete(16 - data.len());
+    }
+    let version = data[12] >> 4;
+    let command = data[12] & 0x0f;
+    let family = data[13] >> 4;
+    let length = u16::from_be_bytes([data[14], data[15]]) as usize;
+    if version != 2 || family > V2_FAMILY_UNIX {
+        return ProxyHeader::Invalid;
+    }
+    if data.len() < 16 + length {
+        return ProxyHeader::Incomplete(16 + length - data.len());
+    }
+    if data.len() > 16 + length {
+        return ProxyHeader::Invalid;
+    }
+
+    let addresses = &data[16..];
+    match command {
+        V2_COMMAND_LOCAL => ProxyHeader::Complete(None),
+        V2_COMMAND_PROXY => match family {
+            V2_FAMILY_INET if addresses.len() >= 12 => {
+                let ip = Ipv4Addr::new(addresses[0], addresses[1], addresses[2], addresses[3]);
+                let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+                ProxyHeader::Complete(Some(SocketAddr::new(IpAddr::V4(ip), port)))
+            }
+            V2_FAMILY_INET6 if addresses.len() >= 36 => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(&addresses[..16]);
+                let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+                ProxyHeader::Complete(Some(SocketAddr::new(IpAddr::V6(octets.into()), port)))
+            }
+            V2_FAMILY_UNSPEC | V2_FAMILY_UNIX => ProxyHeader::Complete(None),
+            _ => ProxyHeader::Invalid,
+        },
+        _ => ProxyHeader::Invalid,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 按 `parse_proxy_header` 要求的字节数逐步喂入数据，返回最终结果和读取的字节数
+    fn feed(data: &[u8]) -> (ProxyHeader, usize) {
+        let mut read = 0;
+        loop {
+            match parse_proxy_header(&data[..read]) {
+                ProxyHeader::Incomplete(more) if read + more <= data.len() => read += more,
+                result => return (result, read),
+            }
+        }
+    }
+
+    fn v2_header(command: u8, family: u8, addresses: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.push(0x20 | command);
+        header.push(family << 4 | 0x1);
+        header.extend_from_slice(&(addresses.len() as u16).to_be_bytes());
+        header.extend_from_slice(addresses);
+        header
+    }
+
+    #[test]
+    fn test_parse_v1() {
+        let header = b"PROXY TCP4 203.0.113.7 192.0.2.1 51234 8080\r\n";
+        let mut data = header.to_vec();
+        data.extend_from_slice(b"GET / HTTP/1.1\r\n\r\n");
+        assert_eq!(
+            feed(&data),
+            (
+                ProxyHeader::Complete(Some("203.0.113.7:51234".parse().unwrap())),
+                header.len()
+            )
+        );
+        assert_eq!(
+            feed(b"PROXY TCP6 2001:db8::1 2001:db8::2 443 8080\r\n").0,
+            ProxyHeader::Complete(Some("[2001:db8::1]:443".parse().unwrap()))
+        );
+        assert_eq!(
+            feed(b"PROXY UNKNOWN\r\n"),
+            (ProxyHeader::Complete(None), 15)
+        );
+
+        for invalid in [
+            &b"PROXY TCP4 203.0.113.7 192.0.2.1 51234\r\n"[..],
+            b"PROXY TCP4 2001:db8::1 192.0.2.1 51234 8080\r\n",
+            b"PROXY TCP4 203.0.113.7 192.0.2.1 70000 8080\r\n",
+            b"PROXY TCP4 203.0.113.7 192.0.2.1 051234 8080\r\n",
+            b"PROXY UDP4 203.0.113.7 192.0.2.1 51234 8080\r\n",
+            b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n",
+        ] {
+            assert_eq!(feed(invalid).0, ProxyHeader::Invalid, "{:?}", invalid);
+        }
+        // 超过最大长度仍没有行结束符
+        let long = format!("PROXY TCP4 {}", "1".repeat(MAX_V1_HEADER));
+        assert_eq!(feed(long.as_bytes()).0, ProxyHeader::Invalid);
+    }
+
+    #[test]
+    fn test_parse_v2() {
+        let mut addresses = vec![203, 0, 113, 7, 192, 0, 2, 1];
+        addresses.extend_from_slice(&51234u16.to_be_bytes());
+        addresses.extend_from_slice(&8080u16.to_be_bytes());
+        // 地址块之后的TLV扩展一并跳过
+        addresses.extend_from_slice(&[0x04, 0x00, 0x01, 0x00]);
+        let header = v2_header(V2_COMMAND_PROXY, V2_FAMILY_INET, &addresses);
+        let mut data = header.clone();
+        data.extend_from_slice(b"GET / HTTP/1.1\r\n\r\n");
+        assert_eq!(
+            feed(&data),
+            (
+                ProxyHeader::Complete(Some("203.0.113.7:51234".parse().unwrap())),
+                header.len()
+            )
+        );
+
+        let mut addresses = "2001:db8::1".parse::<Ipv6Addr>().unwrap().octets().to_vec();
+        addresses.extend_from_slice(&[0; 16]);
+        addresses.extend_from_slice(&443u16.to_be_bytes());
+        addresses.extend_from_slice(&8080u16.to_be_bytes());
+        assert_eq!(
+            feed(&v2_header(V2_COMMAND_PROXY, V2_FAMILY_INET6, &addresses)).0,
+            ProxyHeader::Complete(Some("[2001:db8::1]:443".parse().unwrap()))
+        );
+        assert_eq!(
+            feed(&v2_header(V2_COMMAND_LOCAL, V2_FAMILY_UNSPEC, &[])).0,
+            ProxyHeader::Complete(None)
+        );
+
+        // 地址块短于地址族要求、未知命令或版本
+        assert_eq!(
+            feed(&v2_header(V2_COMMAND_PROXY, V2_FAMILY_INET, &[0; 8])).0,
+            ProxyHeader::Invalid
+        );
+        assert_eq!(
+            feed(&v2_header(0x2, V2_FAMILY_INET, &[0; 12])).0,
+            ProxyHeader::Invalid
+        );
+        let mut header = v2_header(V2_COMMAND_PROXY, V2_FAMILY_INET, &[0; 12]);
+        header[12] = 0x11;
+        assert_eq!(feed(&header).0, ProxyHeader::Invalid);
+    }
+}
@@ -1,258 +1,1601 @@
-use crate::auth::{check_authentication, AuthConfig};
-use crate::connection::{extract_proxy_auth, send_auth_required_response, send_error_response};
+use crate::access_log::{self, AccessEntry, AccessLog};
+use crate::access_rules::AccessRules;
+use crate::admission;
+use crate::auth::{check_authentication, proxy_auth_username, AuthConfig};
+use crate::cidr::IpCidr;
+use crate::connection::{
+    deflected_path, extract_proxy_auth, extract_tenant, infer_scheme, is_scanner_path,
+    read_http_head, read_proxy_header, request_method, request_target, response_version,
+    send_auth_required_response, send_error_response, HeadRead, PrefetchedStream,
+    DEFAULT_INITIAL_READ_SIZE, DEFAULT_MAX_HEADER_SIZE,
+};
+use crate::error::{ConnectionContext, Phase, ProxyError};
 use crate::handlers;
-use crate::parser::detector::ProtocolType;
-use std::net::SocketAddr;
+use crate::handlers::backend::{
+    forbidden_message, is_access_denied, is_timeout, upstream_status, BackendConnector,
+};
+use crate::handlers::http1::{request_policy_violation, Http1Options};
+use crate::health::Readiness;
+use crate::logging::{self, PolicyViolation};
+use crate::metrics::{self, metrics};
+use crate::parser::client_hello::{parse_client_hello, ClientHello, MAX_CLIENT_HELLO};
+use crate::parser::detector::{bad_request_hint, is_request_start, ProtocolType};
+use crate::rejection::{RejectionCallback, RejectionReason};
+use crate::relay::relay;
+use crate::stream::ClientStream;
+#[cfg(unix)]
+use crate::stream::UNIX_CLIENT_ADDR;
+use crate::watchdog::AcceptWatchdog;
+use arc_swap::ArcSwap;
+use rustls::ServerConfig;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
-use tracing::{debug, error, info};
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::sync::{watch, Semaphore};
+use tokio::time::{timeout_at, Instant};
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, error, info, warn, Instrument};
 
 #[derive(Clone)]
 pub struct Proxy {
-    auth_config: Option<AuthConfig>,
+    auth_config: Arc<ArcSwap<Option<AuthConfig>>>,
+    connector: BackendConnector,
+    landing_page: Option<String>,
+    deflect_scanners: bool,
+    scanner_body: Option<String>,
+    max_header_size: usize,
+    initial_read_size: usize,
+    on_rejection: Option<RejectionCallback>,
+    trusted_proxies: Vec<IpCidr>,
+    trust_forwarded_proto: bool,
+    strict_headers: bool,
+    require_user_agent: bool,
+    http10_close: bool,
+    preserve_proxy_connection: bool,
+    readiness: Option<Readiness>,
+    websocket_sessions: Option<Arc<Semaphore>>,
+    websocket_close_frame: bool,
+    /// 停止接受新连接时置为 `true`，通知正在转发的WebSocket连接发送关闭帧后结束
+    closing: Arc<watch::Sender<bool>>,
+    routes: HashMap<String, (String, u16)>,
+    accept_watchdog: Option<Duration>,
+    accept_workers: usize,
+    access_log: Option<AccessLog>,
+    handshake_timeout: Duration,
+    tls_acceptor: Option<TlsAcceptor>,
+    allowed_connect_ports: Option<Vec<u16>>,
+    no_pipelining: bool,
+    strip_forwarded: bool,
+    remove_request_headers: Arc<Vec<String>>,
+    add_request_headers: Arc<Vec<(String, String)>>,
+    allowed_methods: Option<Arc<HashSet<String>>>,
+    allow_generic_upgrade: bool,
+    no_auth_for_localhost: bool,
+    auth_exempt: Vec<IpCidr>,
+    require_sni: bool,
+    accept_proxy_protocol: bool,
 }
 
 impl Proxy {
     pub fn new(auth_config: Option<AuthConfig>) -> Self {
-        Self { auth_config }
+        Self {
+            auth_config: Arc::new(ArcSwap::from_pointee(auth_config)),
+            connector: BackendConnector::new(),
+            landing_page: None,
+            deflect_scanners: false,
+            scanner_body: None,
+            max_header_size: DEFAULT_MAX_HEADER_SIZE,
+            initial_read_size: DEFAULT_INITIAL_READ_SIZE,
+            on_rejection: None,
+            trusted_proxies: Vec::new(),
+            trust_forwarded_proto: false,
+            strict_headers: false,
+            require_user_agent: false,
+            http10_close: true,
+            preserve_proxy_connection: false,
+            readiness: None,
+            websocket_sessions: None,
+            websocket_close_frame: false,
+            closing: Arc::new(watch::Sender::new(false)),
+            routes: HashMap::new(),
+            accept_watchdog: None,
+            accept_workers: 1,
+            access_log: None,
+            handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
+            tls_acceptor: None,
+            allowed_connect_ports: None,
+            no_pipelining: false,
+            strip_forwarded: false,
+            remove_request_headers: Arc::default(),
+            add_request_headers: Arc::default(),
+            allowed_methods: None,
+            allow_generic_upgrade: false,
+            no_auth_for_localhost: false,
+            auth_exempt: Vec::new(),
+            require_sni: false,
+            accept_proxy_protocol: false,
+        }
     }
 
-    pub async fn handle_connection(&self, mut stream: TcpStream, client_addr: SocketAddr) {
-        let mut buffer = [0u8; 8192];
+    /// 设置后端连接器
+    pub fn with_connector(mut self, connector: BackendConnector) -> Self {
+        self.connector = connector;
+        self
+    }
 
-        match stream.read(&mut buffer).await {
-            Ok(0) => {
-                info!("[{}] 客户端关闭连接", client_addr);
-                return;
+    /// 当前的认证配置
+    pub fn auth_config(&self) -> Arc<Option<AuthConfig>> {
+        self.auth_config.load_full()
+    }
+
+    /// 替换认证配置，所有克隆共享同一份配置
+    ///
+    /// 已建立的连接沿用接受时的认证配置，之后接受的连接使用新配置
+    pub fn set_auth_config(&self, auth_config: Option<AuthConfig>) {
+        self.auth_config.store(Arc::new(auth_config));
+    }
+
+    /// 替换出站目标访问规则，见 [`BackendConnector::set_access_rules`]
+    pub fn set_access_rules(&self, access_rules: Option<Arc<AccessRules>>) {
+        self.connector.set_access_rules(access_rules);
+    }
+
+    /// 设置直接访问代理根路径时返回的信息页，未设置时返回404
+    pub fn with_landing_page(mut self, landing_page: Option<String>) -> Self {
+        self.landing_page = landing_page;
+        self
+    }
+
+    /// 是否直接响应扫描器常见路径
+    ///
+    /// 启用后目标为这些路径的origin-form请求即使Host不是代理自身地址也不做转发，
+    /// `/robots.txt` 返回禁止抓取的规则，其余路径返回 [`with_scanner_body`](Self::with_scanner_body)
+    /// 设置的内容或404
+    pub fn with_deflect_scanners(mut self, deflect_scanners: bool) -> Self {
+        self.deflect_scanners = deflect_scanners;
+        self
+    }
+
+    /// 设置扫描器路径返回的响应体，未设置时返回404
+    pub fn with_scanner_body(mut self, scanner_body: Option<String>) -> Self {
+        self.scanner_body = scanner_body;
+        self
+    }
+
+    /// 设置请求头部的最大字节数
+    pub fn with_max_header_size(mut self, max_header_size: usize) -> Self {
+        self.max_header_size = max_header_size;
+        self
+    }
+
+    /// 设置读取请求头部时每次读取的字节数，默认4096
+    ///
+    /// 只影响头部分几次读完，可接受的头部大小仍由 [`with_max_header_size`](Self::with_max_header_size) 决定
+    pub fn with_initial_read_size(mut self, initial_read_size: usize) -> Self {
+        self.initial_read_size = initial_read_size;
+        self
+    }
+
+    /// 注册连接被拒绝时的回调
+    pub fn with_rejection_callback(mut self, callback: RejectionCallback) -> Self {
+        self.on_rejection = Some(callback);
+        self
+    }
+
+    /// 设置受信任的前置代理网段，只有这些客户端的 `X-Forwarded-Proto` 与 `X-Tenant-Id` 会被采信
+    pub fn with_trusted_proxies(mut self, trusted_proxies: Vec<IpCidr>) -> Self {
+        self.trusted_proxies = trusted_proxies;
+        self
+    }
+
+    /// 设置是否免除环回地址客户端的代理认证，其余客户端仍需认证
+    pub fn with_no_auth_for_localhost(mut self, no_auth_for_localhost: bool) -> Self {
+        self.no_auth_for_localhost = no_auth_for_localhost;
+        self
+    }
+
+    /// 设置免代理认证的客户端网段，其余客户端仍需认证
+    pub fn with_auth_exempt(mut self, auth_exempt: Vec<IpCidr>) -> Self {
+        self.auth_exempt = auth_exempt;
+        self
+    }
+
+    /// 是否采信受信任前置代理发送的 `X-Forwarded-Proto`
+    pub fn with_trust_forwarded_proto(mut self, trust_forwarded_proto: bool) -> Self {
+        self.trust_forwarded_proto = trust_forwarded_proto;
+        self
+    }
+
+    /// 是否严格校验请求头部字节
+    ///
+    /// 启用后头部包含控制字符（`\t` 和行结束符除外）的请求直接以 `400` 拒绝，
+    /// 而不是经容错解码后继续处理；请求体不受影响
+    pub fn with_strict_headers(mut self, strict_headers: bool) -> Self {
+        self.strict_headers = strict_headers;
+        self
+    }
+
+    /// 是否拒绝缺少 `User-Agent` 的明文HTTP请求
+    ///
+    /// 启用后HTTP/1.x与WebSocket升级请求缺少 `User-Agent` 时返回 `403`；
+    /// CONNECT隧道和SOCKS看不到内层请求头，不受影响
+    pub fn with_require_user_agent(mut self, require_user_agent: bool) -> Self {
+        self.require_user_agent = require_user_agent;
+        self
+    }
+
+    /// 是否对HTTP/1.0请求强制 `Connection: close`，默认启用
+    ///
+    /// 启用后转发单个响应即关闭连接，避免源站返回keep-alive帧格式时HTTP/1.0客户端挂起
+    pub fn with_http10_close(mut self, http10_close: bool) -> Self {
+        self.http10_close = http10_close;
+        self
+    }
+
+    /// 设置就绪状态，启用 `/readyz` 端点
+    pub fn with_readiness(mut self, readiness: Option<Readiness>) -> Self {
+        self.readiness = readiness;
+        self
+    }
+
+    /// 设置并发WebSocket会话上限，超过时以 `503` 拒绝升级
+    pub fn with_max_websocket_sessions(mut self, max_sessions: Option<usize>) -> Self {
+        self.websocket_sessions = max_sessions.map(|max| Arc::new(Semaphore::new(max)));
+        self
+    }
+
+    /// 设置WebSocket连接因空闲超时或代理停止接受新连接而结束时，是否先向两端发送关闭帧（1001 going away）
+    ///
+    /// 未启用时直接断开TCP连接，两端会视为异常关闭
+    pub fn with_websocket_close_frame(mut self, close_frame: bool) -> Self {
+        self.websocket_close_frame = close_frame;
+        self
+    }
+
+    /// 设置反向代理路由，键为 `Host` 中的主机名（不区分大小写，忽略端口）
+    ///
+    /// 路由非空时进入反向代理模式：HTTP/1.x请求不要求代理认证，按 `Host`
+    /// 转发到对应后端，未匹配的主机返回404
+    pub fn with_routes(mut self, routes: HashMap<String, (String, u16)>) -> Self {
+        self.routes = routes
+            .into_iter()
+            .map(|(host, backend)| (host.to_ascii_lowercase(), backend))
+            .collect();
+        self
+    }
+
+    /// 设置接受循环看门狗间隔，超过该时长未接受任何连接时记录告警
+    pub fn with_accept_watchdog(mut self, interval: Option<Duration>) -> Self {
+        self.accept_watchdog = interval;
+        self
+    }
+
+    /// 设置并发接受连接的任务数，各任务共享同一个监听器，默认为1
+    pub fn with_accept_workers(mut self, accept_workers: usize) -> Self {
+        self.accept_workers = accept_workers.max(1);
+        self
+    }
+
+    /// 设置访问日志，每个连接结束后按通用日志格式追加一行
+    pub fn with_access_log(mut self, access_log: Option<AccessLog>) -> Self {
+        self.access_log = access_log;
+        self
+    }
+
+    /// 设置是否原样转发客户端的 `Proxy-Connection` 头
+    ///
+    /// 默认将其 `close`/`keep-alive` 语义改写为发往源站的 `Connection` 头
+    pub fn with_preserve_proxy_connection(mut self, preserve: bool) -> Self {
+        self.preserve_proxy_connection = preserve;
+        self
+    }
+
+    /// 设置允许CONNECT的目标端口，未设置时不限制
+    ///
+    /// 端口不在列表中的CONNECT请求在连接目标之前即以403拒绝
+    pub fn with_allowed_connect_ports(mut self, ports: Option<Vec<u16>>) -> Self {
+        self.allowed_connect_ports = ports;
+        self
+    }
+
+    /// 设置CONNECT隧道是否必须以携带SNI的TLS ClientHello开始
+    ///
+    /// 启用后在转发任何隧道数据之前读取客户端的ClientHello（可拆分在多个TLS记录中），
+    /// 没有SNI或不是TLS的隧道直接重置连接
+    pub fn with_require_sni(mut self, require_sni: bool) -> Self {
+        self.require_sni = require_sni;
+        self
+    }
+
+    /// 设置是否在每个连接开头读取PROXY协议（v1或v2）头部
+    ///
+    /// 用于部署在四层负载均衡之后：启用后日志、访问日志、免认证判断和拒绝回调都使用头部给出的
+    /// 原始客户端地址，缺少头部或头部无效的连接直接关闭
+    pub fn with_accept_proxy_protocol(mut self, accept_proxy_protocol: bool) -> Self {
+        self.accept_proxy_protocol = accept_proxy_protocol;
+        self
+    }
+
+    /// 设置是否关闭HTTP/1.1流水线，默认按顺序转发同一连接上的流水线请求
+    ///
+    /// 关闭后每个连接只转发第一个请求，以 `Connection: close` 响应后关闭连接，
+    /// 客户端需在新连接上重发其余请求
+    pub fn with_no_pipelining(mut self, no_pipelining: bool) -> Self {
+        self.no_pipelining = no_pipelining;
+        self
+    }
+
+    /// 设置是否移除客户端请求中的 `Via`、`X-Forwarded-For` 和 `Forwarded` 头
+    ///
+    /// 启用后源站看不到客户端之前经过的代理链，也不再追加本代理的 `X-Forwarded-For` 与 `Via`，
+    /// 只作用于HTTP/1.x转发的请求
+    pub fn with_strip_forwarded(mut self, strip_forwarded: bool) -> Self {
+        self.strip_forwarded = strip_forwarded;
+        self
+    }
+
+    /// 设置转发前从请求中移除的头部名称，不区分大小写
+    ///
+    /// 在追加 `X-Forwarded-For` 与 `Via` 之后生效，列出这两个头部即可不向源站发送，
+    /// 只作用于HTTP/1.x转发的请求
+    pub fn with_remove_request_headers(mut self, names: Vec<String>) -> Self {
+        self.remove_request_headers = Arc::new(names);
+        self
+    }
+
+    /// 设置转发前添加到请求中的头部，替换客户端发送的同名头部
+    ///
+    /// 只作用于HTTP/1.x转发的请求
+    pub fn with_add_request_headers(mut self, headers: Vec<(String, String)>) -> Self {
+        self.add_request_headers = Arc::new(headers);
+        self
+    }
+
+    /// 设置是否转发非WebSocket的协议升级请求（如 `Upgrade: h2c`），默认以 `501` 拒绝
+    ///
+    /// 启用后保留升级相关头部转发到源站，源站响应101后按升级后的协议原样双向转发
+    pub fn with_allow_generic_upgrade(mut self, allow: bool) -> Self {
+        self.allow_generic_upgrade = allow;
+        self
+    }
+
+    /// 设置允许转发的HTTP/1.x请求方法（不区分大小写），未设置时不限制
+    ///
+    /// 其余方法以 `405 Method Not Allowed` 拒绝，`Allow` 头列出允许的方法
+    pub fn with_allowed_methods(mut self, methods: Option<HashSet<String>>) -> Self {
+        self.allowed_methods = methods.map(|methods| {
+            Arc::new(
+                methods
+                    .into_iter()
+                    .map(|method| method.to_ascii_uppercase())
+                    .collect(),
+            )
+        });
+        self
+    }
+
+    /// 设置握手阶段的时限，默认30秒
+    ///
+    /// 从接受连接起，TLS握手、识别协议和读取完整请求头须在该时限内完成，
+    /// 否则关闭连接，避免连接后不发送数据的客户端长期占用任务和连接许可
+    pub fn with_handshake_timeout(mut self, handshake_timeout: Duration) -> Self {
+        self.handshake_timeout = handshake_timeout;
+        self
+    }
+
+    /// 设置监听端的TLS配置，设置后客户端需先完成TLS握手，即以 `https://` 代理地址接入
+    ///
+    /// 只作用于客户端到代理这一跳，与到源站的连接无关
+    pub fn with_tls(mut self, tls: Option<Arc<ServerConfig>>) -> Self {
+        self.tls_acceptor = tls.map(TlsAcceptor::from);
+        self
+    }
+
+    fn http1_options(&self, force_close: bool) -> Http1Options {
+        Http1Options {
+            force_close,
+            preserve_proxy_connection: self.preserve_proxy_connection,
+            no_pipelining: self.no_pipelining,
+            strip_forwarded: self.strip_forwarded,
+            remove_request_headers: self.remove_request_headers.clone(),
+            add_request_headers: self.add_request_headers.clone(),
+            max_header_size: self.max_header_size,
+            allowed_methods: self.allowed_methods.clone(),
+            allow_generic_upgrade: self.allow_generic_upgrade,
+            strict_headers: self.strict_headers,
+            require_user_agent: self.require_user_agent,
+            deflect_scanners: self.deflect_scanners,
+            on_rejection: self.on_rejection.clone(),
+        }
+    }
+
+    /// 客户端是否免代理认证
+    fn auth_exempt(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        (self.no_auth_for_localhost && ip.is_loopback())
+            || self.auth_exempt.iter().any(|cidr| cidr.contains(ip))
+    }
+
+    /// 停止接受新连接后完成
+    fn closed(&self) -> impl Future<Output = ()> {
+        let mut closing = self.closing.subscribe();
+        async move {
+            if closing.wait_for(|closing| *closing).await.is_err() {
+                std::future::pending::<()>().await;
             }
-            Ok(n) => {
-                let client_addr_str = client_addr.to_string();
-                debug!("[{}] 收到 {} 字节数据", client_addr_str, n);
+        }
+    }
+
+    /// 通知回调连接被拒绝
+    fn reject(&self, client_addr: SocketAddr, reason: RejectionReason) {
+        debug!("[{}] 拒绝连接: {}", client_addr, reason);
+        if let Some(callback) = &self.on_rejection {
+            callback(client_addr, &reason);
+        }
+    }
 
-                // 提取认证头
-                let auth_header = extract_proxy_auth(&buffer[..n]);
+    /// 在监听器上持续接受连接并处理
+    ///
+    /// 每个连接在获得 `semaphore` 的许可后在独立任务中处理，许可在连接结束时归还
+    ///
+    /// ```no_run
+    /// use rust_proxy::proxy::Proxy;
+    /// use std::sync::Arc;
+    /// use tokio::{net::TcpListener, sync::Semaphore};
+    ///
+    /// # async fn run() -> std::io::Result<()> {
+    /// let listener = TcpListener::bind("127.0.0.1:8080").await?;
+    /// Proxy::new(None).serve(listener, Arc::new(Semaphore::new(100))).await
+    /// # }
+    /// ```
+    pub async fn serve(self, listener: TcpListener, semaphore: Arc<Semaphore>) -> io::Result<()> {
+        self.serve_with_shutdown(listener, semaphore, std::future::pending())
+            .await
+    }
 
-                // 检查认证
-                if !check_authentication(&self.auth_config, auth_header.as_deref()) {
-                    info!("[{}] 认证失败，需要代理认证", client_addr_str);
-                    if let Err(e) = send_auth_required_response(&mut stream).await {
-                        error!("[{}] 发送认证要求响应失败: {}", client_addr_str, e);
-                    }
-                    return;
-                }
+    /// 同 [`serve`](Self::serve)，`shutdown` 完成后停止接受新连接并返回
+    ///
+    /// 已接受的连接继续在各自的任务中运行，调用方可通过 `semaphore` 等待其结束。
+    /// 返回时所有接受任务均已结束，监听器已关闭
+    pub async fn serve_with_shutdown<F>(
+        self,
+        listener: TcpListener,
+        semaphore: Arc<Semaphore>,
+        shutdown: F,
+    ) -> io::Result<()>
+    where
+        F: Future<Output = ()>,
+    {
+        self.serve_listeners_with_shutdown(vec![listener], semaphore, shutdown)
+            .await
+    }
 
-                // 检测协议类型
-                let protocol = crate::parser::detector::detect_protocol(&buffer[..n]);
-                info!("[{}] 检测到协议: {:?}", client_addr_str, protocol);
+    /// 同 [`serve_with_shutdown`](Self::serve_with_shutdown)，同时在多个监听器上接受连接
+    ///
+    /// 所有监听器共享同一个代理配置和 `semaphore`，并发连接上限对全部监听器合计生效
+    pub async fn serve_listeners_with_shutdown<F>(
+        self,
+        listeners: Vec<TcpListener>,
+        semaphore: Arc<Semaphore>,
+        shutdown: F,
+    ) -> io::Result<()>
+    where
+        F: Future<Output = ()>,
+    {
+        self.serve_acceptors(listeners, semaphore, shutdown).await
+    }
 
-                match protocol {
-                    // CONNECT隧道（HTTPS/HTTP/2 over TLS）
-                    ProtocolType::ConnectTunnel { host, port } => {
-                        Proxy::handle_connect_tunnel(stream, client_addr_str.clone(), host, port)
-                            .await;
-                    }
+    /// 同 [`serve_with_shutdown`](Self::serve_with_shutdown)，在Unix域套接字上接受连接
+    ///
+    /// Unix套接字的对端没有网络地址，统一按 [`UNIX_CLIENT_ADDR`] 处理
+    #[cfg(unix)]
+    pub async fn serve_unix_with_shutdown<F>(
+        self,
+        listener: UnixListener,
+        semaphore: Arc<Semaphore>,
+        shutdown: F,
+    ) -> io::Result<()>
+    where
+        F: Future<Output = ()>,
+    {
+        self.serve_acceptors(vec![listener], semaphore, shutdown)
+            .await
+    }
 
-                    // HTTP/1.0
-                    ProtocolType::Http10 => {
-                        if let Err(e) = handlers::http1::handle_http1(
-                            stream,
-                            client_addr_str.clone(),
-                            &self.auth_config,
-                            &buffer[..n],
-                        )
-                        .await
-                        {
-                            error!("[{}] HTTP/1.0处理失败: {}", client_addr_str, e);
-                        }
-                    }
+    async fn serve_acceptors<L, F>(
+        self,
+        listeners: Vec<L>,
+        semaphore: Arc<Semaphore>,
+        shutdown: F,
+    ) -> io::Result<()>
+    where
+        L: Acceptor,
+        F: Future<Output = ()>,
+    {
+        if listeners.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "没有可用的监听器",
+            ));
+        }
+        // 登记自身的监听地址，指向这些地址的请求按转发回环拒绝
+        for listener in &listeners {
+            if let Some(addr) = listener.listen_addr() {
+                self.connector.add_listen_addr(addr);
+            }
+        }
+        let watchdog = self.accept_watchdog.map(AcceptWatchdog::spawn);
+        let (stop_tx, stop_rx) = watch::channel(false);
 
-                    // HTTP/1.1
-                    ProtocolType::Http11 => {
-                        if let Err(e) = handlers::http1::handle_http1(
-                            stream,
-                            client_addr_str.clone(),
-                            &self.auth_config,
-                            &buffer[..n],
-                        )
+        // 每个监听器上运行 accept_workers 个接受任务，同一监听器上的任务并发调用 accept()，
+        // 由运行时分散到各个线程
+        let mut workers = Vec::new();
+        for listener in listeners {
+            let listener = Arc::new(listener);
+            for _ in 0..self.accept_workers {
+                let proxy = self.clone();
+                let listener = listener.clone();
+                let semaphore = semaphore.clone();
+                let watchdog = watchdog.clone();
+                let mut stop = stop_rx.clone();
+                workers.push(tokio::spawn(async move {
+                    let stop = async move {
+                        let _ = stop.wait_for(|stopped| *stopped).await;
+                    };
+                    proxy
+                        .accept_loop(listener.as_ref(), semaphore, watchdog.as_ref(), stop)
                         .await
-                        {
-                            error!("[{}] HTTP/1.1处理失败: {}", client_addr_str, e);
-                        }
-                    }
+                }));
+            }
+        }
 
-                    // HTTP/2 (clear-text)
-                    ProtocolType::Http2 => {
-                        // HTTP/2需要从Host头获取目标
-                        if let Some((host, port)) =
-                            crate::connection::parse_http_request(&buffer[..n]).await
-                        {
-                            if let Err(e) = handlers::http2::handle_http2(
-                                stream,
-                                client_addr_str.clone(),
-                                &host,
-                                port,
-                                &buffer[..n],
-                            )
-                            .await
-                            {
-                                error!("[{}] HTTP/2处理失败: {}", client_addr_str, e);
-                            }
-                        } else {
-                            error!("[{}] HTTP/2请求缺少Host头", client_addr_str);
-                            let _ =
-                                send_error_response(&mut stream, "400 Bad Request", "缺少Host头")
-                                    .await;
-                        }
-                    }
+        shutdown.await;
+        let _ = stop_tx.send(true);
+        self.closing.send_replace(true);
+        for worker in workers {
+            if let Err(e) = worker.await {
+                error!("接受连接任务异常退出: {}", e);
+            }
+        }
+        Ok(())
+    }
 
-                    // WebSocket升级
-                    ProtocolType::WebSocketUpgrade {
-                        key: _,
-                        host: _,
-                        port: _,
-                    } => match crate::handlers::websocket::parse_websocket_upgrade(&buffer[..n]) {
-                        Ok(Some(upgrade)) => {
-                            if let Err(e) = handlers::websocket::handle_websocket(
-                                stream,
-                                client_addr_str.clone(),
-                                upgrade,
-                            )
-                            .await
-                            {
-                                error!("[{}] WebSocket处理失败: {}", client_addr_str, e);
-                            }
-                        }
-                        Ok(None) => {
-                            error!("[{}] WebSocket升级请求解析失败", client_addr_str);
-                            let _ = send_error_response(
-                                &mut stream,
-                                "400 Bad Request",
-                                "无效的WebSocket升级请求",
-                            )
-                            .await;
-                        }
-                        Err(e) => {
-                            error!("[{}] WebSocket升级请求解析错误: {}", client_addr_str, e);
-                            let _ = send_error_response(
-                                &mut stream,
-                                "400 Bad Request",
-                                "解析WebSocket请求失败",
-                            )
-                            .await;
-                        }
-                    },
+    /// 接受连接直到 `stop` 完成，每个连接在独立任务中处理
+    async fn accept_loop<L, F>(
+        &self,
+        listener: &L,
+        semaphore: Arc<Semaphore>,
+        watchdog: Option<&AcceptWatchdog>,
+        stop: F,
+    ) where
+        L: Acceptor,
+        F: Future<Output = ()>,
+    {
+        tokio::pin!(stop);
 
-                    // 未知协议
-                    ProtocolType::Unknown => {
-                        error!("[{}] 无法识别协议类型", client_addr_str);
-                        let _ =
-                            send_error_response(&mut stream, "400 Bad Request", "无法识别的协议")
-                                .await;
+        loop {
+            let (stream, remote_addr) = tokio::select! {
+                _ = &mut stop => return,
+                result = listener.accept_client() => match result {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        error!("接受连接失败: {}", e);
+                        continue;
                     }
-                }
+                },
+            };
+            if let Some(watchdog) = watchdog {
+                watchdog.record_accept();
             }
-            Err(e) => {
-                error!("[{}] 读取客户端数据失败: {}", client_addr, e);
+
+            // 获取信号量许可，记录排队时间
+            // 许可已耗尽时报告达到上限，连接继续排队等待许可
+            let saturated = || {
+                warn!("连接数已达上限，来自 {} 的连接排队等待许可", remote_addr);
+                self.reject(remote_addr, RejectionReason::OverCapacity);
+            };
+            let permit = match admission::acquire_permit(semaphore.clone(), saturated).await {
+                Ok((permit, waited)) => {
+                    info!("接受新连接来自: {} (等待许可 {:?})", remote_addr, waited);
+                    permit
+                }
+                Err(e) => {
+                    error!("获取连接许可失败: {}", e);
+                    continue;
+                }
+            };
+
+            let proxy = self.clone();
+            tokio::spawn(async move {
+                metrics::tenant_scope(proxy.handle_connection(stream, remote_addr)).await;
+                // 释放许可
+                drop(permit);
+            });
+        }
+    }
+
+    /// 处理单个连接
+    ///
+    /// 每个连接生成一个短请求ID，连接内的日志都在带有请求ID和客户端地址的span中输出，
+    /// 错误响应通过 `X-Proxy-Request-Id` 头返回该ID，便于关联客户端反馈与日志
+    ///
+    /// 启用 [`with_accept_proxy_protocol`](Self::with_accept_proxy_protocol) 时 `client_addr`
+    /// 为前置负载均衡的地址，读取PROXY协议头后替换为原始客户端地址
+    pub async fn handle_connection(&self, mut stream: ClientStream, mut client_addr: SocketAddr) {
+        if self.accept_proxy_protocol {
+            client_addr = match self.accept_proxy_header(&mut stream, client_addr).await {
+                Some(addr) => addr,
+                None => return,
+            };
+        }
+        let started = SystemTime::now();
+        let request_id = logging::next_request_id();
+        let span = logging::connection_span(client_addr, &request_id);
+        // 连接处理的future较大，放到堆上避免层层包装时在栈上移动
+        let connection = logging::request_scope(
+            request_id,
+            Box::pin(self.process_connection(stream, client_addr)),
+        );
+        let ((), mut record) = access_log::request_scope(connection).instrument(span).await;
+
+        let access_log = match &self.access_log {
+            Some(access_log) => access_log,
+            None => return,
+        };
+        // 保持的连接上每个请求一条记录，只记录读到了请求的连接
+        let finished = std::mem::take(&mut record.finished);
+        for record in finished.into_iter().chain(std::iter::once(record)) {
+            if let Some(method) = record.method {
+                access_log.log(&AccessEntry {
+                    client_ip: client_addr.ip(),
+                    user: record.user,
+                    time: record.time.unwrap_or(started),
+                    method,
+                    target: record.target,
+                    status: record.status,
+                    bytes: record.bytes,
+                    req_bytes: record.req_bytes,
+                    resp_bytes: record.resp_bytes,
+                });
             }
         }
     }
 
-    /// 处理CONNECT隧道请求（HTTPS/HTTP/2 over TLS）
-    async fn handle_connect_tunnel(
-        mut stream: TcpStream,
-        client_addr: String,
-        host: String,
-        port: u16,
-    ) {
+    /// 按固定顺序处理一个连接，前一阶段拒绝时不再进入后续阶段：
+    /// 头部检查（400）→ 认证（407，免认证的客户端跳过）→ 目标过滤（403）→ 连接目标（502/504）
+    async fn process_connection(&self, stream: ClientStream, client_addr: SocketAddr) {
         let client_addr_str = client_addr.to_string();
-        info!("[{}] CONNECT隧道到 {}:{}", client_addr_str, host, port);
+        let _connection = metrics().connection_opened();
+        let handshake_deadline = Instant::now() + self.handshake_timeout;
 
-        // 先连接到目标服务器，成功后再发送响应
-        match TcpStream::connect((host.as_str(), port)).await {
-            Ok(target_stream) => {
-                info!(
-                    "[{}] 成功连接到目标服务器 {}:{}",
-                    client_addr_str, host, port
-                );
+        // 免认证的客户端按未配置认证处理；连接期间沿用此刻的认证配置，不受重新加载影响
+        let no_auth = None;
+        let current_auth = self.auth_config.load_full();
+        let auth_config = if self.auth_exempt(client_addr.ip()) {
+            debug!("[{}] 客户端免认证", client_addr_str);
+            &no_auth
+        } else {
+            current_auth.as_ref()
+        };
 
-                // 发送连接成功响应
-                let response = b"HTTP/1.0 200 Connection Established\r\n\r\n";
-                if let Err(e) = stream.write_all(response).await {
-                    error!("[{}] 发送连接成功响应失败: {}", client_addr_str, e);
+        // 监听端启用TLS时先完成握手，之后按明文连接处理
+        let mut stream = match &self.tls_acceptor {
+            Some(acceptor) => match timeout_at(handshake_deadline, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => ClientStream::from(stream),
+                Ok(Err(e)) => {
+                    warn!("[{}] TLS握手失败: {}", client_addr_str, e);
                     return;
                 }
+                Err(_) => {
+                    self.handshake_timed_out(client_addr);
+                    return;
+                }
+            },
+            None => stream,
+        };
 
-                if let Err(e) = stream.flush().await {
-                    error!("[{}] 刷新响应失败: {}", client_addr_str, e);
+        // SOCKS没有HTTP头部结束符，需在读取HTTP头部之前通过预读首字节识别
+        let mut first_byte = [0u8; 1];
+        let peeked = match timeout_at(handshake_deadline, stream.peek(&mut first_byte)).await {
+            Ok(peeked) => peeked,
+            Err(_) => {
+                self.handshake_timed_out(client_addr);
+                return;
+            }
+        };
+        match peeked {
+            Ok(0) => {
+                info!("[{}] 客户端关闭连接", client_addr_str);
+                return;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                error!("[{}] 读取客户端数据失败: {}", client_addr_str, e);
+                return;
+            }
+        }
+        // 不可能是请求开头的字节（如发往明文端口的TLS握手）不会带来请求头部结束符，立即拒绝
+        if !is_request_start(first_byte[0]) {
+            metrics().record_request(&ProtocolType::Unknown);
+            logging::record_protocol(ProtocolType::Unknown.label());
+            // 先读走已到达的数据，关闭时未读数据会使内核发送RST，客户端可能收不到响应
+            let _ = stream.read(&mut [0u8; 4096]).await;
+            self.reject_bad_request(&mut stream, client_addr, bad_request_hint(&[]), "HTTP/1.1")
+                .await;
+            let _ = stream.shutdown().await;
+            return;
+        }
+        let socks = crate::parser::detector::detect_protocol(&first_byte);
+        if matches!(socks, ProtocolType::Socks4 | ProtocolType::Socks5) {
+            info!("[{}] 检测到协议: {:?}", client_addr_str, socks);
+            metrics().record_request(&socks);
+            logging::record_protocol(socks.label());
+            access_log::record_method(&socks.label().to_ascii_uppercase());
+            let result = if socks == ProtocolType::Socks4 {
+                handlers::socks4::handle_socks4(
+                    stream,
+                    client_addr_str.clone(),
+                    auth_config,
+                    &self.connector,
+                )
+                .await
+            } else {
+                handlers::socks5::handle_socks5(
+                    stream,
+                    client_addr_str.clone(),
+                    auth_config,
+                    &self.connector,
+                )
+                .await
+            };
+            match result {
+                // 目标被禁止时处理函数已回复客户端，只报告拒绝原因
+                Err(e) if is_access_denied(e.as_ref()) => {
+                    self.reject(client_addr, RejectionReason::BlockedDestination);
+                }
+                Err(e) => error!("[{}] {:?}处理失败: {}", client_addr_str, socks, e),
+                Ok(()) => {}
+            }
+            return;
+        }
+
+        // 读取完整的请求头部
+        // head_len 之后是客户端在头部之后立即发送的数据
+        let head = match timeout_at(
+            handshake_deadline,
+            read_http_head(&mut stream, self.max_header_size, self.initial_read_size),
+        )
+        .await
+        {
+            Ok(head) => head,
+            Err(_) => {
+                self.handshake_timed_out(client_addr);
+                return;
+            }
+        };
+        let (buffer, head_len) = match head {
+            Ok(HeadRead::Complete { mut head, mut rest }) => {
+                let head_len = head.len();
+                head.append(&mut rest);
+                (head, head_len)
+            }
+            Ok(HeadRead::Closed(data)) if data.is_empty() => {
+                info!("[{}] 客户端关闭连接", client_addr_str);
+                return;
+            }
+            Ok(HeadRead::Closed(data)) => {
+                let head_len = data.len();
+                (data, head_len)
+            }
+            Ok(HeadRead::TooLarge) => {
+                error!(
+                    "[{}] 请求头超过上限 {} 字节",
+                    client_addr_str, self.max_header_size
+                );
+                self.reject(client_addr, RejectionReason::HeaderTooLarge);
+                let _ = send_error_response(
+                    &mut stream,
+                    "431 Request Header Fields Too Large",
+                    "请求头过大",
+                    "HTTP/1.1",
+                )
+                .await;
+                return;
+            }
+            Err(e) => {
+                error!("[{}] 读取客户端数据失败: {}", client_addr_str, e);
+                return;
+            }
+        };
+        let n = buffer.len();
+        debug!("[{}] 收到 {} 字节数据", client_addr_str, n);
+        if let Some(method) = request_method(&buffer[..head_len]) {
+            access_log::record_method(method);
+        }
+
+        // 非法字节对所有协议都检查，`User-Agent` 只在识别为明文HTTP后检查
+        let options = Http1Options {
+            strict_headers: self.strict_headers,
+            ..Http1Options::default()
+        };
+        if let Some(violation) = request_policy_violation(&buffer[..head_len], &options) {
+            error!("[{}] 请求头包含非法字节", client_addr_str);
+            self.reject_request(&mut stream, client_addr, violation)
+                .await;
+            return;
+        }
+
+        // 直接访问代理自身地址的请求以及扫描器路径不做转发
+        if let Some(path) = deflected_path(&stream, &buffer[..n], self.deflect_scanners).await {
+            info!("[{}] 直接访问代理自身: {}", client_addr_str, path);
+            if let Err(e) = self.serve_self_request(&mut stream, &path).await {
+                error!("[{}] 发送信息页失败: {}", client_addr_str, e);
+            }
+            return;
+        }
+
+        // 反向代理模式下HTTP/1.x请求按Host路由
+        if !self.routes.is_empty() {
+            let protocol = crate::parser::detector::detect_protocol(&buffer[..n]);
+            if matches!(protocol, ProtocolType::Http10 | ProtocolType::Http11) {
+                metrics().record_request(&protocol);
+                logging::record_protocol(protocol.label());
+                if self
+                    .reject_missing_user_agent(
+                        &mut stream,
+                        client_addr,
+                        &protocol,
+                        &buffer[..head_len],
+                    )
+                    .await
+                {
                     return;
                 }
+                self.handle_reverse(stream, client_addr, &buffer[..n], protocol)
+                    .await;
+                return;
+            }
+        }
 
-                info!("[{}] 连接建立成功，开始透明转发", client_addr_str);
+        // 提取认证头
+        let auth_header = extract_proxy_auth(&buffer[..n]);
 
-                // 建立双向透明转发
-                let (mut client_reader, mut client_writer) = stream.into_split();
-                let (mut target_reader, mut target_writer) = target_stream.into_split();
+        // 检查认证
+        let method = request_method(&buffer[..head_len]).unwrap_or_default();
+        let target = request_target(&buffer[..head_len]).unwrap_or_default();
+        if !check_authentication(auth_config, auth_header.as_deref(), method, target) {
+            info!("[{}] 认证失败，需要代理认证", client_addr_str);
+            metrics().record_auth_failure();
+            self.reject(client_addr, RejectionReason::AuthFailed);
+            let challenge = auth_config
+                .as_ref()
+                .map(AuthConfig::challenge)
+                .unwrap_or_default();
+            if let Err(e) = send_auth_required_response(
+                &mut stream,
+                response_version(&buffer[..head_len]),
+                &challenge,
+            )
+            .await
+            {
+                error!("[{}] 发送认证要求响应失败: {}", client_addr_str, e);
+            }
+            return;
+        }
+        let mut context = ConnectionContext::new(client_addr);
+        context.user = auth_header.as_deref().and_then(proxy_auth_username);
+        if let Some(user) = &context.user {
+            access_log::record_user(user);
+        }
 
-                let client_to_target = async {
-                    let mut buffer = [0u8; 8192];
-                    loop {
-                        match client_reader.read(&mut buffer).await {
-                            Ok(0) => {
-                                debug!("[{}] 隧道客户端流结束", client_addr_str);
-                                break;
-                            }
-                            Ok(n) => {
-                                if let Err(e) = target_writer.write_all(&buffer[..n]).await {
-                                    error!("[{}] 隧道转发到目标失败: {}", client_addr_str, e);
-                                    break;
-                                }
-                            }
-                            Err(e) => {
-                                error!("[{}] 读取隧道客户端数据失败: {}", client_addr_str, e);
-                                break;
-                            }
-                        }
+        // 受信任客户端可通过 X-Tenant-Id 标记租户，按租户统计请求数和字节数
+        context.tenant =
+            extract_tenant(&buffer[..head_len], client_addr.ip(), &self.trusted_proxies);
+        if let Some(tenant) = &context.tenant {
+            debug!("[{}] 租户: {}", client_addr_str, tenant);
+            metrics::set_tenant(tenant);
+            metrics().record_tenant_request(tenant);
+        }
+
+        // 推断原始请求协议（前置TLS终结负载均衡时可能为https）
+        let scheme = if self.trust_forwarded_proto {
+            infer_scheme(&buffer[..n], client_addr.ip(), &self.trusted_proxies)
+        } else {
+            "http"
+        };
+
+        // 检测协议类型
+        let protocol = crate::parser::detector::detect_protocol(&buffer[..n]);
+        info!("[{}] 检测到协议: {:?}", client_addr_str, protocol);
+        metrics().record_request(&protocol);
+        logging::record_protocol(protocol.label());
+        context.protocol = Some(protocol.label().to_string());
+
+        if self
+            .reject_missing_user_agent(&mut stream, client_addr, &protocol, &buffer[..head_len])
+            .await
+        {
+            return;
+        }
+
+        match protocol {
+            // CONNECT隧道（HTTPS/HTTP/2 over TLS）
+            ProtocolType::ConnectTunnel { host, port } => {
+                if let Err(e) = self
+                    .handle_connect_tunnel(
+                        stream,
+                        context,
+                        host,
+                        port,
+                        response_version(&buffer[..head_len]),
+                        &buffer[head_len..],
+                    )
+                    .await
+                {
+                    error!("[{}] CONNECT隧道失败: {}", client_addr_str, e);
+                }
+            }
+
+            // HTTP/1.0
+            ProtocolType::Http10 => {
+                if let Err(e) = handlers::http1::handle_http1(
+                    stream,
+                    client_addr_str.clone(),
+                    auth_config,
+                    &self.connector,
+                    scheme,
+                    &buffer[..n],
+                    self.http1_options(self.http10_close),
+                )
+                .await
+                {
+                    error!(
+                        "[{}] HTTP/1.0处理失败: {}",
+                        client_addr_str,
+                        ProxyError::new(context, e)
+                    );
+                }
+            }
+
+            // HTTP/1.1
+            ProtocolType::Http11 => {
+                if let Err(e) = handlers::http1::handle_http1(
+                    stream,
+                    client_addr_str.clone(),
+                    auth_config,
+                    &self.connector,
+                    scheme,
+                    &buffer[..n],
+                    self.http1_options(false),
+                )
+                .await
+                {
+                    error!(
+                        "[{}] HTTP/1.1处理失败: {}",
+                        client_addr_str,
+                        ProxyError::new(context, e)
+                    );
+                }
+            }
+
+            // HTTP/2 (clear-text)
+            ProtocolType::Http2 => {
+                // HTTP/2需要从Host头获取目标
+                if let Some((host, port)) =
+                    crate::connection::parse_http_request(&buffer[..n]).await
+                {
+                    context.destination = Some(format!("{}:{}", host, port));
+                    if let Err(e) = handlers::http2::handle_http2(
+                        PrefetchedStream::new(stream, buffer[..n].to_vec()),
+                        client_addr_str.clone(),
+                        &self.connector,
+                        &host,
+                        port,
+                    )
+                    .await
+                    {
+                        error!(
+                            "[{}] HTTP/2处理失败: {}",
+                            client_addr_str,
+                            ProxyError::new(context, e)
+                        );
                     }
-                };
+                } else {
+                    error!("[{}] HTTP/2请求缺少Host头", client_addr_str);
+                    self.reject(
+                        client_addr,
+                        RejectionReason::BadRequest("缺少Host头".to_string()),
+                    );
+                    let _ = send_error_response(
+                        &mut stream,
+                        "400 Bad Request",
+                        "缺少Host头",
+                        "HTTP/1.1",
+                    )
+                    .await;
+                }
+            }
 
-                let target_to_client = async {
-                    let mut buffer = [0u8; 8192];
-                    loop {
-                        match target_reader.read(&mut buffer).await {
-                            Ok(0) => {
-                                debug!("[{}] 隧道目标流结束", client_addr_str);
-                                break;
-                            }
-                            Ok(n) => {
-                                if let Err(e) = client_writer.write_all(&buffer[..n]).await {
-                                    error!("[{}] 隧道转发到客户端失败: {}", client_addr_str, e);
-                                    break;
-                                }
-                            }
-                            Err(e) => {
-                                error!("[{}] 读取隧道目标数据失败: {}", client_addr_str, e);
-                                break;
+            // WebSocket升级
+            ProtocolType::WebSocketUpgrade {
+                key: _,
+                host: _,
+                port: _,
+            } => match crate::handlers::websocket::parse_websocket_upgrade(&buffer[..n], scheme) {
+                Ok(Some(upgrade)) => {
+                    // 会话许可在整个WebSocket会话期间持有
+                    let _session = match &self.websocket_sessions {
+                        Some(sessions) => match sessions.clone().try_acquire_owned() {
+                            Ok(permit) => Some(permit),
+                            Err(_) => {
+                                warn!("[{}] WebSocket会话数已达上限，拒绝升级", client_addr_str);
+                                self.reject(client_addr, RejectionReason::TooManyWebSocketSessions);
+                                let _ = send_error_response(
+                                    &mut stream,
+                                    "503 Service Unavailable",
+                                    "WebSocket会话数已达上限",
+                                    "HTTP/1.1",
+                                )
+                                .await;
+                                return;
                             }
+                        },
+                        None => None,
+                    };
+                    context.destination = Some(format!("{}:{}", upgrade.host, upgrade.port));
+                    if let Err(e) = handlers::websocket::handle_websocket(
+                        stream,
+                        client_addr_str.clone(),
+                        &self.connector,
+                        upgrade,
+                        &buffer[..n],
+                        self.websocket_close_frame,
+                        self.closed(),
+                    )
+                    .await
+                    {
+                        if is_access_denied(e.as_ref()) {
+                            self.reject(client_addr, RejectionReason::BlockedDestination);
                         }
+                        error!(
+                            "[{}] WebSocket处理失败: {}",
+                            client_addr_str,
+                            ProxyError::new(context, e)
+                        );
                     }
-                };
+                }
+                Ok(None) => {
+                    self.reject_bad_request(
+                        &mut stream,
+                        client_addr,
+                        bad_request_hint(&buffer[..head_len]),
+                        response_version(&buffer[..head_len]),
+                    )
+                    .await;
+                }
+                Err(e) => {
+                    debug!("[{}] WebSocket升级请求解析错误: {}", client_addr_str, e);
+                    self.reject_bad_request(
+                        &mut stream,
+                        client_addr,
+                        bad_request_hint(&buffer[..head_len]),
+                        response_version(&buffer[..head_len]),
+                    )
+                    .await;
+                }
+            },
 
-                tokio::select! {
-                    _ = client_to_target => {
-                        debug!("[{}] 隧道客户端到目标连接结束", client_addr_str);
-                    }
-                    _ = target_to_client => {
-                        debug!("[{}] 隧道目标到客户端连接结束", client_addr_str);
+            // SOCKS已在读取HTTP头部前处理
+            ProtocolType::Socks4 | ProtocolType::Socks5 => {}
+
+            // 未知协议
+            ProtocolType::Unknown => {
+                self.reject_bad_request(
+                    &mut stream,
+                    client_addr,
+                    bad_request_hint(&buffer[..head_len]),
+                    response_version(&buffer[..head_len]),
+                )
+                .await;
+            }
+        }
+    }
+
+    /// 以 `400` 拒绝无法处理的请求，响应体为说明期望格式的 `hint`，随后由调用方关闭连接
+    async fn reject_bad_request(
+        &self,
+        stream: &mut ClientStream,
+        client_addr: SocketAddr,
+        hint: &str,
+        version: &str,
+    ) {
+        error!("[{}] 无效请求: {}", client_addr, hint);
+        self.reject(client_addr, RejectionReason::BadRequest(hint.to_string()));
+        if let Err(e) = send_error_response(stream, "400 Bad Request", hint, version).await {
+            debug!("[{}] 发送400响应失败: {}", client_addr, e);
+        }
+    }
+
+    /// 读取PROXY协议头，返回原始客户端地址，头部未给出地址时沿用 `peer_addr`
+    ///
+    /// 头部无效、读取超时或连接提前关闭时返回 `None`，由调用方关闭连接
+    async fn accept_proxy_header(
+        &self,
+        stream: &mut ClientStream,
+        peer_addr: SocketAddr,
+    ) -> Option<SocketAddr> {
+        let deadline = Instant::now() + self.handshake_timeout;
+        match timeout_at(deadline, read_proxy_header(stream)).await {
+            Ok(Ok(Some(source))) => {
+                let source = SocketAddr::new(source.ip().to_canonical(), source.port());
+                debug!("[{}] PROXY协议头给出客户端地址: {}", peer_addr, source);
+                Some(source)
+            }
+            Ok(Ok(None)) => Some(peer_addr),
+            Ok(Err(e)) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                debug!("[{}] 读取PROXY协议头时连接已关闭", peer_addr);
+                None
+            }
+            Ok(Err(e)) => {
+                warn!("[{}] {}，关闭连接", peer_addr, e);
+                self.reject(peer_addr, RejectionReason::InvalidProxyHeader);
+                None
+            }
+            Err(_) => {
+                self.handshake_timed_out(peer_addr);
+                None
+            }
+        }
+    }
+
+    /// 握手阶段超时，记录告警后由调用方关闭连接
+    fn handshake_timed_out(&self, client_addr: SocketAddr) {
+        warn!(
+            "[{}] 超过 {:?} 未收到完整请求，关闭连接",
+            client_addr, self.handshake_timeout
+        );
+        self.reject(client_addr, RejectionReason::HandshakeTimeout);
+    }
+
+    /// 启用 [`with_require_user_agent`](Self::with_require_user_agent) 时拒绝缺少
+    /// `User-Agent` 的明文HTTP请求，返回是否已拒绝
+    async fn reject_missing_user_agent(
+        &self,
+        stream: &mut ClientStream,
+        client_addr: SocketAddr,
+        protocol: &ProtocolType,
+        head: &[u8],
+    ) -> bool {
+        let plain_http = matches!(
+            protocol,
+            ProtocolType::Http10 | ProtocolType::Http11 | ProtocolType::WebSocketUpgrade { .. }
+        );
+        if !plain_http {
+            return false;
+        }
+        let options = Http1Options {
+            require_user_agent: self.require_user_agent,
+            ..Http1Options::default()
+        };
+        match request_policy_violation(head, &options) {
+            Some(violation) => {
+                info!("[{}] 请求缺少User-Agent，拒绝", client_addr);
+                self.reject_request(stream, client_addr, violation).await;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 拒绝违反逐请求检查的请求，响应后由调用方关闭连接
+    async fn reject_request(
+        &self,
+        stream: &mut ClientStream,
+        client_addr: SocketAddr,
+        (reason, status, message): (RejectionReason, &str, &str),
+    ) {
+        self.reject(client_addr, reason);
+        let _ = send_error_response(stream, status, message, "HTTP/1.1").await;
+    }
+
+    /// 按 `Host` 将请求转发到路由表中的后端，未匹配时返回404
+    async fn handle_reverse(
+        &self,
+        mut stream: ClientStream,
+        client_addr: SocketAddr,
+        buffer: &[u8],
+        protocol: ProtocolType,
+    ) {
+        let host = crate::connection::parse_http_request(buffer)
+            .await
+            .map(|(host, _)| host.to_ascii_lowercase());
+        let backend = match host.as_ref().and_then(|host| self.routes.get(host)) {
+            Some(backend) => backend,
+            None => {
+                info!("[{}] 反向代理未找到路由: {:?}", client_addr, host);
+                let _ = send_error_response(
+                    &mut stream,
+                    "404 Not Found",
+                    "未配置该主机的路由",
+                    "HTTP/1.1",
+                )
+                .await;
+                return;
+            }
+        };
+
+        let force_close = protocol == ProtocolType::Http10 && self.http10_close;
+        let mut context = ConnectionContext::new(client_addr);
+        context.protocol = Some(protocol.label().to_string());
+        context.destination = Some(format!("{}:{}", backend.0, backend.1));
+        if let Err(e) = handlers::http1::handle_reverse(
+            stream,
+            client_addr.to_string(),
+            &self.connector,
+            buffer,
+            self.http1_options(force_close),
+            backend,
+        )
+        .await
+        {
+            error!(
+                "[{}] 反向代理处理失败: {}",
+                client_addr,
+                ProxyError::new(context, e)
+            );
+        }
+    }
+
+    /// 响应目标为代理自身的请求
+    async fn serve_self_request(
+        &self,
+        stream: &mut ClientStream,
+        path: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let text = "text/plain; charset=utf-8";
+        let (status, content_type, body) = match (&self.landing_page, path) {
+            (Some(page), "/") => ("200 OK", "text/html; charset=utf-8", page.as_str()),
+            (_, path) if self.deflect_scanners && is_scanner_path(path) => {
+                match (path.split('?').next(), &self.scanner_body) {
+                    (Some(p), _) if p.eq_ignore_ascii_case("/robots.txt") => {
+                        ("200 OK", text, ROBOTS_DISALLOW_ALL)
                     }
+                    (_, Some(body)) => ("200 OK", text, body.as_str()),
+                    (_, None) => ("404 Not Found", text, "Not Found"),
                 }
             }
+            (_, "/readyz") => match &self.readiness {
+                Some(readiness) if readiness.is_ready() => ("200 OK", text, "ready"),
+                Some(_) => ("503 Service Unavailable", text, "not ready"),
+                None => ("404 Not Found", text, "Not Found"),
+            },
+            _ => ("404 Not Found", text, "Not Found"),
+        };
+
+        access_log::record_status_text(status);
+        access_log::record_bytes(body.len() as u64);
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            content_type,
+            body.len(),
+            body
+        );
+        stream.write_all(response.as_bytes()).await?;
+        Ok(())
+    }
+
+    /// 处理CONNECT隧道请求（HTTPS/HTTP/2 over TLS）
+    ///
+    /// `early_data` 为客户端紧随CONNECT头部发送、未等待 `200` 响应的数据；
+    /// 响应使用与请求相同的HTTP版本 `version`。
+    /// 失败时返回的错误携带目标、阶段等连接上下文
+    async fn handle_connect_tunnel(
+        &self,
+        mut stream: ClientStream,
+        mut context: ConnectionContext,
+        host: String,
+        port: u16,
+        version: &str,
+        early_data: &[u8],
+    ) -> Result<(), ProxyError> {
+        let client_addr_str = context.client_addr.to_string();
+        info!("[{}] CONNECT隧道到 {}:{}", client_addr_str, host, port);
+        context.destination = Some(format!("{}:{}", host, port));
+        context.phase = Phase::Connect;
+
+        if let Some(ports) = &self.allowed_connect_ports {
+            if !ports.contains(&port) {
+                logging::policy_violation(
+                    PolicyViolation::DisallowedPort,
+                    &format!("[{}] {}:{}", client_addr_str, host, port),
+                );
+                self.reject(context.client_addr, RejectionReason::BlockedDestination);
+                let message = format!("不允许CONNECT到端口 {}", port);
+                let _ = send_error_response(&mut stream, "403 Forbidden", &message, version).await;
+                return Ok(());
+            }
+        }
+
+        // 先连接到目标服务器，成功后再发送响应
+        let mut target_stream = match self.connector.connect(&host, port).await {
+            Ok(target_stream) => target_stream,
+            // 策略拒绝由限速日志记录，不作为连接错误逐条输出
+            Err(e) if is_access_denied(e.as_ref()) => {
+                logging::policy_violation(
+                    PolicyViolation::BlockedDestination,
+                    &format!("[{}] {}:{}: {}", client_addr_str, host, port, e),
+                );
+                self.reject(context.client_addr, RejectionReason::BlockedDestination);
+                let message = forbidden_message(e.as_ref(), &host, port);
+                let _ = send_error_response(&mut stream, "403 Forbidden", &message, version).await;
+                return Ok(());
+            }
             Err(e) => {
-                error!(
-                    "[{}] 连接目标服务器失败 {}:{}: {}",
-                    client_addr_str, host, port, e
+                let (status, message) = if let Some(status) = upstream_status(e.as_ref()) {
+                    upstream_failure(status, &host, port)
+                } else if is_timeout(e.as_ref()) {
+                    (
+                        "504 Gateway Timeout",
+                        format!("连接 {}:{} 超时", host, port),
+                    )
+                } else {
+                    ("502 Bad Gateway", format!("无法连接到 {}:{}", host, port))
+                };
+                let _ = send_error_response(&mut stream, status, &message, version).await;
+                return Err(ProxyError::new(context, e));
+            }
+        };
+        context.phase = Phase::Relay;
+
+        // 客户端未等待200就发送的数据（如TLS ClientHello）先转发给目标；
+        // 要求SNI时与之后的ClientHello一起检查后再转发
+        if !self.require_sni && !early_data.is_empty() {
+            debug!(
+                "[{}] 转发CONNECT之后提前到达的 {} 字节",
+                client_addr_str,
+                early_data.len()
+            );
+            if let Err(e) = target_stream.write_all(early_data).await {
+                return Err(ProxyError::new(context, e.into()));
+            }
+            context.bytes_sent = early_data.len() as u64;
+        }
+
+        // 发送连接成功响应
+        access_log::record_status(200);
+        let response = format!("{} 200 Connection Established\r\n\r\n", version);
+        if let Err(e) = stream.write_all(response.as_bytes()).await {
+            return Err(ProxyError::new(context, e.into()));
+        }
+        if let Err(e) = stream.flush().await {
+            return Err(ProxyError::new(context, e.into()));
+        }
+
+        if self.require_sni {
+            let hello = match self.read_client_hello(&mut stream, early_data).await {
+                Ok(Some(hello)) => hello,
+                Ok(None) => {
+                    logging::policy_violation(
+                        PolicyViolation::MissingSni,
+                        &format!("[{}] {}:{}", client_addr_str, host, port),
+                    );
+                    stream.reset();
+                    return Ok(());
+                }
+                Err(e) => return Err(ProxyError::new(context, e.into())),
+            };
+            if let Err(e) = target_stream.write_all(&hello).await {
+                return Err(ProxyError::new(context, e.into()));
+            }
+            context.bytes_sent = hello.len() as u64;
+        }
+
+        info!("[{}] 连接建立成功，开始透明转发", client_addr_str);
+
+        // 建立双向透明转发
+        match relay(stream, target_stream, self.connector.relay_options()).await {
+            Ok((sent, received)) => {
+                debug!(
+                    "[{}] 隧道结束，上行 {} 字节，下行 {} 字节",
+                    client_addr_str, sent, received
                 );
-                // 连接失败，连接将被客户端或服务端关闭
+                Ok(())
             }
+            Err(e) => Err(ProxyError::new(context, e.into())),
         }
     }
+
+    /// 读取隧道开头的TLS ClientHello，`early_data` 为已读到的数据
+    ///
+    /// 携带SNI时返回读到的全部数据，由调用方转发给目标；没有SNI、不是TLS、
+    /// 超过 [`MAX_CLIENT_HELLO`] 或未在握手超时内到达时返回 `None`
+    async fn read_client_hello(
+        &self,
+        stream: &mut ClientStream,
+        early_data: &[u8],
+    ) -> io::Result<Option<Vec<u8>>> {
+        let deadline = Instant::now() + self.handshake_timeout;
+        let mut data = early_data.to_vec();
+        let mut chunk = [0u8; 4096];
+        loop {
+            match parse_client_hello(&data) {
+                ClientHello::Complete(Some(sni)) => {
+                    debug!("ClientHello的SNI: {}", sni);
+                    return Ok(Some(data));
+                }
+                ClientHello::Complete(None) | ClientHello::Invalid => return Ok(None),
+                ClientHello::Incomplete if data.len() >= MAX_CLIENT_HELLO => return Ok(None),
+                ClientHello::Incomplete => {}
+            }
+            let n = match timeout_at(deadline, stream.read(&mut chunk)).await {
+                Ok(read) => read?,
+                Err(_) => return Ok(None),
+            };
+            if n == 0 {
+                return Ok(None);
+            }
+            data.extend_from_slice(&chunk[..n]);
+        }
+    }
+}
+
+/// 将上游代理拒绝 `CONNECT` 的状态码转换为返回给客户端的响应
+///
+/// 上游要求的认证由本代理而不是客户端提供，上游 `407` 对客户端而言是网关错误，
+/// 原样返回会让客户端误以为需要向本代理认证
+fn upstream_failure(status: u16, host: &str, port: u16) -> (&'static str, String) {
+    match status {
+        407 => ("502 Bad Gateway", "上游代理认证失败".to_string()),
+        403 => (
+            "403 Forbidden",
+            format!("上游代理禁止访问 {}:{}", host, port),
+        ),
+        504 => (
+            "504 Gateway Timeout",
+            format!("上游代理连接 {}:{} 超时", host, port),
+        ),
+        _ => (
+            "502 Bad Gateway",
+            format!("上游代理无法连接到 {}:{}（{}）", host, port, status),
+        ),
+    }
+}
+
+/// 默认的握手阶段时限
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 禁止所有抓取的robots.txt
+const ROBOTS_DISALLOW_ALL: &str = "User-agent: *\nDisallow: /\n";
+
+/// 依次绑定各个监听地址
+///
+/// 绑定失败的地址记录错误后跳过，返回绑定成功的监听器，调用方据此决定是否继续运行
+pub async fn bind_listeners(addrs: &[SocketAddr]) -> Vec<TcpListener> {
+    let mut listeners = Vec::new();
+    for addr in addrs {
+        match bind_listener(*addr) {
+            Ok(listener) => listeners.push(listener),
+            Err(e) => error!("绑定监听地址 {} 失败: {}", addr, e),
+        }
+    }
+    listeners
+}
+
+/// 绑定一个TCP监听地址
+///
+/// IPv6未指定地址（`::`）明确关闭 `IPV6_V6ONLY`，同时接受IPv4与IPv6连接，不依赖系统默认值；
+/// 其他IPv6地址只接受IPv6连接
+pub fn bind_listener(addr: SocketAddr) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(!addr.ip().is_unspecified())?;
+    }
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(LISTEN_BACKLOG)?;
+    TcpListener::from_std(socket.into())
+}
+
+/// 监听队列长度，与 [`TcpListener::bind`] 一致
+const LISTEN_BACKLOG: i32 = 1024;
+
+/// 监听地址是否同时接受IPv4与IPv6连接
+pub fn is_dual_stack(addr: SocketAddr) -> bool {
+    addr.is_ipv6() && addr.ip().is_unspecified()
+}
+
+/// 可接受客户端连接的监听器
+trait Acceptor: Send + Sync + 'static {
+    /// 接受一个连接，返回客户端流及用于日志和访问控制的客户端地址
+    fn accept_client(&self) -> impl Future<Output = io::Result<(ClientStream, SocketAddr)>> + Send;
+
+    /// 监听的网络地址，没有网络地址的监听器返回 `None`
+    fn listen_addr(&self) -> Option<SocketAddr>;
+}
+
+impl Acceptor for TcpListener {
+    async fn accept_client(&self) -> io::Result<(ClientStream, SocketAddr)> {
+        let (stream, addr) = self.accept().await?;
+        // 双栈监听上的IPv4客户端显示为IPv4映射地址，还原为IPv4地址后再做访问控制和记录
+        Ok((
+            stream.into(),
+            SocketAddr::new(addr.ip().to_canonical(), addr.port()),
+        ))
+    }
+
+    fn listen_addr(&self) -> Option<SocketAddr> {
+        self.local_addr().ok()
+    }
+}
+
+#[cfg(unix)]
+impl Acceptor for UnixListener {
+    async fn accept_client(&self) -> io::Result<(ClientStream, SocketAddr)> {
+        let (stream, _) = self.accept().await?;
+        Ok((stream.into(), UNIX_CLIENT_ADDR))
+    }
+
+    fn listen_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpStream;
+
+    #[tokio::test]
+    async fn test_connect_refused_error_carries_context() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (server, client_addr) = listener.accept().await.unwrap();
+
+        // 获取一个当前无人监听的端口
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = closed.local_addr().unwrap().port();
+        drop(closed);
+
+        let mut context = ConnectionContext::new(client_addr);
+        context.protocol = Some(
+            ProtocolType::ConnectTunnel {
+                host: "127.0.0.1".to_string(),
+                port,
+            }
+            .label()
+            .to_string(),
+        );
+
+        let error = Proxy::new(None)
+            .handle_connect_tunnel(
+                server.into(),
+                context,
+                "127.0.0.1".to_string(),
+                port,
+                "HTTP/1.1",
+                &[],
+            )
+            .await
+            .unwrap_err();
+        drop(client);
+
+        let context = error.context();
+        assert_eq!(context.destination, Some(format!("127.0.0.1:{}", port)));
+        assert_eq!(context.protocol.as_deref(), Some("connect"));
+        assert_eq!(context.phase, Phase::Connect);
+        assert!(error.to_string().contains("阶段=连接目标"), "{}", error);
+    }
 }
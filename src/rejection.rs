@@ -0,0 +1,51 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// 连接被拒绝的原因
+///
+/// 通过 `Proxy::with_rejection_callback` 注册的回调获取，便于嵌入方自行记录
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RejectionReason {
+    /// 代理认证失败
+    AuthFailed,
+    /// 请求头超过上限
+    HeaderTooLarge,
+    /// 请求无法解析
+    BadRequest(String),
+    /// WebSocket会话数已达上限
+    TooManyWebSocketSessions,
+    /// 明文HTTP请求缺少 `User-Agent`
+    MissingUserAgent,
+    /// 请求方法不在允许列表中
+    DisallowedMethod(String),
+    /// 握手阶段未在时限内收到完整请求头
+    HandshakeTimeout,
+    /// 启用PROXY协议时连接开头不是有效的PROXY协议头
+    InvalidProxyHeader,
+    /// 目标被访问规则、CONNECT端口限制或内部地址限制禁止，或指向代理自身
+    BlockedDestination,
+    /// 接受连接时连接许可已耗尽，连接排队等待其他连接结束后才被处理
+    OverCapacity,
+}
+
+impl fmt::Display for RejectionReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectionReason::AuthFailed => write!(f, "认证失败"),
+            RejectionReason::HeaderTooLarge => write!(f, "请求头过大"),
+            RejectionReason::BadRequest(detail) => write!(f, "无效请求: {}", detail),
+            RejectionReason::TooManyWebSocketSessions => write!(f, "WebSocket会话数已达上限"),
+            RejectionReason::MissingUserAgent => write!(f, "缺少User-Agent"),
+            RejectionReason::DisallowedMethod(method) => write!(f, "不允许的请求方法: {}", method),
+            RejectionReason::HandshakeTimeout => write!(f, "握手超时"),
+            RejectionReason::InvalidProxyHeader => write!(f, "PROXY协议头无效"),
+            RejectionReason::BlockedDestination => write!(f, "目标被禁止"),
+            RejectionReason::OverCapacity => write!(f, "连接数已达上限"),
+        }
+    }
+}
+
+/// 连接被拒绝时调用的回调
+pub type RejectionCallback = Arc<dyn Fn(SocketAddr, &RejectionReason) + Send + Sync>;
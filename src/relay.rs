@@ -0,0 +1,680 @@
+use crate::access_log;
+use crate::logging;
+use crate::metrics::metrics;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::time::Instant;
+use tracing::{debug, info};
+
+/// 默认的转发缓冲区大小（每个方向）
+pub const DEFAULT_BUFFER_SIZE: usize = 16 * 1024;
+
+/// 转发缓冲区大小的允许范围
+pub const MIN_BUFFER_SIZE: usize = 512;
+pub const MAX_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// 默认的关闭宽限期
+pub const DEFAULT_TEARDOWN_GRACE: Duration = Duration::from_secs(1);
+
+/// 转发参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RelayOptions {
+    /// 两个方向都没有数据流动超过该时长时关闭连接
+    pub idle_timeout: Option<Duration>,
+    /// 每个方向的缓冲区大小
+    pub buffer_size: usize,
+    /// 每个方向已读出、尚未写入对端的字节数上限，达到上限时暂停读取快的一端
+    pub max_inflight: Option<usize>,
+    /// 异常关闭时刷新并关闭两端写方向的最长时间
+    pub teardown_grace: Duration,
+    /// 转发的是WebSocket连接时，空闲超时或代理关闭时先向两端发送关闭帧（1001 going away）
+    pub websocket_close: bool,
+}
+
+impl Default for RelayOptions {
+    fn default() -> Self {
+        Self {
+            idle_timeout: None,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            max_inflight: None,
+            teardown_grace: DEFAULT_TEARDOWN_GRACE,
+            websocket_close: false,
+        }
+    }
+}
+
+/// 校验转发缓冲区大小：须为2的幂且在 [`MIN_BUFFER_SIZE`, `MAX_BUFFER_SIZE`] 范围内
+pub fn validate_buffer_size(size: usize) -> Result<usize, String> {
+    if !size.is_power_of_two() || !(MIN_BUFFER_SIZE..=MAX_BUFFER_SIZE).contains(&size) {
+        return Err(format!(
+            "缓冲区大小须为 {} 到 {} 之间的2的幂: {}",
+            MIN_BUFFER_SIZE, MAX_BUFFER_SIZE, size
+        ));
+    }
+    Ok(size)
+}
+
+/// 在客户端与目标服务器之间双向转发数据
+///
+/// 基于 `tokio::io::copy_bidirectional`：某一方向读到EOF时仅关闭对端的写入方向，
+/// 另一方向继续转发直到同样结束，因此上传结束不会中断仍在进行的下载。
+/// 两端可以是任意异步流（如TCP连接或TLS连接）。转发的字节数计入全局指标。
+///
+/// 每个方向只有一个缓冲区，缓冲区中的数据全部写入对端之后才会再次读取，
+/// 慢的一端由此自然地反压快的一端，单个连接占用的内存不随传输量增长。
+/// 设置 `max_inflight` 后缓冲区进一步缩小到该值，在途数据不超过该上限。
+///
+/// 设置 `idle_timeout` 后，若两个方向在该时长内都没有数据流动，则关闭两端并返回
+/// `TimedOut` 错误，避免半死的对端长期占用任务和连接许可。
+/// 字节数在读取两端时计数，空闲超时或出错结束的转发同样计入指标和访问记录。
+///
+/// 空闲超时或出错时，在 `teardown_grace` 内尝试刷新并关闭两端的写方向，
+/// 已写入写缓冲（如TLS层）的数据尽量送达，避免截断接近完成的响应。
+/// 启用 `websocket_close` 时，空闲超时关闭前先向两端发送WebSocket关闭帧（代理关闭时见 [`relay_until`]），
+/// 让两端看到正常关闭而不是连接异常断开。
+///
+/// # 返回
+/// 返回 (客户端→目标, 目标→客户端) 各自传输的字节数
+pub async fn relay<C, T>(client: C, target: T, options: RelayOptions) -> io::Result<(u64, u64)>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    relay_until(client, target, options, std::future::pending()).await
+}
+
+/// 同 [`relay`]，`shutdown` 完成时（代理正在关闭）结束转发并返回 `Interrupted` 错误
+///
+/// 启用 `websocket_close` 时与空闲超时一样，先向两端发送WebSocket关闭帧
+pub async fn relay_until<C, T, F>(
+    client: C,
+    target: T,
+    options: RelayOptions,
+    shutdown: F,
+) -> io::Result<(u64, u64)>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+    T: AsyncRead + AsyncWrite + Unpin,
+    F: Future<Output = ()>,
+{
+    let buffer_size = match options.max_inflight {
+        Some(max_inflight) => options.buffer_size.min(max_inflight.max(1)),
+        None => options.buffer_size,
+    };
+
+    let tracker = IdleTracker::new();
+    let mut client = tracker.track(client);
+    let mut target = tracker.track(target);
+
+    let result = tokio::select! {
+        result = tokio::io::copy_bidirectional_with_sizes(&mut client, &mut target, buffer_size, buffer_size) => result,
+        _ = tracker.idle(options.idle_timeout) => {
+            info!("连接空闲超过 {:?}，关闭转发", options.idle_timeout);
+            if options.websocket_close {
+                send_close_frames(&mut client, &mut target, options.teardown_grace).await;
+            }
+            Err(io::Error::new(io::ErrorKind::TimedOut, "连接空闲超时"))
+        }
+        _ = shutdown => {
+            info!("代理正在关闭，结束转发");
+            if options.websocket_close {
+                send_close_frames(&mut client, &mut target, options.teardown_grace).await;
+            }
+            Err(io::Error::new(io::ErrorKind::Interrupted, "代理正在关闭"))
+        }
+    };
+    finish(result, &mut client, &mut target, options.teardown_grace).await
+}
+
+/// 记录转发的字节数，无论正常结束、空闲超时还是出错；异常结束时先尽力关闭两端再返回错误
+async fn finish<C, T>(
+    result: io::Result<(u64, u64)>,
+    client: &mut ActivityStream<C>,
+    target: &mut ActivityStream<T>,
+    grace: Duration,
+) -> io::Result<(u64, u64)>
+where
+    C: AsyncWrite + Unpin,
+    T: AsyncWrite + Unpin,
+{
+    // 从客户端读到的数据发往目标，从目标读到的数据发往客户端
+    let (sent, received) = (client.bytes_read(), target.bytes_read());
+    metrics().record_bytes(sent, received);
+    access_log::record_bytes(received);
+    logging::relay_finished(sent, received);
+    match result {
+        Ok(_) => Ok((sent, received)),
+        Err(e) => {
+            teardown(client, target, grace).await;
+            Err(e)
+        }
+    }
+}
+
+/// 在 `grace` 内刷新并关闭两端的写方向，超时则放弃
+async fn teardown<C, T>(client: &mut C, target: &mut T, grace: Duration)
+where
+    C: AsyncWrite + Unpin,
+    T: AsyncWrite + Unpin,
+{
+    let close = async {
+        tokio::join!(close_write(client), close_write(target));
+    };
+    if tokio::time::timeout(grace, close).await.is_err() {
+        debug!("关闭转发时未能在 {:?} 内刷新缓冲数据", grace);
+    }
+}
+
+/// WebSocket关闭状态码：端点离开（going away）
+const CLOSE_GOING_AWAY: u16 = 1001;
+
+/// 在 `grace` 内向两端发送WebSocket关闭帧
+///
+/// 代理对客户端相当于服务端，发往客户端的帧不加掩码；对目标相当于客户端，发往目标的帧必须加掩码
+async fn send_close_frames<C, T>(client: &mut C, target: &mut T, grace: Duration)
+where
+    C: AsyncWrite + Unpin,
+    T: AsyncWrite + Unpin,
+{
+    let to_client = close_frame(CLOSE_GOING_AWAY, None);
+    let to_target = close_frame(CLOSE_GOING_AWAY, Some(rand::random()));
+    let send = async {
+        let _ = tokio::join!(client.write_all(&to_client), target.write_all(&to_target));
+    };
+    if tokio::time::timeout(grace, send).await.is_err() {
+        debug!("未能在 {:?} 内发送WebSocket关闭帧", grace);
+    }
+}
+
+/// 构造只携带状态码的WebSocket关闭帧，`mask` 为掩码键
+fn close_frame(code: u16, mask: Option<[u8; 4]>) -> Vec<u8> {
+    let payload = code.to_be_bytes();
+    let mut frame = vec![0x88];
+    match mask {
+        Some(key) => {
+            frame.push(0x80 | payload.len() as u8);
+            frame.extend_from_slice(&key);
+            frame.extend(payload.iter().zip(key.iter().cycle()).map(|(b, k)| b ^ k));
+        }
+        None => {
+            frame.push(payload.len() as u8);
+            frame.extend_from_slice(&payload);
+        }
+    }
+    frame
+}
+
+async fn close_write<S: AsyncWrite + Unpin>(stream: &mut S) {
+    let _ = stream.flush().await;
+    let _ = stream.shutdown().await;
+}
+
+/// 空闲检测：经 [`track`](Self::track) 包装的任一流读到数据都算作一次活动
+///
+/// 包装的流同时各自统计读到的字节数
+pub(crate) struct IdleTracker {
+    start: Instant,
+    last_activity: Arc<AtomicU64>,
+}
+
+impl IdleTracker {
+    pub(crate) fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            last_activity: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// 包装一个流，其读到的数据刷新最后一次活动的时间
+    pub(crate) fn track<S>(&self, inner: S) -> ActivityStream<S> {
+        ActivityStream {
+            inner,
+            start: self.start,
+            last_activity: self.last_activity.clone(),
+            bytes_read: 0,
+        }
+    }
+
+    /// 在最后一次活动之后等待满 `idle_timeout` 时返回，未设置时永不返回
+    pub(crate) async fn idle(&self, idle_timeout: Option<Duration>) {
+        let idle_timeout = match idle_timeout {
+            Some(idle_timeout) => idle_timeout,
+            None => return std::future::pending().await,
+        };
+        loop {
+            let last =
+                self.start + Duration::from_millis(self.last_activity.load(Ordering::Relaxed));
+            let deadline = last + idle_timeout;
+            if Instant::now() >= deadline {
+                return;
+            }
+            tokio::time::sleep_until(deadline).await;
+        }
+    }
+}
+
+/// 记录最后一次读到数据时间的流包装
+pub(crate) struct ActivityStream<S> {
+    inner: S,
+    start: Instant,
+    last_activity: Arc<AtomicU64>,
+    bytes_read: u64,
+}
+
+impl<S> ActivityStream<S> {
+    pub(crate) fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// 已从该流读到的字节数
+    pub(crate) fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ActivityStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if matches!(result, Poll::Ready(Ok(()))) && buf.filled().len() > filled {
+            self.bytes_read += (buf.filled().len() - filled) as u64;
+            let elapsed = self.start.elapsed().as_millis() as u64;
+            self.last_activity.store(elapsed, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ActivityStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    async fn pair(listener: &TcpListener) -> (TcpStream, TcpStream) {
+        let addr = listener.local_addr().unwrap();
+        let (connected, accepted) = tokio::join!(TcpStream::connect(addr), listener.accept());
+        (connected.unwrap(), accepted.unwrap().0)
+    }
+
+    #[tokio::test]
+    async fn test_upload_half_close_keeps_download() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (mut client, proxy_client_side) = pair(&listener).await;
+        let (proxy_target_side, mut target) = pair(&listener).await;
+
+        let relay_task = tokio::spawn(relay(
+            proxy_client_side,
+            proxy_target_side,
+            RelayOptions::default(),
+        ));
+
+        // 客户端上传完成后关闭写方向
+        client.write_all(b"upload").await.unwrap();
+        client.shutdown().await.unwrap();
+
+        let mut uploaded = Vec::new();
+        target.read_to_end(&mut uploaded).await.unwrap();
+        assert_eq!(uploaded, b"upload");
+
+        // 上传结束后下载仍然可以继续
+        target.write_all(b"download").await.unwrap();
+        target.shutdown().await.unwrap();
+
+        let mut downloaded = Vec::new();
+        client.read_to_end(&mut downloaded).await.unwrap();
+        assert_eq!(downloaded, b"download");
+
+        assert_eq!(relay_task.await.unwrap().unwrap(), (6, 8));
+    }
+
+    #[tokio::test]
+    async fn test_small_buffer_transfers_large_payload() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (mut client, proxy_client_side) = pair(&listener).await;
+        let (proxy_target_side, mut target) = pair(&listener).await;
+
+        let relay_task = tokio::spawn(relay(
+            proxy_client_side,
+            proxy_target_side,
+            RelayOptions {
+                buffer_size: MIN_BUFFER_SIZE,
+                ..RelayOptions::default()
+            },
+        ));
+
+        // 负载远大于缓冲区，需多轮读写才能转发完
+        let payload: Vec<u8> = (0..64 * 1024).map(|i| (i % 251) as u8).collect();
+        let expected = payload.clone();
+        let writer = tokio::spawn(async move {
+            client.write_all(&payload).await.unwrap();
+            client.shutdown().await.unwrap();
+            client
+        });
+
+        let mut received = Vec::new();
+        target.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, expected);
+
+        target.shutdown().await.unwrap();
+        drop(writer.await.unwrap());
+        assert_eq!(
+            relay_task.await.unwrap().unwrap(),
+            (expected.len() as u64, 0)
+        );
+    }
+
+    /// 统计读出和写入字节数的流包装
+    struct Counting<S> {
+        inner: S,
+        read: Arc<AtomicU64>,
+        written: Arc<AtomicU64>,
+    }
+
+    impl<S: AsyncRead + Unpin> AsyncRead for Counting<S> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            let filled = buf.filled().len();
+            let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+            let n = (buf.filled().len() - filled) as u64;
+            self.read.fetch_add(n, Ordering::SeqCst);
+            result
+        }
+    }
+
+    impl<S: AsyncWrite + Unpin> AsyncWrite for Counting<S> {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+            if let Poll::Ready(Ok(n)) = result {
+                self.written.fetch_add(n as u64, Ordering::SeqCst);
+            }
+            result
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_flush(cx)
+        }
+
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_shutdown(cx)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slow_target_bounds_inflight_bytes() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (mut client, proxy_client_side) = pair(&listener).await;
+        let (proxy_target_side, mut target) = pair(&listener).await;
+
+        // 从客户端读出的字节数与写入目标的字节数之差即为在途数据
+        let read = Arc::new(AtomicU64::new(0));
+        let written = Arc::new(AtomicU64::new(0));
+        let proxy_client_side = Counting {
+            inner: proxy_client_side,
+            read: read.clone(),
+            written: Arc::new(AtomicU64::new(0)),
+        };
+        let proxy_target_side = Counting {
+            inner: proxy_target_side,
+            read: Arc::new(AtomicU64::new(0)),
+            written: written.clone(),
+        };
+        let max_inflight = 1000;
+        let relay_task = tokio::spawn(relay(
+            proxy_client_side,
+            proxy_target_side,
+            RelayOptions {
+                max_inflight: Some(max_inflight),
+                ..RelayOptions::default()
+            },
+        ));
+
+        // 客户端尽快写入，目标缓慢读取
+        let payload: Vec<u8> = (0..512 * 1024).map(|i| (i % 251) as u8).collect();
+        let expected = payload.clone();
+        let writer = tokio::spawn(async move {
+            client.write_all(&payload).await.unwrap();
+            client.shutdown().await.unwrap();
+            client
+        });
+
+        let mut received = Vec::new();
+        let mut chunk = [0u8; 4096];
+        let mut peak = 0;
+        loop {
+            let inflight = read.load(Ordering::SeqCst) - written.load(Ordering::SeqCst);
+            peak = peak.max(inflight);
+            let n = target.read(&mut chunk).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            received.extend_from_slice(&chunk[..n]);
+            tokio::time::sleep(Duration::from_micros(200)).await;
+        }
+        assert!(peak <= max_inflight as u64, "在途数据峰值: {}", peak);
+        assert_eq!(received, expected);
+
+        target.shutdown().await.unwrap();
+        drop(writer.await.unwrap());
+        assert_eq!(
+            relay_task.await.unwrap().unwrap(),
+            (expected.len() as u64, 0)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout_closes_silent_tunnel() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (mut client, proxy_client_side) = pair(&listener).await;
+        let (proxy_target_side, mut target) = pair(&listener).await;
+
+        let idle_timeout = Duration::from_millis(200);
+        let relay_task = tokio::spawn(relay(
+            proxy_client_side,
+            proxy_target_side,
+            RelayOptions {
+                idle_timeout: Some(idle_timeout),
+                ..RelayOptions::default()
+            },
+        ));
+
+        // 有数据流动时连接保持
+        client.write_all(b"hello").await.unwrap();
+        let mut received = [0u8; 5];
+        target.read_exact(&mut received).await.unwrap();
+
+        // 目标随后沉默，超时后两端都被关闭
+        let start = std::time::Instant::now();
+        let error = relay_task.await.unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(2));
+
+        let mut rest = Vec::new();
+        assert_eq!(client.read_to_end(&mut rest).await.unwrap(), 0);
+        assert_eq!(target.read_to_end(&mut rest).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout_sends_websocket_close() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (mut client, proxy_client_side) = pair(&listener).await;
+        let (proxy_target_side, mut target) = pair(&listener).await;
+
+        let relay_task = tokio::spawn(relay(
+            proxy_client_side,
+            proxy_target_side,
+            RelayOptions {
+                idle_timeout: Some(Duration::from_millis(100)),
+                websocket_close: true,
+                ..RelayOptions::default()
+            },
+        ));
+        let error = relay_task.await.unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+
+        // 发往客户端的关闭帧不加掩码
+        let mut frame = Vec::new();
+        client.read_to_end(&mut frame).await.unwrap();
+        assert_eq!(frame, [0x88, 0x02, 0x03, 0xe9]);
+
+        // 发往目标的关闭帧加掩码，解码后状态码同为1001
+        let mut frame = Vec::new();
+        target.read_to_end(&mut frame).await.unwrap();
+        assert_eq!(frame.len(), 8);
+        assert_eq!(frame[..2], [0x88, 0x82]);
+        let code = [frame[6] ^ frame[2], frame[7] ^ frame[3]];
+        assert_eq!(u16::from_be_bytes(code), 1001);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_sends_websocket_close() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (mut client, proxy_client_side) = pair(&listener).await;
+        let (proxy_target_side, mut target) = pair(&listener).await;
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let relay_task = tokio::spawn(relay_until(
+            proxy_client_side,
+            proxy_target_side,
+            RelayOptions {
+                websocket_close: true,
+                ..RelayOptions::default()
+            },
+            async {
+                let _ = shutdown_rx.await;
+            },
+        ));
+
+        // 关闭前照常转发
+        client.write_all(b"\x81\x02hi").await.unwrap();
+        let mut received = [0u8; 4];
+        target.read_exact(&mut received).await.unwrap();
+
+        shutdown_tx.send(()).unwrap();
+        let error = relay_task.await.unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Interrupted);
+
+        let mut frame = Vec::new();
+        client.read_to_end(&mut frame).await.unwrap();
+        assert_eq!(frame, [0x88, 0x02, 0x03, 0xe9]);
+        let mut frame = Vec::new();
+        target.read_to_end(&mut frame).await.unwrap();
+        assert_eq!(frame[..2], [0x88, 0x82]);
+    }
+
+    /// 写入先进入内部缓冲，直到 `ready_at` 之后刷新时才写到底层流（模拟TLS等带写缓冲的流）
+    struct SlowFlush<S> {
+        inner: S,
+        pending: Vec<u8>,
+        delay: Pin<Box<tokio::time::Sleep>>,
+    }
+
+    impl<S: AsyncRead + Unpin> AsyncRead for SlowFlush<S> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_read(cx, buf)
+        }
+    }
+
+    impl<S: AsyncWrite + Unpin> AsyncWrite for SlowFlush<S> {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.pending.extend_from_slice(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            if self.delay.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            while !self.pending.is_empty() {
+                let this = &mut *self;
+                let n = match Pin::new(&mut this.inner).poll_write(cx, &this.pending) {
+                    Poll::Ready(Ok(n)) => n,
+                    other => return other.map(|result| result.map(|_| ())),
+                };
+                self.pending.drain(..n);
+            }
+            Pin::new(&mut self.inner).poll_flush(cx)
+        }
+
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            match self.as_mut().poll_flush(cx) {
+                Poll::Ready(Ok(())) => Pin::new(&mut self.inner).poll_shutdown(cx),
+                other => other,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_teardown_flushes_buffered_data() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (mut client, proxy_client_side) = pair(&listener).await;
+        let (proxy_target_side, mut target) = pair(&listener).await;
+
+        // 刷新在空闲超时之后才能完成，转发被强制关闭时数据仍在写缓冲中
+        let proxy_target_side = SlowFlush {
+            inner: proxy_target_side,
+            pending: Vec::new(),
+            delay: Box::pin(tokio::time::sleep(Duration::from_millis(300))),
+        };
+        let relay_task = tokio::spawn(relay(
+            proxy_client_side,
+            proxy_target_side,
+            RelayOptions {
+                idle_timeout: Some(Duration::from_millis(100)),
+                ..RelayOptions::default()
+            },
+        ));
+
+        client.write_all(b"nearly complete").await.unwrap();
+
+        let error = relay_task.await.unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+
+        let mut received = Vec::new();
+        target.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"nearly complete");
+    }
+}
@@ -0,0 +1,148 @@
+use crate::access_rules::AccessRules;
+use crate::auth::{AuthConfig, DigestConfig};
+use crate::config::Config;
+use crate::proxy::Proxy;
+use std::error::Error;
+use std::sync::Arc;
+use tracing::{error, info};
+
+/// 按配置加载认证账号，用户文件与命令行账号可同时使用
+pub fn load_auth_config(
+    config: &Config,
+) -> Result<Option<AuthConfig>, Box<dyn Error + Send + Sync>> {
+    let mut auth_config = match &config.users_file {
+        Some(path) => Some(AuthConfig::from_users_file(path)?),
+        None => None,
+    };
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        match &mut auth_config {
+            Some(auth) => auth.add_user(username.clone(), password.clone()),
+            None => auth_config = Some(AuthConfig::new(username.clone(), password.clone())),
+        }
+    }
+    if config.digest_auth {
+        auth_config = auth_config.map(|auth| auth.with_digest(Some(DigestConfig::default())));
+    }
+    Ok(auth_config)
+}
+
+/// 按配置加载出站目标访问规则，未配置白名单和黑名单文件时为 `None`
+pub fn load_access_rules(
+    config: &Config,
+) -> Result<Option<Arc<AccessRules>>, Box<dyn Error + Send + Sync>> {
+    if config.allow_file.is_none() && config.block_file.is_none() {
+        return Ok(None);
+    }
+    let rules =
+        AccessRules::from_files(config.allow_file.as_deref(), config.block_file.as_deref())?;
+    Ok(Some(Arc::new(rules)))
+}
+
+/// 运行中重新加载认证账号与访问规则
+///
+/// 重新读取用户文件、白名单和黑名单文件后原子替换代理使用的配置；
+/// 任一文件加载失败时保留原配置。已建立的连接不受影响
+#[derive(Clone)]
+pub struct Reloader {
+    config: Config,
+    proxy: Proxy,
+}
+
+impl Reloader {
+    /// `proxy` 与提供服务的代理共享配置，通常传入其克隆
+    pub fn new(config: Config, proxy: Proxy) -> Self {
+        Self { config, proxy }
+    }
+
+    /// 重新加载一次
+    pub fn reload(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut auth_config = load_auth_config(&self.config)?;
+        let access_rules = load_access_rules(&self.config)?;
+
+        // 沿用已签发的Digest nonce，避免客户端因重新加载被要求重新认证
+        if let (Some(auth), Some(current)) = (&mut auth_config, self.proxy.auth_config().as_ref()) {
+            if let Some(digest) = current.digest() {
+                *auth = auth.clone().with_digest(Some(digest.clone()));
+            }
+        }
+
+        let users = auth_config.as_ref().map_or(0, AuthConfig::user_count);
+        let rules = access_rules
+            .as_ref()
+            .map(|rules| (rules.allow_count(), rules.block_count()));
+        self.proxy.set_auth_config(auth_config);
+        self.proxy.set_access_rules(access_rules);
+        match rules {
+            Some((allow, block)) => info!(
+                "🔄 配置已重新加载：{} 个认证账号，白名单 {} 条，黑名单 {} 条",
+                users, allow, block
+            ),
+            None => info!("🔄 配置已重新加载：{} 个认证账号", users),
+        }
+        Ok(())
+    }
+
+    /// 每次收到SIGHUP时重新加载，失败时记录错误并继续使用原配置
+    #[cfg(unix)]
+    pub async fn watch_sighup(self) {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(e) => {
+                error!("无法监听SIGHUP，配置重新加载不可用: {}", e);
+                return;
+            }
+        };
+        while hangup.recv().await.is_some() {
+            info!("收到SIGHUP，重新加载配置");
+            if let Err(e) = self.reload() {
+                error!("重新加载配置失败，继续使用原配置: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("rust_proxy_{}_reload_{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_failed_reload_keeps_current_config() {
+        let users = temp_file("users", "alice:secret\n");
+        let block = temp_file("block", "blocked.example\n");
+        let config = Config {
+            users_file: Some(users.clone()),
+            block_file: Some(block.clone()),
+            ..Config::default()
+        };
+        let proxy = Proxy::new(load_auth_config(&config).unwrap());
+        let reloader = Reloader::new(config, proxy.clone());
+
+        std::fs::write(&users, "alice:secret\nbob:hunter2\n").unwrap();
+        reloader.reload().unwrap();
+        let auth = proxy.auth_config();
+        assert!(auth
+            .as_ref()
+            .as_ref()
+            .unwrap()
+            .validate_credentials("bob", "hunter2"));
+
+        // 用户文件格式错误时保留上一次加载的账号
+        std::fs::write(&users, "not a user line\n").unwrap();
+        assert!(reloader.reload().is_err());
+        let auth = proxy.auth_config();
+        assert_eq!(auth.as_ref().as_ref().unwrap().user_count(), 2);
+
+        std::fs::remove_file(&users).unwrap();
+        std::fs::remove_file(&block).unwrap();
+    }
+}
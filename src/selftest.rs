@@ -0,0 +1,144 @@
+use crate::proxy::Proxy;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{oneshot, Semaphore};
+
+/// 自检默认传输的字节数
+pub const DEFAULT_SELF_TEST_BYTES: u64 = 64 * 1024 * 1024;
+
+/// 吞吐量自检结果
+#[derive(Debug, Clone)]
+pub struct SelfTestReport {
+    /// 经隧道往返传输的字节数
+    pub bytes: u64,
+    /// 从发起连接到隧道建立的耗时
+    pub connect_latency: Duration,
+    /// 数据全部回显完成的耗时
+    pub transfer_time: Duration,
+}
+
+impl SelfTestReport {
+    /// 单向吞吐量（字节/秒）
+    pub fn throughput(&self) -> f64 {
+        self.bytes as f64 / self.transfer_time.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "传输 {} 字节，耗时 {:?}，吞吐量 {:.2} MiB/s，隧道建立延迟 {:?}",
+            self.bytes,
+            self.transfer_time,
+            self.throughput() / (1024.0 * 1024.0),
+            self.connect_latency
+        )
+    }
+}
+
+/// 运行本地回环吞吐量自检
+///
+/// 在回环地址上启动回显服务器和代理，经 `CONNECT` 隧道发送 `bytes` 字节并读取回显，
+/// 不依赖外部网络
+pub async fn run(proxy: Proxy, bytes: u64) -> io::Result<SelfTestReport> {
+    let echo = TcpListener::bind("127.0.0.1:0").await?;
+    let echo_addr = echo.local_addr()?;
+    let echo_task = tokio::spawn(async move {
+        if let Ok((mut stream, _)) = echo.accept().await {
+            let (mut reader, mut writer) = stream.split();
+            let _ = tokio::io::copy(&mut reader, &mut writer).await;
+        }
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let proxy_addr = listener.local_addr()?;
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+    let proxy_task =
+        tokio::spawn(
+            proxy.serve_with_shutdown(listener, Arc::new(Semaphore::new(1)), async {
+                let _ = shutdown_rx.await;
+            }),
+        );
+
+    let result = transfer(proxy_addr.to_string(), echo_addr.to_string(), bytes).await;
+
+    let _ = shutdown_tx.send(());
+    let _ = proxy_task.await;
+    echo_task.abort();
+    result
+}
+
+async fn transfer(proxy_addr: String, target: String, bytes: u64) -> io::Result<SelfTestReport> {
+    let start = Instant::now();
+    let mut stream = TcpStream::connect(&proxy_addr).await?;
+    let request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n\r\n", target);
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        let byte = stream.read_u8().await?;
+        response.push(byte);
+    }
+    let status = String::from_utf8_lossy(&response);
+    if status.split_whitespace().nth(1) != Some("200") {
+        return Err(io::Error::other(format!(
+            "建立隧道失败: {}",
+            status.lines().next().unwrap_or_default()
+        )));
+    }
+    let connect_latency = start.elapsed();
+
+    let (mut reader, mut writer) = stream.into_split();
+    let start = Instant::now();
+    let upload = async move {
+        let chunk = vec![0x5a; 64 * 1024];
+        let mut remaining = bytes;
+        while remaining > 0 {
+            let n = remaining.min(chunk.len() as u64) as usize;
+            writer.write_all(&chunk[..n]).await?;
+            remaining -= n as u64;
+        }
+        writer.shutdown().await
+    };
+    let download = async move {
+        let mut buffer = vec![0u8; 64 * 1024];
+        let mut received = 0u64;
+        while received < bytes {
+            let n = reader.read(&mut buffer).await?;
+            if n == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("回显提前结束，仅收到 {} 字节", received),
+                ));
+            }
+            received += n as u64;
+        }
+        Ok(())
+    };
+    tokio::try_join!(upload, download)?;
+
+    Ok(SelfTestReport {
+        bytes,
+        connect_latency,
+        transfer_time: start.elapsed(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_self_test_reports_throughput() {
+        let report = run(Proxy::new(None), 1024 * 1024).await.unwrap();
+        assert_eq!(report.bytes, 1024 * 1024);
+        assert!(report.transfer_time > Duration::ZERO);
+        assert!(report.throughput() > 0.0);
+        assert!(report.to_string().contains("MiB/s"));
+    }
+}
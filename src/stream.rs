@@ -0,0 +1,198 @@
+use std::io::{self, IoSlice};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tokio_rustls::server::TlsStream;
+
+/// Unix域套接字客户端的合成地址
+///
+/// Unix套接字的对端没有网络地址，按本机回环地址参与访问控制和日志记录
+pub const UNIX_CLIENT_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+
+/// 客户端连接，TCP连接、Unix域套接字连接或在二者之上终结的TLS连接
+///
+/// 各协议处理器统一使用该类型读写客户端，不关心底层连接类型
+#[derive(Debug)]
+pub struct ClientStream {
+    inner: Inner,
+    /// Unix套接字和TLS连接不支持预读，`peek` 读出的数据暂存于此，后续读取先返回这些数据
+    peeked: Vec<u8>,
+}
+
+#[derive(Debug)]
+enum Inner {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+    Tls(Box<TlsStream<ClientStream>>),
+}
+
+impl From<TcpStream> for ClientStream {
+    fn from(stream: TcpStream) -> Self {
+        Self {
+            inner: Inner::Tcp(stream),
+            peeked: Vec::new(),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl From<UnixStream> for ClientStream {
+    fn from(stream: UnixStream) -> Self {
+        Self {
+            inner: Inner::Unix(stream),
+            peeked: Vec::new(),
+        }
+    }
+}
+
+impl From<TlsStream<ClientStream>> for ClientStream {
+    fn from(stream: TlsStream<ClientStream>) -> Self {
+        Self {
+            inner: Inner::Tls(Box::new(stream)),
+            peeked: Vec::new(),
+        }
+    }
+}
+
+impl ClientStream {
+    /// 预读数据而不消费，之后的读取仍从这些数据开始
+    pub async fn peek(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.peeked.is_empty() {
+            if let Inner::Tcp(stream) = &mut self.inner {
+                return stream.peek(buf).await;
+            }
+            let mut chunk = vec![0u8; buf.len()];
+            let n = self.read(&mut chunk).await?;
+            self.peeked.extend_from_slice(&chunk[..n]);
+        }
+        let n = buf.len().min(self.peeked.len());
+        buf[..n].copy_from_slice(&self.peeked[..n]);
+        Ok(n)
+    }
+
+    /// 以RST而不是正常的FIN关闭TCP连接，其他连接类型直接关闭
+    pub fn reset(self) {
+        if let Inner::Tcp(stream) = &self.inner {
+            let _ = stream.set_linger(Some(Duration::ZERO));
+        }
+    }
+
+    /// 代理本端的监听地址，Unix套接字连接返回 `None`
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match &self.inner {
+            Inner::Tcp(stream) => stream.local_addr().ok(),
+            #[cfg(unix)]
+            Inner::Unix(_) => None,
+            Inner::Tls(stream) => stream.get_ref().0.local_addr(),
+        }
+    }
+}
+
+impl AsyncRead for ClientStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if !self.peeked.is_empty() {
+            let n = buf.remaining().min(self.peeked.len());
+            buf.put_slice(&self.peeked[..n]);
+            self.peeked.drain(..n);
+            return Poll::Ready(Ok(()));
+        }
+        match &mut self.inner {
+            Inner::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            Inner::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+            Inner::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for ClientStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match &mut self.inner {
+            Inner::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            Inner::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+            Inner::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match &mut self.inner {
+            Inner::Tcp(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+            #[cfg(unix)]
+            Inner::Unix(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+            Inner::Tls(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match &self.inner {
+            Inner::Tcp(stream) => stream.is_write_vectored(),
+            #[cfg(unix)]
+            Inner::Unix(stream) => stream.is_write_vectored(),
+            Inner::Tls(stream) => stream.is_write_vectored(),
+        }
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.inner {
+            Inner::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            Inner::Unix(stream) => Pin::new(stream).poll_flush(cx),
+            Inner::Tls(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.inner {
+            Inner::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(unix)]
+            Inner::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+            Inner::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_unix_peek_then_read() {
+        let (client, server) = UnixStream::pair().unwrap();
+        let mut client = ClientStream::from(client);
+        let mut server = server;
+
+        server.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+        server.shutdown().await.unwrap();
+
+        let mut first = [0u8; 1];
+        assert_eq!(client.peek(&mut first).await.unwrap(), 1);
+        assert_eq!(&first, b"G");
+        assert_eq!(client.peek(&mut first).await.unwrap(), 1);
+
+        // 预读的数据不丢失也不重复
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"GET / HTTP/1.1\r\n\r\n");
+        assert!(client.local_addr().is_none());
+    }
+}
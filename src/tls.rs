@@ -0,0 +1,182 @@
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::version::{TLS12, TLS13};
+use rustls::{
+    ClientConfig, DigitallySignedStruct, RootCertStore, ServerConfig, SignatureScheme,
+    SupportedProtocolVersion,
+};
+use serde::Deserialize;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// 监听端接受的最低TLS版本，更早版本的客户端在握手时被拒绝
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum MinTlsVersion {
+    #[default]
+    #[serde(rename = "1.2")]
+    Tls12,
+    #[serde(rename = "1.3")]
+    Tls13,
+}
+
+impl MinTlsVersion {
+    /// 允许协商的协议版本
+    fn versions(self) -> &'static [&'static SupportedProtocolVersion] {
+        static TLS12_AND_LATER: [&SupportedProtocolVersion; 2] = [&TLS13, &TLS12];
+        static TLS13_ONLY: [&SupportedProtocolVersion; 1] = [&TLS13];
+        match self {
+            MinTlsVersion::Tls12 => &TLS12_AND_LATER,
+            MinTlsVersion::Tls13 => &TLS13_ONLY,
+        }
+    }
+}
+
+impl FromStr for MinTlsVersion {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "1.2" => Ok(MinTlsVersion::Tls12),
+            "1.3" => Ok(MinTlsVersion::Tls13),
+            _ => Err(format!("最低TLS版本应为 1.2 或 1.3: {}", value)),
+        }
+    }
+}
+
+impl fmt::Display for MinTlsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MinTlsVersion::Tls12 => write!(f, "1.2"),
+            MinTlsVersion::Tls13 => write!(f, "1.3"),
+        }
+    }
+}
+
+/// 构建连接HTTPS源站使用的TLS客户端配置
+///
+/// # 参数
+/// * `ca_file` - PEM格式的CA证书文件，未指定时使用内置的Mozilla根证书
+/// * `insecure` - 跳过证书校验，仅用于测试环境
+pub fn client_config(
+    ca_file: Option<&Path>,
+    insecure: bool,
+) -> Result<Arc<ClientConfig>, Box<dyn Error + Send + Sync>> {
+    let provider = Arc::new(ring::default_provider());
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+
+    let config = if insecure {
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(NoVerification(provider)))
+            .with_no_client_auth()
+    } else {
+        let mut roots = RootCertStore::empty();
+        match ca_file {
+            Some(path) => {
+                for cert in CertificateDer::pem_file_iter(path)
+                    .map_err(|e| format!("读取CA证书 {} 失败: {}", path.display(), e))?
+                {
+                    roots.add(cert?)?;
+                }
+            }
+            None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+        }
+        builder.with_root_certificates(roots).with_no_client_auth()
+    };
+
+    Ok(Arc::new(config))
+}
+
+/// 构建代理监听端使用的TLS服务端配置，客户端以 `https://` 代理地址接入
+///
+/// # 参数
+/// * `cert_file` - PEM格式的证书链文件
+/// * `key_file` - PEM格式的私钥文件
+/// * `alpn` - 按优先级排列的ALPN协议列表，握手时选择列表中第一个客户端也支持的协议；
+///   客户端提供了ALPN但其中没有任何列表内的协议时握手失败，未提供ALPN的客户端不受影响
+/// * `min_version` - 接受的最低TLS版本
+pub fn server_config(
+    cert_file: &Path,
+    key_file: &Path,
+    alpn: &[String],
+    min_version: MinTlsVersion,
+) -> Result<Arc<ServerConfig>, Box<dyn Error + Send + Sync>> {
+    for protocol in alpn {
+        if protocol.is_empty() || protocol.len() > 255 {
+            return Err(format!("无效的ALPN协议名: {:?}", protocol).into());
+        }
+    }
+    let certs = CertificateDer::pem_file_iter(cert_file)
+        .map_err(|e| format!("读取证书 {} 失败: {}", cert_file.display(), e))?
+        .collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err(format!("证书文件 {} 中没有证书", cert_file.display()).into());
+    }
+    let key = PrivateKeyDer::from_pem_file(key_file)
+        .map_err(|e| format!("读取私钥 {} 失败: {}", key_file.display(), e))?;
+
+    let mut config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_protocol_versions(min_version.versions())?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    config.alpn_protocols = alpn
+        .iter()
+        .map(|protocol| protocol.as_bytes().to_vec())
+        .collect();
+    Ok(Arc::new(config))
+}
+
+/// 不校验服务器证书的校验器，仍然校验握手签名
+#[derive(Debug)]
+struct NoVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
@@ -0,0 +1,96 @@
+use crate::metrics::metrics;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// 接受循环看门狗
+///
+/// 接受循环每次接受连接时记录时间，后台任务按固定间隔检查；
+/// 超过间隔仍未接受任何连接时记录告警并计入 `rust_proxy_accept_stalls_total`，
+/// 便于监控发现卡死的运行时。同一段停滞只告警一次
+#[derive(Debug, Clone)]
+pub struct AcceptWatchdog {
+    state: Arc<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    started: Instant,
+    /// 最近一次接受连接距 `started` 的毫秒数
+    last_accept_ms: AtomicU64,
+    stalls: AtomicU64,
+}
+
+impl AcceptWatchdog {
+    /// 启动后台检查任务，所有 `AcceptWatchdog` 实例被释放后任务自动结束
+    pub fn spawn(interval: Duration) -> Self {
+        let state = Arc::new(State {
+            started: Instant::now(),
+            last_accept_ms: AtomicU64::new(0),
+            stalls: AtomicU64::new(0),
+        });
+        let weak = Arc::downgrade(&state);
+
+        tokio::spawn(async move {
+            let mut reported = None;
+            loop {
+                tokio::time::sleep(interval).await;
+                let state = match weak.upgrade() {
+                    Some(state) => state,
+                    None => break,
+                };
+
+                let last_accept = state.last_accept_ms.load(Ordering::Relaxed);
+                let idle = state
+                    .started
+                    .elapsed()
+                    .saturating_sub(Duration::from_millis(last_accept));
+                if idle >= interval && reported != Some(last_accept) {
+                    warn!("⚠️ 接受循环已 {:?} 未接受任何连接", idle);
+                    state.stalls.fetch_add(1, Ordering::Relaxed);
+                    metrics().record_accept_stall();
+                    reported = Some(last_accept);
+                }
+            }
+        });
+
+        AcceptWatchdog { state }
+    }
+
+    /// 记录一次接受连接
+    pub fn record_accept(&self) {
+        let elapsed = self.state.started.elapsed().as_millis() as u64;
+        self.state.last_accept_ms.store(elapsed, Ordering::Relaxed);
+    }
+
+    /// 已告警的停滞次数
+    pub fn stalls(&self) -> u64 {
+        self.state.stalls.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_warns_when_no_accepts() {
+        let watchdog = AcceptWatchdog::spawn(Duration::from_millis(50));
+
+        // 持续接受连接时不告警
+        for _ in 0..6 {
+            watchdog.record_accept();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(watchdog.stalls(), 0);
+
+        // 停滞超过间隔后告警一次，停滞持续期间不重复告警
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(watchdog.stalls(), 1);
+
+        watchdog.record_accept();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(watchdog.stalls(), 2);
+    }
+}
@@ -0,0 +1,94 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+/// 本地模拟HTTP后端
+///
+/// 记录收到的原始请求，并对每个请求返回固定响应后关闭连接
+#[allow(dead_code)]
+pub struct MockBackend {
+    addr: SocketAddr,
+    requests: Arc<Mutex<Vec<Vec<u8>>>>,
+    _handle: JoinHandle<()>,
+}
+
+#[allow(dead_code)]
+impl MockBackend {
+    /// 启动模拟后端
+    pub async fn start(response: Vec<u8>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind mock backend");
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = requests.clone();
+        let handle = tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let recorded = recorded.clone();
+                let response = response.clone();
+                tokio::spawn(async move {
+                    let request = read_request(&mut stream).await;
+                    recorded.lock().unwrap().push(request);
+                    let _ = stream.write_all(&response).await;
+                    let _ = stream.shutdown().await;
+                });
+            }
+        });
+
+        MockBackend {
+            addr,
+            requests,
+            _handle: handle,
+        }
+    }
+
+    /// 获取监听地址
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// 获取端口号
+    pub fn port(&self) -> u16 {
+        self.addr.port()
+    }
+
+    /// 获取已收到的原始请求
+    pub fn requests(&self) -> Vec<Vec<u8>> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// 读取一个完整的HTTP请求（头部及Content-Length指定的body）
+pub async fn read_request<S: AsyncRead + Unpin>(stream: &mut S) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut buffer = [0u8; 4096];
+
+    let head_end = loop {
+        if let Some(pos) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        match stream.read(&mut buffer).await {
+            Ok(0) | Err(_) => return data,
+            Ok(n) => data.extend_from_slice(&buffer[..n]),
+        }
+    };
+
+    let head = String::from_utf8_lossy(&data[..head_end]).to_lowercase();
+    let content_length = head
+        .lines()
+        .find_map(|line| line.strip_prefix("content-length:"))
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+
+    while data.len() < head_end + content_length {
+        match stream.read(&mut buffer).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => data.extend_from_slice(&buffer[..n]),
+        }
+    }
+
+    data
+}
@@ -1,5 +1,7 @@
+pub mod backend;
 pub mod config;
 pub mod proxy;
 
+pub use backend as CBackend;
 pub use config as CConfig;
 pub use proxy as CProxy;
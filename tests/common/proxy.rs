@@ -29,40 +29,26 @@ impl TestProxy {
             None
         };
 
-        let proxy = Proxy::new(auth_config);
+        Self::start_with_proxy(config.clone(), Proxy::new(auth_config)).await
+    }
+
+    /// 使用自定义的代理实例启动测试代理服务器
+    pub async fn start_with_proxy(config: CConfig::TestProxyConfig, proxy: Proxy) -> Self {
         let addr = config.address();
         let listener = TcpListener::bind(&addr)
             .await
-            .expect(&format!("Failed to bind to {}", addr));
+            .unwrap_or_else(|_| panic!("Failed to bind to {}", addr));
 
         let semaphore = Arc::new(Semaphore::new(config.max_connections));
-        let (shutdown_tx, mut shutdown_rx) = tokio::sync::oneshot::channel();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
 
         let handle = tokio::spawn(async move {
-            loop {
-                tokio::select! {
-                    result = listener.accept() => {
-                        match result {
-                            Ok((stream, remote_addr)) => {
-                                let permit = match semaphore.clone().acquire_owned().await {
-                                    Ok(permit) => permit,
-                                    Err(_) => continue,
-                                };
-
-                                let proxy_clone = proxy.clone();
-                                tokio::spawn(async move {
-                                    proxy_clone.handle_connection(stream, remote_addr).await;
-                                    drop(permit);
-                                });
-                            }
-                            Err(_) => break,
-                        }
-                    }
-                    _ = &mut shutdown_rx => {
-                        break;
-                    }
-                }
-            }
+            let shutdown = async {
+                let _ = shutdown_rx.await;
+            };
+            let _ = proxy
+                .serve_with_shutdown(listener, semaphore, shutdown)
+                .await;
         });
 
         // 等待代理启动
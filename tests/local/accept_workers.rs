@@ -0,0 +1,48 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use rust_proxy::proxy::Proxy;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 测试多个接受任务共享监听器时，突发的并发连接全部被正确处理
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_accept_workers_handle_burst() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+    let addr = backend.addr();
+
+    let config = CConfig::TestProxyConfig::new(
+        "accept_workers".to_string(),
+        18129,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy =
+        CProxy::TestProxy::start_with_proxy(config, Proxy::new(None).with_accept_workers(4)).await;
+
+    const CONNECTIONS: usize = 64;
+    let clients: Vec<_> = (0..CONNECTIONS)
+        .map(|_| {
+            let proxy_addr = proxy.address();
+            tokio::spawn(async move {
+                let mut stream = TcpStream::connect(proxy_addr).await.unwrap();
+                stream
+                    .write_all(
+                        format!("GET http://{0}/ HTTP/1.1\r\nHost: {0}\r\n\r\n", addr).as_bytes(),
+                    )
+                    .await
+                    .unwrap();
+                let mut response = Vec::new();
+                stream.read_to_end(&mut response).await.unwrap();
+                String::from_utf8_lossy(&response).to_string()
+            })
+        })
+        .collect();
+
+    for client in clients {
+        let response = client.await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 204"), "响应: {}", response);
+    }
+    assert_eq!(backend.requests().len(), CONNECTIONS);
+
+    // 停止后所有接受任务退出，监听端口不再接受连接
+    proxy.stop().await;
+    assert!(TcpStream::connect(("127.0.0.1", 18129)).await.is_err());
+}
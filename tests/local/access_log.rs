@@ -0,0 +1,267 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use rust_proxy::access_log::AccessLog;
+use rust_proxy::handlers::backend::BackendConnector;
+use rust_proxy::proxy::Proxy;
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// 测试完成的请求按通用日志格式写入访问日志
+#[tokio::test]
+async fn test_access_log_line() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+    let addr = backend.addr();
+
+    let path = std::env::temp_dir().join(format!("rust_proxy_{}_access.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let access_log = AccessLog::open(&path).await.unwrap();
+
+    let config = CConfig::TestProxyConfig::new(
+        "access_log".to_string(),
+        18128,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy = CProxy::TestProxy::start_with_proxy(
+        config,
+        Proxy::new(None).with_access_log(Some(access_log.clone())),
+    )
+    .await;
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    stream
+        .write_all(
+            format!(
+                "GET http://{0}/ HTTP/1.1\r\nHost: {0}\r\nConnection: close\r\n\r\n",
+                addr
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    assert!(response.starts_with(b"HTTP/1.1 204"));
+    drop(stream);
+
+    let line = wait_for_line(&access_log, &path, &format!("\"GET {}\" 204", addr)).await;
+    std::fs::remove_file(&path).unwrap();
+    assert!(line.starts_with("127.0.0.1 - - ["), "日志行: {}", line);
+
+    proxy.stop().await;
+}
+
+/// 测试访问记录分别给出请求体和响应体的字节数
+#[tokio::test]
+async fn test_access_log_body_sizes() {
+    let backend =
+        CBackend::MockBackend::start(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello".to_vec())
+            .await;
+    let addr = backend.addr();
+
+    let path =
+        std::env::temp_dir().join(format!("rust_proxy_{}_access_body.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let access_log = AccessLog::open(&path).await.unwrap();
+
+    let config = CConfig::TestProxyConfig::new(
+        "access_log_body".to_string(),
+        18151,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy = CProxy::TestProxy::start_with_proxy(
+        config,
+        Proxy::new(None).with_access_log(Some(access_log.clone())),
+    )
+    .await;
+
+    let body = "x".repeat(1234);
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    stream
+        .write_all(
+            format!(
+                "POST http://{0}/upload HTTP/1.1\r\nHost: {0}\r\nContent-Length: {1}\r\n\r\n{2}",
+                addr,
+                body.len(),
+                body
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    assert!(response.ends_with(b"hello"));
+    drop(stream);
+
+    let line = wait_for_line(&access_log, &path, &format!("\"POST {}\" 200", addr)).await;
+    std::fs::remove_file(&path).unwrap();
+    assert!(
+        line.ends_with(" req_bytes=1234 resp_bytes=5"),
+        "日志行: {}",
+        line
+    );
+    assert_eq!(backend.requests().len(), 1);
+
+    proxy.stop().await;
+}
+
+/// 测试保持的连接上每个请求各写一条访问记录
+#[tokio::test]
+async fn test_access_log_entry_per_request() {
+    // 同一连接上依次应答两个请求，第一个返回200，第二个返回404
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        for response in [
+            &b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok"[..],
+            b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n",
+        ] {
+            CBackend::read_request(&mut stream).await;
+            stream.write_all(response).await.unwrap();
+        }
+    });
+
+    let path =
+        std::env::temp_dir().join(format!("rust_proxy_{}_access_keep.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let access_log = AccessLog::open(&path).await.unwrap();
+
+    let config = CConfig::TestProxyConfig::new(
+        "access_log_keep_alive".to_string(),
+        18169,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy = CProxy::TestProxy::start_with_proxy(
+        config,
+        Proxy::new(None).with_access_log(Some(access_log.clone())),
+    )
+    .await;
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    stream
+        .write_all(format!("GET http://{0}/a HTTP/1.1\r\nHost: {0}\r\n\r\n", addr).as_bytes())
+        .await
+        .unwrap();
+    let mut first = CBackend::read_request(&mut stream).await;
+    while !first.ends_with(b"ok") {
+        let mut chunk = [0u8; 64];
+        let n = stream.read(&mut chunk).await.unwrap();
+        assert!(n > 0, "响应: {:?}", String::from_utf8_lossy(&first));
+        first.extend_from_slice(&chunk[..n]);
+    }
+    stream
+        .write_all(
+            format!(
+                "HEAD http://{0}/b HTTP/1.1\r\nHost: {0}\r\nConnection: close\r\n\r\n",
+                addr
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+    let mut second = Vec::new();
+    stream.read_to_end(&mut second).await.unwrap();
+    assert!(second.starts_with(b"HTTP/1.1 404"));
+    drop(stream);
+
+    let second = wait_for_line(&access_log, &path, &format!("\"HEAD {}\" 404", addr)).await;
+    let first = wait_for_line(&access_log, &path, &format!("\"GET {}\" 200", addr)).await;
+    std::fs::remove_file(&path).unwrap();
+    assert!(
+        first.ends_with(" req_bytes=0 resp_bytes=2"),
+        "日志行: {}",
+        first
+    );
+    assert!(
+        second.ends_with(" req_bytes=0 resp_bytes=0"),
+        "日志行: {}",
+        second
+    );
+
+    proxy.stop().await;
+}
+
+/// 测试因空闲超时结束的CONNECT隧道仍记录已转发的字节数
+#[tokio::test]
+async fn test_access_log_bytes_after_idle_timeout() {
+    // 回显一次数据后保持连接，不再发送任何数据
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buffer = [0u8; 64];
+        let n = stream.read(&mut buffer).await.unwrap();
+        stream.write_all(&buffer[..n]).await.unwrap();
+        let _ = stream.read(&mut buffer).await;
+    });
+
+    let path =
+        std::env::temp_dir().join(format!("rust_proxy_{}_access_idle.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let access_log = AccessLog::open(&path).await.unwrap();
+
+    let config = CConfig::TestProxyConfig::new(
+        "access_log_idle".to_string(),
+        18168,
+        CConfig::ProxyProtocol::HttpsConnect,
+    );
+    let connector = BackendConnector::new().with_idle_timeout(Some(Duration::from_millis(200)));
+    let proxy = CProxy::TestProxy::start_with_proxy(
+        config,
+        Proxy::new(None)
+            .with_connector(connector)
+            .with_access_log(Some(access_log.clone())),
+    )
+    .await;
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    stream
+        .write_all(format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n\r\n", addr).as_bytes())
+        .await
+        .unwrap();
+    let response = CBackend::read_request(&mut stream).await;
+    assert!(response.starts_with(b"HTTP/1.1 200"), "{:?}", response);
+    stream.write_all(b"hello").await.unwrap();
+    let mut echoed = [0u8; 5];
+    stream.read_exact(&mut echoed).await.unwrap();
+    assert_eq!(&echoed, b"hello");
+
+    // 空闲超时后代理关闭隧道
+    let mut rest = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut rest))
+        .await
+        .expect("隧道未因空闲超时关闭")
+        .unwrap();
+
+    let line = wait_for_line(&access_log, &path, &format!("\"CONNECT {}\" 200", addr)).await;
+    std::fs::remove_file(&path).unwrap();
+    assert!(
+        line.contains(&format!("\"CONNECT {}\" 200 5 ", addr)),
+        "日志行: {}",
+        line
+    );
+
+    proxy.stop().await;
+}
+
+/// 等待访问日志中出现包含 `expected` 的记录并返回该行
+///
+/// 连接结束后才写入记录，轮询等待
+async fn wait_for_line(access_log: &AccessLog, path: &Path, expected: &str) -> String {
+    let mut contents = String::new();
+    for _ in 0..50 {
+        access_log.flush().await;
+        contents = std::fs::read_to_string(path).unwrap();
+        if contents.contains(expected) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    contents
+        .lines()
+        .find(|line| line.contains(expected))
+        .unwrap_or_else(|| panic!("访问日志: {}", contents))
+        .to_string()
+}
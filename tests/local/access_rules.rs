@@ -0,0 +1,112 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use rust_proxy::access_rules::AccessRules;
+use rust_proxy::handlers::backend::BackendConnector;
+use rust_proxy::proxy::Proxy;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 测试被黑名单禁止的目标返回403，且不会连接到目标
+#[tokio::test]
+async fn test_blocked_destination_returns_403() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+
+    let rules = AccessRules::new(Vec::new(), vec!["127.0.0.0/8".parse().unwrap()]);
+    let connector = BackendConnector::new().with_access_rules(Some(Arc::new(rules)));
+    let config = CConfig::TestProxyConfig::new(
+        "access_rules".to_string(),
+        18119,
+        CConfig::ProxyProtocol::HttpsConnect,
+    );
+    let proxy =
+        CProxy::TestProxy::start_with_proxy(config, Proxy::new(None).with_connector(connector))
+            .await;
+
+    for request in [
+        format!(
+            "CONNECT 127.0.0.1:{0} HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\n\r\n",
+            backend.port()
+        ),
+        format!(
+            "GET http://127.0.0.1:{0}/ HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\n\r\n",
+            backend.port()
+        ),
+    ] {
+        let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+        let response = String::from_utf8_lossy(&response);
+        assert!(response.starts_with("HTTP/1.1 403"), "响应: {}", response);
+    }
+    assert!(backend.requests().is_empty());
+
+    proxy.stop().await;
+}
+
+/// 测试启用内部地址拦截后，IP形式和解析到回环地址的域名目标都返回403
+#[tokio::test]
+async fn test_block_private_destination_returns_403() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+
+    let connector = BackendConnector::new().with_block_private(true);
+    let config = CConfig::TestProxyConfig::new(
+        "block_private".to_string(),
+        18137,
+        CConfig::ProxyProtocol::HttpsConnect,
+    );
+    let proxy =
+        CProxy::TestProxy::start_with_proxy(config, Proxy::new(None).with_connector(connector))
+            .await;
+
+    for host in ["127.0.0.1", "localhost"] {
+        let request = format!(
+            "CONNECT {0}:{1} HTTP/1.1\r\nHost: {0}:{1}\r\n\r\n",
+            host,
+            backend.port()
+        );
+        let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+        let response = String::from_utf8_lossy(&response);
+        assert!(response.starts_with("HTTP/1.1 403"), "响应: {}", response);
+    }
+    assert!(backend.requests().is_empty());
+
+    proxy.stop().await;
+}
+
+/// 测试CONNECT到未允许的端口返回403，且不会连接到目标
+#[tokio::test]
+async fn test_disallowed_connect_port_returns_403() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+
+    let config = CConfig::TestProxyConfig::new(
+        "connect_ports".to_string(),
+        18138,
+        CConfig::ProxyProtocol::HttpsConnect,
+    );
+    let proxy = CProxy::TestProxy::start_with_proxy(
+        config,
+        Proxy::new(None).with_allowed_connect_ports(Some(vec![443])),
+    )
+    .await;
+
+    let request = format!(
+        "CONNECT 127.0.0.1:{0} HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\n\r\n",
+        backend.port()
+    );
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    let response = String::from_utf8_lossy(&response);
+    assert!(response.starts_with("HTTP/1.1 403"), "响应: {}", response);
+    assert!(backend.requests().is_empty());
+
+    proxy.stop().await;
+}
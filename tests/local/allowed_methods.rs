@@ -0,0 +1,63 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use rust_proxy::proxy::Proxy;
+use rust_proxy::rejection::RejectionReason;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+async fn send(proxy: &CProxy::TestProxy, method: &str, backend_port: u16) -> String {
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let request = format!(
+        "{1} http://127.0.0.1:{0}/item HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\n\r\n",
+        backend_port, method
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    String::from_utf8(response).unwrap()
+}
+
+/// 测试只允许 `GET`、`POST` 时 `DELETE` 以405拒绝且不会到达源站
+#[tokio::test]
+async fn test_disallowed_method_returns_405() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+
+    let config = CConfig::TestProxyConfig::new(
+        "allowed_methods".to_string(),
+        18145,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let methods = HashSet::from(["GET".to_string(), "POST".to_string()]);
+    let rejections = Arc::new(Mutex::new(Vec::new()));
+    let recorded = rejections.clone();
+    let proxy = Proxy::new(None)
+        .with_allowed_methods(Some(methods))
+        .with_rejection_callback(Arc::new(move |_, reason| {
+            recorded.lock().unwrap().push(reason.clone());
+        }));
+    let proxy = CProxy::TestProxy::start_with_proxy(config, proxy).await;
+
+    let response = send(&proxy, "DELETE", backend.port()).await;
+    assert!(
+        response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"),
+        "响应: {}",
+        response
+    );
+    assert!(
+        response.contains("\r\nAllow: GET, POST\r\n"),
+        "响应: {}",
+        response
+    );
+    assert!(backend.requests().is_empty());
+    assert_eq!(
+        *rejections.lock().unwrap(),
+        vec![RejectionReason::DisallowedMethod("DELETE".to_string())]
+    );
+
+    let response = send(&proxy, "GET", backend.port()).await;
+    assert!(response.starts_with("HTTP/1.1 204"), "响应: {}", response);
+    assert_eq!(backend.requests().len(), 1);
+
+    proxy.stop().await;
+}
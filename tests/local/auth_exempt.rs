@@ -0,0 +1,89 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use rust_proxy::auth::AuthConfig;
+use rust_proxy::proxy::Proxy;
+use rust_proxy::stream::ClientStream;
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+fn authenticated_proxy() -> Proxy {
+    Proxy::new(Some(AuthConfig::new(
+        "testuser".to_string(),
+        "testpass".to_string(),
+    )))
+    .with_no_auth_for_localhost(true)
+    .with_auth_exempt(vec!["10.0.0.0/8".parse().unwrap()])
+}
+
+fn request(backend_port: u16) -> String {
+    format!(
+        "GET http://127.0.0.1:{0}/ HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\n\r\n",
+        backend_port
+    )
+}
+
+/// 以伪造的客户端地址直接交给代理处理一个未认证的请求，返回响应
+async fn send_as(proxy: &Proxy, client_addr: SocketAddr, backend_port: u16) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap())
+        .await
+        .unwrap();
+    let (accepted, _) = listener.accept().await.unwrap();
+
+    client
+        .write_all(request(backend_port).as_bytes())
+        .await
+        .unwrap();
+    let handle = proxy.handle_connection(ClientStream::from(accepted), client_addr);
+    // 读完响应后关闭客户端，代理才会结束这个连接
+    let read = async move {
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).await.unwrap();
+        response
+    };
+    let (_, response) = tokio::join!(handle, read);
+    String::from_utf8(response).unwrap()
+}
+
+/// 测试 `--no-auth-for-localhost` 时环回地址的客户端无需认证
+#[tokio::test]
+async fn test_loopback_client_bypasses_auth() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+    let config = CConfig::TestProxyConfig::new(
+        "auth_exempt".to_string(),
+        18150,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy = CProxy::TestProxy::start_with_proxy(config, authenticated_proxy()).await;
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    stream
+        .write_all(request(backend.port()).as_bytes())
+        .await
+        .unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    let response = String::from_utf8_lossy(&response);
+    assert!(response.starts_with("HTTP/1.1 204"), "响应: {}", response);
+
+    proxy.stop().await;
+}
+
+/// 测试免认证只作用于环回地址和配置的网段，其余客户端仍返回407
+#[tokio::test]
+async fn test_external_client_still_requires_auth() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+    let proxy = authenticated_proxy();
+
+    let response = send_as(&proxy, "203.0.113.9:5000".parse().unwrap(), backend.port()).await;
+    assert!(
+        response.starts_with("HTTP/1.1 407 Proxy Authentication Required"),
+        "响应: {}",
+        response
+    );
+    assert!(backend.requests().is_empty());
+
+    let response = send_as(&proxy, "10.1.2.3:5000".parse().unwrap(), backend.port()).await;
+    assert!(response.starts_with("HTTP/1.1 204"), "响应: {}", response);
+    assert_eq!(backend.requests().len(), 1);
+}
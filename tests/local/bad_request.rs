@@ -0,0 +1,58 @@
+use crate::common::{CConfig, CProxy};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 发送 `data` 后读取代理的完整响应
+async fn send(proxy: &CProxy::TestProxy, data: &[u8]) -> String {
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    stream.write_all(data).await.unwrap();
+    let mut response = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut response))
+        .await
+        .expect("等待响应超时")
+        .unwrap();
+    String::from_utf8_lossy(&response).to_string()
+}
+
+/// 测试无法解析的请求收到说明期望格式的400，而不是直接断开
+#[tokio::test]
+async fn test_garbage_gets_bad_request() {
+    let config = CConfig::TestProxyConfig::new(
+        "bad_request".to_string(),
+        18156,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy = CProxy::TestProxy::start(config).await;
+
+    // 没有请求头部结束符的二进制数据（如发往明文端口的TLS握手）同样立即得到响应
+    let response = send(&proxy, b"\x16\x03\x01\x00\xa5\x01\x00\x00\xa1garbage").await;
+    assert!(
+        response.starts_with("HTTP/1.1 400 Bad Request\r\n"),
+        "{}",
+        response
+    );
+    assert!(response.contains("SOCKS"), "{}", response);
+
+    let response = send(&proxy, b"CONNECT example.com HTTP/1.1\r\n\r\n").await;
+    assert!(
+        response.starts_with("HTTP/1.1 400 Bad Request\r\n"),
+        "{}",
+        response
+    );
+    assert!(response.contains("host:port"), "{}", response);
+
+    let response = send(
+        &proxy,
+        b"GET /chat HTTP/1.1\r\nHost: example.com\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\r\n",
+    )
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 400 Bad Request\r\n"),
+        "{}",
+        response
+    );
+    assert!(response.contains("Sec-WebSocket-Key"), "{}", response);
+
+    proxy.stop().await;
+}
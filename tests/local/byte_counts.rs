@@ -0,0 +1,92 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 收集日志输出的写入器
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Captured {
+    /// 已记录的 `转发结束` 日志
+    fn relay_finished(&self) -> Option<serde_json::Value> {
+        let output = String::from_utf8(self.0.lock().unwrap().clone()).unwrap();
+        output
+            .lines()
+            .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+            .find(|line| line["fields"]["message"] == "转发结束")
+    }
+}
+
+/// 测试连接结束时按目标记录双向字节数，下行字节数与固定大小的响应一致
+#[tokio::test]
+async fn test_relay_logs_downstream_bytes() {
+    let captured = Captured::default();
+    let writer = captured.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .json()
+        .with_writer(move || writer.clone())
+        .finish();
+    // 测试运行在单线程运行时上，代理任务的日志都进入该订阅者
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let response = b"HTTP/1.1 200 OK\r\nContent-Length: 1000\r\n\r\n"
+        .iter()
+        .copied()
+        .chain(std::iter::repeat_n(b'x', 1000))
+        .collect::<Vec<u8>>();
+    let backend = CBackend::MockBackend::start(response.clone()).await;
+
+    let config = CConfig::TestProxyConfig::new(
+        "byte_counts".to_string(),
+        18143,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy = CProxy::TestProxy::start(config).await;
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let request = format!(
+        "GET http://127.0.0.1:{0}/ HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\n\r\n",
+        backend.port()
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut received = Vec::new();
+    stream.read_to_end(&mut received).await.unwrap();
+    assert_eq!(received, response);
+    drop(stream);
+
+    let line = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(line) = captured.relay_finished() {
+                break line;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("等待转发结束日志超时");
+    assert_eq!(line["fields"]["bytes_out"], response.len() as u64);
+    // 请求以源站形式转发，并追加本代理的转发记录
+    let forwarded = format!(
+        "GET / HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nX-Forwarded-For: 127.0.0.1\r\nVia: 1.1 rust-proxy\r\n\r\n",
+        backend.port()
+    );
+    assert_eq!(line["fields"]["bytes_in"], forwarded.len() as u64);
+    assert_eq!(line["span"]["target_host"], "127.0.0.1");
+    assert_eq!(line["span"]["target_port"], backend.port());
+
+    proxy.stop().await;
+}
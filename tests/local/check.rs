@@ -0,0 +1,51 @@
+use rust_proxy::check;
+use rust_proxy::config::Config;
+use tokio::net::TcpListener;
+
+/// 测试 `--check` 遇到格式错误的用户文件时失败，不提供服务
+#[tokio::test]
+async fn test_check_rejects_bad_users_file() {
+    let path = std::env::temp_dir().join(format!("rust_proxy_{}_check_users", std::process::id()));
+    std::fs::write(&path, "alice:wonderland\nthis line has no colon\n").unwrap();
+    let config = Config {
+        users_file: Some(path.clone()),
+        listen: vec!["127.0.0.1:18158".parse().unwrap()],
+        ..Config::default()
+    };
+
+    let result = check::run(&config).await;
+    std::fs::remove_file(&path).unwrap();
+    let error = result.unwrap_err().to_string();
+    assert!(error.contains("第 2 行"), "{}", error);
+}
+
+/// 测试配置正确时检查通过，监听地址在检查后已释放
+#[tokio::test]
+async fn test_check_releases_listen_addresses() {
+    let path = std::env::temp_dir().join(format!(
+        "rust_proxy_{}_check_good_users",
+        std::process::id()
+    ));
+    std::fs::write(&path, "alice:wonderland\nbob:builder\n").unwrap();
+    let config = Config {
+        users_file: Some(path.clone()),
+        listen: vec!["127.0.0.1:18159".parse().unwrap()],
+        ..Config::default()
+    };
+
+    let result = check::run(&config).await;
+    std::fs::remove_file(&path).unwrap();
+    let report = result.unwrap().to_string();
+    assert!(report.contains("认证账号: 2 个"), "{}", report);
+    assert!(report.contains("监听: 127.0.0.1:18159"), "{}", report);
+    TcpListener::bind("127.0.0.1:18159").await.unwrap();
+
+    // 已被占用的监听地址使检查失败
+    let _occupied = TcpListener::bind("127.0.0.1:18159").await.unwrap();
+    let config = Config {
+        listen: vec!["127.0.0.1:18159".parse().unwrap()],
+        ..Config::default()
+    };
+    let error = check::run(&config).await.unwrap_err().to_string();
+    assert!(error.contains("127.0.0.1:18159"), "{}", error);
+}
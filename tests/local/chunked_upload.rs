@@ -0,0 +1,111 @@
+use crate::common::{CConfig, CProxy};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// 上传数据的大小
+const BODY_SIZE: usize = 3 * 1024 * 1024 + 17;
+
+/// 测试数MB的分块编码请求体跨多次读取到达时完整转发，源站解码后的字节与原始数据一致
+#[tokio::test]
+async fn test_large_chunked_post_intact() {
+    // 回显源站：解码分块请求体，以Content-Length响应原样返回
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let origin_port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut stream = BufReader::new(stream);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            stream.read_line(&mut line).await.unwrap();
+            if line == "\r\n" {
+                break;
+            }
+        }
+
+        let mut body = Vec::new();
+        loop {
+            line.clear();
+            stream.read_line(&mut line).await.unwrap();
+            let size = usize::from_str_radix(line.trim(), 16).unwrap();
+            let mut chunk = vec![0u8; size + 2];
+            stream.read_exact(&mut chunk).await.unwrap();
+            if size == 0 {
+                break;
+            }
+            body.extend_from_slice(&chunk[..size]);
+        }
+
+        let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len());
+        stream.write_all(head.as_bytes()).await.unwrap();
+        stream.write_all(&body).await.unwrap();
+    });
+
+    let config = CConfig::TestProxyConfig::new(
+        "chunked_upload".to_string(),
+        18149,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy = CProxy::TestProxy::start(config).await;
+
+    let body: Vec<u8> = (0..BODY_SIZE).map(|i| (i * 7 % 251) as u8).collect();
+    let stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let (mut reader, mut writer) = stream.into_split();
+
+    let upload = {
+        let body = body.clone();
+        tokio::spawn(async move {
+            let head = format!(
+                "POST http://127.0.0.1:{0}/upload HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\nTransfer-Encoding: chunked\r\n\r\n",
+                origin_port
+            );
+            writer.write_all(head.as_bytes()).await.unwrap();
+            // 块大小不规则，块的边界与每次写入的边界错开
+            let mut offset = 0;
+            let mut size = 1;
+            while offset < body.len() {
+                let end = (offset + size).min(body.len());
+                let chunk = format!("{:x}\r\n", end - offset);
+                let mut frame = chunk.into_bytes();
+                frame.extend_from_slice(&body[offset..end]);
+                frame.extend_from_slice(b"\r\n");
+                let (first, second) = frame.split_at(frame.len() / 3);
+                writer.write_all(first).await.unwrap();
+                writer.write_all(second).await.unwrap();
+                offset = end;
+                size = size * 3 % 65521 + 1;
+            }
+            writer.write_all(b"0\r\n\r\n").await.unwrap();
+            writer
+        })
+    };
+
+    let mut response = Vec::new();
+    let mut chunk = vec![0u8; 64 * 1024];
+    let expected_len =
+        format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", BODY_SIZE).len() + BODY_SIZE;
+    while response.len() < expected_len {
+        let n = tokio::time::timeout(Duration::from_secs(10), reader.read(&mut chunk))
+            .await
+            .expect("等待响应超时")
+            .unwrap();
+        assert!(
+            n > 0,
+            "连接在响应完成前关闭，已收到 {} 字节",
+            response.len()
+        );
+        response.extend_from_slice(&chunk[..n]);
+    }
+    let _writer = upload.await.unwrap();
+
+    let head_end = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    assert!(response.starts_with(b"HTTP/1.1 200 OK\r\n"));
+    assert_eq!(response.len(), expected_len);
+    assert!(
+        response[head_end..] == body[..],
+        "回显的请求体与原始数据不一致"
+    );
+
+    proxy.stop().await;
+}
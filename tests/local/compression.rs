@@ -0,0 +1,53 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 测试压缩协商相关头部及gzip响应体经代理转发后保持不变
+#[tokio::test]
+async fn test_gzip_negotiation_round_trip() {
+    // gzip压缩的 "hello"
+    let gzip_body: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0xcb, 0x48, 0xcd, 0xc9, 0xc9,
+        0x07, 0x00, 0x86, 0xa6, 0x10, 0x36, 0x05, 0x00, 0x00, 0x00,
+    ];
+    let mut response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Encoding: gzip\r\nVary: Accept-Encoding\r\nContent-Length: {}\r\n\r\n",
+        gzip_body.len()
+    )
+    .into_bytes();
+    response.extend_from_slice(gzip_body);
+
+    let backend = CBackend::MockBackend::start(response.clone()).await;
+
+    let config = CConfig::TestProxyConfig::new(
+        "compression".to_string(),
+        18103,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy = CProxy::TestProxy::start(config).await;
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let request = format!(
+        "GET http://127.0.0.1:{0}/gzip HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\nAccept-Encoding: gzip, deflate;q=0.5\r\nConnection: close\r\n\r\n",
+        backend.port()
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut received = Vec::new();
+    stream.read_to_end(&mut received).await.unwrap();
+
+    // 响应（包括Content-Encoding和压缩体）逐字节一致
+    assert_eq!(received, response);
+
+    // 后端收到的Accept-Encoding未被改写
+    let requests = backend.requests();
+    assert_eq!(requests.len(), 1);
+    let forwarded = String::from_utf8_lossy(&requests[0]);
+    assert!(
+        forwarded.contains("\r\nAccept-Encoding: gzip, deflate;q=0.5\r\n"),
+        "后端收到: {}",
+        forwarded
+    );
+
+    proxy.stop().await;
+}
@@ -0,0 +1,204 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use rust_proxy::handlers::backend::BackendConnector;
+use rust_proxy::proxy::Proxy;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// 测试启用快速探测时，CONNECT到不应答SYN的目标在探测期限后即返回504，不等待完整连接超时
+#[tokio::test]
+async fn test_connect_quick_check_fails_fast() {
+    let config = CConfig::TestProxyConfig::new(
+        "connect_quick_check".to_string(),
+        18101,
+        CConfig::ProxyProtocol::HttpsConnect,
+    );
+
+    let connect_timeout = Duration::from_secs(5);
+    let connector = BackendConnector::new()
+        .with_connect_timeout(connect_timeout)
+        .with_quick_check(Some(Duration::from_millis(200)));
+    let proxy =
+        CProxy::TestProxy::start_with_proxy(config, Proxy::new(None).with_connector(connector))
+            .await;
+
+    // 监听队列为0且已被占满的监听器丢弃新的SYN，连接会一直挂起到超时
+    let socket = tokio::net::TcpSocket::new_v4().unwrap();
+    socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let listener = socket.listen(0).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let _filler = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let request = format!(
+        "CONNECT 127.0.0.1:{0} HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\n\r\n",
+        port
+    );
+
+    let start = Instant::now();
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    let elapsed = start.elapsed();
+
+    let response = String::from_utf8_lossy(&response);
+    assert!(response.contains(" 504 "), "响应: {}", response);
+    assert!(elapsed < Duration::from_secs(2), "耗时 {:?}", elapsed);
+
+    proxy.stop().await;
+}
+
+/// 测试紧随CONNECT请求发送的ClientHello被转发到目标
+#[tokio::test]
+async fn test_connect_forwards_early_client_hello() {
+    let config = CConfig::TestProxyConfig::new(
+        "connect_early_data".to_string(),
+        18114,
+        CConfig::ProxyProtocol::HttpsConnect,
+    );
+    let proxy = CProxy::TestProxy::start(config).await;
+
+    // 模拟的TLS ClientHello记录头部及部分内容
+    let client_hello: &[u8] = &[
+        0x16, 0x03, 0x01, 0x00, 0x08, 0x01, 0x00, 0x00, 0x04, 0x03, 0x03, 0xab, 0xcd,
+    ];
+
+    let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let target_port = target.local_addr().unwrap().port();
+    let received = tokio::spawn(async move {
+        let (mut stream, _) = target.accept().await.unwrap();
+        let mut data = vec![0u8; client_hello.len()];
+        stream.read_exact(&mut data).await.unwrap();
+        data
+    });
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let mut request = format!(
+        "CONNECT 127.0.0.1:{0} HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\n\r\n",
+        target_port
+    )
+    .into_bytes();
+    request.extend_from_slice(client_hello);
+    stream.write_all(&request).await.unwrap();
+
+    let data = tokio::time::timeout(Duration::from_secs(5), received)
+        .await
+        .expect("目标未收到提前发送的数据")
+        .unwrap();
+    assert_eq!(data, client_hello);
+
+    let mut established = [0u8; 39];
+    stream.read_exact(&mut established).await.unwrap();
+    assert_eq!(&established, b"HTTP/1.1 200 Connection Established\r\n\r\n");
+
+    proxy.stop().await;
+}
+
+/// 测试目标拒绝连接时请求转到备用目标
+#[tokio::test]
+async fn test_fallback_destination_on_refused_connect() {
+    let fallback = CBackend::MockBackend::start(
+        b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 11\r\n\r\nmaintenance".to_vec(),
+    )
+    .await;
+
+    let config = CConfig::TestProxyConfig::new(
+        "connect_fallback".to_string(),
+        18117,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let connector =
+        BackendConnector::new().with_fallback(Some(("127.0.0.1".to_string(), fallback.port())));
+    let proxy =
+        CProxy::TestProxy::start_with_proxy(config, Proxy::new(None).with_connector(connector))
+            .await;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let refused_port = listener.local_addr().unwrap().port();
+    drop(listener);
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let request = format!(
+        "GET http://127.0.0.1:{0}/ HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\n\r\n",
+        refused_port
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    let response = String::from_utf8_lossy(&response);
+    assert!(response.ends_with("maintenance"), "响应: {}", response);
+    assert_eq!(fallback.requests().len(), 1);
+
+    proxy.stop().await;
+}
+
+/// 测试被 `--block-private` 禁止的目标直接返回403，不改连备用目标
+#[tokio::test]
+async fn test_blocked_destination_skips_fallback() {
+    let fallback = CBackend::MockBackend::start(
+        b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 11\r\n\r\nmaintenance".to_vec(),
+    )
+    .await;
+
+    let config = CConfig::TestProxyConfig::new(
+        "connect_fallback_blocked".to_string(),
+        18182,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let connector = BackendConnector::new()
+        .with_block_private(true)
+        .with_fallback(Some(("127.0.0.1".to_string(), fallback.port())));
+    let proxy =
+        CProxy::TestProxy::start_with_proxy(config, Proxy::new(None).with_connector(connector))
+            .await;
+
+    // 域名解析到回环地址，在建立连接时才被判定为内部地址
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    stream
+        .write_all(b"GET http://localhost:9/ HTTP/1.1\r\nHost: localhost:9\r\n\r\n")
+        .await
+        .unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    let response = String::from_utf8_lossy(&response);
+    assert!(response.starts_with("HTTP/1.1 403 "), "响应: {}", response);
+    assert!(fallback.requests().is_empty());
+
+    proxy.stop().await;
+}
+
+/// 测试CONNECT成功响应的HTTP版本与请求一致
+#[tokio::test]
+async fn test_connect_response_matches_request_version() {
+    let config = CConfig::TestProxyConfig::new(
+        "connect_version".to_string(),
+        18139,
+        CConfig::ProxyProtocol::HttpsConnect,
+    );
+    let proxy = CProxy::TestProxy::start(config).await;
+
+    let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let target_port = target.local_addr().unwrap().port();
+
+    for version in ["HTTP/1.0", "HTTP/1.1"] {
+        let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+        let request = format!(
+            "CONNECT 127.0.0.1:{0} {1}\r\nHost: 127.0.0.1:{0}\r\n\r\n",
+            target_port, version
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let (_accepted, _) = target.accept().await.unwrap();
+
+        let mut established = [0u8; 39];
+        stream.read_exact(&mut established).await.unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&established),
+            format!("{} 200 Connection Established\r\n\r\n", version)
+        );
+    }
+
+    proxy.stop().await;
+}
@@ -0,0 +1,233 @@
+use crate::common::{CConfig, CProxy};
+use rust_proxy::handlers::backend::{BackendConnector, ConnectionPool};
+use rust_proxy::proxy::Proxy;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// 启动一个keep-alive源站，返回其端口与已接受的连接数
+async fn start_origin() -> (u16, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let counter = accepted.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            counter.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                loop {
+                    let mut line = String::new();
+                    if stream.read_line(&mut line).await.unwrap_or(0) == 0 {
+                        return;
+                    }
+                    while line != "\r\n" {
+                        line.clear();
+                        if stream.read_line(&mut line).await.unwrap_or(0) == 0 {
+                            return;
+                        }
+                    }
+                    let response = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+                    if stream.write_all(response).await.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+    (port, accepted)
+}
+
+/// 通过代理发送一个GET请求，读完响应后关闭客户端连接
+async fn get(proxy: &CProxy::TestProxy, port: u16) {
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let request = format!(
+        "GET http://127.0.0.1:{0}/ HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\n\r\n",
+        port
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !response.ends_with(b"\r\n\r\nok") {
+        let n = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut chunk))
+            .await
+            .expect("等待响应超时")
+            .unwrap();
+        assert!(n > 0, "连接在响应完成前关闭: {:?}", response);
+        response.extend_from_slice(&chunk[..n]);
+    }
+    assert!(response.starts_with(b"HTTP/1.1 200 OK\r\n"));
+}
+
+/// 测试先后两个客户端连接发往同一源站的请求共用一个池中的源站连接
+#[tokio::test]
+async fn test_second_request_reuses_pooled_connection() {
+    let (port, accepted) = start_origin().await;
+    let pool = ConnectionPool::new(Duration::from_secs(30));
+    let config = CConfig::TestProxyConfig::new(
+        "connection_pool".to_string(),
+        18154,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy = CProxy::TestProxy::start_with_proxy(
+        config,
+        Proxy::new(None).with_connector(BackendConnector::new().with_pool(Some(pool.clone()))),
+    )
+    .await;
+
+    get(&proxy, port).await;
+    // 客户端关闭后源站连接才放回池中
+    for _ in 0..50 {
+        if pool.idle_connections() == 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(pool.idle_connections(), 1);
+    assert_eq!(pool.reused(), 0);
+
+    get(&proxy, port).await;
+    assert_eq!(pool.reused(), 1);
+    assert_eq!(accepted.load(Ordering::SeqCst), 1);
+
+    proxy.stop().await;
+}
+
+/// 测试目标不可达时改连备用目标得到的连接不以原目标为键放回连接池
+#[tokio::test]
+async fn test_fallback_connection_is_not_pooled() {
+    let (fallback_port, accepted) = start_origin().await;
+    let pool = ConnectionPool::new(Duration::from_secs(30));
+    let config = CConfig::TestProxyConfig::new(
+        "connection_pool_fallback".to_string(),
+        18183,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let connector = BackendConnector::new()
+        .with_pool(Some(pool.clone()))
+        .with_fallback(Some(("127.0.0.1".to_string(), fallback_port)));
+    let proxy =
+        CProxy::TestProxy::start_with_proxy(config, Proxy::new(None).with_connector(connector))
+            .await;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    drop(listener);
+
+    get(&proxy, port).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(pool.idle_connections(), 0);
+
+    // 目标恢复后请求到达目标本身，而不是池中到备用目标的连接
+    let target = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    let served = tokio::spawn(async move {
+        let (mut stream, _) = target.accept().await.unwrap();
+        let mut head = Vec::new();
+        let mut chunk = [0u8; 1024];
+        while !head.ends_with(b"\r\n\r\n") {
+            let n = stream.read(&mut chunk).await.unwrap();
+            head.extend_from_slice(&chunk[..n]);
+        }
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+            .await
+            .unwrap();
+    });
+    get(&proxy, port).await;
+    served.await.unwrap();
+    assert_eq!(pool.reused(), 0);
+    assert_eq!(accepted.load(Ordering::SeqCst), 1);
+
+    proxy.stop().await;
+}
+
+/// 启动一个源站：每个连接只响应第一个请求，收到下一个请求时不作响应直接关闭，
+/// 模拟池中连接在取出后才被源站关闭。返回其端口与已接受的连接数
+async fn start_stale_origin() -> (u16, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let counter = accepted.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            counter.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                for served in [true, false] {
+                    let mut line = String::new();
+                    while line != "\r\n" {
+                        line.clear();
+                        if stream.read_line(&mut line).await.unwrap_or(0) == 0 {
+                            return;
+                        }
+                    }
+                    if served {
+                        let response = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+                        if stream.write_all(response).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+    });
+    (port, accepted)
+}
+
+/// 等待客户端关闭后源站连接放回池中
+async fn wait_idle(pool: &ConnectionPool) {
+    for _ in 0..50 {
+        if pool.idle_connections() == 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(pool.idle_connections(), 1);
+}
+
+/// 测试池中连接已失效时GET请求在新连接上重试，POST请求不重试
+#[tokio::test]
+async fn test_stale_pooled_connection_retries_idempotent_only() {
+    let (port, accepted) = start_stale_origin().await;
+    let pool = ConnectionPool::new(Duration::from_secs(30));
+    let config = CConfig::TestProxyConfig::new(
+        "connection_pool_stale".to_string(),
+        18184,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy = CProxy::TestProxy::start_with_proxy(
+        config,
+        Proxy::new(None).with_connector(BackendConnector::new().with_pool(Some(pool.clone()))),
+    )
+    .await;
+
+    get(&proxy, port).await;
+    wait_idle(&pool).await;
+
+    // 取出的连接在请求发出后被源站关闭，GET在新连接上透明重试
+    get(&proxy, port).await;
+    assert_eq!(pool.reused(), 1);
+    assert_eq!(accepted.load(Ordering::SeqCst), 2);
+    wait_idle(&pool).await;
+
+    // POST不是幂等请求，不会重试
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let request = format!(
+        "POST http://127.0.0.1:{0}/ HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\nContent-Length: 3\r\n\r\na=1",
+        port
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut response))
+        .await
+        .expect("等待响应超时")
+        .unwrap();
+    let response = String::from_utf8_lossy(&response);
+    assert!(!response.contains("200 OK"), "响应: {}", response);
+    assert_eq!(pool.reused(), 2);
+    assert_eq!(accepted.load(Ordering::SeqCst), 2);
+
+    proxy.stop().await;
+}
@@ -0,0 +1,186 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+async fn start_proxy(id: &str, port: u16) -> CProxy::TestProxy {
+    let config =
+        CConfig::TestProxyConfig::new(id.to_string(), port, CConfig::ProxyProtocol::Http11);
+    CProxy::TestProxy::start(config).await
+}
+
+/// 测试SOCKS4请求交给SOCKS4处理函数，返回8字节的SOCKS4应答
+#[tokio::test]
+async fn test_socks4_dispatches_to_socks4_handler() {
+    let backend = CBackend::MockBackend::start(b"pong".to_vec()).await;
+    let proxy = start_proxy("dispatch_socks4", 18174).await;
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let mut request = vec![0x04, 0x01];
+    request.extend_from_slice(&backend.port().to_be_bytes());
+    request.extend_from_slice(&[127, 0, 0, 1, 0]);
+    stream.write_all(&request).await.unwrap();
+
+    let mut reply = [0u8; 8];
+    stream.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[..2], [0x00, 0x5a]);
+
+    stream.write_all(b"ping\r\n\r\n").await.unwrap();
+    let mut relayed = Vec::new();
+    stream.read_to_end(&mut relayed).await.unwrap();
+    assert_eq!(relayed, b"pong");
+
+    proxy.stop().await;
+}
+
+/// 测试SOCKS5请求交给SOCKS5处理函数，返回方法协商应答
+#[tokio::test]
+async fn test_socks5_dispatches_to_socks5_handler() {
+    let proxy = start_proxy("dispatch_socks5", 18175).await;
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply, [0x05, 0x00]);
+
+    proxy.stop().await;
+}
+
+/// 测试CONNECT请求走隧道路径，建立隧道后原样转发字节
+#[tokio::test]
+async fn test_connect_dispatches_to_tunnel() {
+    let backend = CBackend::MockBackend::start(b"pong".to_vec()).await;
+    let proxy = start_proxy("dispatch_connect", 18176).await;
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let request = format!(
+        "CONNECT 127.0.0.1:{0} HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\n\r\n",
+        backend.port()
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let head = CBackend::read_request(&mut stream).await;
+    let head = String::from_utf8_lossy(&head);
+    assert!(
+        head.starts_with("HTTP/1.1 200 Connection Established\r\n"),
+        "响应: {}",
+        head
+    );
+
+    stream.write_all(b"ping\r\n\r\n").await.unwrap();
+    let mut relayed = Vec::new();
+    stream.read_to_end(&mut relayed).await.unwrap();
+    assert_eq!(relayed, b"pong");
+    assert_eq!(backend.requests(), vec![b"ping\r\n\r\n".to_vec()]);
+
+    proxy.stop().await;
+}
+
+/// 测试WebSocket升级请求交给WebSocket处理函数：升级请求连同握手头部到达源站，
+/// 101响应原样返回，之后双向原样转发帧
+#[tokio::test]
+async fn test_websocket_dispatches_to_websocket_handler() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_port = listener.local_addr().unwrap().port();
+    let backend = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let request = CBackend::read_request(&mut stream).await;
+        stream
+            .write_all(b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n\x81\x02hi")
+            .await
+            .unwrap();
+        let mut frame = [0u8; 4];
+        stream.read_exact(&mut frame).await.unwrap();
+        (String::from_utf8_lossy(&request).into_owned(), frame)
+    });
+    let proxy = start_proxy("dispatch_websocket", 18177).await;
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let upgrade = format!(
+        "GET ws://127.0.0.1:{0}/chat HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        backend_port
+    );
+    stream.write_all(upgrade.as_bytes()).await.unwrap();
+
+    let data = CBackend::read_request(&mut stream).await;
+    let head_end = data.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    let response = String::from_utf8_lossy(&data[..head_end]);
+    assert!(
+        response.starts_with("HTTP/1.1 101 Switching Protocols\r\n"),
+        "响应: {}",
+        response
+    );
+    assert!(
+        response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"),
+        "响应: {}",
+        response
+    );
+    let mut frame = data[head_end..].to_vec();
+    while frame.len() < 4 {
+        let mut chunk = [0u8; 4];
+        let n = stream.read(&mut chunk).await.unwrap();
+        assert!(n > 0, "连接在帧到达前关闭");
+        frame.extend_from_slice(&chunk[..n]);
+    }
+    assert_eq!(frame, b"\x81\x02hi");
+
+    stream.write_all(b"\x81\x02yo").await.unwrap();
+    let (request, frame) = backend.await.unwrap();
+    assert!(
+        request.starts_with("GET /chat HTTP/1.1\r\n"),
+        "请求: {}",
+        request
+    );
+    assert!(
+        request.contains("Upgrade: websocket\r\n"),
+        "请求: {}",
+        request
+    );
+    assert!(
+        request.contains("Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n"),
+        "请求: {}",
+        request
+    );
+    assert_eq!(&frame, b"\x81\x02yo");
+
+    proxy.stop().await;
+}
+
+/// 测试普通HTTP请求交给HTTP/1.x处理函数，改写为origin-form后转发
+#[tokio::test]
+async fn test_http_dispatches_to_http1_handler() {
+    let backend = CBackend::MockBackend::start(
+        b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok".to_vec(),
+    )
+    .await;
+    let proxy = start_proxy("dispatch_http", 18178).await;
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let request = format!(
+        "GET http://127.0.0.1:{0}/item HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\nConnection: close\r\n\r\n",
+        backend.port()
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    let response = String::from_utf8_lossy(&response);
+    assert!(
+        response.starts_with("HTTP/1.1 200 OK\r\n"),
+        "响应: {}",
+        response
+    );
+    assert!(response.ends_with("ok"), "响应: {}", response);
+
+    let requests = backend.requests();
+    assert_eq!(requests.len(), 1);
+    let forwarded = String::from_utf8_lossy(&requests[0]);
+    assert!(
+        forwarded.starts_with("GET /item HTTP/1.1\r\n"),
+        "{}",
+        forwarded
+    );
+
+    proxy.stop().await;
+}
@@ -0,0 +1,54 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use rust_proxy::proxy::Proxy;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 测试源站收到追加了客户端IP的 `X-Forwarded-For`、本代理的 `Via` 以及按配置增删的头部
+#[tokio::test]
+async fn test_forwarded_headers_injected() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+
+    let config = CConfig::TestProxyConfig::new(
+        "forwarded_headers".to_string(),
+        18162,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy = Proxy::new(None)
+        .with_remove_request_headers(vec!["cookie".to_string()])
+        .with_add_request_headers(vec![("X-Env".to_string(), "staging".to_string())]);
+    let proxy = CProxy::TestProxy::start_with_proxy(config, proxy).await;
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let request = format!(
+        "GET http://127.0.0.1:{0}/ HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\nX-Forwarded-For: 203.0.113.7\r\nVia: 1.1 corp-proxy\r\nCookie: session=1\r\nX-Env: prod\r\nConnection: close\r\n\r\n",
+        backend.port()
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    assert!(String::from_utf8_lossy(&response).starts_with("HTTP/1.1 204"));
+
+    let requests = backend.requests();
+    assert_eq!(requests.len(), 1);
+    let forwarded = String::from_utf8_lossy(&requests[0]);
+    assert!(
+        forwarded.contains("\r\nX-Forwarded-For: 203.0.113.7, 127.0.0.1\r\n"),
+        "{}",
+        forwarded
+    );
+    assert!(
+        forwarded.contains("\r\nVia: 1.1 corp-proxy, 1.1 rust-proxy\r\n"),
+        "{}",
+        forwarded
+    );
+    assert!(
+        forwarded.contains("\r\nX-Env: staging\r\n"),
+        "{}",
+        forwarded
+    );
+    assert!(!forwarded.contains("X-Env: prod"), "{}", forwarded);
+    assert!(!forwarded.contains("session=1"), "{}", forwarded);
+
+    proxy.stop().await;
+}
@@ -0,0 +1,89 @@
+use crate::common::{CConfig, CProxy};
+use rust_proxy::handlers::backend::BackendConnector;
+use rust_proxy::proxy::Proxy;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpSocket, TcpStream};
+
+/// 创建一个不响应SYN的黑洞地址
+///
+/// 监听队列为0且已被占满的监听器会丢弃新的SYN，效果等同于不可达的地址，
+/// 返回值需保持存活
+async fn blackhole() -> (TcpListener, TcpStream, SocketAddr) {
+    let socket = TcpSocket::new_v4().unwrap();
+    socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let listener = socket.listen(0).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let filler = TcpStream::connect(addr).await.unwrap();
+    (listener, filler, addr)
+}
+
+async fn send(proxy: &CProxy::TestProxy, request: &str) -> String {
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut response))
+        .await
+        .expect("代理应在连接超时后响应")
+        .unwrap();
+    String::from_utf8_lossy(&response).to_string()
+}
+
+/// 测试连接目标超时返回504，连接被拒绝返回502
+#[tokio::test]
+async fn test_connect_timeout_maps_to_504() {
+    let (_listener, _filler, blackhole) = blackhole().await;
+
+    let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let refused = closed.local_addr().unwrap();
+    drop(closed);
+
+    let config = CConfig::TestProxyConfig::new(
+        "gateway_timeout".to_string(),
+        18124,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let connector = BackendConnector::new().with_connect_timeout(Duration::from_secs(1));
+    let proxy =
+        CProxy::TestProxy::start_with_proxy(config, Proxy::new(None).with_connector(connector))
+            .await;
+
+    let started = Instant::now();
+    let response = send(
+        &proxy,
+        &format!("GET http://{0}/ HTTP/1.1\r\nHost: {0}\r\n\r\n", blackhole),
+    )
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 504 Gateway Timeout"),
+        "响应: {}",
+        response
+    );
+    assert!(started.elapsed() < Duration::from_secs(3));
+
+    let response = send(
+        &proxy,
+        &format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n\r\n", blackhole),
+    )
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 504 Gateway Timeout"),
+        "响应: {}",
+        response
+    );
+
+    let response = send(
+        &proxy,
+        &format!("GET http://{0}/ HTTP/1.1\r\nHost: {0}\r\n\r\n", refused),
+    )
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 502 Bad Gateway"),
+        "响应: {}",
+        response
+    );
+
+    proxy.stop().await;
+}
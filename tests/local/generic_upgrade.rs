@@ -0,0 +1,109 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use rust_proxy::proxy::Proxy;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+fn upgrade_request(origin_port: u16) -> String {
+    format!(
+        "GET http://127.0.0.1:{0}/ HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\nConnection: Upgrade\r\nUpgrade: example/1\r\n\r\n",
+        origin_port
+    )
+}
+
+/// 测试默认以501拒绝非WebSocket的协议升级请求，且不连接源站
+#[tokio::test]
+async fn test_generic_upgrade_rejected_by_default() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+    let config = CConfig::TestProxyConfig::new(
+        "generic_upgrade_rejected".to_string(),
+        18147,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy = CProxy::TestProxy::start(config).await;
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    stream
+        .write_all(upgrade_request(backend.port()).as_bytes())
+        .await
+        .unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    let response = String::from_utf8_lossy(&response);
+    assert!(
+        response.starts_with("HTTP/1.1 501 Not Implemented\r\n"),
+        "响应: {}",
+        response
+    );
+    assert!(backend.requests().is_empty());
+
+    proxy.stop().await;
+}
+
+/// 测试 `--allow-generic-upgrade` 时转发升级请求和101响应，之后按新协议原样双向转发
+#[tokio::test]
+async fn test_generic_upgrade_relayed_when_allowed() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let origin_port = listener.local_addr().unwrap().port();
+    let origin = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let request = CBackend::read_request(&mut stream).await;
+        stream
+            .write_all(b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: example/1\r\n\r\n")
+            .await
+            .unwrap();
+        // 升级后的协议：原样回显
+        let mut buffer = [0u8; 64];
+        let n = stream.read(&mut buffer).await.unwrap();
+        stream.write_all(&buffer[..n]).await.unwrap();
+        String::from_utf8(request).unwrap()
+    });
+
+    let config = CConfig::TestProxyConfig::new(
+        "generic_upgrade_allowed".to_string(),
+        18148,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy = CProxy::TestProxy::start_with_proxy(
+        config,
+        Proxy::new(None).with_allow_generic_upgrade(true),
+    )
+    .await;
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    stream
+        .write_all(upgrade_request(origin_port).as_bytes())
+        .await
+        .unwrap();
+
+    let expected =
+        b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: example/1\r\n\r\n";
+    let mut response = vec![0u8; expected.len()];
+    tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut response))
+        .await
+        .expect("等待101响应超时")
+        .unwrap();
+    assert_eq!(response, expected);
+
+    stream.write_all(b"\x00\x01binary").await.unwrap();
+    let mut echoed = [0u8; 8];
+    tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut echoed))
+        .await
+        .expect("等待回显超时")
+        .unwrap();
+    assert_eq!(&echoed, b"\x00\x01binary");
+
+    let request = origin.await.unwrap();
+    assert!(
+        request.contains("\r\nUpgrade: example/1\r\n"),
+        "{}",
+        request
+    );
+    assert!(
+        request.contains("\r\nConnection: upgrade\r\n"),
+        "{}",
+        request
+    );
+
+    proxy.stop().await;
+}
@@ -0,0 +1,53 @@
+use crate::common::{CConfig, CProxy};
+use rust_proxy::proxy::Proxy;
+use rust_proxy::rejection::RejectionReason;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 测试连接后不发送数据或只发送部分请求头的客户端在握手时限后被关闭
+#[tokio::test]
+async fn test_silent_client_closed_after_handshake_timeout() {
+    let config = CConfig::TestProxyConfig::new(
+        "handshake_timeout".to_string(),
+        18136,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let rejections = Arc::new(Mutex::new(Vec::new()));
+    let recorded = rejections.clone();
+    let proxy = Proxy::new(None)
+        .with_handshake_timeout(Duration::from_millis(300))
+        .with_rejection_callback(Arc::new(move |_, reason| {
+            recorded.lock().unwrap().push(reason.clone());
+        }));
+    let proxy = CProxy::TestProxy::start_with_proxy(config, proxy).await;
+
+    let silent = TcpStream::connect(proxy.address()).await.unwrap();
+    let mut partial = TcpStream::connect(proxy.address()).await.unwrap();
+    partial
+        .write_all(b"GET http://example.com/ HTTP/1.1\r\nHost: exa")
+        .await
+        .unwrap();
+
+    for mut stream in [silent, partial] {
+        let started = Instant::now();
+        let mut buffer = Vec::new();
+        let n = tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut buffer))
+            .await
+            .expect("握手超时后连接应被关闭")
+            .unwrap_or(0);
+        assert_eq!(n, 0);
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    assert_eq!(
+        *rejections.lock().unwrap(),
+        vec![
+            RejectionReason::HandshakeTimeout,
+            RejectionReason::HandshakeTimeout
+        ]
+    );
+
+    proxy.stop().await;
+}
@@ -0,0 +1,48 @@
+use crate::common::{CConfig, CProxy};
+use rust_proxy::health;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// 测试 `/healthz` 返回200及包含活跃连接数的JSON
+#[tokio::test]
+async fn test_healthz_reports_active_connections() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let health_addr = listener.local_addr().unwrap();
+    tokio::spawn(health::serve(listener));
+
+    let config =
+        CConfig::TestProxyConfig::new("healthz".to_string(), 18140, CConfig::ProxyProtocol::Http11);
+    let proxy = CProxy::TestProxy::start(config).await;
+
+    // 保持一个未发送请求的代理连接，确保活跃连接数不为0
+    let idle = TcpStream::connect(proxy.address()).await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    let mut stream = TcpStream::connect(health_addr).await.unwrap();
+    stream
+        .write_all(b"GET /healthz HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    let response = String::from_utf8(response).unwrap();
+    assert!(
+        response.starts_with("HTTP/1.1 200 OK"),
+        "响应: {}",
+        response
+    );
+
+    let (_, body) = response.split_once("\r\n\r\n").unwrap();
+    let body: serde_json::Value = serde_json::from_str(body).unwrap();
+    assert_eq!(body["status"], "ok");
+    assert!(
+        body["active_connections"].as_i64().unwrap() >= 1,
+        "{}",
+        body
+    );
+    assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+    assert!(body["uptime_secs"].is_u64());
+
+    drop(idle);
+    proxy.stop().await;
+}
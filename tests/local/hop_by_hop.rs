@@ -0,0 +1,37 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 测试代理认证头不会被转发到目标服务器
+#[tokio::test]
+async fn test_proxy_authorization_not_forwarded() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+
+    let config = CConfig::TestProxyConfig::new(
+        "hop_by_hop".to_string(),
+        18105,
+        CConfig::ProxyProtocol::Http11,
+    )
+    .with_auth("testuser".to_string(), "testpass".to_string());
+    let proxy = CProxy::TestProxy::start(config).await;
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let request = format!(
+        "GET http://127.0.0.1:{0}/ HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\nProxy-Authorization: {1}\r\nProxy-Connection: keep-alive\r\n\r\n",
+        backend.port(),
+        proxy.auth_header().unwrap()
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    assert!(String::from_utf8_lossy(&response).starts_with("HTTP/1.1 204"));
+
+    let requests = backend.requests();
+    assert_eq!(requests.len(), 1);
+    let forwarded = String::from_utf8_lossy(&requests[0]).to_lowercase();
+    assert!(!forwarded.contains("proxy-authorization"), "{}", forwarded);
+    assert!(!forwarded.contains("proxy-connection"), "{}", forwarded);
+
+    proxy.stop().await;
+}
@@ -0,0 +1,66 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+
+/// 测试HTTP/1.0请求在源站返回keep-alive帧格式响应时，代理转发单个响应后关闭连接
+#[tokio::test]
+async fn test_http10_closes_after_single_response() {
+    // 源站声明keep-alive且发送响应后不关闭连接
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let origin_port = listener.local_addr().unwrap().port();
+    let (request_tx, request_rx) = oneshot::channel();
+    let origin = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let _ = request_tx.send(CBackend::read_request(&mut stream).await);
+        stream
+            .write_all(
+                b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: keep-alive\r\n\r\nhello",
+            )
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        drop(stream);
+    });
+
+    let config = CConfig::TestProxyConfig::new(
+        "http10_close".to_string(),
+        18118,
+        CConfig::ProxyProtocol::Http10,
+    );
+    let proxy = CProxy::TestProxy::start(config).await;
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let request = format!(
+        "GET http://127.0.0.1:{0}/ HTTP/1.0\r\nHost: 127.0.0.1:{0}\r\nConnection: keep-alive\r\n\r\n",
+        origin_port
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut response))
+        .await
+        .expect("代理应在单个响应后关闭连接")
+        .unwrap();
+    let response = String::from_utf8_lossy(&response);
+    assert!(
+        response.starts_with("HTTP/1.1 200 OK"),
+        "响应: {}",
+        response
+    );
+    assert!(
+        response.contains("Connection: close\r\n"),
+        "响应: {}",
+        response
+    );
+    assert!(!response.contains("keep-alive"), "响应: {}", response);
+    assert!(response.ends_with("hello"), "响应: {}", response);
+
+    let forwarded = String::from_utf8(request_rx.await.unwrap()).unwrap();
+    assert!(forwarded.contains("Connection: close\r\n"), "{}", forwarded);
+    assert!(!forwarded.contains("keep-alive"), "{}", forwarded);
+
+    origin.abort();
+    proxy.stop().await;
+}
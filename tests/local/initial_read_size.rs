@@ -0,0 +1,51 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use rust_proxy::proxy::Proxy;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 测试每次只读16字节时，跨越多次读取且分批到达的请求头部仍被完整转发
+#[tokio::test]
+async fn test_small_initial_read_size() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+    let addr = backend.addr();
+    let config = CConfig::TestProxyConfig::new(
+        "initial_read_size".to_string(),
+        18157,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy =
+        CProxy::TestProxy::start_with_proxy(config, Proxy::new(None).with_initial_read_size(16))
+            .await;
+
+    let cookie = "c".repeat(600);
+    let request = format!(
+        "GET http://{0}/path HTTP/1.1\r\nHost: {0}\r\nCookie: {1}\r\nConnection: close\r\n\r\n",
+        addr, cookie
+    );
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    for chunk in request.as_bytes().chunks(100) {
+        stream.write_all(chunk).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    let response = String::from_utf8_lossy(&response);
+    assert!(response.starts_with("HTTP/1.1 204"), "响应: {}", response);
+
+    let requests = backend.requests();
+    assert_eq!(requests.len(), 1);
+    let forwarded = String::from_utf8_lossy(&requests[0]);
+    assert!(
+        forwarded.starts_with("GET /path HTTP/1.1\r\n"),
+        "{}",
+        forwarded
+    );
+    assert!(
+        forwarded.contains(&format!("Cookie: {}\r\n", cookie)),
+        "{}",
+        forwarded
+    );
+
+    proxy.stop().await;
+}
@@ -0,0 +1,87 @@
+use crate::common::CBackend;
+use rust_proxy::auth::AuthConfig;
+use rust_proxy::proxy::{bind_listeners, Proxy};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::Semaphore;
+
+/// 经 `proxy_addr` 代理一个到 `target` 的GET请求，返回响应
+async fn get(proxy_addr: SocketAddr, target: SocketAddr) -> String {
+    let mut stream = TcpStream::connect(proxy_addr).await.unwrap();
+    stream
+        .write_all(
+            format!(
+                "GET http://{0}/ HTTP/1.1\r\nHost: {0}\r\nConnection: close\r\n\r\n",
+                target
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    String::from_utf8_lossy(&response).to_string()
+}
+
+/// 测试监听 `::1` 时经IPv6代理请求
+#[tokio::test]
+async fn test_ipv6_loopback_listener() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+
+    let listeners = bind_listeners(&["[::1]:0".parse().unwrap()]).await;
+    assert_eq!(listeners.len(), 1);
+    let proxy_addr = listeners[0].local_addr().unwrap();
+    assert!(proxy_addr.is_ipv6());
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(Proxy::new(None).serve_listeners_with_shutdown(
+        listeners,
+        Arc::new(Semaphore::new(10)),
+        async {
+            let _ = shutdown_rx.await;
+        },
+    ));
+
+    let response = get(proxy_addr, backend.addr()).await;
+    assert!(response.starts_with("HTTP/1.1 204"), "响应: {}", response);
+    // 只监听IPv6回环地址，IPv4连接不可达
+    let ipv4 = SocketAddr::new("127.0.0.1".parse().unwrap(), proxy_addr.port());
+    assert!(TcpStream::connect(ipv4).await.is_err());
+
+    let _ = shutdown_tx.send(());
+    server.await.unwrap().unwrap();
+}
+
+/// 测试监听 `::` 时同时接受IPv4与IPv6连接，IPv4客户端按IPv4地址做访问控制
+#[tokio::test]
+async fn test_dual_stack_listener() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+
+    let listeners = bind_listeners(&["[::]:0".parse().unwrap()]).await;
+    assert_eq!(listeners.len(), 1);
+    let port = listeners[0].local_addr().unwrap().port();
+
+    // 回环客户端免认证：IPv4映射地址若未还原则不会被识别为回环地址
+    let proxy = Proxy::new(Some(AuthConfig::new("user".into(), "pass".into())))
+        .with_no_auth_for_localhost(true);
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(proxy.serve_listeners_with_shutdown(
+        listeners,
+        Arc::new(Semaphore::new(10)),
+        async {
+            let _ = shutdown_rx.await;
+        },
+    ));
+
+    for ip in ["127.0.0.1", "::1"] {
+        let proxy_addr = SocketAddr::new(ip.parse().unwrap(), port);
+        let response = get(proxy_addr, backend.addr()).await;
+        assert!(response.starts_with("HTTP/1.1 204"), "{}: {}", ip, response);
+    }
+    assert_eq!(backend.requests().len(), 2);
+
+    let _ = shutdown_tx.send(());
+    server.await.unwrap().unwrap();
+}
@@ -0,0 +1,76 @@
+use crate::common::{CConfig, CProxy};
+use rust_proxy::proxy::Proxy;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+async fn get_self(proxy: &CProxy::TestProxy, path: &str) -> String {
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, proxy.address());
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    String::from_utf8_lossy(&response).to_string()
+}
+
+/// 测试直接访问代理根路径返回信息页
+#[tokio::test]
+async fn test_landing_page_on_proxy_root() {
+    let config = CConfig::TestProxyConfig::new(
+        "landing_page".to_string(),
+        18102,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy = Proxy::new(None).with_landing_page(Some("<h1>RustProxy</h1>".to_string()));
+    let proxy = CProxy::TestProxy::start_with_proxy(config, proxy).await;
+
+    let response = get_self(&proxy, "/").await;
+    assert!(
+        response.starts_with("HTTP/1.1 200 OK"),
+        "响应: {}",
+        response
+    );
+    assert!(response.ends_with("<h1>RustProxy</h1>"));
+
+    let response = get_self(&proxy, "/other").await;
+    assert!(
+        response.starts_with("HTTP/1.1 404 Not Found"),
+        "响应: {}",
+        response
+    );
+
+    proxy.stop().await;
+}
+
+/// 测试自身请求按不区分大小写的Host头识别，不会被X-Forwarded-Host误判
+#[tokio::test]
+async fn test_landing_page_uses_host_header_only() {
+    let config = CConfig::TestProxyConfig::new(
+        "landing_host_header".to_string(),
+        18173,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy = Proxy::new(None).with_landing_page(Some("<h1>RustProxy</h1>".to_string()));
+    let proxy = CProxy::TestProxy::start_with_proxy(config, proxy).await;
+
+    for (headers, is_self) in [
+        (format!("host: {}\r\n", proxy.address()), true),
+        (format!("X-Forwarded-Host: {}\r\n", proxy.address()), false),
+    ] {
+        let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+        let request = format!("GET / HTTP/1.1\r\n{}\r\n", headers);
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+        let response = String::from_utf8_lossy(&response);
+        assert_eq!(
+            response.ends_with("<h1>RustProxy</h1>"),
+            is_self,
+            "响应: {}",
+            response
+        );
+    }
+
+    proxy.stop().await;
+}
@@ -0,0 +1,52 @@
+use crate::common::CBackend;
+use rust_proxy::proxy::{bind_listeners, Proxy};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+
+/// 测试同时监听多个地址，绑定失败的地址被跳过，其余地址都能代理请求
+#[tokio::test]
+async fn test_multiple_listeners() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+    let addr = backend.addr();
+
+    // 已被占用的地址绑定失败，不影响其余地址
+    let occupied = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let ephemeral: SocketAddr = "127.0.0.1:0".parse().unwrap();
+    let listeners = bind_listeners(&[ephemeral, occupied.local_addr().unwrap(), ephemeral]).await;
+    assert_eq!(listeners.len(), 2);
+    let proxy_addrs: Vec<_> = listeners
+        .iter()
+        .map(|listener| listener.local_addr().unwrap())
+        .collect();
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(Proxy::new(None).serve_listeners_with_shutdown(
+        listeners,
+        Arc::new(Semaphore::new(10)),
+        async {
+            let _ = shutdown_rx.await;
+        },
+    ));
+
+    for proxy_addr in &proxy_addrs {
+        let mut stream = TcpStream::connect(proxy_addr).await.unwrap();
+        stream
+            .write_all(format!("GET http://{0}/ HTTP/1.1\r\nHost: {0}\r\n\r\n", addr).as_bytes())
+            .await
+            .unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+        let response = String::from_utf8_lossy(&response);
+        assert!(response.starts_with("HTTP/1.1 204"), "响应: {}", response);
+    }
+    assert_eq!(backend.requests().len(), 2);
+
+    let _ = shutdown_tx.send(());
+    server.await.unwrap().unwrap();
+    for proxy_addr in &proxy_addrs {
+        assert!(TcpStream::connect(proxy_addr).await.is_err());
+    }
+}
@@ -0,0 +1,66 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use rust_proxy::metrics;
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+async fn scrape(addr: SocketAddr) -> String {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    let response = String::from_utf8(response).unwrap();
+    assert!(
+        response.starts_with("HTTP/1.1 200 OK"),
+        "响应: {}",
+        response
+    );
+    response
+}
+
+fn value(output: &str, name: &str) -> u64 {
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+        .and_then(|v| v.parse().ok())
+        .unwrap_or_else(|| panic!("缺少指标 {}:\n{}", name, output))
+}
+
+/// 测试代理一次请求后连接计数增加
+#[tokio::test]
+async fn test_metrics_count_proxied_request() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let metrics_addr = listener.local_addr().unwrap();
+    tokio::spawn(metrics::serve(listener));
+
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+    let config =
+        CConfig::TestProxyConfig::new("metrics".to_string(), 18113, CConfig::ProxyProtocol::Http11);
+    let proxy = CProxy::TestProxy::start(config).await;
+
+    let before = scrape(metrics_addr).await;
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let request = format!(
+        "GET http://127.0.0.1:{0}/ HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\n\r\n",
+        backend.port()
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    assert!(String::from_utf8_lossy(&response).starts_with("HTTP/1.1 204"));
+
+    let after = scrape(metrics_addr).await;
+    assert!(
+        value(&after, "rust_proxy_connections_total")
+            > value(&before, "rust_proxy_connections_total")
+    );
+    let http1 = "rust_proxy_requests_total{protocol=\"http1\"}";
+    assert!(value(&after, http1) > value(&before, http1));
+
+    proxy.stop().await;
+}
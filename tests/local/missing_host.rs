@@ -0,0 +1,45 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+async fn send(proxy: &CProxy::TestProxy, request: &[u8]) -> String {
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    stream.write_all(request).await.unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    String::from_utf8_lossy(&response).to_string()
+}
+
+/// 测试既没有Host头也没有绝对URI的请求返回明确的400，而不是连接错误
+#[tokio::test]
+async fn test_missing_host_rejected() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+
+    let config = CConfig::TestProxyConfig::new(
+        "missing_host".to_string(),
+        18133,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy = CProxy::TestProxy::start(config).await;
+
+    let response = send(&proxy, b"GET /index.html HTTP/1.0\r\nAccept: */*\r\n\r\n").await;
+    assert!(
+        response.starts_with("HTTP/1.0 400 Bad Request"),
+        "响应: {}",
+        response
+    );
+    assert!(
+        response.contains("没有Host头，也没有绝对URI"),
+        "响应: {}",
+        response
+    );
+
+    // 没有Host头的绝对URI请求按URI中的主机转发
+    let request = format!("GET http://{}/ HTTP/1.0\r\n\r\n", backend.addr());
+    let response = send(&proxy, request.as_bytes()).await;
+    assert!(response.starts_with("HTTP/1.1 204"), "响应: {}", response);
+    assert_eq!(backend.requests().len(), 1);
+
+    proxy.stop().await;
+}
@@ -0,0 +1,43 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 测试绝对形式的请求目标以源站形式转发，`Host` 头保持不变
+#[tokio::test]
+async fn test_absolute_form_rewritten_to_origin_form() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+
+    let config = CConfig::TestProxyConfig::new(
+        "origin_form".to_string(),
+        18146,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy = CProxy::TestProxy::start(config).await;
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let request = format!(
+        "GET http://127.0.0.1:{0}/path?x=1 HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\n\r\n",
+        backend.port()
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    assert!(String::from_utf8_lossy(&response).starts_with("HTTP/1.1 204"));
+
+    let requests = backend.requests();
+    assert_eq!(requests.len(), 1);
+    let forwarded = String::from_utf8_lossy(&requests[0]);
+    assert!(
+        forwarded.starts_with("GET /path?x=1 HTTP/1.1\r\n"),
+        "{}",
+        forwarded
+    );
+    assert!(
+        forwarded.contains(&format!("\r\nHost: 127.0.0.1:{}\r\n", backend.port())),
+        "{}",
+        forwarded
+    );
+
+    proxy.stop().await;
+}
@@ -0,0 +1,153 @@
+use crate::common::{CConfig, CProxy};
+use rust_proxy::proxy::Proxy;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// 启动一个keep-alive源站，在同一连接上逐个应答请求，响应体为请求路径
+///
+/// 返回源站端口以及收到的请求（头部和请求体）
+async fn start_origin() -> (u16, JoinHandle<Vec<(String, Vec<u8>)>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let origin = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut stream = BufReader::new(stream);
+        let mut requests = Vec::new();
+        loop {
+            let mut head = String::new();
+            loop {
+                let mut line = String::new();
+                if stream.read_line(&mut line).await.unwrap_or(0) == 0 {
+                    return requests;
+                }
+                head.push_str(&line);
+                if line == "\r\n" {
+                    break;
+                }
+            }
+
+            let content_length = head
+                .to_lowercase()
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length:"))
+                .and_then(|v| v.trim().parse::<usize>().ok())
+                .unwrap_or(0);
+            let mut body = vec![0u8; content_length];
+            stream.read_exact(&mut body).await.unwrap();
+
+            // 请求目标为绝对URI，取其路径部分
+            let target = head.split_whitespace().nth(1).unwrap();
+            let path = match target.split_once("://") {
+                Some((_, rest)) => &rest[rest.find('/').unwrap_or(rest.len())..],
+                None => target,
+            };
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                path.len(),
+                path
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            requests.push((head, body));
+        }
+    });
+    (port, origin)
+}
+
+/// 在一次写入中发送两个流水线请求，第一个带有请求体
+async fn send_pipelined(stream: &mut TcpStream, origin_port: u16) {
+    let requests = format!(
+        "POST http://127.0.0.1:{0}/first HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\nContent-Length: 3\r\n\r\nabc\
+         GET http://127.0.0.1:{0}/second HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\nProxy-Connection: keep-alive\r\n\r\n",
+        origin_port
+    );
+    stream.write_all(requests.as_bytes()).await.unwrap();
+}
+
+/// 测试同一次写入中的两个流水线请求都被转发，响应按请求顺序返回
+#[tokio::test]
+async fn test_pipelined_requests_answered_in_order() {
+    let (origin_port, origin) = start_origin().await;
+    let config = CConfig::TestProxyConfig::new(
+        "pipelining".to_string(),
+        18141,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy = CProxy::TestProxy::start(config).await;
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    send_pipelined(&mut stream, origin_port).await;
+
+    let mut response = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !response.ends_with(b"/second") {
+        let n = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut chunk))
+            .await
+            .expect("等待响应超时")
+            .unwrap();
+        assert!(n > 0, "连接在响应完成前关闭: {:?}", response);
+        response.extend_from_slice(&chunk[..n]);
+    }
+    let response = String::from_utf8(response).unwrap();
+    assert_eq!(
+        response,
+        "HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\n/first\
+         HTTP/1.1 200 OK\r\nContent-Length: 7\r\n\r\n/second"
+    );
+
+    drop(stream);
+    let requests = origin.await.unwrap();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].1, b"abc");
+    // 后续请求同样经过逐跳头部处理
+    assert!(
+        !requests[1].0.contains("Proxy-Connection"),
+        "请求: {}",
+        requests[1].0
+    );
+    assert!(
+        requests[1].0.contains("Connection: keep-alive\r\n"),
+        "请求: {}",
+        requests[1].0
+    );
+
+    proxy.stop().await;
+}
+
+/// 测试 `--no-pipelining` 时只转发第一个请求，响应后关闭连接
+#[tokio::test]
+async fn test_no_pipelining_handles_first_request_only() {
+    let (origin_port, origin) = start_origin().await;
+    let config = CConfig::TestProxyConfig::new(
+        "no_pipelining".to_string(),
+        18142,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy =
+        CProxy::TestProxy::start_with_proxy(config, Proxy::new(None).with_no_pipelining(true))
+            .await;
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    send_pipelined(&mut stream, origin_port).await;
+
+    let mut response = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut response))
+        .await
+        .expect("等待连接关闭超时")
+        .unwrap();
+    let response = String::from_utf8(response).unwrap();
+    assert!(
+        response.contains("Connection: close\r\n"),
+        "响应: {}",
+        response
+    );
+    assert!(response.ends_with("/first"), "响应: {}", response);
+    assert!(!response.contains("/second"), "响应: {}", response);
+
+    let requests = origin.await.unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].1, b"abc");
+
+    proxy.stop().await;
+}
@@ -0,0 +1,105 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use rust_proxy::proxy::Proxy;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const RESPONSE_BODY: &str = "hello";
+
+/// 读取一个以固定响应体结尾的响应
+async fn read_response(stream: &mut TcpStream) -> String {
+    let mut response = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !response.ends_with(RESPONSE_BODY.as_bytes()) {
+        let n = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut chunk))
+            .await
+            .expect("等待响应超时")
+            .unwrap();
+        assert!(n > 0, "连接在响应完成前关闭: {:?}", response);
+        response.extend_from_slice(&chunk[..n]);
+    }
+    String::from_utf8(response).unwrap()
+}
+
+/// 测试只发送 `Proxy-Connection: keep-alive` 的旧客户端可复用连接发送两个请求
+#[tokio::test]
+async fn test_proxy_connection_keep_alive() {
+    // HTTP/1.0源站：仅当请求带有 Connection: keep-alive 时保持连接
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let origin_port = listener.local_addr().unwrap().port();
+    let origin = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut requests = Vec::new();
+        loop {
+            let request = CBackend::read_request(&mut stream).await;
+            if request.is_empty() {
+                break;
+            }
+            let request = String::from_utf8(request).unwrap();
+            let keep_alive = request.contains("Connection: keep-alive\r\n");
+            let connection = if keep_alive {
+                "Connection: keep-alive\r\n"
+            } else {
+                ""
+            };
+            let response = format!(
+                "HTTP/1.0 200 OK\r\nContent-Length: {}\r\n{}\r\n{}",
+                RESPONSE_BODY.len(),
+                connection,
+                RESPONSE_BODY
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            requests.push(request);
+            if !keep_alive {
+                break;
+            }
+        }
+        requests
+    });
+
+    let config = CConfig::TestProxyConfig::new(
+        "proxy_connection".to_string(),
+        18130,
+        CConfig::ProxyProtocol::Http10,
+    );
+    let proxy =
+        CProxy::TestProxy::start_with_proxy(config, Proxy::new(None).with_http10_close(false))
+            .await;
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    for path in ["/first", "/second"] {
+        let request = format!(
+            "GET http://127.0.0.1:{0}{1} HTTP/1.0\r\nHost: 127.0.0.1:{0}\r\nProxy-Connection: keep-alive\r\n\r\n",
+            origin_port, path
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let response = read_response(&mut stream).await;
+        assert!(
+            response.starts_with("HTTP/1.0 200 OK"),
+            "响应: {}",
+            response
+        );
+        assert!(
+            response.contains("Connection: keep-alive\r\n"),
+            "响应: {}",
+            response
+        );
+    }
+    drop(stream);
+
+    // 两个请求经同一条源站连接到达，首个请求的 Proxy-Connection 已改写为 Connection
+    let requests = tokio::time::timeout(Duration::from_secs(5), origin)
+        .await
+        .expect("源站应收到两个请求")
+        .unwrap();
+    assert_eq!(requests.len(), 2);
+    assert!(
+        requests[0].starts_with("GET /first HTTP/1.0\r\n"),
+        "{}",
+        requests[0]
+    );
+    assert!(!requests[0].contains("Proxy-Connection"), "{}", requests[0]);
+    assert!(requests[1].contains("/second"), "{}", requests[1]);
+
+    proxy.stop().await;
+}
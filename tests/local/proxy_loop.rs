@@ -0,0 +1,52 @@
+use crate::common::{CConfig, CProxy};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 发送请求并读取到连接关闭
+async fn send(proxy: &CProxy::TestProxy, request: String) -> String {
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    String::from_utf8_lossy(&response).into_owned()
+}
+
+/// 测试目标为代理自身监听地址的CONNECT与HTTP请求以403拒绝，而不是连回代理形成回环
+#[tokio::test]
+async fn test_self_targeting_requests_forbidden() {
+    let config = CConfig::TestProxyConfig::new(
+        "proxy_loop".to_string(),
+        18163,
+        CConfig::ProxyProtocol::HttpsConnect,
+    );
+    let proxy = CProxy::TestProxy::start(config).await;
+
+    for target in ["127.0.0.1:18163", "localhost:18163"] {
+        let response = send(
+            &proxy,
+            format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n\r\n", target),
+        )
+        .await;
+        assert!(
+            response.starts_with("HTTP/1.1 403 Forbidden\r\n"),
+            "{}",
+            response
+        );
+        assert!(response.contains("检测到转发回环"), "{}", response);
+    }
+
+    let response = send(
+        &proxy,
+        "GET http://127.0.0.1:18163/ HTTP/1.1\r\nHost: 127.0.0.1:18163\r\nConnection: close\r\n\r\n"
+            .to_string(),
+    )
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 403 Forbidden\r\n"),
+        "{}",
+        response
+    );
+    assert!(response.contains("检测到转发回环"), "{}", response);
+
+    proxy.stop().await;
+}
@@ -0,0 +1,154 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use rust_proxy::access_log::AccessLog;
+use rust_proxy::auth::AuthConfig;
+use rust_proxy::proxy::Proxy;
+use rust_proxy::rejection::RejectionReason;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 构造TCP over IPv4的PROXY协议v2头部
+fn v2_header(source: SocketAddr, destination: SocketAddr) -> Vec<u8> {
+    let (source, destination) = match (source, destination) {
+        (SocketAddr::V4(source), SocketAddr::V4(destination)) => (source, destination),
+        _ => panic!("只支持IPv4地址"),
+    };
+    let mut header = b"\r\n\r\n\0\r\nQUIT\n".to_vec();
+    header.extend_from_slice(&[0x21, 0x11, 0x00, 0x0c]);
+    header.extend_from_slice(&source.ip().octets());
+    header.extend_from_slice(&destination.ip().octets());
+    header.extend_from_slice(&source.port().to_be_bytes());
+    header.extend_from_slice(&destination.port().to_be_bytes());
+    header
+}
+
+/// 发送PROXY协议头和一个不带认证的请求，返回连接关闭前收到的响应
+async fn send(proxy: &CProxy::TestProxy, header: &[u8], target: SocketAddr) -> String {
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let mut data = header.to_vec();
+    data.extend_from_slice(
+        format!(
+            "GET http://{0}/ HTTP/1.1\r\nHost: {0}\r\nConnection: close\r\n\r\n",
+            target
+        )
+        .as_bytes(),
+    );
+    stream.write_all(&data).await.unwrap();
+    let mut response = Vec::new();
+    // 拒绝时未读取的请求数据可能让连接以RST关闭，读取错误按已关闭处理
+    let _ = tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut response))
+        .await
+        .expect("等待响应超时");
+    String::from_utf8_lossy(&response).into_owned()
+}
+
+/// 测试v1和v2头部给出的客户端地址用于免认证判断、拒绝回调与访问日志
+#[tokio::test]
+async fn test_source_address_from_proxy_header() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+    let target = backend.addr();
+
+    let path = std::env::temp_dir().join(format!(
+        "rust_proxy_{}_proxy_protocol.log",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    let access_log = AccessLog::open(&path).await.unwrap();
+
+    let rejections = Arc::new(Mutex::new(Vec::new()));
+    let recorded = rejections.clone();
+    let config = CConfig::TestProxyConfig::new(
+        "proxy_protocol".to_string(),
+        18160,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy = Proxy::new(Some(AuthConfig::new(
+        "testuser".to_string(),
+        "testpass".to_string(),
+    )))
+    .with_accept_proxy_protocol(true)
+    .with_auth_exempt(vec!["203.0.113.0/24".parse().unwrap()])
+    .with_access_log(Some(access_log.clone()))
+    .with_rejection_callback(Arc::new(move |addr, reason| {
+        recorded.lock().unwrap().push((addr, reason.clone()));
+    }));
+    let proxy = CProxy::TestProxy::start_with_proxy(config, proxy).await;
+
+    // v1头部给出免认证网段内的地址，请求无需认证
+    let response = send(
+        &proxy,
+        format!("PROXY TCP4 203.0.113.7 {} 51234 18160\r\n", target.ip()).as_bytes(),
+        target,
+    )
+    .await;
+    assert!(response.starts_with("HTTP/1.1 204"), "响应: {}", response);
+
+    // v2头部给出的地址不在免认证网段内，以该地址记录认证失败
+    let source: SocketAddr = "198.51.100.9:40000".parse().unwrap();
+    let response = send(
+        &proxy,
+        &v2_header(source, proxy.address().parse().unwrap()),
+        target,
+    )
+    .await;
+    assert!(response.contains(" 407 "), "响应: {}", response);
+    assert_eq!(
+        *rejections.lock().unwrap(),
+        vec![(source, RejectionReason::AuthFailed)]
+    );
+
+    let expected = format!("\"GET {}\" 204", target);
+    let mut contents = String::new();
+    for _ in 0..50 {
+        access_log.flush().await;
+        contents = std::fs::read_to_string(&path).unwrap();
+        if contents.contains(&expected) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    std::fs::remove_file(&path).unwrap();
+    let line = contents
+        .lines()
+        .find(|line| line.contains(&expected))
+        .unwrap_or_else(|| panic!("访问日志: {}", contents));
+    assert!(line.starts_with("203.0.113.7 - - ["), "日志行: {}", line);
+
+    proxy.stop().await;
+}
+
+/// 测试缺少PROXY协议头或头部无效的连接被直接关闭
+#[tokio::test]
+async fn test_malformed_proxy_header_rejected() {
+    let rejections = Arc::new(Mutex::new(Vec::new()));
+    let recorded = rejections.clone();
+    let config = CConfig::TestProxyConfig::new(
+        "proxy_protocol_malformed".to_string(),
+        18161,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy = Proxy::new(None)
+        .with_accept_proxy_protocol(true)
+        .with_rejection_callback(Arc::new(move |_, reason| {
+            recorded.lock().unwrap().push(reason.clone());
+        }));
+    let proxy = CProxy::TestProxy::start_with_proxy(config, proxy).await;
+    let target: SocketAddr = "127.0.0.1:9".parse().unwrap();
+
+    for header in [
+        &b""[..],
+        b"PROXY TCP4 203.0.113.7 127.0.0.1 51234\r\n",
+        b"PROXY TCP4 not-an-ip 127.0.0.1 51234 18161\r\n",
+    ] {
+        let response = send(&proxy, header, target).await;
+        assert!(response.is_empty(), "响应: {}", response);
+    }
+    assert_eq!(
+        *rejections.lock().unwrap(),
+        vec![RejectionReason::InvalidProxyHeader; 3]
+    );
+
+    proxy.stop().await;
+}
@@ -0,0 +1,71 @@
+use crate::common::{CConfig, CProxy};
+use rust_proxy::handlers::backend::BackendConnector;
+use rust_proxy::health::Readiness;
+use rust_proxy::proxy::Proxy;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+async fn get_readyz(proxy: &CProxy::TestProxy) -> String {
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let request = format!("GET /readyz HTTP/1.1\r\nHost: {}\r\n\r\n", proxy.address());
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    String::from_utf8_lossy(&response).to_string()
+}
+
+/// 启动带就绪探测的代理，并等待首次探测完成
+async fn start_with_canary(id: &str, port: u16, canary_port: u16) -> CProxy::TestProxy {
+    let config =
+        CConfig::TestProxyConfig::new(id.to_string(), port, CConfig::ProxyProtocol::Http11);
+    let readiness = Readiness::spawn(
+        BackendConnector::new(),
+        "127.0.0.1".to_string(),
+        canary_port,
+        Duration::from_millis(100),
+    );
+    let proxy = CProxy::TestProxy::start_with_proxy(
+        config,
+        Proxy::new(None).with_readiness(Some(readiness)),
+    )
+    .await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    proxy
+}
+
+/// 测试探测目标可达时 /readyz 返回200
+#[tokio::test]
+async fn test_readyz_when_canary_reachable() {
+    let canary = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let canary_port = canary.local_addr().unwrap().port();
+    let proxy = start_with_canary("readyz_ready", 18111, canary_port).await;
+
+    let response = get_readyz(&proxy).await;
+    assert!(
+        response.starts_with("HTTP/1.1 200 OK"),
+        "响应: {}",
+        response
+    );
+
+    proxy.stop().await;
+}
+
+/// 测试探测目标不可达时 /readyz 返回503
+#[tokio::test]
+async fn test_readyz_when_canary_unreachable() {
+    let canary = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let canary_port = canary.local_addr().unwrap().port();
+    drop(canary);
+    let proxy = start_with_canary("readyz_not_ready", 18112, canary_port).await;
+
+    let response = get_readyz(&proxy).await;
+    assert!(
+        response.starts_with("HTTP/1.1 503 Service Unavailable"),
+        "响应: {}",
+        response
+    );
+
+    proxy.stop().await;
+}
@@ -0,0 +1,126 @@
+use crate::common::{CConfig, CProxy};
+use rust_proxy::access_rules::AccessRules;
+use rust_proxy::auth::AuthConfig;
+use rust_proxy::handlers::backend::BackendConnector;
+use rust_proxy::proxy::Proxy;
+use rust_proxy::rejection::RejectionReason;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 测试认证失败时回调收到对应的拒绝原因
+#[tokio::test]
+async fn test_rejection_reason_reaches_callback() {
+    let config = CConfig::TestProxyConfig::new(
+        "rejection".to_string(),
+        18104,
+        CConfig::ProxyProtocol::Http11,
+    );
+
+    let rejections = Arc::new(Mutex::new(Vec::new()));
+    let recorded = rejections.clone();
+    let proxy = Proxy::new(Some(AuthConfig::new(
+        "testuser".to_string(),
+        "testpass".to_string(),
+    )))
+    .with_rejection_callback(Arc::new(move |_, reason| {
+        recorded.lock().unwrap().push(reason.clone());
+    }));
+    let proxy = CProxy::TestProxy::start_with_proxy(config, proxy).await;
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    stream
+        .write_all(b"GET http://example.com/ HTTP/1.1\r\nHost: example.com\r\n\r\n")
+        .await
+        .unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    assert!(String::from_utf8_lossy(&response).contains(" 407 "));
+
+    assert_eq!(
+        *rejections.lock().unwrap(),
+        vec![RejectionReason::AuthFailed]
+    );
+
+    proxy.stop().await;
+}
+
+/// 测试目标被访问规则禁止时，CONNECT和普通HTTP请求都向回调报告对应的拒绝原因
+#[tokio::test]
+async fn test_blocked_destination_reaches_callback() {
+    let config = CConfig::TestProxyConfig::new(
+        "rejection_blocked".to_string(),
+        18170,
+        CConfig::ProxyProtocol::HttpsConnect,
+    );
+
+    let rules = AccessRules::new(Vec::new(), vec!["127.0.0.0/8".parse().unwrap()]);
+    let connector = BackendConnector::new().with_access_rules(Some(Arc::new(rules)));
+    let rejections = Arc::new(Mutex::new(Vec::new()));
+    let recorded = rejections.clone();
+    let proxy = Proxy::new(None)
+        .with_connector(connector)
+        .with_rejection_callback(Arc::new(move |_, reason| {
+            recorded.lock().unwrap().push(reason.clone());
+        }));
+    let proxy = CProxy::TestProxy::start_with_proxy(config, proxy).await;
+
+    for request in [
+        "CONNECT 127.0.0.1:9 HTTP/1.1\r\nHost: 127.0.0.1:9\r\n\r\n",
+        "GET http://127.0.0.1:9/ HTTP/1.1\r\nHost: 127.0.0.1:9\r\n\r\n",
+    ] {
+        let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+        assert!(String::from_utf8_lossy(&response).contains(" 403 "));
+    }
+
+    assert_eq!(
+        *rejections.lock().unwrap(),
+        vec![RejectionReason::BlockedDestination; 2]
+    );
+
+    proxy.stop().await;
+}
+
+/// 测试连接许可耗尽时回调收到达到上限的原因，且排队的连接在许可释放后仍被处理
+#[tokio::test]
+async fn test_over_capacity_reaches_callback() {
+    let mut config = CConfig::TestProxyConfig::new(
+        "rejection_capacity".to_string(),
+        18171,
+        CConfig::ProxyProtocol::Http11,
+    );
+    config.max_connections = 1;
+
+    let rejections = Arc::new(Mutex::new(Vec::new()));
+    let recorded = rejections.clone();
+    let proxy = Proxy::new(None).with_rejection_callback(Arc::new(move |_, reason| {
+        recorded.lock().unwrap().push(reason.clone());
+    }));
+    let proxy = CProxy::TestProxy::start_with_proxy(config, proxy).await;
+
+    // 第一个连接占用唯一的许可
+    let held = TcpStream::connect(proxy.address()).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(rejections.lock().unwrap().is_empty());
+
+    let mut queued = TcpStream::connect(proxy.address()).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(
+        *rejections.lock().unwrap(),
+        vec![RejectionReason::OverCapacity]
+    );
+
+    drop(held);
+    queued.write_all(b"GARBAGE\r\n\r\n").await.unwrap();
+    let mut response = Vec::new();
+    queued.read_to_end(&mut response).await.unwrap();
+    assert!(String::from_utf8_lossy(&response).contains(" 400 "));
+
+    proxy.stop().await;
+}
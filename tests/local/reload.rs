@@ -0,0 +1,60 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use base64::Engine;
+use rust_proxy::config::Config;
+use rust_proxy::proxy::Proxy;
+use rust_proxy::reload::{self, Reloader};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+async fn request_as(
+    proxy: &CProxy::TestProxy,
+    backend_port: u16,
+    user: &str,
+    pass: &str,
+) -> String {
+    let credentials =
+        base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", user, pass));
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let request = format!(
+        "GET http://127.0.0.1:{0}/ HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\nProxy-Authorization: Basic {1}\r\nConnection: close\r\n\r\n",
+        backend_port, credentials
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    String::from_utf8_lossy(&response).to_string()
+}
+
+/// 测试修改用户文件并重新加载后，新增账号即可通过认证，已删除的账号被拒绝
+#[tokio::test]
+async fn test_reload_picks_up_new_users() {
+    let path = std::env::temp_dir().join(format!("rust_proxy_{}_reload_users", std::process::id()));
+    std::fs::write(&path, "alice:wonderland\n").unwrap();
+    let proxy_config = Config {
+        users_file: Some(path.clone()),
+        ..Config::default()
+    };
+    let server = Proxy::new(reload::load_auth_config(&proxy_config).unwrap());
+    let reloader = Reloader::new(proxy_config, server.clone());
+
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+    let config =
+        CConfig::TestProxyConfig::new("reload".to_string(), 18155, CConfig::ProxyProtocol::Http11);
+    let proxy = CProxy::TestProxy::start_with_proxy(config, server).await;
+
+    let response = request_as(&proxy, backend.port(), "bob", "builder").await;
+    assert!(response.contains(" 407 "), "{}", response);
+
+    std::fs::write(&path, "bob:builder\n").unwrap();
+    reloader.reload().unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let response = request_as(&proxy, backend.port(), "bob", "builder").await;
+    assert!(response.starts_with("HTTP/1.1 204"), "{}", response);
+    let response = request_as(&proxy, backend.port(), "alice", "wonderland").await;
+    assert!(response.contains(" 407 "), "{}", response);
+    assert_eq!(backend.requests().len(), 1);
+
+    proxy.stop().await;
+}
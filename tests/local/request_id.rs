@@ -0,0 +1,46 @@
+use crate::common::{CConfig, CProxy};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// 测试502错误响应带有请求ID，且每个连接的请求ID不同
+#[tokio::test]
+async fn test_bad_gateway_carries_request_id() {
+    let config = CConfig::TestProxyConfig::new(
+        "request_id".to_string(),
+        18126,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy = CProxy::TestProxy::start(config).await;
+
+    // 获取一个当前无人监听的端口
+    let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let refused = closed.local_addr().unwrap();
+    drop(closed);
+
+    let mut ids = Vec::new();
+    for _ in 0..2 {
+        let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+        let request = format!("GET http://{0}/ HTTP/1.1\r\nHost: {0}\r\n\r\n", refused);
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+        let response = String::from_utf8_lossy(&response).to_string();
+        assert!(
+            response.starts_with("HTTP/1.1 502 Bad Gateway"),
+            "响应: {}",
+            response
+        );
+
+        let id = response
+            .lines()
+            .find_map(|line| line.strip_prefix("X-Proxy-Request-Id: "))
+            .unwrap_or_else(|| panic!("缺少请求ID: {}", response))
+            .to_string();
+        assert_eq!(id.len(), 12, "请求ID: {}", id);
+        ids.push(id);
+    }
+    assert_ne!(ids[0], ids[1]);
+
+    proxy.stop().await;
+}
@@ -0,0 +1,100 @@
+use crate::common::{CConfig, CProxy};
+use rust_proxy::proxy::Proxy;
+use rustls::pki_types::ServerName;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+/// 生成连接 `server_name` 的ClientHello，IP地址不会带SNI
+fn client_hello(server_name: &str) -> Vec<u8> {
+    let config = rust_proxy::tls::client_config(None, true).unwrap();
+    let name = ServerName::try_from(server_name.to_string()).unwrap();
+    let mut connection = rustls::ClientConnection::new(config, name).unwrap();
+    let mut hello = Vec::new();
+    while connection.wants_write() {
+        connection.write_tls(&mut hello).unwrap();
+    }
+    hello
+}
+
+/// 启动一个记录每个连接首批数据的目标
+async fn start_target() -> (u16, mpsc::UnboundedReceiver<Vec<u8>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (sender, receiver) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                let mut data = vec![0u8; 16 * 1024];
+                let n = stream.read(&mut data).await.unwrap_or(0);
+                let _ = sender.send(data[..n].to_vec());
+            });
+        }
+    });
+    (port, receiver)
+}
+
+/// 建立CONNECT隧道，收到200后分两次写入 `data`，模拟拆分到达的ClientHello
+async fn open_tunnel(proxy: &CProxy::TestProxy, target_port: u16, data: &[u8]) -> TcpStream {
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let request = format!(
+        "CONNECT 127.0.0.1:{0} HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\n\r\n",
+        target_port
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = [0u8; 39];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(
+        &response[..],
+        b"HTTP/1.1 200 Connection Established\r\n\r\n"
+    );
+
+    let (first, second) = data.split_at(data.len() / 2);
+    stream.write_all(first).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    stream.write_all(second).await.unwrap();
+    stream
+}
+
+/// 测试 `--require-sni` 时没有SNI的ClientHello被重置，携带SNI的隧道正常转发
+#[tokio::test]
+async fn test_tunnel_without_sni_is_reset() {
+    let (target_port, mut received) = start_target().await;
+    let config = CConfig::TestProxyConfig::new(
+        "require_sni".to_string(),
+        18152,
+        CConfig::ProxyProtocol::HttpsConnect,
+    );
+    let proxy =
+        CProxy::TestProxy::start_with_proxy(config, Proxy::new(None).with_require_sni(true)).await;
+
+    let without_sni = client_hello("127.0.0.1");
+    let mut stream = open_tunnel(&proxy, target_port, &without_sni).await;
+    let mut buffer = [0u8; 64];
+    let result = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buffer))
+        .await
+        .expect("等待连接关闭超时");
+    assert!(
+        matches!(result, Ok(0) | Err(_)),
+        "隧道未被关闭: {:?}",
+        result
+    );
+    // 目标连接已建立，但收不到任何数据
+    let data = tokio::time::timeout(Duration::from_secs(5), received.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(data.is_empty(), "目标收到数据: {:?}", data);
+
+    let with_sni = client_hello("localhost");
+    let _stream = open_tunnel(&proxy, target_port, &with_sni).await;
+    let data = tokio::time::timeout(Duration::from_secs(5), received.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(data, with_sni);
+
+    proxy.stop().await;
+}
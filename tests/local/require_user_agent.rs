@@ -0,0 +1,99 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use rust_proxy::proxy::Proxy;
+use rust_proxy::rejection::RejectionReason;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+async fn send(proxy: &CProxy::TestProxy, request: &str) -> String {
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    String::from_utf8_lossy(&response).to_string()
+}
+
+/// 测试启用后缺少User-Agent的请求返回403，带User-Agent的请求正常转发
+#[tokio::test]
+async fn test_missing_user_agent_rejected() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+    let addr = backend.addr();
+
+    let config = CConfig::TestProxyConfig::new(
+        "require_user_agent".to_string(),
+        18127,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy =
+        CProxy::TestProxy::start_with_proxy(config, Proxy::new(None).with_require_user_agent(true))
+            .await;
+
+    let response = send(
+        &proxy,
+        &format!("GET http://{0}/ HTTP/1.1\r\nHost: {0}\r\n\r\n", addr),
+    )
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 403 Forbidden"),
+        "响应: {}",
+        response
+    );
+    assert!(backend.requests().is_empty());
+
+    let response = send(
+        &proxy,
+        &format!(
+            "GET http://{0}/ HTTP/1.1\r\nHost: {0}\r\nUser-Agent: curl/8.0\r\n\r\n",
+            addr
+        ),
+    )
+    .await;
+    assert!(response.starts_with("HTTP/1.1 204"), "响应: {}", response);
+    assert_eq!(backend.requests().len(), 1);
+
+    proxy.stop().await;
+}
+
+/// 测试同一连接上缺少User-Agent的后续请求同样返回403并通知拒绝回调
+#[tokio::test]
+async fn test_missing_user_agent_rejected_on_keep_alive() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+    let addr = backend.addr();
+
+    let rejections = Arc::new(Mutex::new(Vec::new()));
+    let recorded = rejections.clone();
+    let config = CConfig::TestProxyConfig::new(
+        "require_user_agent_keep_alive".to_string(),
+        18167,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy = Proxy::new(None)
+        .with_require_user_agent(true)
+        .with_rejection_callback(Arc::new(move |_, reason| {
+            recorded.lock().unwrap().push(reason.clone());
+        }));
+    let proxy = CProxy::TestProxy::start_with_proxy(config, proxy).await;
+
+    let response = send(
+        &proxy,
+        &format!(
+            "GET http://{0}/first HTTP/1.1\r\nHost: {0}\r\nUser-Agent: curl/8.0\r\n\r\nGET http://{0}/second HTTP/1.1\r\nHost: {0}\r\n\r\n",
+            addr
+        ),
+    )
+    .await;
+    assert!(response.starts_with("HTTP/1.1 204"), "响应: {}", response);
+    assert!(
+        response.contains("\r\n\r\nHTTP/1.1 403 Forbidden"),
+        "响应: {}",
+        response
+    );
+    assert_eq!(backend.requests().len(), 1);
+    assert_eq!(
+        *rejections.lock().unwrap(),
+        vec![RejectionReason::MissingUserAgent]
+    );
+
+    proxy.stop().await;
+}
@@ -0,0 +1,132 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use rust_proxy::proxy::Proxy;
+use std::collections::HashMap;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+async fn get(proxy: &CProxy::TestProxy, host: &str) -> String {
+    send(proxy, &format!("Host: {}\r\n", host)).await
+}
+
+/// 发送带有给定头部行的请求，返回完整响应
+async fn send(proxy: &CProxy::TestProxy, headers: &str) -> String {
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let request = format!(
+        "GET /status HTTP/1.1\r\n{}Connection: close\r\n\r\n",
+        headers
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    String::from_utf8_lossy(&response).to_string()
+}
+
+/// 测试反向代理模式按Host将请求转发到对应后端，未配置的主机返回404
+#[tokio::test]
+async fn test_reverse_routes_by_host() {
+    let app = CBackend::MockBackend::start(
+        b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\nConnection: close\r\n\r\napp".to_vec(),
+    )
+    .await;
+    let api = CBackend::MockBackend::start(
+        b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\nConnection: close\r\n\r\napi".to_vec(),
+    )
+    .await;
+
+    let route = |backend: &CBackend::MockBackend| {
+        let addr = backend.addr();
+        (addr.ip().to_string(), addr.port())
+    };
+    let routes = HashMap::from([
+        ("app.internal".to_string(), route(&app)),
+        ("API.internal".to_string(), route(&api)),
+    ]);
+
+    let config =
+        CConfig::TestProxyConfig::new("reverse".to_string(), 18122, CConfig::ProxyProtocol::Http11);
+    let proxy =
+        CProxy::TestProxy::start_with_proxy(config, Proxy::new(None).with_routes(routes)).await;
+
+    let response = get(&proxy, "app.internal").await;
+    assert!(response.ends_with("app"), "响应: {}", response);
+
+    // 主机名不区分大小写，端口不参与匹配
+    let response = get(&proxy, "api.internal:8080").await;
+    assert!(response.ends_with("api"), "响应: {}", response);
+
+    let response = get(&proxy, "unknown.internal").await;
+    assert!(
+        response.starts_with("HTTP/1.1 404 Not Found"),
+        "响应: {}",
+        response
+    );
+
+    // 请求原样转发，保留原始Host
+    let requests = app.requests();
+    assert_eq!(requests.len(), 1);
+    let forwarded = String::from_utf8_lossy(&requests[0]);
+    assert!(
+        forwarded.starts_with("GET /status HTTP/1.1\r\n"),
+        "{}",
+        forwarded
+    );
+    assert!(
+        forwarded.contains("Host: app.internal\r\n"),
+        "{}",
+        forwarded
+    );
+    assert_eq!(api.requests().len(), 1);
+
+    proxy.stop().await;
+}
+
+/// 测试路由使用的Host头名不区分大小写，且不会被X-Forwarded-Host等其他头冒充
+#[tokio::test]
+async fn test_reverse_routes_by_host_header_only() {
+    let app = CBackend::MockBackend::start(
+        b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\nConnection: close\r\n\r\napp".to_vec(),
+    )
+    .await;
+    let admin = CBackend::MockBackend::start(
+        b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nadmin".to_vec(),
+    )
+    .await;
+
+    let route = |backend: &CBackend::MockBackend| {
+        let addr = backend.addr();
+        (addr.ip().to_string(), addr.port())
+    };
+    let routes = HashMap::from([
+        ("app.internal".to_string(), route(&app)),
+        ("admin.internal".to_string(), route(&admin)),
+    ]);
+
+    let config = CConfig::TestProxyConfig::new(
+        "reverse_host_header".to_string(),
+        18172,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy =
+        CProxy::TestProxy::start_with_proxy(config, Proxy::new(None).with_routes(routes)).await;
+
+    let response = send(&proxy, "host: app.internal\r\n").await;
+    assert!(response.ends_with("app"), "响应: {}", response);
+
+    let response = send(
+        &proxy,
+        "X-Forwarded-Host: admin.internal\r\nHost: app.internal\r\n",
+    )
+    .await;
+    assert!(response.ends_with("app"), "响应: {}", response);
+
+    let response = send(&proxy, "X-Forwarded-Host: admin.internal\r\n").await;
+    assert!(
+        response.starts_with("HTTP/1.1 404 Not Found"),
+        "响应: {}",
+        response
+    );
+    assert!(admin.requests().is_empty());
+
+    proxy.stop().await;
+}
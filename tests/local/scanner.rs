@@ -0,0 +1,55 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use rust_proxy::proxy::Proxy;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+async fn get(proxy: &CProxy::TestProxy, path: &str, host: &str) -> String {
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, host);
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    String::from_utf8_lossy(&response).to_string()
+}
+
+/// 测试扫描器路径直接返回配置的响应，不尝试转发
+#[tokio::test]
+async fn test_scanner_paths_not_forwarded() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+
+    let config =
+        CConfig::TestProxyConfig::new("scanner".to_string(), 18121, CConfig::ProxyProtocol::Http11);
+    let proxy = Proxy::new(None)
+        .with_deflect_scanners(true)
+        .with_scanner_body(Some("nothing here".to_string()));
+    let proxy = CProxy::TestProxy::start_with_proxy(config, proxy).await;
+
+    // Host指向其他地址（如代理的公网地址）时同样不转发
+    let backend_host = backend.addr().to_string();
+    let response = get(&proxy, "/.env", &backend_host).await;
+    assert!(
+        response.starts_with("HTTP/1.1 200 OK"),
+        "响应: {}",
+        response
+    );
+    assert!(response.ends_with("nothing here"), "响应: {}", response);
+
+    let response = get(&proxy, "/wp-admin/install.php", &backend_host).await;
+    assert!(response.ends_with("nothing here"), "响应: {}", response);
+
+    let response = get(&proxy, "/robots.txt", &proxy.address()).await;
+    assert!(
+        response.ends_with("User-agent: *\nDisallow: /\n"),
+        "响应: {}",
+        response
+    );
+    assert!(backend.requests().is_empty());
+
+    // 其他路径照常转发
+    let response = get(&proxy, "/index.html", &backend_host).await;
+    assert!(response.starts_with("HTTP/1.1 204"), "响应: {}", response);
+    assert_eq!(backend.requests().len(), 1);
+
+    proxy.stop().await;
+}
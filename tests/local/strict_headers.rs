@@ -0,0 +1,86 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use rust_proxy::proxy::Proxy;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+async fn send(proxy: &CProxy::TestProxy, request: &[u8]) -> String {
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    stream.write_all(request).await.unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    String::from_utf8_lossy(&response).to_string()
+}
+
+/// 测试严格模式下头部包含控制字符的请求返回400且不转发，请求体中的任意字节不受影响
+#[tokio::test]
+async fn test_invalid_header_bytes_rejected() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+    let addr = backend.addr();
+
+    let config = CConfig::TestProxyConfig::new(
+        "strict_headers".to_string(),
+        18123,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy =
+        CProxy::TestProxy::start_with_proxy(config, Proxy::new(None).with_strict_headers(true))
+            .await;
+
+    let request = format!(
+        "GET http://{0}/ HTTP/1.1\r\nHost: {0}\r\nX-Note: bad\x01value\r\n\r\n",
+        addr
+    );
+    let response = send(&proxy, request.as_bytes()).await;
+    assert!(
+        response.starts_with("HTTP/1.1 400 Bad Request"),
+        "响应: {}",
+        response
+    );
+    assert!(backend.requests().is_empty());
+
+    let mut request = format!(
+        "POST http://{0}/ HTTP/1.1\r\nHost: {0}\r\nContent-Length: 3\r\n\r\n",
+        addr
+    )
+    .into_bytes();
+    request.extend_from_slice(&[0x00, 0x01, 0xff]);
+    let response = send(&proxy, &request).await;
+    assert!(response.starts_with("HTTP/1.1 204"), "响应: {}", response);
+    assert_eq!(backend.requests().len(), 1);
+
+    proxy.stop().await;
+}
+
+/// 测试同一连接上的后续请求同样检查头部：第一个请求正常转发，第二个请求返回400
+#[tokio::test]
+async fn test_invalid_header_bytes_rejected_on_keep_alive() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+    let addr = backend.addr();
+
+    let config = CConfig::TestProxyConfig::new(
+        "strict_headers_keep_alive".to_string(),
+        18166,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy =
+        CProxy::TestProxy::start_with_proxy(config, Proxy::new(None).with_strict_headers(true))
+            .await;
+
+    let request = format!(
+        "GET http://{0}/first HTTP/1.1\r\nHost: {0}\r\n\r\nGET http://{0}/second HTTP/1.1\r\nHost: {0}\r\nX-Note: bad\x01value\r\n\r\n",
+        addr
+    );
+    let response = send(&proxy, request.as_bytes()).await;
+    assert!(response.starts_with("HTTP/1.1 204"), "响应: {}", response);
+    assert!(
+        response.contains("\r\n\r\nHTTP/1.1 400 Bad Request"),
+        "响应: {}",
+        response
+    );
+    let requests = backend.requests();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].starts_with(b"GET /first "));
+
+    proxy.stop().await;
+}
@@ -0,0 +1,39 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use rust_proxy::proxy::Proxy;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 测试启用 `--strip-forwarded` 时客户端发送的转发链头部不会到达源站
+#[tokio::test]
+async fn test_client_forwarded_headers_stripped() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+
+    let config = CConfig::TestProxyConfig::new(
+        "strip_forwarded".to_string(),
+        18144,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy =
+        CProxy::TestProxy::start_with_proxy(config, Proxy::new(None).with_strip_forwarded(true))
+            .await;
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let request = format!(
+        "GET http://127.0.0.1:{0}/ HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\nX-Forwarded-For: 203.0.113.7\r\nVia: 1.1 corp-proxy\r\nForwarded: for=203.0.113.7\r\nX-Keep: 1\r\n\r\n",
+        backend.port()
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    assert!(String::from_utf8_lossy(&response).starts_with("HTTP/1.1 204"));
+
+    let requests = backend.requests();
+    assert_eq!(requests.len(), 1);
+    let forwarded = String::from_utf8_lossy(&requests[0]).to_lowercase();
+    assert!(!forwarded.contains("203.0.113.7"), "{}", forwarded);
+    assert!(!forwarded.contains("via:"), "{}", forwarded);
+    assert!(forwarded.contains("x-keep: 1"), "{}", forwarded);
+
+    proxy.stop().await;
+}
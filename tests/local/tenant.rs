@@ -0,0 +1,75 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use rust_proxy::metrics::metrics;
+use rust_proxy::proxy::Proxy;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+async fn get(proxy: &CProxy::TestProxy, backend: &CBackend::MockBackend, tenant: &str) {
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let request = format!(
+        "GET http://{0}/ HTTP/1.1\r\nHost: {0}\r\nX-Tenant-Id: {1}\r\n\r\n",
+        backend.addr(),
+        tenant
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    stream.shutdown().await.unwrap();
+    assert!(String::from_utf8_lossy(&response).starts_with("HTTP/1.1 204"));
+}
+
+/// 转发结束后字节数才计入指标，等待直到指标非零
+async fn wait_for_metric(name: &str, tenant: &str) -> u64 {
+    for _ in 0..50 {
+        match tenant_metric(&metrics().render(), name, tenant) {
+            Some(value) if value > 0 => return value,
+            _ => {}
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    panic!("缺少指标 {} (租户 {})", name, tenant);
+}
+
+fn tenant_metric(output: &str, name: &str, tenant: &str) -> Option<u64> {
+    let prefix = format!("{}{{tenant=\"{}\"}} ", name, tenant);
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix(&prefix)?.parse().ok())
+}
+
+/// 测试受信任客户端设置的租户计入按租户的请求数和字节数，不受信任的客户端无法设置
+#[tokio::test]
+async fn test_trusted_client_tags_tenant() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+
+    let config =
+        CConfig::TestProxyConfig::new("tenant".to_string(), 18125, CConfig::ProxyProtocol::Http11);
+    let proxy = Proxy::new(None).with_trusted_proxies(vec!["127.0.0.0/8".parse().unwrap()]);
+    let trusted = CProxy::TestProxy::start_with_proxy(config, proxy).await;
+
+    get(&trusted, &backend, "tenant-acme").await;
+    let output = metrics().render();
+    assert_eq!(
+        tenant_metric(&output, "rust_proxy_tenant_requests_total", "tenant-acme"),
+        Some(1),
+        "{}",
+        output
+    );
+    assert!(wait_for_metric("rust_proxy_tenant_bytes_out_total", "tenant-acme").await > 0);
+    trusted.stop().await;
+
+    let config =
+        CConfig::TestProxyConfig::new("tenant".to_string(), 18125, CConfig::ProxyProtocol::Http11);
+    let untrusted = CProxy::TestProxy::start(config).await;
+    get(&untrusted, &backend, "tenant-mallory").await;
+    let output = metrics().render();
+    assert_eq!(
+        tenant_metric(
+            &output,
+            "rust_proxy_tenant_requests_total",
+            "tenant-mallory"
+        ),
+        None
+    );
+    untrusted.stop().await;
+}
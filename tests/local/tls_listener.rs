@@ -0,0 +1,214 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use rcgen::CertifiedKey;
+use rust_proxy::proxy::Proxy;
+use rust_proxy::tls::{self, MinTlsVersion};
+use rustls::pki_types::ServerName;
+use rustls::version::{TLS12, TLS13};
+use rustls::{ClientConfig, RootCertStore, ServerConfig, SupportedProtocolVersion};
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+
+/// 将自签名证书写入临时文件，按命令行配置的方式加载监听端TLS配置
+fn listener_config(
+    certified: &CertifiedKey,
+    alpn: &[&str],
+    min_version: MinTlsVersion,
+    name: &str,
+) -> Arc<ServerConfig> {
+    let dir = std::env::temp_dir();
+    let cert_path = dir.join(format!(
+        "rust_proxy_{}_{}_cert.pem",
+        std::process::id(),
+        name
+    ));
+    let key_path = dir.join(format!(
+        "rust_proxy_{}_{}_key.pem",
+        std::process::id(),
+        name
+    ));
+    std::fs::write(&cert_path, certified.cert.pem()).unwrap();
+    std::fs::write(&key_path, certified.key_pair.serialize_pem()).unwrap();
+    let alpn: Vec<String> = alpn.iter().map(|protocol| protocol.to_string()).collect();
+    let server_config = tls::server_config(&cert_path, &key_path, &alpn, min_version).unwrap();
+    std::fs::remove_file(&cert_path).unwrap();
+    std::fs::remove_file(&key_path).unwrap();
+    server_config
+}
+
+/// 以给定的ALPN列表与监听端握手，返回协商出的协议
+async fn negotiate(
+    certified: &CertifiedKey,
+    port: u16,
+    offered: &[&str],
+) -> std::io::Result<Option<Vec<u8>>> {
+    let ca_path =
+        std::env::temp_dir().join(format!("rust_proxy_{}_alpn_ca.pem", std::process::id()));
+    std::fs::write(&ca_path, certified.cert.pem()).unwrap();
+    let mut client_config = (*tls::client_config(Some(&ca_path), false).unwrap()).clone();
+    std::fs::remove_file(&ca_path).unwrap();
+    client_config.alpn_protocols = offered
+        .iter()
+        .map(|protocol| protocol.as_bytes().to_vec())
+        .collect();
+
+    let stream = TcpStream::connect(("127.0.0.1", port)).await?;
+    let stream = TlsConnector::from(Arc::new(client_config))
+        .connect(ServerName::try_from("localhost").unwrap(), stream)
+        .await?;
+    Ok(stream.get_ref().1.alpn_protocol().map(<[u8]>::to_vec))
+}
+
+/// 测试客户端以 `https://` 代理地址接入，经TLS监听端代理明文HTTP请求
+#[tokio::test]
+async fn test_https_proxy_listener() {
+    let backend = CBackend::MockBackend::start(
+        b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello".to_vec(),
+    )
+    .await;
+
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let server_config =
+        listener_config(&certified, &["http/1.1"], MinTlsVersion::Tls12, "listener");
+
+    let config = CConfig::TestProxyConfig::new(
+        "tls_listener".to_string(),
+        18131,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy =
+        CProxy::TestProxy::start_with_proxy(config, Proxy::new(None).with_tls(Some(server_config)))
+            .await;
+
+    let client = reqwest::Client::builder()
+        .proxy(reqwest::Proxy::all(format!("https://localhost:{}", proxy.port())).unwrap())
+        .add_root_certificate(
+            reqwest::Certificate::from_pem(certified.cert.pem().as_bytes()).unwrap(),
+        )
+        .build()
+        .unwrap();
+    let response = client
+        .get(format!("http://127.0.0.1:{}/through-tls", backend.port()))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await.unwrap(), "hello");
+
+    let requests = backend.requests();
+    assert_eq!(requests.len(), 1);
+    let request = String::from_utf8_lossy(&requests[0]);
+    assert!(request.contains("/through-tls"), "{}", request);
+
+    proxy.stop().await;
+}
+
+/// 测试监听端按配置的ALPN优先级协商，客户端只提供不支持的协议时握手失败
+#[tokio::test]
+async fn test_tls_alpn_preference() {
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let config = CConfig::TestProxyConfig::new(
+        "tls_alpn".to_string(),
+        18132,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let server_config = listener_config(
+        &certified,
+        &["http/1.1", "h2"],
+        MinTlsVersion::Tls12,
+        "alpn",
+    );
+    let proxy =
+        CProxy::TestProxy::start_with_proxy(config, Proxy::new(None).with_tls(Some(server_config)))
+            .await;
+
+    // 按服务端的优先级选择，而不是客户端的
+    let negotiated = negotiate(&certified, proxy.port(), &["h2", "http/1.1"])
+        .await
+        .unwrap();
+    assert_eq!(negotiated.as_deref(), Some(&b"http/1.1"[..]));
+    let negotiated = negotiate(&certified, proxy.port(), &["h2"]).await.unwrap();
+    assert_eq!(negotiated.as_deref(), Some(&b"h2"[..]));
+
+    // 未提供ALPN的客户端照常握手
+    let negotiated = negotiate(&certified, proxy.port(), &[]).await.unwrap();
+    assert_eq!(negotiated, None);
+
+    assert!(negotiate(&certified, proxy.port(), &["spdy/3"])
+        .await
+        .is_err());
+
+    proxy.stop().await;
+}
+
+/// 以限定的TLS版本与监听端握手
+async fn handshake(
+    certified: &CertifiedKey,
+    port: u16,
+    versions: &[&'static SupportedProtocolVersion],
+) -> std::io::Result<()> {
+    let mut roots = RootCertStore::empty();
+    roots.add(certified.cert.der().clone()).unwrap();
+    let client_config =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_protocol_versions(versions)
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+    let stream = TcpStream::connect(("127.0.0.1", port)).await?;
+    TlsConnector::from(Arc::new(client_config))
+        .connect(ServerName::try_from("localhost").unwrap(), stream)
+        .await?;
+    Ok(())
+}
+
+/// 测试监听端拒绝低于最低版本的TLS客户端，默认接受TLS 1.2及以上
+#[tokio::test]
+async fn test_min_tls_version() {
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+
+    let config = CConfig::TestProxyConfig::new(
+        "tls_min_version_default".to_string(),
+        18185,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let server_config = listener_config(
+        &certified,
+        &["http/1.1"],
+        MinTlsVersion::default(),
+        "min_version_default",
+    );
+    let proxy =
+        CProxy::TestProxy::start_with_proxy(config, Proxy::new(None).with_tls(Some(server_config)))
+            .await;
+    handshake(&certified, proxy.port(), &[&TLS12])
+        .await
+        .unwrap();
+    handshake(&certified, proxy.port(), &[&TLS13])
+        .await
+        .unwrap();
+    proxy.stop().await;
+
+    let config = CConfig::TestProxyConfig::new(
+        "tls_min_version_13".to_string(),
+        18186,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let server_config = listener_config(
+        &certified,
+        &["http/1.1"],
+        MinTlsVersion::Tls13,
+        "min_version_13",
+    );
+    let proxy =
+        CProxy::TestProxy::start_with_proxy(config, Proxy::new(None).with_tls(Some(server_config)))
+            .await;
+    assert!(handshake(&certified, proxy.port(), &[&TLS12])
+        .await
+        .is_err());
+    handshake(&certified, proxy.port(), &[&TLS13])
+        .await
+        .unwrap();
+    proxy.stop().await;
+}
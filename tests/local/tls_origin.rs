@@ -0,0 +1,140 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use rcgen::CertifiedKey;
+use rust_proxy::handlers::backend::BackendConnector;
+use rust_proxy::proxy::Proxy;
+use rust_proxy::tls;
+use rustls::crypto::ring;
+use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+use rustls::{ClientConfig, ServerConfig};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio_rustls::LazyConfigAcceptor;
+
+/// 启动使用自签名证书的HTTPS源站
+///
+/// 源站处理一个请求，返回ClientHello中的SNI以及收到的请求
+async fn start_origin(certified: &CertifiedKey) -> (u16, JoinHandle<(Option<String>, Vec<u8>)>) {
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der()));
+    let server_config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(vec![certified.cert.der().clone()], key)
+        .unwrap();
+    let server_config = Arc::new(server_config);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let handle = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let start = LazyConfigAcceptor::new(rustls::server::Acceptor::default(), stream)
+            .await
+            .unwrap();
+        let sni = start.client_hello().server_name().map(str::to_string);
+        let mut stream = start.into_stream(server_config).await.unwrap();
+
+        let request = CBackend::read_request(&mut stream).await;
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\nConnection: close\r\n\r\nsecure")
+            .await
+            .unwrap();
+        stream.shutdown().await.unwrap();
+        (sni, request)
+    });
+
+    (port, handle)
+}
+
+/// 构建信任该自签名证书的TLS客户端配置
+fn trusting(certified: &CertifiedKey, name: &str) -> Arc<ClientConfig> {
+    let ca_path =
+        std::env::temp_dir().join(format!("rust_proxy_{}_{}_ca.pem", std::process::id(), name));
+    std::fs::write(&ca_path, certified.cert.pem()).unwrap();
+    let client_config = tls::client_config(Some(&ca_path), false).unwrap();
+    std::fs::remove_file(&ca_path).unwrap();
+    client_config
+}
+
+/// 经代理发送明文请求并读取完整响应
+async fn request_through(proxy: &CProxy::TestProxy, host: &str, port: u16) -> String {
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let request = format!(
+        "GET https://{0}:{1}/secure HTTP/1.1\r\nHost: {0}:{1}\r\n\r\n",
+        host, port
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    String::from_utf8_lossy(&response).to_string()
+}
+
+/// 测试明文客户端请求经代理以TLS转发到HTTPS源站
+#[tokio::test]
+async fn test_plaintext_request_to_https_origin() {
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let (origin_port, origin) = start_origin(&certified).await;
+
+    let config = CConfig::TestProxyConfig::new(
+        "tls_origin".to_string(),
+        18110,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let connector = BackendConnector::new().with_tls(Some(trusting(&certified, "origin")));
+    let proxy =
+        CProxy::TestProxy::start_with_proxy(config, Proxy::new(None).with_connector(connector))
+            .await;
+
+    let response = request_through(&proxy, "localhost", origin_port).await;
+    assert!(
+        response.starts_with("HTTP/1.1 200 OK"),
+        "响应: {}",
+        response
+    );
+    assert!(response.ends_with("secure"), "响应: {}", response);
+
+    let (sni, received) = origin.await.unwrap();
+    assert_eq!(sni.as_deref(), Some("localhost"));
+    let received = String::from_utf8(received).unwrap();
+    assert!(received.contains("/secure"), "{}", received);
+
+    proxy.stop().await;
+}
+
+/// 测试按目标主机覆盖源站TLS握手使用的SNI
+#[tokio::test]
+async fn test_outbound_sni_override() {
+    let certified = rcgen::generate_simple_self_signed(vec!["origin.test".to_string()]).unwrap();
+    let (origin_port, origin) = start_origin(&certified).await;
+
+    let config = CConfig::TestProxyConfig::new(
+        "tls_sni_override".to_string(),
+        18115,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let connector = BackendConnector::new()
+        .with_tls(Some(trusting(&certified, "sni_override")))
+        .with_sni_overrides(HashMap::from([(
+            "127.0.0.1".to_string(),
+            "origin.test".to_string(),
+        )]));
+    let proxy =
+        CProxy::TestProxy::start_with_proxy(config, Proxy::new(None).with_connector(connector))
+            .await;
+
+    // 按IP连接，握手时使用覆盖后的SNI并按其校验证书
+    let response = request_through(&proxy, "127.0.0.1", origin_port).await;
+    assert!(
+        response.starts_with("HTTP/1.1 200 OK"),
+        "响应: {}",
+        response
+    );
+
+    let (sni, _) = origin.await.unwrap();
+    assert_eq!(sni.as_deref(), Some("origin.test"));
+
+    proxy.stop().await;
+}
@@ -0,0 +1,41 @@
+use crate::common::CBackend;
+use rust_proxy::proxy::Proxy;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::Semaphore;
+
+/// 测试通过Unix域套接字接入的客户端可以正常代理HTTP请求
+#[tokio::test]
+async fn test_unix_socket_listener() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+    let addr = backend.addr();
+
+    let path = std::env::temp_dir().join(format!("rust_proxy_test_{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(Proxy::new(None).serve_unix_with_shutdown(
+        listener,
+        Arc::new(Semaphore::new(10)),
+        async {
+            let _ = shutdown_rx.await;
+        },
+    ));
+
+    let mut stream = UnixStream::connect(&path).await.unwrap();
+    stream
+        .write_all(format!("GET http://{0}/ HTTP/1.1\r\nHost: {0}\r\n\r\n", addr).as_bytes())
+        .await
+        .unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    let response = String::from_utf8_lossy(&response);
+    assert!(response.starts_with("HTTP/1.1 204"), "响应: {}", response);
+    assert_eq!(backend.requests().len(), 1);
+
+    let _ = shutdown_tx.send(());
+    server.await.unwrap().unwrap();
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,134 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use rust_proxy::handlers::backend::BackendConnector;
+use rust_proxy::proxy::Proxy;
+use rust_proxy::upstream::UpstreamProxy;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 启动需要认证的上游代理，以及使用 `password` 经由它转发的前置代理
+async fn start_chain(
+    upstream_port: u16,
+    front_port: u16,
+    password: &str,
+) -> (CProxy::TestProxy, CProxy::TestProxy) {
+    let upstream_config = CConfig::TestProxyConfig::new(
+        "upstream_parent".to_string(),
+        upstream_port,
+        CConfig::ProxyProtocol::Http11,
+    )
+    .with_auth("parent".to_string(), "secret".to_string());
+    let upstream = CProxy::TestProxy::start(upstream_config).await;
+
+    let front_config = CConfig::TestProxyConfig::new(
+        "upstream_front".to_string(),
+        front_port,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let parent: UpstreamProxy = format!("http://parent:{}@{}", password, upstream.address())
+        .parse()
+        .unwrap();
+    let connector = BackendConnector::new().with_upstream(Some(parent));
+    let front = CProxy::TestProxy::start_with_proxy(
+        front_config,
+        Proxy::new(None).with_connector(connector),
+    )
+    .await;
+
+    (upstream, front)
+}
+
+/// 测试明文HTTP请求以绝对URI形式经上游代理转发
+#[tokio::test]
+async fn test_http_request_through_upstream() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+    let (upstream, front) = start_chain(18106, 18107, "secret").await;
+
+    let mut stream = TcpStream::connect(front.address()).await.unwrap();
+    let request = format!(
+        "GET /path HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nConnection: close\r\n\r\n",
+        backend.port()
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    let response = String::from_utf8_lossy(&response);
+    assert!(response.starts_with("HTTP/1.1 204"), "响应: {}", response);
+
+    let requests = backend.requests();
+    assert_eq!(requests.len(), 1);
+    // 上游代理收到绝对URI形式的请求，转发给源站时再改写为源站形式
+    let forwarded = String::from_utf8_lossy(&requests[0]);
+    assert!(
+        forwarded.starts_with("GET /path HTTP/1.1\r\n"),
+        "{}",
+        forwarded
+    );
+    assert!(!forwarded.to_lowercase().contains("proxy-authorization"));
+
+    front.stop().await;
+    upstream.stop().await;
+}
+
+/// 测试 CONNECT 隧道经上游代理建立
+#[tokio::test]
+async fn test_connect_through_upstream() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+    let (upstream, front) = start_chain(18108, 18109, "secret").await;
+
+    let mut stream = TcpStream::connect(front.address()).await.unwrap();
+    let request = format!(
+        "CONNECT 127.0.0.1:{0} HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\n\r\n",
+        backend.port()
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut established = [0u8; 39];
+    stream.read_exact(&mut established).await.unwrap();
+    assert_eq!(&established, b"HTTP/1.1 200 Connection Established\r\n\r\n");
+
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    assert!(String::from_utf8_lossy(&response).starts_with("HTTP/1.1 204"));
+    assert_eq!(backend.requests().len(), 1);
+
+    front.stop().await;
+    upstream.stop().await;
+}
+
+/// 测试上游代理要求的认证失败时，客户端收到502而不是407
+#[tokio::test]
+async fn test_connect_upstream_auth_failure() {
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+    let (upstream, front) = start_chain(18134, 18135, "wrong").await;
+
+    let mut stream = TcpStream::connect(front.address()).await.unwrap();
+    let request = format!(
+        "CONNECT 127.0.0.1:{0} HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\n\r\n",
+        backend.port()
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    let response = String::from_utf8_lossy(&response);
+    assert!(
+        response.starts_with("HTTP/1.1 502 Bad Gateway"),
+        "响应: {}",
+        response
+    );
+    assert!(response.contains("上游代理认证失败"), "响应: {}", response);
+    assert!(
+        !response.contains("Proxy-Authenticate"),
+        "响应: {}",
+        response
+    );
+    assert!(backend.requests().is_empty());
+
+    front.stop().await;
+    upstream.stop().await;
+}
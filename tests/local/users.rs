@@ -0,0 +1,70 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use base64::Engine;
+use rust_proxy::auth::AuthConfig;
+use rust_proxy::proxy::Proxy;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+async fn request_as(
+    proxy: &CProxy::TestProxy,
+    backend_port: u16,
+    user: &str,
+    pass: &str,
+) -> String {
+    let credentials =
+        base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", user, pass));
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let request = format!(
+        "GET http://127.0.0.1:{0}/ HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\nProxy-Authorization: Basic {1}\r\n\r\n",
+        backend_port, credentials
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    String::from_utf8_lossy(&response).to_string()
+}
+
+/// 测试用户文件中的每个账号都能通过认证，未知账号收到407
+#[tokio::test]
+async fn test_users_file_accounts() {
+    let path = std::env::temp_dir().join(format!("rust_proxy_{}_users", std::process::id()));
+    std::fs::write(
+        &path,
+        "# 团队账号\nalice:wonderland\n\nbob:builder\ncarol:singer\n",
+    )
+    .unwrap();
+    let auth = AuthConfig::from_users_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(auth.user_count(), 3);
+
+    let backend = CBackend::MockBackend::start(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()).await;
+    let config = CConfig::TestProxyConfig::new(
+        "users_file".to_string(),
+        18116,
+        CConfig::ProxyProtocol::Http11,
+    );
+    let proxy = CProxy::TestProxy::start_with_proxy(config, Proxy::new(Some(auth))).await;
+
+    for (user, pass) in [
+        ("alice", "wonderland"),
+        ("bob", "builder"),
+        ("carol", "singer"),
+    ] {
+        let response = request_as(&proxy, backend.port(), user, pass).await;
+        assert!(
+            response.starts_with("HTTP/1.1 204"),
+            "{}: {}",
+            user,
+            response
+        );
+    }
+
+    for (user, pass) in [("mallory", "wonderland"), ("alice", "builder")] {
+        let response = request_as(&proxy, backend.port(), user, pass).await;
+        assert!(response.contains(" 407 "), "{}: {}", user, response);
+    }
+    assert_eq!(backend.requests().len(), 3);
+
+    proxy.stop().await;
+}
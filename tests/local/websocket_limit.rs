@@ -0,0 +1,75 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use rust_proxy::proxy::Proxy;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// 启动接受所有升级并保持连接的WebSocket后端
+async fn start_websocket_backend() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                CBackend::read_request(&mut stream).await;
+                let _ = stream
+                    .write_all(b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\r\n")
+                    .await;
+                let mut buffer = [0u8; 1024];
+                while let Ok(n) = stream.read(&mut buffer).await {
+                    if n == 0 {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    port
+}
+
+/// 发送WebSocket升级请求并读取响应头
+async fn upgrade(proxy: &CProxy::TestProxy, backend_port: u16) -> (TcpStream, String) {
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let request = format!(
+        "GET ws://127.0.0.1:{0}/chat HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        backend_port
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let response = CBackend::read_request(&mut stream).await;
+    (stream, String::from_utf8_lossy(&response).to_string())
+}
+
+/// 测试WebSocket会话达到上限后，新的升级请求在101之前被503拒绝
+#[tokio::test]
+async fn test_websocket_sessions_capped() {
+    let backend_port = start_websocket_backend().await;
+
+    let config = CConfig::TestProxyConfig::new(
+        "websocket_limit".to_string(),
+        18120,
+        CConfig::ProxyProtocol::WebSocket,
+    );
+    let proxy = CProxy::TestProxy::start_with_proxy(
+        config,
+        Proxy::new(None).with_max_websocket_sessions(Some(2)),
+    )
+    .await;
+
+    let mut sessions = Vec::new();
+    for _ in 0..2 {
+        let (stream, response) = upgrade(&proxy, backend_port).await;
+        assert!(response.starts_with("HTTP/1.1 101"), "响应: {}", response);
+        sessions.push(stream);
+    }
+
+    let (_, response) = upgrade(&proxy, backend_port).await;
+    assert!(response.starts_with("HTTP/1.1 503"), "响应: {}", response);
+
+    // 会话结束后许可被释放
+    drop(sessions.pop());
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    let (_, response) = upgrade(&proxy, backend_port).await;
+    assert!(response.starts_with("HTTP/1.1 101"), "响应: {}", response);
+
+    proxy.stop().await;
+}
@@ -0,0 +1,210 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use rust_proxy::proxy::Proxy;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+
+/// 启动协商 `chat.v2` 子协议的WebSocket后端，返回端口和收到的升级请求
+///
+/// 101响应头部分两次写出，随后紧跟一个文本帧
+async fn start_websocket_backend() -> (u16, oneshot::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (sender, receiver) = oneshot::channel();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let request = CBackend::read_request(&mut stream).await;
+        let _ = sender.send(String::from_utf8_lossy(&request).into_owned());
+        stream
+            .write_all(b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n")
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        stream
+            .write_all(b"Connection: Upgrade\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\nSec-WebSocket-Protocol: chat.v2\r\n\r\n\x81\x02hi")
+            .await
+            .unwrap();
+        let mut buffer = [0u8; 1024];
+        while let Ok(n) = stream.read(&mut buffer).await {
+            if n == 0 {
+                break;
+            }
+        }
+    });
+    (port, receiver)
+}
+
+/// 测试客户端的子协议、扩展及其他头部原样到达源站，源站协商的子协议经代理返回给客户端
+#[tokio::test]
+async fn test_subprotocol_survives_proxy() {
+    let (backend_port, request) = start_websocket_backend().await;
+
+    let config = CConfig::TestProxyConfig::new(
+        "websocket_passthrough".to_string(),
+        18164,
+        CConfig::ProxyProtocol::WebSocket,
+    );
+    let proxy = CProxy::TestProxy::start(config).await;
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let upgrade = format!(
+        "GET ws://127.0.0.1:{0}/chat?room=1 HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\nSec-WebSocket-Protocol: chat.v1, chat.v2\r\nSec-WebSocket-Extensions: permessage-deflate\r\nOrigin: http://example.com\r\nCookie: session=abc\r\nProxy-Authorization: Basic dXNlcjpwYXNz\r\n\r\n",
+        backend_port
+    );
+    stream.write_all(upgrade.as_bytes()).await.unwrap();
+
+    let data = CBackend::read_request(&mut stream).await;
+    let head_end = data.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    let response = String::from_utf8_lossy(&data[..head_end]);
+    assert!(response.starts_with("HTTP/1.1 101"), "响应: {}", response);
+    assert!(
+        response.contains("\r\nSec-WebSocket-Protocol: chat.v2\r\n"),
+        "响应: {}",
+        response
+    );
+    assert!(
+        response.contains("\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"),
+        "响应: {}",
+        response
+    );
+    // 101之后的帧照常转发，可能与响应头部一同读到
+    let mut frame = data[head_end..].to_vec();
+    let mut rest = vec![0u8; 4 - frame.len()];
+    tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut rest))
+        .await
+        .expect("等待WebSocket帧超时")
+        .unwrap();
+    frame.extend_from_slice(&rest);
+    assert_eq!(frame, b"\x81\x02hi");
+
+    let forwarded = tokio::time::timeout(Duration::from_secs(5), request)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(
+        forwarded.starts_with("GET /chat?room=1 HTTP/1.1\r\n"),
+        "{}",
+        forwarded
+    );
+    for header in [
+        "Sec-WebSocket-Protocol: chat.v1, chat.v2",
+        "Sec-WebSocket-Extensions: permessage-deflate",
+        "Origin: http://example.com",
+        "Cookie: session=abc",
+        "Upgrade: websocket",
+        "Connection: upgrade",
+    ] {
+        assert!(forwarded.contains(header), "缺少 {}: {}", header, forwarded);
+    }
+    assert!(!forwarded.contains("Proxy-Authorization"), "{}", forwarded);
+
+    proxy.stop().await;
+}
+
+/// 测试源站不可达时WebSocket升级请求收到带 `Content-Length` 和说明正文的502
+#[tokio::test]
+async fn test_unreachable_origin_error_has_body() {
+    let config = CConfig::TestProxyConfig::new(
+        "websocket_error_body".to_string(),
+        18181,
+        CConfig::ProxyProtocol::WebSocket,
+    );
+    let proxy = CProxy::TestProxy::start(config).await;
+
+    // 占用后立即释放一个端口，确保目标拒绝连接
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let refused_port = listener.local_addr().unwrap().port();
+    drop(listener);
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let upgrade = format!(
+        "GET ws://127.0.0.1:{0}/chat HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        refused_port
+    );
+    stream.write_all(upgrade.as_bytes()).await.unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    let response = String::from_utf8(response).unwrap();
+    assert!(
+        response.starts_with("HTTP/1.1 502 Bad Gateway\r\n"),
+        "响应: {}",
+        response
+    );
+    assert!(
+        response.contains("Connection: close\r\n"),
+        "响应: {}",
+        response
+    );
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let body_len = format!("Content-Length: {}\r\n", body.len());
+    assert!(head.contains(body_len.trim_end()), "响应: {}", response);
+    assert!(
+        body.contains(&refused_port.to_string()),
+        "响应: {}",
+        response
+    );
+
+    proxy.stop().await;
+}
+
+/// 测试启用关闭帧时，代理停止接受新连接后向仍在转发的WebSocket连接两端发送关闭帧
+#[tokio::test]
+async fn test_shutdown_sends_close_frames() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_port = listener.local_addr().unwrap().port();
+    let backend = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        CBackend::read_request(&mut stream).await;
+        stream
+            .write_all(b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n")
+            .await
+            .unwrap();
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).await.unwrap();
+        received
+    });
+
+    let config = CConfig::TestProxyConfig::new(
+        "websocket_shutdown".to_string(),
+        18187,
+        CConfig::ProxyProtocol::WebSocket,
+    );
+    let proxy = CProxy::TestProxy::start_with_proxy(
+        config,
+        Proxy::new(None).with_websocket_close_frame(true),
+    )
+    .await;
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let upgrade = format!(
+        "GET ws://127.0.0.1:{0}/chat HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        backend_port
+    );
+    stream.write_all(upgrade.as_bytes()).await.unwrap();
+    let response = CBackend::read_request(&mut stream).await;
+    assert!(
+        response.starts_with(b"HTTP/1.1 101"),
+        "响应: {}",
+        String::from_utf8_lossy(&response)
+    );
+
+    proxy.stop().await;
+
+    // 发往客户端的关闭帧不加掩码，发往源站的加掩码，状态码都是1001
+    let mut frame = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut frame))
+        .await
+        .expect("等待关闭帧超时")
+        .unwrap();
+    assert_eq!(frame, [0x88, 0x02, 0x03, 0xe9]);
+    let frame = tokio::time::timeout(Duration::from_secs(5), backend)
+        .await
+        .expect("等待关闭帧超时")
+        .unwrap();
+    assert_eq!(frame.len(), 8);
+    assert_eq!(frame[..2], [0x88, 0x82]);
+    let code = [frame[6] ^ frame[2], frame[7] ^ frame[3]];
+    assert_eq!(u16::from_be_bytes(code), 1001);
+}
@@ -0,0 +1,104 @@
+use crate::common::{CBackend, CConfig, CProxy};
+use rcgen::CertifiedKey;
+use rust_proxy::handlers::backend::BackendConnector;
+use rust_proxy::proxy::Proxy;
+use rust_proxy::tls;
+use rustls::crypto::ring;
+use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+use rustls::ServerConfig;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio_rustls::TlsAcceptor;
+
+/// 启动使用自签名证书的wss回显源站，返回端口和收到的升级请求
+///
+/// 源站接受升级后原样回显收到的数据
+async fn start_echo_origin(certified: &CertifiedKey) -> (u16, JoinHandle<String>) {
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der()));
+    let server_config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(vec![certified.cert.der().clone()], key)
+        .unwrap();
+    let acceptor = TlsAcceptor::from(Arc::new(server_config));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let handle = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut stream = acceptor.accept(stream).await.unwrap();
+        let request = CBackend::read_request(&mut stream).await;
+        stream
+            .write_all(b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n")
+            .await
+            .unwrap();
+        stream.flush().await.unwrap();
+        let mut buffer = [0u8; 1024];
+        while let Ok(n) = stream.read(&mut buffer).await {
+            if n == 0 || stream.write_all(&buffer[..n]).await.is_err() {
+                break;
+            }
+            let _ = stream.flush().await;
+        }
+        String::from_utf8_lossy(&request).into_owned()
+    });
+
+    (port, handle)
+}
+
+/// 测试wss升级请求经TLS连接源站，升级后数据双向转发
+#[tokio::test]
+async fn test_secure_upgrade_to_tls_origin() {
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let (origin_port, origin) = start_echo_origin(&certified).await;
+
+    let ca_path = std::env::temp_dir().join(format!(
+        "rust_proxy_{}_websocket_tls_ca.pem",
+        std::process::id()
+    ));
+    std::fs::write(&ca_path, certified.cert.pem()).unwrap();
+    let client_config = tls::client_config(Some(&ca_path), false).unwrap();
+    std::fs::remove_file(&ca_path).unwrap();
+
+    let config = CConfig::TestProxyConfig::new(
+        "websocket_tls".to_string(),
+        18165,
+        CConfig::ProxyProtocol::WebSocket,
+    );
+    let connector = BackendConnector::new().with_tls(Some(client_config));
+    let proxy =
+        CProxy::TestProxy::start_with_proxy(config, Proxy::new(None).with_connector(connector))
+            .await;
+
+    let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+    let upgrade = format!(
+        "GET wss://localhost:{0}/echo HTTP/1.1\r\nHost: localhost:{0}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        origin_port
+    );
+    stream.write_all(upgrade.as_bytes()).await.unwrap();
+
+    let response = CBackend::read_request(&mut stream).await;
+    let response = String::from_utf8_lossy(&response);
+    assert!(response.starts_with("HTTP/1.1 101"), "响应: {}", response);
+
+    stream.write_all(b"\x81\x02hi").await.unwrap();
+    let mut echoed = [0u8; 4];
+    tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut echoed))
+        .await
+        .expect("等待回显超时")
+        .unwrap();
+    assert_eq!(&echoed, b"\x81\x02hi");
+    drop(stream);
+
+    let request = tokio::time::timeout(Duration::from_secs(5), origin)
+        .await
+        .expect("源站未结束")
+        .unwrap();
+    assert!(request.starts_with("GET /echo HTTP/1.1\r\n"), "{}", request);
+
+    proxy.stop().await;
+}
@@ -18,6 +18,63 @@ mod auth {
     mod websocket;
 }
 
+// Local tests（使用本地模拟后端，不依赖外部网络）
+mod local {
+    mod accept_workers;
+    mod access_log;
+    mod access_rules;
+    mod allowed_methods;
+    mod auth_exempt;
+    mod bad_request;
+    mod byte_counts;
+    mod check;
+    mod chunked_upload;
+    mod compression;
+    mod connect;
+    mod connection_pool;
+    mod dispatch;
+    mod forwarded_headers;
+    mod gateway_timeout;
+    mod generic_upgrade;
+    mod handshake_timeout;
+    mod healthz;
+    mod hop_by_hop;
+    mod http10_close;
+    mod initial_read_size;
+    mod ipv6;
+    mod keep_alive;
+    mod landing;
+    mod listen;
+    mod metrics;
+    mod missing_host;
+    mod origin_form;
+    mod pipeline_order;
+    mod pipelining;
+    mod proxy_connection;
+    mod proxy_loop;
+    mod proxy_protocol;
+    mod readiness;
+    mod rejection;
+    mod reload;
+    mod request_id;
+    mod require_sni;
+    mod require_user_agent;
+    mod reverse;
+    mod scanner;
+    mod strict_headers;
+    mod strip_forwarded;
+    mod tenant;
+    mod tls_listener;
+    mod tls_origin;
+    #[cfg(unix)]
+    mod unix_socket;
+    mod upstream;
+    mod users;
+    mod websocket_limit;
+    mod websocket_passthrough;
+    mod websocket_tls;
+}
+
 // Std tests
 mod std {
     mod config;
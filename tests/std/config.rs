@@ -40,7 +40,7 @@ impl ProxyConfig {
 
         // 读取用户名（可选）
         let username = std::env::var("PROXY_USERNAME").ok();
-        let username = if username.as_ref().map_or(false, |s| !s.is_empty()) {
+        let username = if username.as_ref().is_some_and(|s| !s.is_empty()) {
             Some(username.unwrap())
         } else {
             None
@@ -48,7 +48,7 @@ impl ProxyConfig {
 
         // 读取密码（可选）
         let password = std::env::var("PROXY_PASSWORD").ok();
-        let password = if password.as_ref().map_or(false, |s| !s.is_empty()) {
+        let password = if password.as_ref().is_some_and(|s| !s.is_empty()) {
             Some(password.unwrap())
         } else {
             None
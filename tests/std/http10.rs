@@ -10,19 +10,18 @@ async fn test_http10_get_via_proxy() {
     let proxy_addr = config.address();
 
     // 连接到代理服务器
-    let mut stream =
-        TcpStream::connect(&proxy_addr).expect(&format!("无法连接到代理服务器: {}", proxy_addr));
+    let mut stream = TcpStream::connect(&proxy_addr)
+        .unwrap_or_else(|_| panic!("无法连接到代理服务器: {}", proxy_addr));
     stream
         .set_read_timeout(Some(Duration::from_secs(10)))
         .expect("设置读取超时失败");
 
     // 构建 HTTP/1.0 请求
-    let request = format!(
-        "GET http://httpbin.org/get HTTP/1.0\r\n\
+    let request = "GET http://httpbin.org/get HTTP/1.0\r\n\
          Host: httpbin.org\r\n\
          User-Agent: RustProxy-Test/1.0\r\n\
          Connection: close\r\n\r\n"
-    );
+        .to_string();
 
     // 发送请求
     stream
@@ -78,8 +77,8 @@ async fn test_http10_post_via_proxy() {
     let proxy_addr = config.address();
 
     // 连接到代理服务器
-    let mut stream =
-        TcpStream::connect(&proxy_addr).expect(&format!("无法连接到代理服务器: {}", proxy_addr));
+    let mut stream = TcpStream::connect(&proxy_addr)
+        .unwrap_or_else(|_| panic!("无法连接到代理服务器: {}", proxy_addr));
     stream
         .set_read_timeout(Some(Duration::from_secs(10)))
         .expect("设置读取超时失败");
@@ -129,19 +128,18 @@ async fn test_http10_head_via_proxy() {
     let proxy_addr = config.address();
 
     // 连接到代理服务器
-    let mut stream =
-        TcpStream::connect(&proxy_addr).expect(&format!("无法连接到代理服务器: {}", proxy_addr));
+    let mut stream = TcpStream::connect(&proxy_addr)
+        .unwrap_or_else(|_| panic!("无法连接到代理服务器: {}", proxy_addr));
     stream
         .set_read_timeout(Some(Duration::from_secs(10)))
         .expect("设置读取超时失败");
 
     // 构建 HTTP/1.0 HEAD 请求
-    let request = format!(
-        "HEAD http://httpbin.org/get HTTP/1.0\r\n\
+    let request = "HEAD http://httpbin.org/get HTTP/1.0\r\n\
          Host: httpbin.org\r\n\
          User-Agent: RustProxy-Test/1.0\r\n\
          Connection: close\r\n\r\n"
-    );
+        .to_string();
 
     // 发送请求
     stream
@@ -165,7 +163,7 @@ async fn test_http10_head_via_proxy() {
 
     // HEAD 请求不应该有响应体
     let mut has_body = false;
-    for line in lines.flatten() {
+    for line in lines.map_while(Result::ok) {
         if !line.is_empty() {
             has_body = true;
             break;
@@ -184,21 +182,20 @@ async fn test_http10_custom_headers_via_proxy() {
     let proxy_addr = config.address();
 
     // 连接到代理服务器
-    let mut stream =
-        TcpStream::connect(&proxy_addr).expect(&format!("无法连接到代理服务器: {}", proxy_addr));
+    let mut stream = TcpStream::connect(&proxy_addr)
+        .unwrap_or_else(|_| panic!("无法连接到代理服务器: {}", proxy_addr));
     stream
         .set_read_timeout(Some(Duration::from_secs(10)))
         .expect("设置读取超时失败");
 
     // 构建 HTTP/1.0 请求（带自定义头）
-    let request = format!(
-        "GET http://httpbin.org/headers HTTP/1.0\r\n\
+    let request = "GET http://httpbin.org/headers HTTP/1.0\r\n\
          Host: httpbin.org\r\n\
          User-Agent: RustProxy-Test/1.0\r\n\
          X-Custom-Header: CustomValue\r\n\
          X-Test-Header: TestValue\r\n\
          Connection: close\r\n\r\n"
-    );
+        .to_string();
 
     // 发送请求
     stream
@@ -221,7 +218,7 @@ async fn test_http10_custom_headers_via_proxy() {
     );
 
     // 读取响应体，查找自定义头
-    let response_body: String = lines.filter_map(|l| l.ok()).collect();
+    let response_body: String = lines.map_while(Result::ok).collect();
 
     // 验证自定义头是否被代理转发
     assert!(
@@ -224,7 +224,7 @@ async fn test_http11_keep_alive_via_proxy() {
             .get(format!("http://httpbin.org/get?req={}", i))
             .send()
             .await
-            .expect(&format!("发送第 {} 个请求失败", i + 1));
+            .unwrap_or_else(|_| panic!("发送第 {} 个请求失败", i + 1));
 
         assert_eq!(
             response.status().as_u16(),
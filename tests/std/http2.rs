@@ -116,7 +116,7 @@ async fn test_http2_multiplexing_via_proxy() {
                 .get(format!("https://httpbin.org/get?req={}", i))
                 .send()
                 .await
-                .expect(&format!("HTTP/2 并发请求 {} 失败", i));
+                .unwrap_or_else(|_| panic!("HTTP/2 并发请求 {} 失败", i));
 
             assert_eq!(
                 response.status().as_u16(),
@@ -212,7 +212,7 @@ async fn test_http2_stream_multiplexing_via_proxy() {
             .get(format!("https://httpbin.org/get?stream={}", i))
             .send()
             .await
-            .expect(&format!("HTTP/2 流请求 {} 失败", i));
+            .unwrap_or_else(|_| panic!("HTTP/2 流请求 {} 失败", i));
 
         assert_eq!(
             response.status().as_u16(),
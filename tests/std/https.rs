@@ -203,7 +203,7 @@ async fn test_https_keep_alive_via_proxy() {
             .get(format!("https://httpbin.org/get?req={}", i))
             .send()
             .await
-            .expect(&format!("发送第 {} 个 HTTPS 请求失败", i + 1));
+            .unwrap_or_else(|_| panic!("发送第 {} 个 HTTPS 请求失败", i + 1));
 
         assert_eq!(
             response.status().as_u16(),
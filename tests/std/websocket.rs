@@ -1,8 +1,8 @@
 use crate::std::config::ProxyConfig;
 use futures::{SinkExt, StreamExt};
 use std::time::Duration;
-use tokio_tungstenite::tungstenite::protocol::Message;
 use tokio_tungstenite::tungstenite::handshake::client::Request;
+use tokio_tungstenite::tungstenite::protocol::Message;
 use tokio_tungstenite::WebSocketStream;
 
 /// 测试 WebSocket 连接通过代理（无认证）
@@ -77,9 +77,7 @@ async fn test_websocket_via_proxy_with_auth() {
     // 构建 WebSocket 连接 URL
     let ws_url = "wss://echo.websocket.org";
 
-    let auth_header = config
-        .auth_header()
-        .expect("应该有认证头");
+    let auth_header = config.auth_header().expect("应该有认证头");
 
     // 构建带有代理认证的请求
     let request = Request::builder()
@@ -155,7 +153,7 @@ async fn test_websocket_multiple_messages_via_proxy() {
     let mut ws_stream = connect_websocket(request).await;
 
     // 发送多条消息
-    let messages = vec![
+    let messages = [
         "Message 1",
         "Message 2",
         "Message 3",
@@ -371,7 +369,9 @@ async fn test_websocket_stability_via_proxy() {
 }
 
 /// 辅助函数：连接 WebSocket（通过代理）
-async fn connect_websocket(request: Request) -> WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>> {
+async fn connect_websocket(
+    request: Request,
+) -> WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>> {
     let (ws_stream, _) = tokio_tungstenite::connect_async_with_config(request, None, false)
         .await
         .expect("无法连接到 WebSocket 服务器");